@@ -0,0 +1,61 @@
+//! 打印截图的灰度分布，以及模板在截图中的匹配值分布，用于选取各种阈值
+//!
+//! ```sh
+//! cargo run -p aah-core --example threshold_stats -- screen.png resources/templates/1920x1080/battle_deploy-card-cost-icon1.png
+//! ```
+
+use aah_core::vision::utils::{average_hsv_v, luma_histogram, otsu_threshold, score_stats};
+use aah_cv::{find_extremes, match_template, MatchTemplateMethod};
+
+/// 直方图每一行合并的灰度级数
+const BUCKET_SIZE: usize = 16;
+/// 直方图中最长的一行的宽度
+const BAR_WIDTH: usize = 50;
+
+fn main() -> Result<(), String> {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() < 2 {
+        return Err(format!("usage: {} <screenshot> [template]", args[0]));
+    }
+    let screen =
+        image::open(&args[1]).map_err(|err| format!("failed to open screenshot: {err}"))?;
+
+    println!("{}x{}", screen.width(), screen.height());
+    println!("average HSV V: {}", average_hsv_v(&screen));
+    println!("otsu threshold: {}", otsu_threshold(&screen));
+
+    let histogram = luma_histogram(&screen);
+    let buckets = histogram
+        .chunks(BUCKET_SIZE)
+        .map(|bucket| bucket.iter().sum::<u32>())
+        .collect::<Vec<_>>();
+    let max = buckets.iter().copied().max().unwrap_or(0).max(1);
+    for (i, count) in buckets.iter().enumerate() {
+        let start = i * BUCKET_SIZE;
+        let bar = "#".repeat(*count as usize * BAR_WIDTH / max as usize);
+        println!("{start:>3}-{:>3} {count:>8} {bar}", start + BUCKET_SIZE - 1);
+    }
+
+    let Some(template) = args.get(2) else {
+        return Ok(());
+    };
+    let template =
+        image::open(template).map_err(|err| format!("failed to open template: {err}"))?;
+    // 分析器中的模板匹配大多使用 CCOEFF_NORMED
+    let res = match_template(
+        &screen.to_luma32f(),
+        &template.to_luma32f(),
+        MatchTemplateMethod::CCOEFF_NORMED,
+    )?;
+    let stats = score_stats(&res);
+    println!();
+    println!(
+        "CCOEFF_NORMED: min {:.4}, max {:.4} at {:?}, mean {:.4}, stddev {:.4}",
+        stats.min,
+        stats.max,
+        find_extremes(&res).max_value_location,
+        stats.mean,
+        stats.stddev
+    );
+    Ok(())
+}
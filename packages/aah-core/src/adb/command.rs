@@ -1,4 +1,6 @@
-use super::AdbTcpStream;
+use std::time::Duration;
+
+use super::{AdbError, AdbTcpStream, Timeouts};
 
 pub mod host_service;
 pub mod local_service;
@@ -8,5 +10,10 @@ pub trait AdbCommand {
 
     fn raw_command(&self) -> String;
 
-    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, String>;
+    /// 执行命令时 socket 的读写超时，默认为 [`Timeouts::shell`]
+    fn timeout(&self, timeouts: &Timeouts) -> Duration {
+        timeouts.shell
+    }
+
+    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, AdbError>;
 }
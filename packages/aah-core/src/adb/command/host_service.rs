@@ -1,4 +1,6 @@
-use crate::adb::{utils::read_payload_to_string, AdbTcpStream, DeviceInfo};
+use crate::adb::{
+    parse_devices, utils::read_payload_to_string, AdbError, AdbTcpStream, DeviceInfo,
+};
 
 use super::AdbCommand;
 
@@ -39,7 +41,7 @@ impl AdbCommand for Version {
         "host:version".to_string()
     }
 
-    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, String> {
+    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, AdbError> {
         stream.check_response_status()?;
         read_payload_to_string(stream)
     }
@@ -61,16 +63,12 @@ impl AdbCommand for DeviceLong {
         "host:devices-l".to_string()
     }
 
-    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, String> {
+    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, AdbError> {
         stream.check_response_status()?;
 
         let response = read_payload_to_string(stream)?;
 
-        let devices_info = response
-            .lines()
-            .filter_map(|line| line.try_into().ok())
-            .collect::<Vec<DeviceInfo>>();
-        return Ok(devices_info);
+        Ok(parse_devices(&response))
     }
 }
 
@@ -92,7 +90,7 @@ impl AdbCommand for Transport {
         format!("host:transport:{}", self.serial_number)
     }
 
-    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, String> {
+    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, AdbError> {
         stream.check_response_status()
     }
 }
@@ -1,8 +1,19 @@
-use std::time::Duration;
+use std::{
+    io::{BufRead, BufReader, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::error;
 
 use crate::adb::{
-    utils::{read_to_end, read_to_end_to_string},
-    AdbTcpStream,
+    utils::{read_exact, read_exact_to_string, read_to_end, read_to_end_to_string},
+    AdbError, AdbTcpStream, Timeouts,
 };
 
 use super::AdbCommand;
@@ -11,7 +22,7 @@ use super::AdbCommand;
 mod test {
     use crate::adb::host;
 
-    use super::{ScreenCap, ShellCommand};
+    use super::*;
 
     #[test]
     fn test_screencap() {
@@ -33,6 +44,116 @@ mod test {
             .unwrap();
         println!("{res}")
     }
+
+    #[test]
+    fn test_push() {
+        use std::io::{Read, Write};
+        use std::net::{SocketAddr, TcpListener};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let SocketAddr::V4(addr) = listener.local_addr().unwrap() else {
+            unreachable!()
+        };
+        let data = (0..SYNC_DATA_MAX + 10)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut request = [0; 9];
+            socket.read_exact(&mut request).unwrap();
+            assert_eq!(&request, b"0005sync:");
+            socket.write_all(b"OKAY").unwrap();
+
+            let mut requests = vec![];
+            let mut received = vec![];
+            loop {
+                let mut header = [0; 8];
+                socket.read_exact(&mut header).unwrap();
+                let id = String::from_utf8(header[..4].to_vec()).unwrap();
+                let len = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+                match id.as_str() {
+                    "SEND" => {
+                        let mut target = vec![0; len];
+                        socket.read_exact(&mut target).unwrap();
+                        requests.push(format!("SEND {}", String::from_utf8(target).unwrap()));
+                    }
+                    "DATA" => {
+                        let mut chunk = vec![0; len];
+                        socket.read_exact(&mut chunk).unwrap();
+                        requests.push(format!("DATA {len}"));
+                        received.extend(chunk);
+                    }
+                    "DONE" => {
+                        requests.push("DONE".to_string());
+                        socket.write_all(b"OKAY\0\0\0\0").unwrap();
+                    }
+                    _ => {
+                        requests.push(id);
+                        break;
+                    }
+                }
+            }
+            (requests, received)
+        });
+
+        let mut stream = AdbTcpStream::connect_with_timeouts(addr, Timeouts::default()).unwrap();
+        stream
+            .execute_command(Push::new(
+                data.clone(),
+                "/data/local/tmp/minitouch".to_string(),
+                0o755,
+            ))
+            .unwrap();
+        let (requests, received) = server.join().unwrap();
+        assert_eq!(
+            requests,
+            vec![
+                "SEND /data/local/tmp/minitouch,33261".to_string(),
+                format!("DATA {SYNC_DATA_MAX}"),
+                "DATA 10".to_string(),
+                "DONE".to_string(),
+                "QUIT".to_string(),
+            ]
+        );
+        assert_eq!(received, data);
+    }
+
+    #[test]
+    fn test_logcat_stream() {
+        use std::io::Write;
+        use std::net::{SocketAddr, TcpListener};
+
+        use crate::adb::Timeouts;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let SocketAddr::V4(addr) = listener.local_addr().unwrap() else {
+            unreachable!()
+        };
+        let server = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            socket.write_all(b"OKAY").unwrap();
+            socket.write_all(b"line 1\r\nline ").unwrap();
+            thread::sleep(Duration::from_millis(300));
+            socket.write_all(b"2\n").unwrap();
+            // 保持连接，直到 stream 被停止
+            thread::sleep(Duration::from_millis(500));
+        });
+
+        let mut stream = AdbTcpStream::connect_with_timeouts(addr, Timeouts::default()).unwrap();
+        let logcat = Logcat::new("*:S".to_string());
+        assert_eq!(logcat.raw_command(), "shell:logcat *:S");
+        stream.execute_command(logcat).unwrap();
+        let mut logcat = LogcatStream::spawn(stream);
+
+        assert_eq!(logcat.next().as_deref(), Some("line 1"));
+        // 跨越多次读取超时的行
+        assert_eq!(logcat.next().as_deref(), Some("line 2"));
+        assert!(logcat.is_running());
+        logcat.stop();
+        assert!(!logcat.is_running());
+        assert_eq!(logcat.next(), None);
+        server.join().unwrap();
+    }
 }
 
 /// shell:command
@@ -55,7 +176,7 @@ impl AdbCommand for ShellCommand {
         format!("shell:{}", self.command)
     }
 
-    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, String> {
+    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, AdbError> {
         stream.check_response_status()?;
         read_to_end_to_string(stream)
     }
@@ -77,7 +198,39 @@ impl AdbCommand for ScreenCap {
         "shell:screencap -p".to_string()
     }
 
-    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, String> {
+    fn timeout(&self, timeouts: &Timeouts) -> Duration {
+        timeouts.screencap
+    }
+
+    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, AdbError> {
+        stream.check_response_status()?;
+        read_to_end(stream)
+    }
+}
+
+/// exec:screencap
+///
+/// 不经过 PNG 编码，直接获取原始的帧缓冲数据，见 [`decode_raw_screencap`](crate::adb::decode_raw_screencap)
+pub struct ScreenCapRaw;
+
+impl ScreenCapRaw {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl AdbCommand for ScreenCapRaw {
+    type Output = Vec<u8>;
+
+    fn raw_command(&self) -> String {
+        "exec:screencap".to_string()
+    }
+
+    fn timeout(&self, timeouts: &Timeouts) -> Duration {
+        timeouts.screencap
+    }
+
+    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, AdbError> {
         stream.check_response_status()?;
         read_to_end(stream)
     }
@@ -110,7 +263,235 @@ impl AdbCommand for InputSwipe {
         )
     }
 
-    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, String> {
+    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, AdbError> {
         stream.check_response_status()
     }
 }
+
+/// localabstract:name
+///
+/// 连接到设备上名为 `name` 的 abstract unix socket（比如 minitouch 的 `minitouch`），
+/// 只检查响应状态，之后连接的读写即为与该 socket 的读写，与 `adb forward` 相同但不占用本地端口
+pub struct LocalAbstract {
+    name: String,
+}
+
+impl LocalAbstract {
+    pub fn new(name: String) -> Self {
+        Self { name }
+    }
+}
+
+impl AdbCommand for LocalAbstract {
+    type Output = ();
+
+    fn raw_command(&self) -> String {
+        format!("localabstract:{}", self.name)
+    }
+
+    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, AdbError> {
+        stream.check_response_status()
+    }
+}
+
+/// sync 协议中每个 `DATA` 块的最大长度
+pub const SYNC_DATA_MAX: usize = 64 * 1024;
+
+/// sync: 中的 SEND
+///
+/// 通过 adb 的 sync 协议将 `data` 写入设备上的 `remote_path`（与 `adb push` 相同），
+/// 文件的权限为 `mode`（比如 `0o755`），不需要本地存在对应的文件，也不依赖 adb 命令行
+pub struct Push {
+    data: Vec<u8>,
+    remote_path: String,
+    mode: u32,
+}
+
+impl Push {
+    pub fn new(data: Vec<u8>, remote_path: String, mode: u32) -> Self {
+        Self {
+            data,
+            remote_path,
+            mode,
+        }
+    }
+}
+
+/// 写入 sync 协议的一个请求：4 字节的 `id`，之后是小端序的 `len`
+fn write_sync_request<T: Write>(target: &mut T, id: &[u8; 4], len: u32) -> Result<(), AdbError> {
+    target.write_all(id)?;
+    target.write_all(&len.to_le_bytes())?;
+    Ok(())
+}
+
+impl AdbCommand for Push {
+    type Output = ();
+
+    fn raw_command(&self) -> String {
+        "sync:".to_string()
+    }
+
+    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, AdbError> {
+        stream.check_response_status()?;
+
+        // 与 adb 相同，mode 中带上普通文件的类型位 S_IFREG
+        let target = format!("{},{}", self.remote_path, 0o100000 | (self.mode & 0o7777));
+        write_sync_request(stream, b"SEND", target.len() as u32)?;
+        stream.write_all(target.as_bytes())?;
+        for chunk in self.data.chunks(SYNC_DATA_MAX) {
+            write_sync_request(stream, b"DATA", chunk.len() as u32)?;
+            stream.write_all(chunk)?;
+        }
+        let mtime = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or_default();
+        write_sync_request(stream, b"DONE", mtime)?;
+
+        // OKAY 或 FAIL，之后是小端序的长度（OKAY 时为 0）和错误信息
+        let status = read_exact_to_string(stream, 4)?;
+        let len = read_exact(stream, 4)?;
+        let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        let res = match status.as_str() {
+            "OKAY" => Ok(()),
+            "FAIL" => Err(AdbError::Protocol(format!(
+                "failed to push {}: {}",
+                self.remote_path,
+                read_exact_to_string(stream, len)?
+            ))),
+            _ => Err(AdbError::Protocol(format!(
+                "unknown sync response {status:?}"
+            ))),
+        };
+        write_sync_request(stream, b"QUIT", 0)?;
+        res
+    }
+}
+
+/// shell:logcat filter
+///
+/// 只检查响应状态，之后的输出通过 [`LogcatStream`] 逐行读取，见 [`Host::stream_logcat`](crate::adb::host::Host::stream_logcat)
+pub struct Logcat {
+    /// logcat 的参数，如 `-v time ActivityManager:I *:S`
+    filter: String,
+}
+
+impl Logcat {
+    pub fn new(filter: String) -> Self {
+        Self { filter }
+    }
+}
+
+impl AdbCommand for Logcat {
+    type Output = ();
+
+    fn raw_command(&self) -> String {
+        format!("shell:logcat {}", self.filter)
+            .trim_end()
+            .to_string()
+    }
+
+    fn handle_response(&self, stream: &mut AdbTcpStream) -> Result<Self::Output, AdbError> {
+        stream.check_response_status()
+    }
+}
+
+/// 读取 logcat 输出时的读取超时，即检查停止信号的间隔
+const LOGCAT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// 未被取走的行数上限，超过后读取线程会等待，不会在内存中无限堆积
+const LOGCAT_CHANNEL_CAPACITY: usize = 1024;
+
+/// 在后台线程中逐行读取一个已经执行了 [`Logcat`] 的连接，作为 [`Iterator`] 取出每一行
+///
+/// 调用 [`LogcatStream::stop`] 或 drop 后停止读取并关闭连接
+pub struct LogcatStream {
+    rx: Receiver<String>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl LogcatStream {
+    pub fn spawn(stream: AdbTcpStream) -> Self {
+        let (tx, rx) = mpsc::sync_channel(LOGCAT_CHANNEL_CAPACITY);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut reader = BufReader::new(stream);
+                if let Err(err) = reader.get_mut().set_timeout(LOGCAT_POLL_INTERVAL) {
+                    error!("failed to set logcat timeout: {err}");
+                    return;
+                }
+                // 超时时已读到的部分保留在 buf 中，下次继续读取
+                let mut buf = Vec::new();
+                while !stop.load(Ordering::Relaxed) {
+                    match reader.read_until(b'\n', &mut buf) {
+                        Ok(0) => break,
+                        Ok(_) => {
+                            let line = String::from_utf8_lossy(&buf).trim_end().to_string();
+                            buf.clear();
+                            // 接收端已被 drop
+                            if tx.send(line).is_err() {
+                                break;
+                            }
+                        }
+                        Err(err) => match AdbError::from(err) {
+                            AdbError::Timeout => continue,
+                            AdbError::ConnectionClosed => break,
+                            err => {
+                                error!("failed to read logcat: {err}");
+                                break;
+                            }
+                        },
+                    }
+                }
+                stop.store(true, Ordering::Relaxed);
+            })
+        };
+
+        Self {
+            rx,
+            stop,
+            handle: Some(handle),
+        }
+    }
+
+    /// 读取线程是否仍在运行，连接关闭或被停止后返回 `false`
+    pub fn is_running(&self) -> bool {
+        !self.stop.load(Ordering::Relaxed)
+    }
+
+    /// 停止读取并等待读取线程退出，丢弃还未取出的行，之后 [`Iterator::next`] 返回 `None`
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // 读取线程可能正阻塞在已满的 channel 上
+        while self.rx.try_recv().is_ok() {}
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+
+    /// 等待下一行最多 `timeout`，超时或 stream 已结束时返回 `None`
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<String> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(line) => Some(line),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+}
+
+impl Iterator for LogcatStream {
+    type Item = String;
+
+    /// 阻塞直到读到下一行，stream 结束后返回 `None`
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rx.recv().ok()
+    }
+}
+
+impl Drop for LogcatStream {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
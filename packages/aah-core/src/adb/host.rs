@@ -5,9 +5,10 @@ use log::info;
 use super::{
     command::{
         host_service::{self, DeviceLong},
+        local_service::{Logcat, LogcatStream},
         AdbCommand,
     },
-    AdbTcpStream,
+    AdbError, AdbTcpStream, Timeouts,
 };
 // use self::command::AdbCommand;
 
@@ -54,14 +55,15 @@ pub struct Host {
     socket_addr: SocketAddrV4,
     adb_tcp_stream: Option<AdbTcpStream>,
     transported_serial: Option<String>,
+    timeouts: Timeouts,
 }
 
-pub fn connect_default() -> Result<Host, String> {
+pub fn connect_default() -> Result<Host, AdbError> {
     connect(Ipv4Addr::new(127, 0, 0, 1), 5037)
 }
 
 // to get a host connection
-pub fn connect(ip: Ipv4Addr, port: u16) -> Result<Host, String> {
+pub fn connect(ip: Ipv4Addr, port: u16) -> Result<Host, AdbError> {
     // TODO: if the daemon is not started first start the daemon
     // TODO: or else just use process, don't use socket
     // TODO: or, separate them?
@@ -76,27 +78,39 @@ impl Host {
             socket_addr,
             adb_tcp_stream: None,
             transported_serial: None,
+            timeouts: Timeouts::default(),
         }
     }
 
-    pub fn reconnect(&mut self) -> Result<(), String> {
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts
+    }
+
+    /// 设置执行命令时的读写超时，超时后返回 [`AdbError::Timeout`]
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+        if let Some(stream) = self.adb_tcp_stream.as_mut() {
+            stream.set_timeouts(timeouts);
+        }
+    }
+
+    pub fn reconnect(&mut self) -> Result<(), AdbError> {
         self.transported_serial = None;
-        self.adb_tcp_stream = AdbTcpStream::connect(self.socket_addr).ok();
+        self.adb_tcp_stream =
+            AdbTcpStream::connect_with_timeouts(self.socket_addr, self.timeouts).ok();
         Ok(())
     }
 
     // get devices
     pub fn devices_long(&mut self) -> Result<Vec<DeviceInfo>, MyError> {
-        let response = self
-            .execute_command(DeviceLong::new())
-            .map_err(|err| MyError::Adb(err.to_string()))?;
+        let response = self.execute_command(DeviceLong::new())?;
         Ok(response)
     }
 
     pub fn execute_command<T>(
         &mut self,
         command: impl AdbCommand<Output = T>,
-    ) -> Result<T, String> {
+    ) -> Result<T, AdbError> {
         // TODO: maybe reconnect every time is a good choice?
         // TODO: no, for transport
         if self.adb_tcp_stream.is_none() {
@@ -106,11 +120,11 @@ impl Host {
 
         self.adb_tcp_stream
             .as_mut()
-            .ok_or("not connected".to_string())
+            .ok_or(AdbError::ConnectionClosed)
             .and_then(|stream| stream.execute_command(command))
     }
 
-    fn transport<S: AsRef<str>>(&mut self, serial_number: S) -> Result<(), String> {
+    fn transport<S: AsRef<str>>(&mut self, serial_number: S) -> Result<(), AdbError> {
         let serial_number = serial_number.as_ref();
         info!("transporting to {}...", serial_number);
         if let Some(serial) = &self.transported_serial {
@@ -127,11 +141,27 @@ impl Host {
         Ok(())
     }
 
+    /// 在新的连接上执行 `logcat filter`，在后台线程中逐行读取输出，不影响当前连接
+    ///
+    /// 调用 [`LogcatStream::stop`] 或 drop 返回的 [`LogcatStream`] 以停止
+    pub fn stream_logcat<S: AsRef<str>>(
+        &self,
+        serial_number: S,
+        filter: String,
+    ) -> Result<LogcatStream, AdbError> {
+        let mut stream = AdbTcpStream::connect_with_timeouts(self.socket_addr, self.timeouts)?;
+        stream.execute_command(host_service::Transport::new(
+            serial_number.as_ref().to_string(),
+        ))?;
+        stream.execute_command(Logcat::new(filter))?;
+        Ok(LogcatStream::spawn(stream))
+    }
+
     pub fn execute_local_command<T, S: AsRef<str>>(
         &mut self,
         serial_number: S,
         command: impl AdbCommand<Output = T>,
-    ) -> Result<T, String> {
+    ) -> Result<T, AdbError> {
         let serial_number = serial_number.as_ref();
         self.reconnect()?;
         self.transport(serial_number)?;
@@ -2,17 +2,20 @@ use std::{
     collections::BTreeMap,
     error::Error,
     fmt::Display,
-    io::{Cursor, Read, Write},
+    io::{Read, Write},
     net::{Ipv4Addr, SocketAddrV4, TcpStream},
     process::Command,
     sync::Mutex,
     time::Duration,
 };
 
-use image::{codecs::png::PngDecoder, DynamicImage};
+use image::{DynamicImage, ImageFormat, RgbaImage};
 use log::{error, info};
 
-use crate::adb::utils::{read_payload_to_string, read_response_status, ResponseStatus};
+use crate::{
+    adb::utils::{read_payload_to_string, read_response_status, ResponseStatus},
+    vision::utils::Rect,
+};
 
 use self::{
     command::{host_service, local_service, AdbCommand},
@@ -45,47 +48,272 @@ impl Display for MyError {
 
 impl Error for MyError {}
 
+impl From<AdbError> for MyError {
+    fn from(err: AdbError) -> Self {
+        match err {
+            AdbError::DeviceNotFound(reason) => MyError::DeviceNotFound(reason),
+            err => MyError::Adb(err.to_string()),
+        }
+    }
+}
+
+/// 与 adb server 通信时的错误
+#[derive(Debug)]
+pub enum AdbError {
+    /// 连接已被关闭，比如读取响应时遇到了 EOF
+    ConnectionClosed,
+    /// 不符合协议的响应，或者 adb server 返回的 `FAIL` 及其原因
+    Protocol(String),
+    Io(std::io::Error),
+    /// 找不到设备，包含 adb server 返回的原因
+    DeviceNotFound(String),
+    /// 设备未授权 USB 调试，包含 adb server 返回的原因
+    Unauthorized(String),
+    /// 读写超时，比如设备在截图时卡住，见 [`Timeouts`]
+    Timeout,
+}
+
+impl AdbError {
+    /// 根据 adb server 返回的 `FAIL` 原因（如 `device 'xxx' not found`）构造错误
+    pub fn from_fail_reason(reason: String) -> Self {
+        if reason.contains("unauthorized") {
+            AdbError::Unauthorized(reason)
+        } else if reason.contains("not found") || reason.contains("no devices") {
+            AdbError::DeviceNotFound(reason)
+        } else {
+            AdbError::Protocol(reason)
+        }
+    }
+}
+
+impl Display for AdbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdbError::ConnectionClosed => write!(f, "connection closed"),
+            AdbError::Timeout => write!(f, "timed out"),
+            AdbError::Io(err) => write!(f, "{:?}", err),
+            AdbError::Protocol(reason)
+            | AdbError::DeviceNotFound(reason)
+            | AdbError::Unauthorized(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl Error for AdbError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AdbError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for AdbError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::UnexpectedEof => AdbError::ConnectionClosed,
+            // 设置了读写超时的 socket 超时后，unix 上是 `WouldBlock`，windows 上是 `TimedOut`
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => AdbError::Timeout,
+            _ => AdbError::Io(err),
+        }
+    }
+}
+
+/// 供仍然使用 `String` 作为错误的调用方直接使用 `?`
+impl From<AdbError> for String {
+    fn from(err: AdbError) -> Self {
+        err.to_string()
+    }
+}
+
+/// adb socket 的读写超时，见 [`AdbCommand::timeout`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// 截图的超时，默认 5s
+    pub screencap: Duration,
+    /// 其他命令（shell 等）的超时，默认 2s
+    pub shell: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            screencap: Duration::from_secs(5),
+            shell: Duration::from_secs(2),
+        }
+    }
+}
+
+/// `adb devices -l` 中设备的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceState {
+    /// 已连接，可以使用
+    Device,
+    Offline,
+    /// 未授权 USB 调试
+    Unauthorized,
+    /// 其他状态，如 `bootloader`、`recovery`、`no permissions`
+    Other(String),
+}
+
+impl DeviceState {
+    fn parse(state: &str) -> Self {
+        match state {
+            "device" => DeviceState::Device,
+            "offline" => DeviceState::Offline,
+            "unauthorized" => DeviceState::Unauthorized,
+            state => DeviceState::Other(state.to_string()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DeviceInfo {
     pub serial: String,
+    pub state: DeviceState,
+    /// `product`、`model`、`device`、`transport_id` 等键值对
     pub info: BTreeMap<String, String>,
 }
 
+impl DeviceInfo {
+    /// 只有 [`DeviceState::Device`] 状态的设备可以使用
+    pub fn is_available(&self) -> bool {
+        self.state == DeviceState::Device
+    }
+
+    pub fn product(&self) -> Option<&str> {
+        self.info.get("product").map(String::as_str)
+    }
+
+    pub fn model(&self) -> Option<&str> {
+        self.info.get("model").map(String::as_str)
+    }
+
+    pub fn transport_id(&self) -> Option<u32> {
+        self.info.get("transport_id")?.parse().ok()
+    }
+}
+
 impl TryFrom<&str> for DeviceInfo {
     type Error = MyError;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        // Turn "serial\tdevice key1:value1 key2:value2 ..." into a `DeviceInfo`.
-        let mut pairs = value.split_whitespace();
-        let serial = pairs.next();
-        let state = pairs.next();
-        if let (Some(serial), Some("device")) = (serial, state) {
-            let info: BTreeMap<String, String> = pairs
-                .filter_map(|pair| {
-                    let mut kv = pair.split(':');
-                    if let (Some(k), Some(v), None) = (kv.next(), kv.next(), kv.next()) {
-                        Some((k.to_owned(), v.to_owned()))
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-
-            Ok(DeviceInfo {
-                serial: serial.to_owned(),
-                info,
-            })
-        } else {
-            Err(MyError::ParseError(
+        // Turn "serial\tstate key1:value1 key2:value2 ..." into a `DeviceInfo`.
+        let mut pairs = value.split_whitespace().peekable();
+        let (Some(serial), Some(state)) = (pairs.next(), pairs.next()) else {
+            return Err(MyError::ParseError(
                 "failed to parse device info".to_string(),
+            ));
+        };
+        // "no permissions (...)" is the only state with spaces
+        let state = if state == "no" && pairs.peek() == Some(&"permissions") {
+            pairs.next();
+            "no permissions"
+        } else {
+            state
+        };
+
+        let info: BTreeMap<String, String> = pairs
+            .filter_map(|pair| {
+                let mut kv = pair.split(':');
+                if let (Some(k), Some(v), None) = (kv.next(), kv.next(), kv.next()) {
+                    Some((k.to_owned(), v.to_owned()))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        Ok(DeviceInfo {
+            serial: serial.to_owned(),
+            state: DeviceState::parse(state),
+            info,
+        })
+    }
+}
+
+/// 解析 `adb devices -l`（`host:devices-l`）的输出，包括所有状态的设备，跳过无法解析的行
+pub fn parse_devices(output: &str) -> Vec<DeviceInfo> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with("List of devices"))
+        .filter_map(|line| line.try_into().ok())
+        .collect()
+}
+
+/// 解码 `screencap -p` 输出的图像，根据开头的字节判断格式（见 [`image::guess_format`]）
+///
+/// 大部分设备输出 PNG，部分模拟器/ROM 会输出 JPEG 或 WebP，其他格式返回错误
+pub fn decode_screencap(bytes: &[u8]) -> Result<DynamicImage, String> {
+    let format = image::guess_format(bytes).map_err(|_| {
+        format!(
+            "unknown screencap format, header: {:02x?}",
+            &bytes[..bytes.len().min(16)]
+        )
+    })?;
+    if !matches!(
+        format,
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP
+    ) {
+        return Err(format!("unsupported screencap format {format:?}"));
+    }
+    image::load_from_memory_with_format(bytes, format)
+        .map_err(|err| format!("failed to decode {format:?} screencap: {err}"))
+}
+
+/// 解析 `screencap`（不带 `-p`）输出的原始帧缓冲数据，只转换 `rect` 区域内的像素
+///
+/// 数据由 `width`、`height`、`format` 三个 u32 开头（Android 9 起还有一个 u32 的 color space），
+/// 后面是逐行排列的像素，目前支持 RGBA_8888(1)、RGBX_8888(2)、RGB_888(3) 和 BGRA_8888(5) 格式。
+/// `rect` 超出屏幕的部分会被截掉
+pub fn decode_raw_screencap(bytes: &[u8], rect: &Rect) -> Result<DynamicImage, String> {
+    let read_u32 = |offset: usize| -> Result<u32, String> {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or("raw screencap is too short".to_string())
+    };
+    let (width, height, format) = (read_u32(0)?, read_u32(4)?, read_u32(8)?);
+    let bytes_per_pixel = match format {
+        1 | 2 | 5 => 4,
+        3 => 3,
+        _ => return Err(format!("unsupported raw screencap format {format}")),
+    };
+    let data_len = width as usize * height as usize * bytes_per_pixel;
+    let header_len = match bytes.len().checked_sub(data_len) {
+        Some(len @ (12 | 16)) => len,
+        _ => {
+            return Err(format!(
+                "unexpected raw screencap size {} for {width}x{height}",
+                bytes.len()
             ))
         }
+    };
+    let data = &bytes[header_len..];
+
+    let x = rect.x.min(width);
+    let y = rect.y.min(height);
+    let region_width = rect.width.min(width - x);
+    let region_height = rect.height.min(height - y);
+
+    let mut image = RgbaImage::new(region_width, region_height);
+    for (dy, row) in image.rows_mut().enumerate() {
+        let start = ((y as usize + dy) * width as usize + x as usize) * bytes_per_pixel;
+        let src = &data[start..start + region_width as usize * bytes_per_pixel];
+        for (pixel, src) in row.zip(src.chunks_exact(bytes_per_pixel)) {
+            pixel.0 = match format {
+                1 => [src[0], src[1], src[2], src[3]],
+                5 => [src[2], src[1], src[0], src[3]],
+                _ => [src[0], src[1], src[2], 255],
+            };
+        }
     }
+    Ok(DynamicImage::ImageRgba8(image))
 }
 
 #[cfg(test)]
 mod test {
-    use std::time::Instant;
+    use std::{io::Cursor, time::Instant};
 
     use super::*;
     use crate::adb::command::local_service;
@@ -96,6 +324,187 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_parse_devices() {
+        let output = "List of devices attached
+127.0.0.1:16384        device product:MuMu model:MuMu device:x86_64 transport_id:3
+emulator-5554          offline transport_id:1
+R58M12345AB            unauthorized usb:1-1 transport_id:2
+0123456789ABCDEF       no permissions (user in plugdev group; are your udev rules wrong?); see [http://developer.android.com/tools/device.html] usb:1-2 transport_id:4
+";
+        let devices = parse_devices(output);
+        assert_eq!(devices.len(), 4);
+
+        assert_eq!(devices[0].serial, "127.0.0.1:16384");
+        assert!(devices[0].is_available());
+        assert_eq!(devices[0].product(), Some("MuMu"));
+        assert_eq!(devices[0].model(), Some("MuMu"));
+        assert_eq!(devices[0].transport_id(), Some(3));
+
+        assert_eq!(devices[1].state, DeviceState::Offline);
+        assert_eq!(devices[2].state, DeviceState::Unauthorized);
+        assert_eq!(devices[2].transport_id(), Some(2));
+        assert!(!devices[2].is_available());
+        assert_eq!(
+            devices[3].state,
+            DeviceState::Other("no permissions".to_string())
+        );
+        assert_eq!(devices[3].transport_id(), Some(4));
+    }
+
+    #[test]
+    fn test_adb_error() {
+        let err = AdbError::from_fail_reason("device 'emulator-5556' not found".to_string());
+        assert!(matches!(err, AdbError::DeviceNotFound(_)));
+        assert_eq!(err.to_string(), "device 'emulator-5556' not found");
+        assert!(matches!(
+            AdbError::from_fail_reason("device unauthorized.".to_string()),
+            AdbError::Unauthorized(_)
+        ));
+        assert!(matches!(
+            AdbError::from_fail_reason("closed".to_string()),
+            AdbError::Protocol(_)
+        ));
+
+        // 读取响应时连接被关闭
+        let mut stream: &[u8] = b"OK";
+        let err = utils::read_response_status(&mut stream).unwrap_err();
+        assert!(matches!(err, AdbError::ConnectionClosed));
+        let mut stream: &[u8] = b"WHAT";
+        let err = utils::read_response_status(&mut stream).unwrap_err();
+        assert!(matches!(err, AdbError::Protocol(_)));
+
+        assert!(matches!(
+            MyError::from(AdbError::DeviceNotFound("not found".to_string())),
+            MyError::DeviceNotFound(_)
+        ));
+    }
+
+    #[test]
+    fn test_timeout() {
+        // 接受连接但从不响应的 server
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let std::net::SocketAddr::V4(addr) = listener.local_addr().unwrap() else {
+            unreachable!()
+        };
+        let handle = std::thread::spawn(move || listener.accept().unwrap());
+
+        let timeouts = Timeouts {
+            screencap: Duration::from_millis(300),
+            shell: Duration::from_millis(100),
+        };
+        let mut stream = AdbTcpStream::connect_with_timeouts(addr, timeouts).unwrap();
+        let _server = handle.join().unwrap();
+
+        let start = Instant::now();
+        let err = stream
+            .execute_command(local_service::ShellCommand::new("echo".to_string()))
+            .unwrap_err();
+        assert!(matches!(err, AdbError::Timeout));
+        let shell_cost = start.elapsed();
+
+        let start = Instant::now();
+        let err = stream
+            .execute_command(local_service::ScreenCap::new())
+            .unwrap_err();
+        assert!(matches!(err, AdbError::Timeout));
+        let screencap_cost = start.elapsed();
+        println!("shell: {shell_cost:?}, screencap: {screencap_cost:?}");
+        assert!(screencap_cost >= Duration::from_millis(300));
+        assert!(shell_cost < screencap_cost);
+    }
+
+    #[test]
+    fn test_decode_raw_screencap() {
+        // 4x3 的 RGBA_8888 帧，带有 color space
+        let mut bytes = [4u32, 3, 1, 0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        for i in 0..12u8 {
+            bytes.extend([i, i + 100, i + 200, 255]);
+        }
+        let rect = Rect {
+            x: 1,
+            y: 1,
+            width: 10,
+            height: 1,
+        };
+        let image = decode_raw_screencap(&bytes, &rect).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (3, 1));
+        assert_eq!(image.get_pixel(0, 0).0, [5, 105, 205, 255]);
+        assert_eq!(image.get_pixel(2, 0).0, [7, 107, 207, 255]);
+
+        // 没有 color space 的旧格式
+        let image = decode_raw_screencap(&[&bytes[..12], &bytes[16..]].concat(), &rect).unwrap();
+        assert_eq!(image.to_rgba8().get_pixel(0, 0).0, [5, 105, 205, 255]);
+
+        assert!(decode_raw_screencap(&bytes[..20], &rect).is_err());
+    }
+
+    #[test]
+    fn test_decode_screencap() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(8, 6, |x, y| {
+            image::Rgba([x as u8 * 30, y as u8 * 40, 128, 255])
+        }));
+        let encode = |format: ImageFormat| {
+            let mut bytes = vec![];
+            // JPEG 不支持透明通道
+            DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_to(&mut Cursor::new(&mut bytes), format)
+                .unwrap();
+            bytes
+        };
+
+        for (format, magic) in [
+            (ImageFormat::Png, &b"\x89PNG"[..]),
+            (ImageFormat::Jpeg, &b"\xff\xd8\xff"[..]),
+            (ImageFormat::WebP, &b"RIFF"[..]),
+        ] {
+            let bytes = encode(format);
+            assert!(bytes.starts_with(magic), "{format:?}");
+            let decoded = decode_screencap(&bytes).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (8, 6), "{format:?}");
+        }
+
+        // 未知格式
+        let err = decode_screencap(b"not an image at all").unwrap_err();
+        println!("{err}");
+        assert!(err.contains("unknown screencap format"));
+        // 可以识别但不支持的格式
+        let err = decode_screencap(&encode(ImageFormat::Bmp)).unwrap_err();
+        assert!(err.contains("unsupported"));
+        // 头部正确但数据不完整
+        let bytes = encode(ImageFormat::Png);
+        assert!(decode_screencap(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn test_screencap_region() {
+        let device = connect("127.0.0.1:16384").unwrap();
+        let rect = Rect {
+            x: 0,
+            y: 810,
+            width: 1920,
+            height: 270,
+        };
+
+        let start = Instant::now();
+        let region = device.screencap_region(&rect).unwrap();
+        println!("region cost: {}", start.elapsed().as_millis());
+
+        let start = Instant::now();
+        let screen = device.screencap().unwrap();
+        println!("full cost: {}", start.elapsed().as_millis());
+
+        assert_eq!(
+            region.to_rgba8(),
+            screen
+                .crop_imm(rect.x, rect.y, rect.width, rect.height)
+                .to_rgba8()
+        );
+    }
+
     #[test]
     fn test_screencap() {
         let device = connect("127.0.0.1:16384").unwrap();
@@ -133,28 +542,50 @@ impl Write for AdbTcpStream {
 
 pub struct AdbTcpStream {
     inner: TcpStream,
+    timeouts: Timeouts,
 }
 
 impl AdbTcpStream {
-    pub fn connect(socket_addr: SocketAddrV4) -> Result<Self, String> {
+    pub fn connect(socket_addr: SocketAddrV4) -> Result<Self, AdbError> {
+        Self::connect_with_timeouts(socket_addr, Timeouts::default())
+    }
+
+    pub fn connect_with_timeouts(
+        socket_addr: SocketAddrV4,
+        timeouts: Timeouts,
+    ) -> Result<Self, AdbError> {
         info!("connecting to {:?}...", socket_addr);
-        let stream = TcpStream::connect(socket_addr).map_err(|err| format!("{:?}", err))?;
-        stream
-            .set_read_timeout(Some(Duration::from_secs(2)))
-            .map_err(|err| format!("{:?}", err))?;
-        stream
-            .set_write_timeout(Some(Duration::from_secs(2)))
-            .map_err(|err| format!("{:?}", err))?;
-        let res = Self { inner: stream };
+        let stream = TcpStream::connect(socket_addr)?;
+        let mut res = Self {
+            inner: stream,
+            timeouts,
+        };
+        res.set_timeout(timeouts.shell)?;
         info!("connected");
         Ok(res)
     }
 
-    pub fn connect_host() -> Result<Self, String> {
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts
+    }
+
+    /// 之后执行的命令使用 `timeouts` 作为超时
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// 设置 socket 的读写超时，超时后读写会返回 [`AdbError::Timeout`]
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), AdbError> {
+        self.inner.set_read_timeout(Some(timeout))?;
+        self.inner.set_write_timeout(Some(timeout))?;
+        Ok(())
+    }
+
+    pub fn connect_host() -> Result<Self, AdbError> {
         Self::connect(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 5037))
     }
 
-    pub fn connect_device<S: AsRef<str>>(serial: S) -> Result<Self, String> {
+    pub fn connect_device<S: AsRef<str>>(serial: S) -> Result<Self, AdbError> {
         let serial = serial.as_ref();
         let mut stream = Self::connect_host()?;
         stream.execute_command(host_service::Transport::new(serial.to_string()))?;
@@ -164,22 +595,23 @@ impl AdbTcpStream {
     pub fn execute_command<T>(
         &mut self,
         command: impl AdbCommand<Output = T>,
-    ) -> Result<T, String> {
+    ) -> Result<T, AdbError> {
         // TODO: maybe reconnect every time is a good choice?
         // TODO: no, for transport
         info!("executing command: {:?}...", command.raw_command());
+        self.set_timeout(command.timeout(&self.timeouts))?;
         write_request(self, command.raw_command())?;
 
         command.handle_response(self)
     }
 
-    pub fn check_response_status(&mut self) -> Result<(), String> {
+    pub fn check_response_status(&mut self) -> Result<(), AdbError> {
         info!("checking response_status...");
         let status = read_response_status(self)?;
         if let ResponseStatus::Fail = status {
             let reason = read_payload_to_string(self)?;
             error!("response status is FAIL, reason: {}", reason);
-            return Err(reason);
+            return Err(AdbError::from_fail_reason(reason));
         }
         info!("response status is OKAY");
         Ok(())
@@ -204,6 +636,7 @@ pub fn connect<S: AsRef<str>>(serial: S) -> Result<Device, MyError> {
     let serials = host
         .devices_long()?
         .iter()
+        .filter(|device_info| device_info.is_available())
         .map(|device_info| device_info.serial.clone())
         .collect::<Vec<String>>();
 
@@ -231,7 +664,19 @@ impl Device {
     }
 
     pub fn connect_adb_tcp_stream(&self) -> Result<AdbTcpStream, MyError> {
-        AdbTcpStream::connect_device(&self.serial).map_err(|err| MyError::S(err))
+        let mut stream = AdbTcpStream::connect_device(&self.serial)?;
+        stream.set_timeouts(self.timeouts());
+        Ok(stream)
+    }
+
+    /// 同 [`Host::timeouts`]
+    pub fn timeouts(&self) -> Timeouts {
+        self.host.lock().unwrap().timeouts()
+    }
+
+    /// 同 [`Host::set_timeouts`]，之后与设备建立的连接都会使用 `timeouts`
+    pub fn set_timeouts(&self, timeouts: Timeouts) {
+        self.host.lock().unwrap().set_timeouts(timeouts);
     }
 
     // pub fn get_screen_size(&self) -> Result<(u32, u32), MyError> {
@@ -241,19 +686,22 @@ impl Device {
 
     pub fn screencap(&self) -> Result<image::DynamicImage, MyError> {
         let mut adb_tcp_stream = self.connect_adb_tcp_stream()?;
-        let bytes = adb_tcp_stream
-            .execute_command(local_service::ScreenCap::new())
-            .expect("failed to screencap");
+        let bytes = adb_tcp_stream.execute_command(local_service::ScreenCap::new())?;
         // let bytes = self
         //     .execute_command_by_process("exec-out screencap -p")
         //     .expect("failed to screencap");
 
-        let decoder = PngDecoder::new(Cursor::new(bytes))
-            .map_err(|err| MyError::ImageDecodeError(format!("{:?}", err)))?;
+        decode_screencap(&bytes).map_err(MyError::ImageDecodeError)
+    }
 
-        let image = DynamicImage::from_decoder(decoder)
-            .map_err(|err| MyError::ImageDecodeError(format!("{:?}", err)))?;
-        Ok(image)
+    /// 只截取屏幕中 `rect`（设备分辨率下）区域的内容
+    ///
+    /// 通过 `exec:screencap` 获取原始的帧缓冲数据，跳过 PNG 的编码和解码，
+    /// 只转换区域内的像素，见 [`decode_raw_screencap`]
+    pub fn screencap_region(&self, rect: &Rect) -> Result<image::DynamicImage, MyError> {
+        let mut adb_tcp_stream = self.connect_adb_tcp_stream()?;
+        let bytes = adb_tcp_stream.execute_command(local_service::ScreenCapRaw::new())?;
+        decode_raw_screencap(&bytes, rect).map_err(MyError::ImageDecodeError)
     }
 
     pub fn execute_command_by_process(&self, command: &str) -> Result<Vec<u8>, MyError> {
@@ -275,6 +723,6 @@ impl Device {
         let mut adb_tcp_stream = self.connect_adb_tcp_stream()?;
         adb_tcp_stream
             .execute_command(command)
-            .map_err(|err| MyError::Adb(err.to_string()))
+            .map_err(MyError::from)
     }
 }
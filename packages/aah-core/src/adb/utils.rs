@@ -4,6 +4,8 @@ use std::{
     str::FromStr,
 };
 
+use super::AdbError;
+
 pub fn execute_adb_command(serial: &str, command: &str) -> Result<Vec<u8>, String> {
     let mut args = vec!["-s", serial];
     args.extend(command.split_whitespace().collect::<Vec<&str>>());
@@ -18,51 +20,48 @@ pub fn execute_adb_command(serial: &str, command: &str) -> Result<Vec<u8>, Strin
 
 // Streaming
 
-pub fn read_exact<T: Read>(source: &mut T, len: usize) -> Result<Vec<u8>, String> {
+pub fn read_exact<T: Read>(source: &mut T, len: usize) -> Result<Vec<u8>, AdbError> {
     let mut buf = [0; 65536];
-    source
-        .read_exact(&mut buf[..len])
-        .map_err(|err| format!("{:?}", err))?;
+    source.read_exact(&mut buf[..len])?;
     Ok(buf[..len].to_vec())
 }
 
-pub fn read_exact_to_string<T: Read>(source: &mut T, len: usize) -> Result<String, String> {
+pub fn read_exact_to_string<T: Read>(source: &mut T, len: usize) -> Result<String, AdbError> {
     let bytes = read_exact(source, len)?;
-    let s = std::str::from_utf8(&bytes).map_err(|err| format!("{:?}", err))?;
+    let s = std::str::from_utf8(&bytes).map_err(|err| AdbError::Protocol(format!("{:?}", err)))?;
     Ok(s.to_string())
 }
 
-pub fn read_to_end<T: Read>(source: &mut T) -> Result<Vec<u8>, String> {
+pub fn read_to_end<T: Read>(source: &mut T) -> Result<Vec<u8>, AdbError> {
     let mut response = Vec::new();
-    source
-        .read_to_end(&mut response)
-        .map_err(|err| format!("{:?}", err))?;
+    source.read_to_end(&mut response)?;
     Ok(response)
 }
 
-pub fn read_to_end_to_string<T: Read>(source: &mut T) -> Result<String, String> {
+pub fn read_to_end_to_string<T: Read>(source: &mut T) -> Result<String, AdbError> {
     let bytes = read_to_end(source)?;
-    let s = std::str::from_utf8(&bytes).map_err(|err| format!("{:?}", err))?;
+    let s = std::str::from_utf8(&bytes).map_err(|err| AdbError::Protocol(format!("{:?}", err)))?;
     Ok(s.to_string())
 }
 
 // Following are more utilized things
 
-pub fn read_payload_len<T: Read>(source: &mut T) -> Result<usize, String> {
+pub fn read_payload_len<T: Read>(source: &mut T) -> Result<usize, AdbError> {
     let len = read_exact_to_string(source, 4)?;
-    let len = usize::from_str_radix(&len, 16).unwrap();
+    let len = usize::from_str_radix(&len, 16)
+        .map_err(|_| AdbError::Protocol(format!("invalid payload length {len:?}")))?;
     Ok(len)
 }
 
-pub fn read_payload<T: Read>(source: &mut T) -> Result<Vec<u8>, String> {
+pub fn read_payload<T: Read>(source: &mut T) -> Result<Vec<u8>, AdbError> {
     let len = read_payload_len(source)?;
     let bytes = read_exact(source, len)?;
     Ok(bytes)
 }
 
-pub fn read_payload_to_string<T: Read>(source: &mut T) -> Result<String, String> {
+pub fn read_payload_to_string<T: Read>(source: &mut T) -> Result<String, AdbError> {
     let bytes = read_payload(source)?;
-    let s = std::str::from_utf8(&bytes).map_err(|err| format!("{:?}", err))?;
+    let s = std::str::from_utf8(&bytes).map_err(|err| AdbError::Protocol(format!("{:?}", err)))?;
     Ok(s.to_string())
 }
 
@@ -84,14 +83,14 @@ impl FromStr for ResponseStatus {
     }
 }
 
-pub fn read_response_status<T: Read>(source: &mut T) -> Result<ResponseStatus, String> {
+pub fn read_response_status<T: Read>(source: &mut T) -> Result<ResponseStatus, AdbError> {
     let status = read_exact_to_string(source, 4)?;
-    let status = ResponseStatus::from_str(&status).unwrap();
+    let status = ResponseStatus::from_str(&status).map_err(AdbError::Protocol)?;
     Ok(status)
 }
 
-pub fn write_request<T: Write>(target: &mut T, request: String) -> Result<(), String> {
+pub fn write_request<T: Write>(target: &mut T, request: String) -> Result<(), AdbError> {
     target
         .write_all(format!("{:04x}{}", request.len(), request).as_bytes())
-        .map_err(|err| format!("tcp error: {:?}", err))
+        .map_err(AdbError::from)
 }
@@ -1,2 +1,3 @@
 pub mod navigate;
+pub mod popup;
 pub mod task;
@@ -1,4 +1,10 @@
-use std::{collections::HashMap, error::Error, fs, path::Path};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+    fs,
+    path::Path,
+    sync::RwLock,
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -9,7 +15,7 @@ use crate::task::{
 
 #[cfg(test)]
 mod test {
-    use std::{error::Error, fs::OpenOptions, io::Write};
+    use std::{cell::RefCell, error::Error, fs::OpenOptions, io::Write};
 
     use super::*;
 
@@ -33,8 +39,135 @@ mod test {
         println!("{:?}", config);
         Ok(())
     }
+
+    fn test_navigate(parent: Option<&str>) -> Navigate {
+        Navigate {
+            template: None,
+            parent: parent.map(|s| s.to_string()),
+            enter_task: BuiltinTask::ByName(ByName::new("enter", None)),
+            exit_task: BuiltinTask::ByName(ByName::new("back", None)),
+        }
+    }
+
+    #[test]
+    fn test_find_path() {
+        let mut map = HashMap::new();
+        map.insert("mission".to_string(), test_navigate(None));
+        map.insert("base".to_string(), test_navigate(None));
+        map.insert("base_room".to_string(), test_navigate(Some("base")));
+        let config = NavigateConfig(map);
+
+        let to_names = |path: Vec<NavigateStep>| {
+            path.into_iter()
+                .map(|step| (step.from, step.to))
+                .collect::<Vec<(String, String)>>()
+        };
+
+        assert_eq!(to_names(config.find_path("base", "base").unwrap()), vec![]);
+        assert_eq!(
+            to_names(config.find_path(MAIN_PAGE, "mission").unwrap()),
+            vec![(MAIN_PAGE.to_string(), "mission".to_string())]
+        );
+        assert_eq!(
+            to_names(config.find_path("mission", "base_room").unwrap()),
+            vec![
+                ("mission".to_string(), MAIN_PAGE.to_string()),
+                (MAIN_PAGE.to_string(), "base".to_string()),
+                ("base".to_string(), "base_room".to_string()),
+            ]
+        );
+        assert!(config.find_path("mission", "unknown").is_none());
+    }
+
+    /// 模拟导航，从 `start` 页面出发，`jumps` 中的 `(预期页面, 实际页面)` 表示执行某一步后实际到达的页面，
+    /// 执行过的每一步会被记录在 `steps` 中
+    fn navigate(
+        config: NavigateConfig,
+        start: &str,
+        target: &str,
+        jumps: &[(&str, &str)],
+        steps: &mut Vec<(String, String)>,
+    ) -> Result<Vec<String>, String> {
+        // 只有配置了模板的页面可以被识别
+        let recognizable = config
+            .pages()
+            .into_iter()
+            .filter(|page| config.page_template(page).is_some())
+            .collect::<Vec<_>>();
+        let config = RwLock::new(config);
+        let screen = RefCell::new(start.to_string());
+        navigate_with(
+            &config,
+            target,
+            |step| {
+                steps.push((step.from.clone(), step.to.clone()));
+                let to = jumps
+                    .iter()
+                    .find(|(expected, _)| *expected == step.to)
+                    .map_or(step.to.as_str(), |(_, actual)| *actual);
+                *screen.borrow_mut() = to.to_string();
+                Ok(())
+            },
+            || {
+                let screen = screen.borrow();
+                recognizable.contains(&screen).then(|| screen.clone())
+            },
+        )
+    }
+
+    #[test]
+    fn test_navigate_with() {
+        let mut map = HashMap::new();
+        map.insert("mission".to_string(), test_navigate(None));
+        map.insert("base".to_string(), test_navigate(None));
+        map.insert("base_room".to_string(), test_navigate(Some("base")));
+        let config = || NavigateConfig(map.clone());
+
+        // 没有模板的页面直接认为已经到达
+        let mut steps = vec![];
+        let res = navigate(config(), MAIN_PAGE, "base_room", &[], &mut steps);
+        assert_eq!(res.unwrap(), vec![MAIN_PAGE, "base", "base_room"]);
+        assert_eq!(
+            steps,
+            vec![
+                (MAIN_PAGE.to_string(), "base".to_string()),
+                ("base".to_string(), "base_room".to_string()),
+            ]
+        );
+
+        // 当前页面无法识别
+        let mut steps = vec![];
+        let res = navigate(config(), "mission", "base", &[], &mut steps);
+        assert!(res
+            .unwrap_err()
+            .contains("failed to recognize current screen"));
+        assert!(steps.is_empty());
+
+        // 配置了模板的页面会重新识别，没有到达预期的页面时重新寻路
+        let mut map = map.clone();
+        map.get_mut("base").unwrap().template = Some("base.png".to_string());
+        let config = NavigateConfig(map);
+        let mut steps = vec![];
+        let res = navigate(
+            config,
+            MAIN_PAGE,
+            "base",
+            &[("base", MAIN_PAGE)],
+            &mut steps,
+        );
+        println!("{:?}", res);
+        assert!(res.unwrap_err().contains("failed to reach"));
+        assert!(steps
+            .iter()
+            .all(|step| step == &(MAIN_PAGE.to_string(), "base".to_string())));
+    }
 }
 
+/// 导航图的根节点，即主页
+pub const MAIN_PAGE: &str = "main";
+/// 用于识别主页的模板
+pub const MAIN_PAGE_TEMPLATE: &str = "main_base.png";
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NavigateConfig(pub HashMap<String, Navigate>);
 impl NavigateConfig {
@@ -76,6 +209,92 @@ impl NavigateConfig {
             ))
             .map(|navigate| navigate.clone())
     }
+
+    /// 获取导航图中的所有页面（包括主页 [`MAIN_PAGE`]）
+    pub fn pages(&self) -> Vec<String> {
+        let mut pages = vec![MAIN_PAGE.to_string()];
+        for (name, navigate) in &self.0 {
+            pages.push(name.clone());
+            pages.push(navigate.parent().to_string());
+        }
+        pages.sort();
+        pages.dedup();
+        pages
+    }
+
+    /// 获取用于识别页面 `page` 的模板
+    pub fn page_template<S: AsRef<str>>(&self, page: S) -> Option<String> {
+        let page = page.as_ref();
+        if page == MAIN_PAGE {
+            return Some(MAIN_PAGE_TEMPLATE.to_string());
+        }
+        self.0.get(page).and_then(|navigate| navigate.template.clone())
+    }
+
+    /// 由每个 [`Navigate`] 构建出的导航图的边：
+    /// - `parent -> name`：执行 `enter_task`
+    /// - `name -> parent`：执行 `exit_task`
+    fn edges(&self) -> HashMap<String, Vec<NavigateStep>> {
+        let mut edges: HashMap<String, Vec<NavigateStep>> = HashMap::new();
+        for (name, navigate) in &self.0 {
+            let parent = navigate.parent().to_string();
+            edges.entry(parent.clone()).or_default().push(NavigateStep {
+                from: parent.clone(),
+                to: name.clone(),
+                task: navigate.enter_task.clone(),
+            });
+            edges.entry(name.clone()).or_default().push(NavigateStep {
+                from: name.clone(),
+                to: parent,
+                task: navigate.exit_task.clone(),
+            });
+        }
+        // 保证相同配置下路径的确定性
+        for steps in edges.values_mut() {
+            steps.sort_by(|a, b| a.to.cmp(&b.to));
+        }
+        edges
+    }
+
+    /// 通过 BFS 寻找从页面 `from` 到页面 `to` 的最短路径
+    ///
+    /// 若不可达则返回 [`None`]，若 `from` 与 `to` 相同则返回空路径
+    pub fn find_path<S1: AsRef<str>, S2: AsRef<str>>(
+        &self,
+        from: S1,
+        to: S2,
+    ) -> Option<Vec<NavigateStep>> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        if from == to {
+            return Some(vec![]);
+        }
+
+        let edges = self.edges();
+        let mut visited = HashSet::from([from.to_string()]);
+        let mut prev: HashMap<String, NavigateStep> = HashMap::new();
+        let mut queue = VecDeque::from([from.to_string()]);
+
+        while let Some(cur) = queue.pop_front() {
+            for step in edges.get(&cur).into_iter().flatten() {
+                if !visited.insert(step.to.clone()) {
+                    continue;
+                }
+                prev.insert(step.to.clone(), step.clone());
+                if step.to == to {
+                    let mut path = vec![];
+                    let mut cur = to.to_string();
+                    while let Some(step) = prev.get(&cur) {
+                        path.push(step.clone());
+                        cur = step.from.clone();
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                queue.push_back(step.to.clone());
+            }
+        }
+        None
+    }
 }
 
 impl Default for NavigateConfig {
@@ -85,6 +304,8 @@ impl Default for NavigateConfig {
         map.insert(
             "base".to_string(),
             Navigate {
+                template: None,
+                parent: None,
                 enter_task: BuiltinTask::ActionClickMatch(ActionClickMatch::new(
                     MatchTask::Template("EnterInfrastMistCity.png".to_string()),
                     None,
@@ -96,6 +317,8 @@ impl Default for NavigateConfig {
         map.insert(
             "mission".to_string(),
             Navigate {
+                template: None,
+                parent: None,
                 enter_task: BuiltinTask::ActionClickMatch(ActionClickMatch::new(
                     MatchTask::Template("EnterMissionMistCity.png".to_string()),
                     None,
@@ -108,8 +331,82 @@ impl Default for NavigateConfig {
     }
 }
 
+/// 一个页面的导航方式
+/// - `template`: 用于识别当前是否处于该页面的模板
+/// - `parent`: 上级页面，为空时为主页 [`MAIN_PAGE`]
+/// - `enter_task`: 从上级页面进入该页面的任务
+/// - `exit_task`: 从该页面退出到上级页面的任务
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Navigate {
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub parent: Option<String>,
     pub enter_task: BuiltinTask,
     pub exit_task: BuiltinTask,
 }
+
+impl Navigate {
+    pub fn parent(&self) -> &str {
+        self.parent.as_deref().unwrap_or(MAIN_PAGE)
+    }
+}
+
+/// [`AAH::navigate_to`](crate::AAH::navigate_to) 的实现，从当前页面导航到 `target` 页面
+///
+/// - `run`: 执行导航图中的一步
+/// - `current_page`: 识别当前所处的页面
+///
+/// 每执行一步后，若到达的页面配置了模板（见 [`NavigateConfig::page_template`]）则重新识别当前页面，
+/// 与预期不符时从识别到的页面重新寻路；没有配置模板的页面无法识别，此时认为已经到达了该页面。
+/// 返回走过的页面，无法识别页面时返回的错误中包含已经走过的路径
+pub fn navigate_with(
+    config: &RwLock<NavigateConfig>,
+    target: &str,
+    mut run: impl FnMut(&NavigateStep) -> Result<(), String>,
+    mut current_page: impl FnMut() -> Option<String>,
+) -> Result<Vec<String>, String> {
+    let mut cur =
+        current_page().ok_or("[navigate_to]: failed to recognize current screen".to_string())?;
+    let mut walked = vec![cur.clone()];
+
+    let max_steps = config.read().unwrap().pages().len() * 2;
+    for _ in 0..max_steps {
+        if cur == target {
+            return Ok(walked);
+        }
+
+        let (step, has_template) = {
+            let config = config.read().unwrap();
+            let path = config
+                .find_path(&cur, target)
+                .ok_or(format!("[navigate_to]: no path from {cur:?} to {target:?}"))?;
+            let step = path[0].clone();
+            let has_template = config.page_template(&step.to).is_some();
+            (step, has_template)
+        };
+        println!("[navigate_to]: {} -> {}", step.from, step.to);
+        run(&step)?;
+
+        cur = if has_template {
+            current_page().ok_or(format!(
+                "[navigate_to]: failed to recognize screen after {:?} -> {:?}, walked: {:?}",
+                step.from, step.to, walked
+            ))?
+        } else {
+            step.to.clone()
+        };
+        walked.push(cur.clone());
+    }
+    Err(format!(
+        "[navigate_to]: failed to reach {target:?} in {max_steps} steps, walked: {walked:?}"
+    ))
+}
+
+/// 导航图中的一条边，执行 `task` 从页面 `from` 到达页面 `to`
+#[derive(Debug, Clone)]
+pub struct NavigateStep {
+    pub from: String,
+    pub to: String,
+    pub task: BuiltinTask,
+}
@@ -0,0 +1,94 @@
+use std::{error::Error, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::vision::utils::Rect;
+
+#[cfg(test)]
+mod test {
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn test_load_popup_config() -> Result<(), Box<dyn Error>> {
+        let config = PopupConfig::load("../../resources")?;
+        println!("{:?}", config);
+        assert!(!config.popups.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_popup_config() -> Result<(), Box<dyn Error>> {
+        let config = toml::from_str::<PopupConfig>(
+            r#"
+            [[popup]]
+            name = "level_up"
+            template = "confirm.png"
+
+            [[popup]]
+            name = "event_banner"
+            template = "close.png"
+            click = { x = 1700, y = 60, width = 100, height = 100 }
+            "#,
+        )?;
+        assert_eq!(config.templates(), vec!["confirm.png", "close.png"]);
+        assert_eq!(config.popups[0].click, None);
+        assert_eq!(config.popups[1].click.as_ref().unwrap().x, 1700);
+        assert_eq!(
+            config.get_by_template("close.png").unwrap().name,
+            "event_banner"
+        );
+        assert!(config.get_by_template("back.png").is_none());
+
+        assert!(toml::from_str::<PopupConfig>("")?.popups.is_empty());
+        Ok(())
+    }
+}
+
+/// 一种可以自动关闭的弹窗（比如升级、理智恢复、活动公告），见 [`PopupGuard`](crate::task::popup_guard::PopupGuard)
+///
+/// - `name`: 弹窗的名称，用于 [`TaskEvt::PopupDismissed`](crate::task::TaskEvt::PopupDismissed)
+/// - `template`: 用于识别弹窗的模板，一般为弹窗的关闭或确认按钮
+/// - `click`: 识别到弹窗后点击的区域（1920x1080 下），[`None`] 时点击模板匹配到的位置
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Popup {
+    pub name: String,
+    pub template: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub click: Option<Rect>,
+}
+
+/// 由 `popups.toml` 加载的弹窗配置，文件中的每个 `[[popup]]` 为一个 [`Popup`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PopupConfig {
+    #[serde(default, rename = "popup")]
+    pub popups: Vec<Popup>,
+}
+
+impl PopupConfig {
+    /// 从 `path` 下的 `popups.toml` 加载，文件不存在时为空配置（不自动关闭任何弹窗）
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PopupConfig, Box<dyn Error>> {
+        let config = path.as_ref().join("popups.toml");
+        if !config.exists() {
+            return Ok(PopupConfig::default());
+        }
+        let config = fs::read_to_string(config)?;
+        Ok(toml::from_str::<PopupConfig>(&config)?)
+    }
+
+    /// 所有弹窗的模板，按配置中的顺序排列
+    pub fn templates(&self) -> Vec<String> {
+        self.popups
+            .iter()
+            .map(|popup| popup.template.clone())
+            .collect()
+    }
+
+    /// 获取模板为 `template` 的弹窗，有多个时返回第一个
+    pub fn get_by_template<S: AsRef<str>>(&self, template: S) -> Option<&Popup> {
+        self.popups
+            .iter()
+            .find(|popup| popup.template == template.as_ref())
+    }
+}
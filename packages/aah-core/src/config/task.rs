@@ -1,9 +1,11 @@
 use serde::{Deserialize, Serialize};
 
+use std::fmt::Display;
 use std::path::Path;
 use std::{collections::HashMap, error::Error, fs};
 
-use crate::task::builtins::{test_tasks, BuiltinTask};
+use crate::config::navigate::NavigateConfig;
+use crate::task::builtins::{test_tasks, BuiltinTask, TaskRef};
 
 #[cfg(test)]
 mod test {
@@ -53,6 +55,60 @@ mod test {
         println!("{:?}", task);
         Ok(())
     }
+
+    #[test]
+    fn test_validate() {
+        let navigate_config = NavigateConfig::load("../../resources").unwrap();
+        let config = TaskConfig::load("../../resources").unwrap();
+        if let Err(errors) = config.validate("../../resources", &navigate_config) {
+            for err in errors {
+                println!("{err}");
+            }
+        }
+
+        let mut config = TaskConfig(HashMap::new());
+        config.0.insert(
+            "foo".to_string(),
+            toml::from_str(
+                r#"
+                [Multi]
+                tasks = [
+                    { ByName = { name = "not_exist" } },
+                    { NavigateIn = "not_exist" },
+                    { ActionClickMatch = { match_task = { type = "Template", template = "not_exist.png" } } },
+                    { ActionClickMatch = { match_task = { type = "Template", template = "main_base.png" } } },
+                    { NavigateIn = "mission" },
+                ]
+                "#,
+            )
+            .unwrap(),
+        );
+        let errors = config
+            .validate("../../resources", &navigate_config)
+            .unwrap_err();
+        // navigates.toml 中的任务引用了不在该配置中的任务，这里只检查 foo
+        let errors = errors
+            .into_iter()
+            .filter(|err| !err.to_string().starts_with("[navigate:"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                ConfigError::TaskNotFound {
+                    task: "foo".to_string(),
+                    name: "not_exist".to_string()
+                },
+                ConfigError::NavigateNotFound {
+                    task: "foo".to_string(),
+                    name: "not_exist".to_string()
+                },
+                ConfigError::TemplateNotFound {
+                    task: "foo".to_string(),
+                    template: "not_exist.png".to_string()
+                },
+            ]
+        );
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -98,6 +154,102 @@ impl TaskConfig {
     }
 }
 
+/// [`TaskConfig::validate`] 发现的配置错误
+/// - `task`: 出错的任务名（导航中的任务为 `navigate:<导航名>`）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// 引用的模板文件不存在
+    TemplateNotFound { task: String, template: String },
+    /// 引用的任务不存在
+    TaskNotFound { task: String, name: String },
+    /// 引用的导航不存在
+    NavigateNotFound { task: String, name: String },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::TemplateNotFound { task, template } => {
+                write!(f, "[{task}]: template {template:?} not found")
+            }
+            ConfigError::TaskNotFound { task, name } => {
+                write!(f, "[{task}]: task {name:?} not found")
+            }
+            ConfigError::NavigateNotFound { task, name } => {
+                write!(f, "[{task}]: navigate {name:?} not found")
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl TaskConfig {
+    /// 检查所有任务（包括 `navigate_config` 中的任务）引用的资源是否存在：
+    /// - 模板文件位于 `{res_dir}/templates/1920x1080` 下
+    /// - 通过 `ByName` 引用的任务存在
+    /// - 通过 `NavigateIn`/`NavigateOut` 引用的导航存在于 `navigate_config` 中
+    ///
+    /// 会收集所有的错误，而不是在遇到第一个错误时返回
+    pub fn validate<P: AsRef<Path>>(
+        &self,
+        res_dir: P,
+        navigate_config: &NavigateConfig,
+    ) -> Result<(), Vec<ConfigError>> {
+        let res_dir = res_dir.as_ref();
+        let mut errors = vec![];
+
+        let mut tasks = self
+            .0
+            .iter()
+            .map(|(name, task)| (name.clone(), task))
+            .collect::<Vec<_>>();
+        for (name, navigate) in &navigate_config.0 {
+            tasks.push((format!("navigate:{name}"), &navigate.enter_task));
+            tasks.push((format!("navigate:{name}"), &navigate.exit_task));
+        }
+        tasks.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let templates_dir = res_dir.join("templates").join("1920x1080");
+        for (name, task) in tasks {
+            for task_ref in task.refs() {
+                match task_ref {
+                    TaskRef::Template(template) => {
+                        if !templates_dir.join(&template).exists() {
+                            errors.push(ConfigError::TemplateNotFound {
+                                task: name.clone(),
+                                template,
+                            });
+                        }
+                    }
+                    TaskRef::Task(task_name) => {
+                        if !self.0.contains_key(&task_name) {
+                            errors.push(ConfigError::TaskNotFound {
+                                task: name.clone(),
+                                name: task_name,
+                            });
+                        }
+                    }
+                    TaskRef::Navigate(navigate_name) => {
+                        if !navigate_config.0.contains_key(&navigate_name) {
+                            errors.push(ConfigError::NavigateNotFound {
+                                task: name.clone(),
+                                name: navigate_name,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
 impl Default for TaskConfig {
     fn default() -> Self {
         let mut map = HashMap::new();
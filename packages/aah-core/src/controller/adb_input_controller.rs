@@ -4,7 +4,7 @@ use log::info;
 
 use crate::adb::{connect, Device, MyError};
 
-use super::Controller;
+use super::{Controller, KeyEvent};
 
 #[cfg(test)]
 mod test {
@@ -90,15 +90,10 @@ impl Controller for AdbInputController {
         self.inner.screencap()
     }
 
-    fn press_home(&self) -> Result<(), MyError> {
+    fn key_event(&self, key: KeyEvent) -> Result<(), MyError> {
+        info!("[Controller]: key event {:?}", key);
         self.inner
-            .execute_command_by_process("shell input keyevent HOME")?;
-        Ok(())
-    }
-
-    fn press_esc(&self) -> Result<(), MyError> {
-        self.inner
-            .execute_command_by_process("shell input keyevent 111")?;
+            .execute_command_by_process(format!("shell input keyevent {}", key.code()).as_str())?;
         Ok(())
     }
 }
@@ -0,0 +1,115 @@
+use crate::adb::MyError;
+
+use super::{minitouch::MiniTouchController, mumu::MuMuController, Controller};
+
+/// MuMu 12 第一个实例的 adb 端口，之后的实例依次加 [`MUMU12_PORT_STEP`]
+pub const MUMU12_BASE_PORT: u16 = 16384;
+pub const MUMU12_PORT_STEP: u16 = 32;
+/// MuMu 6 及 MuMu X 的 adb 端口
+pub const MUMU6_PORT: u16 = 7555;
+/// 雷电模拟器第一个实例的 adb 端口，之后的实例依次加 2
+pub const LDPLAYER_BASE_PORT: u16 = 5555;
+/// 按端口识别模拟器时考虑的最大实例数
+const MAX_INSTANCES: u16 = 32;
+
+/// 通过 adb 序列号识别出的模拟器，见 [`Emulator::detect`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emulator {
+    /// MuMu 模拟器，有专用的 [`MuMuController`]
+    MuMu,
+    /// 雷电模拟器，还没有专用的后端，使用 [`MiniTouchController`]
+    LDPlayer,
+}
+
+impl Emulator {
+    /// 根据 `serial` 中的 adb 端口识别模拟器，识别不出时返回 [`None`]
+    ///
+    /// - MuMu: `127.0.0.1:16384`、`127.0.0.1:16416` 等（MuMu 12 的多开实例，间隔 32），
+    ///   以及 `127.0.0.1:7555`（MuMu 6 / MuMu X）
+    /// - 雷电: `emulator-5554`、`127.0.0.1:5555` 等（多开实例间隔 2），
+    ///   Android SDK 的模拟器使用同样的端口，也会被识别为雷电，不过两者都使用通用的后端
+    ///
+    /// 只看端口，不会访问设备。端口被改过或者通过局域网 IP 连接时识别不出
+    pub fn detect(serial: &str) -> Option<Self> {
+        let port = if let Some(console_port) = serial.strip_prefix("emulator-") {
+            // emulator-<控制台端口>，adb 端口为控制台端口加 1
+            console_port.parse::<u16>().ok()?.checked_add(1)?
+        } else {
+            let (host, port) = serial.rsplit_once(':')?;
+            if host != "127.0.0.1" && host != "localhost" {
+                return None;
+            }
+            port.parse::<u16>().ok()?
+        };
+
+        let is_instance = |base: u16, step: u16| {
+            port >= base && (port - base) % step == 0 && (port - base) / step < MAX_INSTANCES
+        };
+        if port == MUMU6_PORT || is_instance(MUMU12_BASE_PORT, MUMU12_PORT_STEP) {
+            Some(Emulator::MuMu)
+        } else if is_instance(LDPLAYER_BASE_PORT, 2) {
+            Some(Emulator::LDPlayer)
+        } else {
+            None
+        }
+    }
+}
+
+/// 连接设备时使用的 [`Controller`]，见 [`AAH::connect_with_backend`](crate::AAH::connect_with_backend)
+///
+/// - `Auto`: 通过 [`Emulator::detect`] 选择模拟器专用的后端，识别不出、没有专用后端或者专用后端连接失败时使用 `Adb`
+/// - `Adb`: 通用的 [`MiniTouchController`]，点击、滑动使用 `adb shell input`，适用于所有设备
+/// - `MuMu`: [`MuMuController`]，连接失败时返回错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ControllerBackend {
+    #[default]
+    Auto,
+    Adb,
+    MuMu,
+}
+
+impl ControllerBackend {
+    /// 连接到 `serial` 指定的设备
+    pub fn connect(&self, serial: &str) -> Result<Box<dyn Controller + Sync + Send>, MyError> {
+        match self {
+            ControllerBackend::Adb => Ok(Box::new(MiniTouchController::connect(serial)?)),
+            ControllerBackend::MuMu => Ok(Box::new(MuMuController::connect(serial)?)),
+            ControllerBackend::Auto => {
+                let emulator = Emulator::detect(serial);
+                println!("[ControllerBackend]: detected emulator: {emulator:?}");
+                if emulator == Some(Emulator::MuMu) {
+                    match MuMuController::connect(serial) {
+                        Ok(controller) => return Ok(Box::new(controller)),
+                        Err(err) => {
+                            println!("[ControllerBackend]: MuMu backend failed: {err}, falling back to adb")
+                        }
+                    }
+                }
+                ControllerBackend::Adb.connect(serial)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_detect_emulator() {
+        assert_eq!(Emulator::detect("127.0.0.1:16384"), Some(Emulator::MuMu));
+        assert_eq!(Emulator::detect("127.0.0.1:16448"), Some(Emulator::MuMu));
+        assert_eq!(Emulator::detect("localhost:7555"), Some(Emulator::MuMu));
+        assert_eq!(Emulator::detect("127.0.0.1:16385"), None);
+
+        assert_eq!(Emulator::detect("emulator-5554"), Some(Emulator::LDPlayer));
+        assert_eq!(Emulator::detect("127.0.0.1:5557"), Some(Emulator::LDPlayer));
+        assert_eq!(Emulator::detect("127.0.0.1:5556"), None);
+
+        // 真机、局域网中的设备和无法解析的序列号
+        assert_eq!(Emulator::detect("1a2b3c4d"), None);
+        assert_eq!(Emulator::detect("192.168.1.10:16384"), None);
+        assert_eq!(Emulator::detect("127.0.0.1:abc"), None);
+        assert_eq!(Emulator::detect("emulator-65535"), None);
+    }
+}
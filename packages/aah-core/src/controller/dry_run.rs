@@ -0,0 +1,199 @@
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{
+    adb::MyError,
+    task::{TaskEvt, TaskEvtBroadcaster},
+    vision::{utils::Rect, Calibration},
+};
+
+use super::{Controller, KeyEvent};
+
+/// 包装另一个 [`Controller`]，开启 dry run 时不会操作设备，而是将点击、滑动等操作
+/// 作为 [`TaskEvt::DryRunAction`] 发出，截图仍然由被包装的 [`Controller`] 进行，视觉部分照常工作
+///
+/// 关闭 dry run 时所有操作都直接转发给被包装的 [`Controller`]，见 [`AAH::set_dry_run`](crate::AAH::set_dry_run)
+pub struct DryRunController {
+    inner: Box<dyn Controller + Sync + Send>,
+    enabled: Arc<AtomicBool>,
+    task_evt: TaskEvtBroadcaster,
+}
+
+impl DryRunController {
+    pub fn new(
+        inner: Box<dyn Controller + Sync + Send>,
+        enabled: Arc<AtomicBool>,
+        task_evt: TaskEvtBroadcaster,
+    ) -> Self {
+        Self {
+            inner,
+            enabled,
+            task_evt,
+        }
+    }
+
+    /// dry run 开启时记录 `action` 并返回 `true`
+    fn intercept(&self, action: String) -> bool {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return false;
+        }
+        println!("[DryRun]: {action}");
+        self.task_evt.emit(TaskEvt::DryRunAction(action));
+        true
+    }
+}
+
+impl Controller for DryRunController {
+    fn screen_size(&self) -> (u32, u32) {
+        self.inner.screen_size()
+    }
+
+    fn calibration(&self) -> Calibration {
+        self.inner.calibration()
+    }
+
+    fn scale_factor(&self) -> f32 {
+        self.inner.scale_factor()
+    }
+
+    fn click(&self, x: u32, y: u32) -> Result<(), MyError> {
+        if self.intercept(format!("click ({x}, {y})")) {
+            return Ok(());
+        }
+        self.inner.click(x, y)
+    }
+
+    fn swipe(&self, start: (u32, u32), end: (i32, i32), duration: Duration) -> Result<(), MyError> {
+        if self.intercept(format!("swipe {start:?} -> {end:?} for {duration:?}")) {
+            return Ok(());
+        }
+        self.inner.swipe(start, end, duration)
+    }
+
+    fn press_and_drag(
+        &self,
+        card_pos: (u32, u32),
+        tile_pos: (u32, u32),
+        direction_pos: (i32, i32),
+        hold: Duration,
+    ) -> Result<(), MyError> {
+        if self.intercept(format!(
+            "press and drag {card_pos:?} -> {tile_pos:?} -> {direction_pos:?}, hold {hold:?}"
+        )) {
+            return Ok(());
+        }
+        self.inner
+            .press_and_drag(card_pos, tile_pos, direction_pos, hold)
+    }
+
+    fn screencap(&self) -> Result<image::DynamicImage, MyError> {
+        self.inner.screencap()
+    }
+
+    fn screencap_region(&self, rect: &Rect) -> Result<image::DynamicImage, MyError> {
+        self.inner.screencap_region(rect)
+    }
+
+    fn key_event(&self, key: KeyEvent) -> Result<(), MyError> {
+        if self.intercept(format!("key event {key:?}")) {
+            return Ok(());
+        }
+        self.inner.key_event(key)
+    }
+
+    fn current_foreground_package(&self) -> Result<String, MyError> {
+        self.inner.current_foreground_package()
+    }
+
+    fn launch_app(&self, package: &str) -> Result<(), MyError> {
+        if self.intercept(format!("launch app {package}")) {
+            return Ok(());
+        }
+        self.inner.launch_app(package)
+    }
+
+    fn stop_app(&self, package: &str) -> Result<(), MyError> {
+        if self.intercept(format!("stop app {package}")) {
+            return Ok(());
+        }
+        self.inner.stop_app(package)
+    }
+
+    fn ensure_minitouch(&self, res_dir: &Path) -> Result<(), MyError> {
+        if self.intercept("ensure minitouch".to_string()) {
+            return Ok(());
+        }
+        self.inner.ensure_minitouch(res_dir)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    /// 记录操作次数的 [`Controller`]
+    struct CountingController(Arc<AtomicUsize>);
+
+    impl Controller for CountingController {
+        fn screen_size(&self) -> (u32, u32) {
+            (1920, 1080)
+        }
+
+        fn click(&self, _x: u32, _y: u32) -> Result<(), MyError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn swipe(&self, _: (u32, u32), _: (i32, i32), _: Duration) -> Result<(), MyError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn screencap(&self) -> Result<image::DynamicImage, MyError> {
+            Ok(image::DynamicImage::new_rgb8(1920, 1080))
+        }
+
+        fn key_event(&self, _key: KeyEvent) -> Result<(), MyError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_dry_run_controller() {
+        let enabled = Arc::new(AtomicBool::new(true));
+        let task_evt = TaskEvtBroadcaster::default();
+        let rx = task_evt.subscribe();
+        let count = Arc::new(AtomicUsize::new(0));
+        let controller = DryRunController::new(
+            Box::new(CountingController(count.clone())),
+            enabled.clone(),
+            task_evt,
+        );
+
+        controller.click(10, 20).unwrap();
+        controller
+            .swipe((0, 0), (100, 0), Duration::from_millis(200))
+            .unwrap();
+        controller.press_esc().unwrap();
+        assert!(controller.screencap().is_ok());
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+        let actions = rx.try_iter().collect::<Vec<_>>();
+        println!("{actions:?}");
+        assert_eq!(actions.len(), 3);
+        assert!(matches!(&actions[2], TaskEvt::DryRunAction(action) if action.contains("Escape")));
+
+        enabled.store(false, Ordering::Relaxed);
+        controller.click(10, 20).unwrap();
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+        assert!(rx.try_iter().next().is_none());
+    }
+}
@@ -1,17 +1,92 @@
 pub mod toucher;
 
-use std::time::Duration;
+use std::{path::Path, sync::Mutex, time::Duration};
 
 use log::info;
+use toucher::MiniToucher;
 
-use crate::adb::{self, MyError};
+use crate::{
+    adb::{
+        self,
+        command::local_service::{Push, ShellCommand},
+        MyError,
+    },
+    vision::{utils::Rect, Calibration},
+};
 
-use super::Controller;
+use super::{crop_clamped, parse_foreground_package, Controller, KeyEvent, PlayAreaCache};
 
+/// 查询前台应用使用的命令，依次尝试直到能够解析出包名，见 [`parse_foreground_package`]
+const FOREGROUND_QUERY_COMMANDS: [&str; 2] = ["dumpsys window", "dumpsys activity activities"];
+
+/// minitouch 在设备上的路径
+pub const MINITOUCH_PATH: &str = "/data/local/tmp/minitouch";
+
+/// 设备上的 minitouch 能否执行，`-h` 会输出以 `Usage` 开头的帮助
+fn minitouch_executable(device: &adb::Device) -> Result<bool, MyError> {
+    let output =
+        device.execute_command_by_socket(ShellCommand::new(format!("{MINITOUCH_PATH} -h")))?;
+    Ok(output.starts_with("Usage"))
+}
+
+/// 确保设备上有可以执行的 minitouch（见 [`MINITOUCH_PATH`]）
+///
+/// 已经存在且可以执行时直接返回，否则根据 `ro.product.cpu.abi` 从 `res_dir/minitouch/<abi>/minitouch`
+/// 选取对应的二进制文件，通过 [`Push`] 推送到设备上并设置为可执行。
+/// 没有该 abi 的二进制文件，或推送后仍然无法执行时返回错误
+pub fn ensure_minitouch(device: &adb::Device, res_dir: &Path) -> Result<(), MyError> {
+    if minitouch_executable(device)? {
+        return Ok(());
+    }
+
+    let abi = device
+        .execute_command_by_socket(ShellCommand::new("getprop ro.product.cpu.abi".to_string()))?
+        .trim()
+        .to_string();
+    let minitouch_dir = res_dir.join("minitouch");
+    let binary = minitouch_dir.join(&abi).join("minitouch");
+    if !binary.is_file() {
+        let mut bundled = std::fs::read_dir(&minitouch_dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().join("minitouch").is_file())
+                    .map(|entry| entry.file_name().to_string_lossy().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        bundled.sort();
+        return Err(MyError::S(format!(
+            "no minitouch binary for abi {abi:?} in {}, bundled abis: {bundled:?}",
+            minitouch_dir.display()
+        )));
+    }
+
+    info!(
+        "[Controller]: pushing {} to {MINITOUCH_PATH}",
+        binary.display()
+    );
+    let data = std::fs::read(&binary)
+        .map_err(|err| MyError::S(format!("failed to read {}: {err}", binary.display())))?;
+    device.execute_command_by_socket(Push::new(data, MINITOUCH_PATH.to_string(), 0o755))?;
+    // sync 协议写入的权限可能受 umask 等影响，再 chmod 一次
+    device.execute_command_by_socket(ShellCommand::new(format!("chmod 755 {MINITOUCH_PATH}")))?;
+
+    if minitouch_executable(device)? {
+        Ok(())
+    } else {
+        Err(MyError::S(format!(
+            "minitouch for abi {abi:?} is pushed but not executable"
+        )))
+    }
+}
+
+/// 截图会被裁剪为游戏画面（见 [`PlayAreaCache`]），点击、滑动等操作的坐标也是游戏画面中的坐标
 pub struct MiniTouchController {
     pub inner: adb::Device,
-    width: u32,
-    height: u32,
+    /// 用于需要保持按下的手势，见 [`Controller::press_and_drag`]
+    toucher: Mutex<MiniToucher>,
+    play_area: PlayAreaCache,
 }
 
 impl MiniTouchController {
@@ -22,8 +97,8 @@ impl MiniTouchController {
         let device = adb::connect(device_serial)?;
         let controller = Self {
             inner: device,
-            width: 0,
-            height: 0,
+            toucher: Mutex::new(MiniToucher::new(device_serial.to_string())),
+            play_area: PlayAreaCache::default(),
         };
         let screen = controller.screencap()?;
 
@@ -33,25 +108,28 @@ impl MiniTouchController {
             screen.width(),
             screen.height()
         );
-
-        let controller = Self {
-            width: screen.width(),
-            height: screen.height(),
-            ..controller
-        };
         Ok(controller)
     }
 }
 
 impl Controller for MiniTouchController {
     fn screen_size(&self) -> (u32, u32) {
-        (self.width, self.height)
+        self.play_area
+            .get()
+            .map(|area| (area.width, area.height))
+            .unwrap_or_default()
+    }
+
+    fn calibration(&self) -> Calibration {
+        self.play_area.calibration()
     }
 
     fn click(&self, x: u32, y: u32) -> Result<(), MyError> {
-        if x > self.width || y > self.height {
+        let (width, height) = self.screen_size();
+        if x > width || y > height {
             return Err(MyError::S("coord out of screen".to_string()));
         }
+        let (x, y) = self.calibration().screen_point_to_device((x, y));
         info!("[Controller]: clicking ({}, {})", x, y);
         self.inner
             .execute_command_by_process(format!("shell input tap {} {}", x, y).as_str())?;
@@ -63,6 +141,11 @@ impl Controller for MiniTouchController {
             "[Controller]: swiping from {:?} to {:?} for {:?}",
             start, end, duration
         );
+        let calibration = self.calibration();
+        let (start, end) = (
+            calibration.screen_point_to_device(start),
+            calibration.screen_offset_to_device(end),
+        );
         self.inner.execute_command_by_process(
             format!(
                 "shell input swipe {} {} {} {} {}",
@@ -76,19 +159,121 @@ impl Controller for MiniTouchController {
         )?;
         Ok(())
     }
+    fn press_and_drag(
+        &self,
+        card_pos: (u32, u32),
+        tile_pos: (u32, u32),
+        direction_pos: (i32, i32),
+        hold: Duration,
+    ) -> Result<(), MyError> {
+        info!(
+            "[Controller]: dragging from {:?} to {:?}, then to {:?}",
+            card_pos, tile_pos, direction_pos
+        );
+        let calibration = self.calibration();
+        let (card_pos, tile_pos, direction_pos) = (
+            calibration.screen_point_to_device(card_pos),
+            calibration.screen_point_to_device(tile_pos),
+            calibration.screen_offset_to_device(direction_pos),
+        );
+        self.toucher
+            .lock()
+            .unwrap()
+            .press_and_drag(card_pos, tile_pos, direction_pos, hold)
+            .map_err(MyError::S)
+    }
+
     fn screencap(&self) -> Result<image::DynamicImage, MyError> {
-        self.inner.screencap()
+        Ok(self.play_area.crop(self.inner.screencap()?))
+    }
+
+    fn screencap_region(&self, rect: &Rect) -> Result<image::DynamicImage, MyError> {
+        // 先限制在游戏画面内，再转换为设备坐标
+        let (width, height) = self.screen_size();
+        let (x, y) = (rect.x.min(width), rect.y.min(height));
+        let rect = self.calibration().screen_rect_to_device(&Rect {
+            x,
+            y,
+            width: rect.width.min(width - x),
+            height: rect.height.min(height - y),
+        });
+        match self.inner.screencap_region(&rect) {
+            Ok(image) => Ok(image),
+            Err(err) => {
+                info!("[Controller]: raw screencap failed: {err}, falling back to png");
+                Ok(crop_clamped(&self.inner.screencap()?, &rect))
+            }
+        }
     }
 
-    fn press_home(&self) -> Result<(), MyError> {
+    fn key_event(&self, key: KeyEvent) -> Result<(), MyError> {
+        info!("[Controller]: key event {:?}", key);
         self.inner
-            .execute_command_by_process("shell input keyevent HOME")?;
+            .execute_command_by_process(format!("shell input keyevent {}", key.code()).as_str())?;
         Ok(())
     }
 
-    fn press_esc(&self) -> Result<(), MyError> {
+    fn current_foreground_package(&self) -> Result<String, MyError> {
+        for command in FOREGROUND_QUERY_COMMANDS {
+            let output = self
+                .inner
+                .execute_command_by_socket(ShellCommand::new(command.to_string()))?;
+            if let Some(package) = parse_foreground_package(&output) {
+                return Ok(package);
+            }
+        }
+        Err(MyError::ParseError(
+            "failed to find the foreground package in dumpsys output".to_string(),
+        ))
+    }
+
+    fn launch_app(&self, package: &str) -> Result<(), MyError> {
+        info!("[Controller]: launching {}", package);
+        let shell = |command: String| {
+            self.inner
+                .execute_command_by_socket(ShellCommand::new(command))
+        };
+        if !shell(format!("pm path {package}"))?.contains("package:") {
+            return Err(MyError::S(format!("{package} is not installed")));
+        }
+
+        // 各客户端的启动 Activity 不同，通过 resolve-activity 获取（Android 7 及以上）
+        let resolved = shell(format!(
+            "cmd package resolve-activity --brief -c android.intent.category.LAUNCHER {package}"
+        ))?;
+        let prefix = format!("{package}/");
+        match resolved
+            .lines()
+            .map(str::trim)
+            .rfind(|line| line.starts_with(&prefix))
+        {
+            Some(component) => {
+                let output = shell(format!("am start -n {component}"))?;
+                if output.contains("Error") {
+                    return Err(MyError::S(format!(
+                        "failed to start {component}: {}",
+                        output.trim()
+                    )));
+                }
+            }
+            // 与点击桌面图标相同，不需要知道 Activity 名
+            None => {
+                shell(format!(
+                    "monkey -p {package} -c android.intent.category.LAUNCHER 1"
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stop_app(&self, package: &str) -> Result<(), MyError> {
+        info!("[Controller]: stopping {}", package);
         self.inner
-            .execute_command_by_process("shell input keyevent 111")?;
+            .execute_command_by_socket(ShellCommand::new(format!("am force-stop {package}")))?;
         Ok(())
     }
+
+    fn ensure_minitouch(&self, res_dir: &Path) -> Result<(), MyError> {
+        ensure_minitouch(&self.inner, res_dir)
+    }
 }
@@ -1,11 +1,15 @@
 use std::{
     io::{self, BufRead, Write},
-    process::{ChildStdin, Command, Stdio},
+    process::{Child, Command, Stdio},
     time::Duration,
 };
 
 use crate::{
-    adb::{command::local_service::ShellCommand, utils::execute_adb_command, AdbTcpStream},
+    adb::{
+        command::local_service::{LocalAbstract, ShellCommand},
+        utils::execute_adb_command,
+        AdbTcpStream,
+    },
     controller::Toucher,
 };
 use log::{error, info};
@@ -45,6 +49,123 @@ mod test {
         toucher.click(1000, 1000).unwrap();
     }
 
+    #[test]
+    fn test_parse_header() {
+        let header = MiniTouchHeader::parse(
+            "Note: device /dev/input/event2 is not supported by libevdev\r\nv 1\r\n^ 10 1079 1919 2048\r\n$ 12345\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            header,
+            MiniTouchHeader {
+                version: 1,
+                max_contacts: 10,
+                max_x: 1079,
+                max_y: 1919,
+                max_pressure: 2048,
+                pid: Some(12345),
+            }
+        );
+
+        // 还没有读到 `$` 一行
+        let header = MiniTouchHeader::parse("v 1\n^ 2 32767 32767 0\n").unwrap();
+        assert_eq!((header.max_contacts, header.pid), (2, None));
+
+        assert!(MiniTouchHeader::parse("").is_err());
+        assert!(MiniTouchHeader::parse("v 1\n$ 1\n").is_err());
+        assert!(MiniTouchHeader::parse("^ 10 1079 1919 2048\n").is_err());
+        assert!(MiniTouchHeader::parse("v 1\n^ 10 1079\n").is_err());
+        assert!(MiniTouchHeader::parse("v 1\n^ 10 1079 abc 2048\n").is_err());
+    }
+
+    #[test]
+    fn test_map_point() {
+        // 触摸坐标与 2560x1440 的横屏相同
+        let header = MiniTouchHeader::parse("v 1\n^ 10 2559 1439 0\n").unwrap();
+        assert_eq!(header.map_point((2560, 1440), (1280, 720)), (1280, 720));
+        assert_eq!(header.map_point((2560, 1440), (2559, 1439)), (2559, 1439));
+        // 超出屏幕的点被限制在触摸范围内
+        assert_eq!(header.map_point((2560, 1440), (-100, 2000)), (0, 1439));
+
+        // 触摸坐标为竖屏，需要交换 x 和 y
+        let header = MiniTouchHeader::parse("v 1\n^ 10 1439 2559 0\n").unwrap();
+        assert_eq!(header.map_point((2560, 1440), (2000, 100)), (100, 2000));
+
+        // 触摸分辨率与屏幕分辨率不同
+        let header = MiniTouchHeader::parse("v 1\n^ 10 32767 32767 0\n").unwrap();
+        assert_eq!(header.map_point((1920, 1080), (0, 0)), (0, 0));
+        assert_eq!(header.map_point((1920, 1080), (960, 540)), (16384, 16384));
+        assert_eq!(header.map_point((1920, 1080), (1919, 1079)), (32750, 32737));
+        // 屏幕分辨率为竖屏时同样按横屏处理
+        assert_eq!(header.map_point((1080, 1920), (960, 540)), (16384, 16384));
+    }
+
+    #[test]
+    fn test_parse_wm_size() {
+        assert_eq!(
+            parse_wm_size("Physical size: 1080x1920\n"),
+            Some((1920, 1080))
+        );
+        assert_eq!(
+            parse_wm_size("Physical size: 1440x2560\nOverride size: 1080x1920\n"),
+            Some((1920, 1080))
+        );
+        assert_eq!(
+            parse_wm_size("Physical size: 1600x900\r\n"),
+            Some((1600, 900))
+        );
+        assert_eq!(parse_wm_size("error"), None);
+    }
+
+    #[test]
+    fn test_easing() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+            Easing::CubicBezier(0.2, 0.8, 0.3, 1.0),
+        ] {
+            assert!(easing.progress(0.0).abs() < 1e-4);
+            assert!((easing.progress(1.0) - 1.0).abs() < 1e-4);
+            let progress = (0..=20)
+                .map(|i| easing.progress(i as f32 / 20.0))
+                .collect::<Vec<_>>();
+            assert!(progress.windows(2).all(|w| w[0] <= w[1] + 1e-4));
+        }
+        assert!(Easing::EaseIn.progress(0.5) < 0.5);
+        assert!(Easing::EaseOut.progress(0.5) > 0.5);
+        assert!((Easing::EaseInOut.progress(0.5) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_swipe_curved() {
+        init();
+        let mut toucher = MiniToucher::new("127.0.0.1:16384".to_string());
+        toucher
+            .swipe_curved(
+                (2000, 720),
+                (500, 720),
+                Duration::from_millis(300),
+                Easing::EaseIn,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_press_and_drag() {
+        init();
+        let mut toucher = MiniToucher::new("127.0.0.1:16384".to_string());
+        toucher
+            .press_and_drag(
+                (2400, 1300),
+                (1280, 720),
+                (1280, 400),
+                Duration::from_millis(DEFAULT_DEPLOY_HOLD_MS),
+            )
+            .unwrap();
+    }
+
     #[test]
     fn test_slowly_swipe() {
         init();
@@ -61,6 +182,52 @@ mod test {
     }
 }
 
+/// [`MiniToucher::swipe_curved`] 中手指移动进度随时间变化的曲线
+///
+/// 除 `Linear` 外均为与 CSS 相同的三次贝塞尔曲线，`CubicBezier(x1, y1, x2, y2)` 为两个控制点，
+/// `x1` 与 `x2` 需在 `[0, 1]` 内
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Easing {
+    Linear,
+    /// 缓慢起步，松手时速度最快，适合快速划动产生惯性滚动
+    EaseIn,
+    /// 快速起步，缓慢停下，适合精确拖动
+    EaseOut,
+    EaseInOut,
+    CubicBezier(f32, f32, f32, f32),
+}
+
+impl Easing {
+    /// 时间进度 `t`（`[0, 1]`）对应的移动进度
+    pub fn progress(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        let (x1, y1, x2, y2) = match *self {
+            Self::Linear => return t,
+            Self::EaseIn => (0.42, 0.0, 1.0, 1.0),
+            Self::EaseOut => (0.0, 0.0, 0.58, 1.0),
+            Self::EaseInOut => (0.42, 0.0, 0.58, 1.0),
+            Self::CubicBezier(x1, y1, x2, y2) => (x1, y1, x2, y2),
+        };
+
+        let bezier = |p1: f32, p2: f32, s: f32| {
+            3.0 * (1.0 - s).powi(2) * s * p1 + 3.0 * (1.0 - s) * s.powi(2) * p2 + s.powi(3)
+        };
+        // x(s) 单调递增，二分求出 x(s) = t 时的 s
+        let (mut lo, mut hi) = (0.0, 1.0);
+        for _ in 0..32 {
+            let mid = (lo + hi) / 2.0;
+            if bezier(x1, x2, mid) < t {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        bezier(y1, y2, (lo + hi) / 2.0)
+    }
+}
+
+/// 方向，比如部署干员时的朝向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Up,
     Down,
@@ -68,14 +235,154 @@ pub enum Direction {
     Right,
 }
 
+/// minitouch 启动时输出的 header，见 [`MiniTouchHeader::parse`]
+///
+/// 触摸坐标的范围与设备的触摸屏有关，不一定与屏幕的分辨率相同（比如 `32767x32767`），
+/// 方向也是触摸屏的自然方向（手机一般为竖屏），发送命令前需要通过 [`MiniTouchHeader::map_point`] 转换
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MiniTouchHeader {
+    /// 协议版本，`v <version>`
+    pub version: u32,
+    /// 最多同时按下的触点数，命令中的 contact 需小于它
+    pub max_contacts: u32,
+    pub max_x: u32,
+    pub max_y: u32,
+    pub max_pressure: u32,
+    /// minitouch 的进程号，`$ <pid>`，header 还没有读完时为 [`None`]
+    pub pid: Option<u32>,
+}
+
+impl MiniTouchHeader {
+    /// 解析 minitouch 启动时输出的内容：
+    ///
+    /// ```text
+    /// v 1
+    /// ^ 10 1079 1919 2048
+    /// $ 12345
+    /// ```
+    ///
+    /// 分别为版本，`^ <max-contacts> <max-x> <max-y> <max-pressure>` 和进程号。
+    /// 其他行（比如 libevdev 输出的警告）会被忽略，缺少 `v` 或 `^` 一行时返回错误
+    pub fn parse(banner: &str) -> Result<Self, String> {
+        let parse_u32 = |value: &str, line: &str| {
+            value
+                .parse::<u32>()
+                .map_err(|err| format!("invalid minitouch header {line:?}: {err}"))
+        };
+
+        let mut version = None;
+        let mut limits = None;
+        let mut pid = None;
+        for line in banner.lines().map(str::trim) {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("v") => {
+                    version = Some(parse_u32(fields.next().unwrap_or_default(), line)?);
+                }
+                Some("^") => {
+                    let values = fields
+                        .map(|value| parse_u32(value, line))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let [max_contacts, max_x, max_y, max_pressure] = values[..] else {
+                        return Err(format!(
+                            "invalid minitouch header {line:?}: expected 4 values"
+                        ));
+                    };
+                    limits = Some((max_contacts, max_x, max_y, max_pressure));
+                }
+                Some("$") => {
+                    pid = Some(parse_u32(fields.next().unwrap_or_default(), line)?);
+                }
+                _ => {}
+            }
+        }
+
+        let version = version.ok_or("minitouch header has no version line".to_string())?;
+        let (max_contacts, max_x, max_y, max_pressure) =
+            limits.ok_or("minitouch header has no limits line".to_string())?;
+        Ok(Self {
+            version,
+            max_contacts,
+            max_x,
+            max_y,
+            max_pressure,
+            pid,
+        })
+    }
+
+    /// 将屏幕分辨率为 `screen_size` 的设备上的像素坐标 `point` 转换为触摸坐标
+    ///
+    /// 游戏为横屏，`screen_size` 与触摸坐标的范围都会先按横屏（宽不小于高）处理，
+    /// 触摸屏的自然方向为竖屏时再交换 x 和 y。超出屏幕的点会被限制在触摸坐标的范围内
+    pub fn map_point(&self, screen_size: (u32, u32), point: (i32, i32)) -> (u32, u32) {
+        let (flip_xy, touch_width, touch_height) = if self.max_x >= self.max_y {
+            (false, self.max_x, self.max_y)
+        } else {
+            (true, self.max_y, self.max_x)
+        };
+        let (screen_width, screen_height) = (
+            screen_size.0.max(screen_size.1),
+            screen_size.0.min(screen_size.1),
+        );
+
+        // [0, screen) 映射到 [0, max]，分辨率相同时不变
+        let scale = |value: i32, screen: u32, max: u32| {
+            let value = value.max(0) as u64 * (max as u64 + 1) / screen.max(1) as u64;
+            value.min(max as u64) as u32
+        };
+        let x = scale(point.0, screen_width, touch_width);
+        let y = scale(point.1, screen_height, touch_height);
+        if flip_xy {
+            (y, x)
+        } else {
+            (x, y)
+        }
+    }
+}
+
+/// 解析 `wm size` 的输出，有 `Override size` 时使用它，返回横屏（宽不小于高）的分辨率
+fn parse_wm_size(output: &str) -> Option<(u32, u32)> {
+    let parse_line = |prefix: &str| {
+        let size = output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(prefix))?;
+        let (width, height) = size.trim().split_once('x')?;
+        let (width, height) = (width.parse::<u32>().ok()?, height.parse::<u32>().ok()?);
+        Some((width.max(height), width.min(height)))
+    };
+    parse_line("Override size:").or_else(|| parse_line("Physical size:"))
+}
+
+/// minitouch 在设备上监听的 abstract socket 的名称（不带 `-i` 启动时）
+pub const MINITOUCH_SOCKET: &str = "minitouch";
+/// [`MiniTouchTransport::Socket`] 启动 minitouch 服务后连接其 socket 的最大尝试次数
+const MINITOUCH_SOCKET_RETRIES: u32 = 10;
+/// 连接 minitouch 的 socket 失败后重试的间隔
+const MINITOUCH_SOCKET_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// [`MiniToucher`] 向 minitouch 发送命令的方式
+///
+/// - `Stdin`: 以 `adb shell minitouch -i` 启动，命令写入其标准输入，每条命令都要经过本地的 adb 进程和管道
+/// - `Socket`: 在设备上以服务的方式启动 minitouch，通过 adb server 直接连接它监听的
+///   [`MINITOUCH_SOCKET`]（见 [`LocalAbstract`]），延迟更低，见 [`MuMuController`](crate::controller::mumu::MuMuController)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MiniTouchTransport {
+    #[default]
+    Stdin,
+    Socket,
+}
+
 pub struct MiniToucher {
     serial: String,
-    minitouch_stdin: Option<ChildStdin>,
-    flip_xy: bool,
-    max_contact: u32,
-    max_x: u32, // 横屏的 x!
-    max_y: u32,
-    max_pressure: u32,
+    transport: MiniTouchTransport,
+    /// 写入命令的目标，初始化后才有值
+    minitouch_writer: Option<Box<dyn Write + Send>>,
+    /// [`MiniTouchTransport::Socket`] 启动的 minitouch 服务，随 [`MiniToucher`] 一起结束
+    minitouch_daemon: Option<Child>,
+    /// 初始化时读取的 header，见 [`MiniToucher::header`]
+    header: Option<MiniTouchHeader>,
+    /// 初始化时通过 `wm size` 获取的屏幕分辨率（横屏），获取失败时不转换坐标
+    screen_size: Option<(u32, u32)>,
 }
 
 /// A Toucher based n [MiniTouch](https://github.com/DeviceFarmer/minitouch)
@@ -83,15 +390,26 @@ impl MiniToucher {
     pub fn new(serial: String) -> Self {
         Self {
             serial,
-            minitouch_stdin: None,
-            flip_xy: false,
-            max_contact: 0,
-            max_x: 0,
-            max_y: 0,
-            max_pressure: 0,
+            transport: MiniTouchTransport::default(),
+            minitouch_writer: None,
+            minitouch_daemon: None,
+            header: None,
+            screen_size: None,
         }
     }
 
+    /// 初始化时读取的 minitouch header，包括最多同时按下的触点数和触摸坐标的范围，
+    /// 还没有初始化时为 [`None`]
+    pub fn header(&self) -> Option<MiniTouchHeader> {
+        self.header
+    }
+
+    /// 设置发送命令的方式，默认为 [`MiniTouchTransport::Stdin`]，需要在初始化之前设置
+    pub fn with_transport(mut self, transport: MiniTouchTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
     fn check_minitouch(&mut self) -> Result<(), String> {
         let mut device_adb_stream = AdbTcpStream::connect_device(&self.serial)?;
         let res = device_adb_stream
@@ -117,10 +435,19 @@ impl MiniToucher {
         Ok(())
     }
 
+    fn get_screen_size(&self) -> Result<(u32, u32), String> {
+        let mut device_adb_stream = AdbTcpStream::connect_device(&self.serial)?;
+        let output = device_adb_stream
+            .execute_command(ShellCommand::new("wm size".to_string()))
+            .map_err(String::from)?;
+        parse_wm_size(&output).ok_or(format!("failed to parse the output of wm size: {output:?}"))
+    }
+
     fn get_abi(&self) -> Result<String, String> {
         let mut device_adb_stream = AdbTcpStream::connect_device(&self.serial)?;
         device_adb_stream
             .execute_command(ShellCommand::new("getprop ro.product.cpu.abi".to_string()))
+            .map_err(String::from)
     }
 
     pub fn init(&mut self) -> Result<(), String> {
@@ -131,6 +458,22 @@ impl MiniToucher {
         }
         self.check_minitouch()?;
 
+        self.screen_size = match self.get_screen_size() {
+            Ok(size) => Some(size),
+            Err(err) => {
+                error!("{err}, touch coordinates will not be scaled");
+                None
+            }
+        };
+        match self.transport {
+            MiniTouchTransport::Stdin => self.init_stdin()?,
+            MiniTouchTransport::Socket => self.init_socket()?,
+        }
+        info!("minitouch initialized: {:?}", self.header);
+        Ok(())
+    }
+
+    fn init_stdin(&mut self) -> Result<(), String> {
         info!("spawning minitouch...");
         let mut minitouch_child = Command::new("adb")
             .args(vec![
@@ -155,62 +498,91 @@ impl MiniToucher {
             .take()
             .ok_or("cannot get stdout of minitouch".to_string())?;
 
-        // read info
-        let mut reader = io::BufReader::new(child_err);
+        self.read_banner(io::BufReader::new(child_err))?;
+        self.minitouch_writer = Some(Box::new(child_in));
+        Ok(())
+    }
+
+    fn init_socket(&mut self) -> Result<(), String> {
+        info!("spawning minitouch daemon...");
+        let daemon = Command::new("adb")
+            .args([
+                "-s",
+                self.serial.as_str(),
+                "shell",
+                "/data/local/tmp/minitouch",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| format!("{:?}", err))?;
+        self.minitouch_daemon = Some(daemon);
+
+        // 服务启动需要一点时间
+        let mut retries = 0;
+        let mut stream = loop {
+            let res = AdbTcpStream::connect_device(&self.serial).and_then(|mut stream| {
+                stream.execute_command(LocalAbstract::new(MINITOUCH_SOCKET.to_string()))?;
+                Ok(stream)
+            });
+            match res {
+                Ok(stream) => break stream,
+                Err(err) if retries + 1 >= MINITOUCH_SOCKET_RETRIES => {
+                    return Err(format!("failed to connect to the minitouch socket: {err}"))
+                }
+                Err(_) => {
+                    retries += 1;
+                    std::thread::sleep(MINITOUCH_SOCKET_RETRY_INTERVAL);
+                }
+            }
+        };
+
+        // banner 之后 minitouch 不会再发送任何内容，BufReader 中不会残留数据
+        self.read_banner(io::BufReader::new(&mut stream))?;
+        self.minitouch_writer = Some(Box::new(stream));
+        Ok(())
+    }
+
+    /// 读取 minitouch 启动时输出的 header，直到 `$ pid` 一行，见 [`MiniTouchHeader::parse`]
+    fn read_banner<R: BufRead>(&mut self, mut reader: R) -> Result<(), String> {
         info!("start reading info...");
+        let mut banner = String::new();
         loop {
-            let mut buf = String::new();
-            match reader.read_line(&mut buf) {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                // socket 读取超时等错误时不再重试，以免一直卡在这里
                 Err(err) => {
-                    error!("{}", err)
+                    error!("{}", err);
+                    return Err(format!("failed to read the minitouch banner: {err}"));
                 }
-                Ok(sz) => {
-                    if sz == 0 {
-                        info!("readed Ok(0)");
-                        break;
-                    }
-                    buf = buf
-                        .replace("\r\n", "\n")
-                        .strip_suffix("\n")
-                        .unwrap()
-                        .to_string();
-                    info!("readed info: {}", buf);
-                    if buf.starts_with('^') {
-                        let params = &buf.split(' ').skip(1).collect::<Vec<&str>>();
-                        let max_contact = u32::from_str_radix(params[0], 10)
-                            .map_err(|err| format!("{:?}", err))?;
-                        let max_size1 = u32::from_str_radix(params[1], 10)
-                            .map_err(|err| format!("{:?}", err))?;
-                        let max_size2 = u32::from_str_radix(params[2], 10)
-                            .map_err(|err| format!("{:?}", err))?;
-                        let max_pressure = u32::from_str_radix(params[3], 10)
-                            .map_err(|err| format!("{:?}", err))?;
-
-                        let (max_x, max_y) = if max_size1 > max_size2 {
-                            (max_size1, max_size2)
-                        } else {
-                            self.flip_xy = true;
-                            (max_size2, max_size1)
-                        };
-
-                        self.max_contact = max_contact;
-                        self.max_x = max_x;
-                        self.max_y = max_y;
-                        self.max_pressure = max_pressure;
-                    } else if buf.starts_with('$') {
+                Ok(0) => {
+                    info!("readed Ok(0)");
+                    break;
+                }
+                Ok(_) => {
+                    info!("readed info: {}", line.trim_end());
+                    banner.push_str(&line);
+                    if line.starts_with('$') {
                         break;
                     }
-                    buf.clear();
                 }
             }
         }
-        self.minitouch_stdin = Some(child_in);
-        info!("minitouch initialized");
+        self.header = Some(MiniTouchHeader::parse(&banner)?);
         Ok(())
     }
 
+    /// 将设备上的像素坐标转换为触摸坐标，见 [`MiniTouchHeader::map_point`]
+    fn touch_point(&self, x: i32, y: i32) -> (u32, u32) {
+        match (self.header, self.screen_size) {
+            (Some(header), Some(screen_size)) => header.map_point(screen_size, (x, y)),
+            _ => (x.max(0) as u32, y.max(0) as u32),
+        }
+    }
+
     fn write_command(&mut self, command: &str) -> Result<(), String> {
-        if self.minitouch_stdin.is_none() {
+        if self.minitouch_writer.is_none() {
             self.init()?;
         }
 
@@ -219,7 +591,7 @@ impl MiniToucher {
         if !command.ends_with('\n') {
             command.push('\n');
         }
-        self.minitouch_stdin
+        self.minitouch_writer
             .as_mut()
             .ok_or("not conneted".to_string())
             .and_then(|s| {
@@ -236,13 +608,29 @@ impl MiniToucher {
         self.write_command("r")
     }
 
+    /// 按下触点 `contact`，`x`、`y` 为设备上的像素坐标，会被转换为触摸坐标
     pub fn down(&mut self, contact: u32, x: u32, y: u32, pressure: u32) -> Result<(), String> {
-        let (x, y) = if self.flip_xy { (y, x) } else { (x, y) };
+        if self.minitouch_writer.is_none() {
+            self.init()?;
+        }
+        if let Some(header) = self.header {
+            if contact >= header.max_contacts {
+                return Err(format!(
+                    "contact {contact} exceeds the {} contacts supported by the device",
+                    header.max_contacts
+                ));
+            }
+        }
+        let (x, y) = self.touch_point(x as i32, y as i32);
         self.write_command(format!("d {contact} {x} {y} {pressure}").as_str())
     }
 
+    /// 移动触点 `contact`，`x`、`y` 为设备上的像素坐标，会被转换为触摸坐标
     pub fn mv(&mut self, contact: u32, x: i32, y: i32, pressure: u32) -> Result<(), String> {
-        let (x, y) = if self.flip_xy { (y, x) } else { (x, y) };
+        if self.minitouch_writer.is_none() {
+            self.init()?;
+        }
+        let (x, y) = self.touch_point(x, y);
         self.write_command(format!("m {contact} {x} {y} {pressure}").as_str())
     }
 
@@ -253,10 +641,108 @@ impl MiniToucher {
     pub fn wait(&mut self, duration: Duration) -> Result<(), String> {
         self.write_command(format!("w {}", duration.as_millis()).as_str())
     }
+
+    /// 按 `easing` 从 `start` 滑动到 `end`，每 [`SWIPE_DELAY_MS`] 发送一次移动事件，到达终点后立即抬起
+    pub fn swipe_curved(
+        &mut self,
+        start: (u32, u32),
+        end: (i32, i32),
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<(), String> {
+        self.down(0, start.0, start.1, 0)?;
+        self.commit()?;
+        self.move_eased((start.0 as i32, start.1 as i32), end, duration, easing)?;
+        self.up(0)?;
+        self.commit()?;
+
+        Ok(())
+    }
+
+    /// 按住部署卡片 `card_pos`，拖动到地块 `tile_pos`，停留 `hold` 后向 `direction_pos` 划动并抬起，
+    /// 整个过程中手指不会抬起
+    ///
+    /// 各阶段的时长：
+    /// - 按下：[`DEPLOY_PRESS_MS`]，游戏需要这段时间选中卡片
+    /// - 拖动：[`DEPLOY_DRAG_MS`]，太快时游戏可能跟不上，干员会被放在路径上的其他地块
+    /// - 停留：`hold`，等待方向选择界面出现，一般需要 [`DEFAULT_DEPLOY_HOLD_MS`] 左右，设备较慢时需要更长
+    /// - 划向方向：[`DEPLOY_FLICK_MS`]
+    pub fn press_and_drag(
+        &mut self,
+        card_pos: (u32, u32),
+        tile_pos: (u32, u32),
+        direction_pos: (i32, i32),
+        hold: Duration,
+    ) -> Result<(), String> {
+        let tile = (tile_pos.0 as i32, tile_pos.1 as i32);
+
+        self.down(0, card_pos.0, card_pos.1, 0)?;
+        self.commit()?;
+        self.wait(Duration::from_millis(DEPLOY_PRESS_MS))?;
+
+        self.move_eased(
+            (card_pos.0 as i32, card_pos.1 as i32),
+            tile,
+            Duration::from_millis(DEPLOY_DRAG_MS),
+            Easing::EaseInOut,
+        )?;
+        self.wait(hold)?;
+
+        self.move_eased(
+            tile,
+            direction_pos,
+            Duration::from_millis(DEPLOY_FLICK_MS),
+            Easing::Linear,
+        )?;
+        self.up(0)?;
+        self.commit()?;
+
+        Ok(())
+    }
+
+    /// 保持按下，按 `easing` 将触点从 `start` 移动到 `end`
+    fn move_eased(
+        &mut self,
+        start: (i32, i32),
+        end: (i32, i32),
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<(), String> {
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+        let duration_ms = (duration.as_millis() as u32).max(SWIPE_DELAY_MS);
+        let mut t = 0;
+        while t < duration_ms {
+            t = (t + SWIPE_DELAY_MS).min(duration_ms);
+            let progress = easing.progress(t as f32 / duration_ms as f32);
+            let cur_x = lerp(start.0 as f32, end.0 as f32, progress) as i32;
+            let cur_y = lerp(start.1 as f32, end.1 as f32, progress) as i32;
+            self.mv(0, cur_x, cur_y, 0)?;
+            self.commit()?;
+            self.wait(Duration::from_millis(SWIPE_DELAY_MS as u64))?;
+        }
+        Ok(())
+    }
 }
 
 const SWIPE_DELAY_MS: u32 = 2;
 const CLICK_DELAY_MS: u32 = 50;
+/// [`MiniToucher::press_and_drag`] 按下卡片后等待的时间
+pub const DEPLOY_PRESS_MS: u64 = 200;
+/// [`MiniToucher::press_and_drag`] 从卡片拖动到地块的时间
+pub const DEPLOY_DRAG_MS: u64 = 300;
+/// [`MiniToucher::press_and_drag`] 从地块划向方向的时间
+pub const DEPLOY_FLICK_MS: u64 = 100;
+/// [`MiniToucher::press_and_drag`] 建议的停留时间
+pub const DEFAULT_DEPLOY_HOLD_MS: u64 = 500;
+
+impl Drop for MiniToucher {
+    fn drop(&mut self) {
+        if let Some(mut daemon) = self.minitouch_daemon.take() {
+            let _ = daemon.kill();
+        }
+    }
+}
 
 impl Toucher for MiniToucher {
     fn click(&mut self, x: u32, y: u32) -> Result<(), String> {
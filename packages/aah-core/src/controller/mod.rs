@@ -1,11 +1,25 @@
-use std::time::Duration;
+use std::{
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
 use image::DynamicImage;
+use rand::{Rng, RngCore};
 
-use crate::{adb::MyError, vision::utils::Rect};
+use crate::{
+    adb::MyError,
+    vision::{
+        utils::{detect_play_area, Rect},
+        Calibration,
+    },
+};
 
 // pub mod adb_input_controller;
+pub mod backend;
+pub mod dry_run;
 pub mod minitouch;
+pub mod mumu;
 // pub use adb_input_controller::AdbInputController;
 
 /// 默认宽高
@@ -29,45 +43,276 @@ struct RawScreenPos {
 //     }
 // }
 
+/// 将 `screen` 等比缩放到高度为 [`DEFAULT_HEIGHT`]
+pub fn scale_to_default_height(screen: DynamicImage) -> DynamicImage {
+    if screen.height() != DEFAULT_HEIGHT {
+        // let scale_factor = 2560.0 / image.width() as f32;
+        let scale_factor = DEFAULT_HEIGHT as f32 / screen.height() as f32;
+
+        let new_width = (screen.width() as f32 * scale_factor) as u32;
+        let new_height = (screen.height() as f32 * scale_factor) as u32;
+
+        DynamicImage::from(image::imageops::resize(
+            &screen,
+            new_width,
+            new_height,
+            image::imageops::FilterType::Triangle,
+        ))
+    } else {
+        screen
+    }
+}
+
+/// 裁剪 `screen` 中的 `rect` 区域，超出屏幕的部分会被截掉
+pub fn crop_clamped(screen: &DynamicImage, rect: &Rect) -> DynamicImage {
+    let x = rect.x.min(screen.width());
+    let y = rect.y.min(screen.height());
+    screen.crop_imm(
+        x,
+        y,
+        rect.width.min(screen.width() - x),
+        rect.height.min(screen.height() - y),
+    )
+}
+
+/// 缓存的游戏画面区域（见 [`detect_play_area`]），只在截图的分辨率变化时重新检测
+///
+/// [`Controller`] 的实现通过它将截图裁剪为游戏画面，并将游戏画面中的坐标转换为设备坐标，
+/// 这样在有黑边的设备上，分析器和任务看到的仍然是不带黑边的画面
+#[derive(Debug, Default)]
+pub struct PlayAreaCache {
+    /// `(截图的分辨率, 游戏画面区域)`
+    inner: Mutex<Option<((u32, u32), Rect)>>,
+}
+
+impl PlayAreaCache {
+    /// 当前缓存的游戏画面区域（设备坐标），还没有截图时为 [`None`]
+    pub fn get(&self) -> Option<Rect> {
+        self.inner
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(_, area)| area.clone())
+    }
+
+    /// 根据完整的截图 `screen` 更新游戏画面区域，分辨率与上次相同时直接返回缓存的区域
+    pub fn update(&self, screen: &DynamicImage) -> Rect {
+        let mut inner = self.inner.lock().unwrap();
+        let size = (screen.width(), screen.height());
+        match inner.as_ref() {
+            Some((cached_size, area)) if *cached_size == size => area.clone(),
+            _ => {
+                let area = detect_play_area(screen);
+                println!("[PlayAreaCache]: screen {size:?}, play area: {area:?}");
+                *inner = Some((size, area.clone()));
+                area
+            }
+        }
+    }
+
+    /// 根据缓存的游戏画面区域创建 [`Calibration`]，还没有截图时为 [`Calibration::default`]
+    pub fn calibration(&self) -> Calibration {
+        self.get().map(Calibration::new).unwrap_or_default()
+    }
+
+    /// 清除缓存，下一次 [`PlayAreaCache::update`] 时重新检测
+    pub fn reset(&self) {
+        *self.inner.lock().unwrap() = None;
+    }
+
+    /// 更新游戏画面区域后将 `screen` 裁剪为游戏画面
+    pub fn crop(&self, screen: DynamicImage) -> DynamicImage {
+        let area = self.update(&screen);
+        if area.width == screen.width() && area.height == screen.height() {
+            screen
+        } else {
+            screen.crop_imm(area.x, area.y, area.width, area.height)
+        }
+    }
+}
+
+/// 通过 `adb shell input keyevent` 发送的按键，值为 Android 的 `KEYCODE_*`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    /// 返回
+    Back,
+    /// 回到桌面
+    Home,
+    Enter,
+    /// 退格
+    Del,
+    /// 最近任务
+    AppSwitch,
+    /// 明日方舟中与返回相同，[`Controller::press_esc`] 使用该按键
+    Escape,
+}
+
+impl KeyEvent {
+    /// Android 中的键值
+    pub fn code(&self) -> u32 {
+        match self {
+            KeyEvent::Home => 3,
+            KeyEvent::Back => 4,
+            KeyEvent::Enter => 66,
+            KeyEvent::Del => 67,
+            KeyEvent::Escape => 111,
+            KeyEvent::AppSwitch => 187,
+        }
+    }
+}
+
+/// 明日方舟（国服）的包名，[`AAH`](crate::AAH) 默认操作的客户端，见 [`GameClient`]
+pub const ARKNIGHTS_PACKAGE: &str = "com.hypergryph.arknights";
+
+/// 明日方舟的各个客户端，见 [`AAH::set_game_package`](crate::AAH::set_game_package)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameClient {
+    /// 国服
+    #[default]
+    Cn,
+    /// 国际服
+    Global,
+    /// 日服
+    Jp,
+}
+
+impl GameClient {
+    /// 客户端的包名
+    pub fn package(&self) -> &'static str {
+        match self {
+            GameClient::Cn => ARKNIGHTS_PACKAGE,
+            GameClient::Global => "com.YoStarEN.Arknights",
+            GameClient::Jp => "com.YoStarJP.Arknights",
+        }
+    }
+}
+
+/// `dumpsys` 输出中记录前台应用的字段，按优先级排列
+///
+/// - `mCurrentFocus`: 获得焦点的窗口，弹出系统对话框时不包含包名
+/// - `mFocusedApp`: 获得焦点的应用，Android 10 以下为 `AppWindowToken{...}`，之后为 `ActivityRecord{...}`
+/// - `topResumedActivity`、`mResumedActivity`: `dumpsys activity activities` 中的前台 Activity
+const FOREGROUND_FIELDS: [&str; 4] = [
+    "mCurrentFocus",
+    "mFocusedApp",
+    "topResumedActivity",
+    "mResumedActivity",
+];
+
+/// 从 `dumpsys window` 或 `dumpsys activity activities` 的输出中解析前台应用的包名
+///
+/// 各个字段中都以 `包名/Activity` 的形式记录前台的 Activity，比如
+/// `mCurrentFocus=Window{5d2c u0 com.hypergryph.arknights/com.u8.sdk.U8UnityContext}`，
+/// 找不到时返回 [`None`]
+pub fn parse_foreground_package(dumpsys: &str) -> Option<String> {
+    FOREGROUND_FIELDS.iter().find_map(|field| {
+        dumpsys
+            .lines()
+            .filter(|line| line.trim_start().starts_with(field))
+            .find_map(|line| {
+                line.split(|c: char| c.is_whitespace() || c == '{' || c == '=')
+                    .filter_map(|token| token.split_once('/'))
+                    .map(|(package, _)| package)
+                    .find(|package| {
+                        package.contains('.')
+                            && package
+                                .chars()
+                                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_')
+                    })
+                    .map(|package| package.to_string())
+            })
+    })
+}
+
+/// 在矩形区域内点击时选取点击位置的策略
+///
+/// - `Center`: 总是点击中心
+/// - `Uniform`: 在区域内均匀分布
+/// - `Gaussian`: 以中心为均值的正态分布，标准差为宽高乘以 `sigma_ratio`，超出区域的部分会被截断到区域内
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ClickStrategy {
+    Center,
+    #[default]
+    Uniform,
+    Gaussian {
+        sigma_ratio: f32,
+    },
+}
+
+impl ClickStrategy {
+    /// 使用 `rng` 在 `rect` 中按策略选取一个点，传入固定种子的 rng 即可得到可复现的结果
+    pub fn point_in_rect(&self, rect: &Rect, rng: &mut dyn RngCore) -> (u32, u32) {
+        let (width, height) = (rect.width.max(1), rect.height.max(1));
+        let (dx, dy) = match self {
+            Self::Center => (width / 2, height / 2),
+            Self::Uniform => (rng.gen_range(0..width), rng.gen_range(0..height)),
+            Self::Gaussian { sigma_ratio } => {
+                let mut sample = |len: u32| {
+                    // Box-Muller
+                    let u1 = 1.0 - rng.gen::<f32>();
+                    let u2 = rng.gen::<f32>();
+                    let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos();
+                    let v = len as f32 / 2.0 + z * sigma_ratio * len as f32;
+                    (v.max(0.0) as u32).min(len - 1)
+                };
+                (sample(width), sample(height))
+            }
+        };
+        (rect.x + dx, rect.y + dy)
+    }
+}
+
 /// [`Controller`] 承担着设备操作相关的事情，如点击、滑动、截图
-/// 实现了两种 [`Controller`]：
+/// 实现了以下几种 [`Controller`]：
 /// - [`AdbInputController`] 使用 adb input 命令
 /// - [`MiniTouchController`] 使用 minitouch
+/// - [`MuMuController`](mumu::MuMuController) 通过 socket 连接 minitouch，用于 MuMu 模拟器
+///
+/// 连接时使用哪一个由 [`ControllerBackend`](backend::ControllerBackend) 决定
+///
+/// 有黑边的设备上，截图和坐标都只包含游戏画面，见 [`PlayAreaCache`]
 pub trait Controller {
     fn screen_size(&self) -> (u32, u32);
+
+    /// 模板坐标与屏幕、设备坐标之间的转换，默认为没有黑边、大小为 [`Controller::screen_size`] 的屏幕
+    fn calibration(&self) -> Calibration {
+        Calibration::from_screen_size(self.screen_size())
+    }
+
     /// A scale factor from the device's resolution to 1920x1080
     /// $device_res * scale_factor = 1920x1080$
     fn scale_factor(&self) -> f32 {
-        self.screen_size().0 as f32 / DEFAULT_HEIGHT as f32
+        1.0 / self.calibration().scale()
     }
 
     fn click_in_rect(&self, rect: Rect) -> Result<(), MyError> {
-        let x = rand::random::<u32>() % rect.width + rect.x;
-        let y = rand::random::<u32>() % rect.height + rect.y;
+        self.click_in_rect_with(rect, ClickStrategy::Uniform, &mut rand::thread_rng())
+    }
+
+    /// 按 `strategy` 使用 `rng` 选取点击位置，见 [`ClickStrategy::point_in_rect`]
+    fn click_in_rect_with(
+        &self,
+        rect: Rect,
+        strategy: ClickStrategy,
+        rng: &mut dyn RngCore,
+    ) -> Result<(), MyError> {
+        let (x, y) = strategy.point_in_rect(&rect, rng);
         self.click(x, y)
     }
 
     /// click in rect scaled to 1920x1080
     fn click_in_rect_scaled(&self, rect_scaled: Rect) -> Result<(), MyError> {
-        let scale_fector = self.scale_factor();
-        let rect = Rect {
-            x: (rect_scaled.x as f32 / scale_fector) as u32,
-            y: (rect_scaled.y as f32 / scale_fector) as u32,
-            width: (rect_scaled.width as f32 / scale_fector) as u32,
-            height: (rect_scaled.height as f32 / scale_fector) as u32,
-        };
+        let rect = self.calibration().template_rect_to_screen(&rect_scaled);
         self.click_in_rect(rect)
     }
 
     fn click(&self, x: u32, y: u32) -> Result<(), MyError>;
 
     fn click_scaled(&self, x_scaled: u32, y_scaled: u32) -> Result<(), MyError> {
-        let scale_factor = self.scale_factor();
-        let (x, y) = (
-            x_scaled as f32 / scale_factor,
-            y_scaled as f32 / scale_factor,
-        );
-        self.click(x as u32, y as u32)
+        let (x, y) = self
+            .calibration()
+            .template_point_to_screen((x_scaled, y_scaled));
+        self.click(x, y)
     }
 
     fn swipe(&self, start: (u32, u32), end: (i32, i32), duration: Duration) -> Result<(), MyError>;
@@ -78,57 +323,135 @@ pub trait Controller {
         end_scaled: (i32, i32),
         duration: Duration,
     ) -> Result<(), MyError> {
-        let scale_factor = self.scale_factor();
-        let (start, end) = (
-            (
-                start_scaled.0 as f32 / scale_factor,
-                start_scaled.1 as f32 / scale_factor,
-            ),
-            (
-                end_scaled.0 as f32 / scale_factor,
-                end_scaled.1 as f32 / scale_factor,
-            ),
-        );
+        let calibration = self.calibration();
         self.swipe(
-            (start.0 as u32, start.1 as u32),
-            (end.0 as i32, end.1 as i32),
+            calibration.template_point_to_screen(start_scaled),
+            calibration.template_offset_to_screen(end_scaled),
             duration,
         )
     }
 
+    /// 按住 `card_pos`，拖动到 `tile_pos`，停留 `hold` 后划向 `direction_pos` 再抬起，用于部署干员
+    ///
+    /// 需要整个过程中保持按下，默认返回错误，见 [`MiniToucher::press_and_drag`](minitouch::toucher::MiniToucher::press_and_drag)
+    fn press_and_drag(
+        &self,
+        _card_pos: (u32, u32),
+        _tile_pos: (u32, u32),
+        _direction_pos: (i32, i32),
+        _hold: Duration,
+    ) -> Result<(), MyError> {
+        Err(MyError::S(
+            "press_and_drag is not supported by this controller".to_string(),
+        ))
+    }
+
     fn screencap(&self) -> Result<image::DynamicImage, MyError>;
 
+    /// 只截取屏幕中 `rect`（设备分辨率下）区域的内容，`rect` 超出屏幕的部分会被截掉
+    ///
+    /// 默认截取整个屏幕后再裁剪，只是为了统一接口，并不会更快。
+    /// 原生支持区域截图的实现：
+    /// - [`MiniTouchController`](minitouch::MiniTouchController): 获取原始帧缓冲，跳过 PNG 解码，
+    ///   只转换区域内的像素，设备不支持时退回到截取整个屏幕后裁剪
+    /// - [`DryRunController`](dry_run::DryRunController): 转发给被包装的 [`Controller`]
+    fn screencap_region(&self, rect: &Rect) -> Result<image::DynamicImage, MyError> {
+        let screen = self.screencap()?;
+        Ok(crop_clamped(&screen, rect))
+    }
+
     fn screencap_scaled(&self) -> Result<image::DynamicImage, MyError> {
         let screen = self.screencap()?;
-        let screen = if screen.height() != DEFAULT_HEIGHT {
-            // let scale_factor = 2560.0 / image.width() as f32;
-            let scale_factor = DEFAULT_HEIGHT as f32 / screen.height() as f32;
-
-            let new_width = (screen.width() as f32 * scale_factor) as u32;
-            let new_height = (screen.height() as f32 * scale_factor) as u32;
-
-            DynamicImage::from(image::imageops::resize(
-                &screen,
-                new_width,
-                new_height,
-                image::imageops::FilterType::Triangle,
-            ))
-        } else {
-            screen
-        };
-        Ok(screen)
+        Ok(scale_to_default_height(screen))
+    }
+
+    /// 发送按键 `key`
+    fn key_event(&self, key: KeyEvent) -> Result<(), MyError>;
+
+    fn press_home(&self) -> Result<(), MyError> {
+        self.key_event(KeyEvent::Home)
+    }
+
+    fn press_esc(&self) -> Result<(), MyError> {
+        self.key_event(KeyEvent::Escape)
     }
 
-    fn press_home(&self) -> Result<(), MyError>;
+    /// 当前前台应用的包名，用于确认操作的是游戏而不是桌面或其他应用
+    ///
+    /// 默认返回错误，见 [`MiniTouchController`](minitouch::MiniTouchController)
+    fn current_foreground_package(&self) -> Result<String, MyError> {
+        Err(MyError::S(
+            "current_foreground_package is not supported by this controller".to_string(),
+        ))
+    }
 
-    fn press_esc(&self) -> Result<(), MyError>;
+    /// 启动应用 `package`，已经在运行时将其切换到前台，没有安装时返回错误
+    ///
+    /// 默认返回错误，见 [`MiniTouchController`](minitouch::MiniTouchController)
+    fn launch_app(&self, _package: &str) -> Result<(), MyError> {
+        Err(MyError::S(
+            "launch_app is not supported by this controller".to_string(),
+        ))
+    }
+
+    /// 强制停止应用 `package`
+    ///
+    /// 默认返回错误，见 [`MiniTouchController`](minitouch::MiniTouchController)
+    fn stop_app(&self, _package: &str) -> Result<(), MyError> {
+        Err(MyError::S(
+            "stop_app is not supported by this controller".to_string(),
+        ))
+    }
+
+    /// 确保设备上有可以执行的 minitouch，需要时从 `res_dir` 中推送对应 abi 的二进制文件
+    ///
+    /// 默认返回错误，见 [`minitouch::ensure_minitouch`]
+    fn ensure_minitouch(&self, _res_dir: &Path) -> Result<(), MyError> {
+        Err(MyError::S(
+            "ensure_minitouch is not supported by this controller".to_string(),
+        ))
+    }
+}
+
+/// 按固定帧率不断截取屏幕的迭代器，每次迭代返回 [`Controller::screencap`] 的结果
+///
+/// 两次截图开始的间隔不会小于 `1 / fps` 秒，截图本身耗时更长时不会额外等待，
+/// 用于战斗分析这类需要持续截图的场景，以免占满 CPU 和 adb
+pub struct ScreenStream<'a, C: Controller + ?Sized> {
+    controller: &'a C,
+    interval: Duration,
+    last_capture: Option<Instant>,
+}
+
+impl<'a, C: Controller + ?Sized> ScreenStream<'a, C> {
+    pub fn new(controller: &'a C, fps: f32) -> Self {
+        Self {
+            controller,
+            interval: Duration::from_secs_f32(1.0 / fps.max(f32::EPSILON)),
+            last_capture: None,
+        }
+    }
+}
+
+impl<'a, C: Controller + ?Sized> Iterator for ScreenStream<'a, C> {
+    type Item = Result<DynamicImage, MyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(last_capture) = self.last_capture {
+            let elapsed = last_capture.elapsed();
+            if elapsed < self.interval {
+                std::thread::sleep(self.interval - elapsed);
+            }
+        }
+        self.last_capture = Some(Instant::now());
+        Some(self.controller.screencap())
+    }
 }
 
 /// A toucher contains [`Toucher::click`] and [`Toucher::swipe`]
 pub trait Toucher {
     fn click_in_rect(&mut self, rect: Rect) -> Result<(), String> {
-        let x = rand::random::<u32>() % rect.width + rect.x;
-        let y = rand::random::<u32>() % rect.height + rect.y;
+        let (x, y) = ClickStrategy::Uniform.point_in_rect(&rect, &mut rand::thread_rng());
         self.click(x, y)
     }
 
@@ -183,3 +506,139 @@ pub trait Toucher {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    struct BlankController;
+
+    impl Controller for BlankController {
+        fn screen_size(&self) -> (u32, u32) {
+            (16, 9)
+        }
+
+        fn click(&self, _x: u32, _y: u32) -> Result<(), MyError> {
+            Ok(())
+        }
+
+        fn swipe(&self, _: (u32, u32), _: (i32, i32), _: Duration) -> Result<(), MyError> {
+            Ok(())
+        }
+
+        fn screencap(&self) -> Result<DynamicImage, MyError> {
+            Ok(DynamicImage::new_rgb8(16, 9))
+        }
+
+        fn key_event(&self, _key: KeyEvent) -> Result<(), MyError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_screen_stream() {
+        let start = Instant::now();
+        let frames = ScreenStream::new(&BlankController, 20.0)
+            .take(5)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(frames.len(), 5);
+        // 第一帧不等待，之后每帧间隔 50ms
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_click_strategy() {
+        let rect = Rect {
+            x: 100,
+            y: 200,
+            width: 50,
+            height: 30,
+        };
+        for strategy in [
+            ClickStrategy::Center,
+            ClickStrategy::Uniform,
+            ClickStrategy::Gaussian { sigma_ratio: 0.2 },
+        ] {
+            let sample = |seed: u64| {
+                let mut rng = StdRng::seed_from_u64(seed);
+                (0..100)
+                    .map(|_| strategy.point_in_rect(&rect, &mut rng))
+                    .collect::<Vec<_>>()
+            };
+            let points = sample(42);
+            assert_eq!(points, sample(42));
+            for (x, y) in points {
+                assert!((100..150).contains(&x) && (200..230).contains(&y));
+            }
+        }
+        assert_eq!(
+            ClickStrategy::Center.point_in_rect(&rect, &mut rand::thread_rng()),
+            (125, 215)
+        );
+    }
+
+    #[test]
+    fn test_parse_foreground_package() {
+        // Android 9, dumpsys window
+        let dumpsys = "  mCurrentFocus=Window{5d2c8e1 u0 com.hypergryph.arknights/com.u8.sdk.U8UnityContext}
+  mFocusedApp=AppWindowToken{9f1d2a token=Token{3c4e5f ActivityRecord{1a2b3c u0 com.hypergryph.arknights/com.u8.sdk.U8UnityContext t12}}}";
+        assert_eq!(
+            parse_foreground_package(dumpsys).as_deref(),
+            Some(ARKNIGHTS_PACKAGE)
+        );
+
+        // Android 12，弹出系统对话框时退回到 mFocusedApp
+        let dumpsys =
+            "  mCurrentFocus=Window{8e1 u0 Application Not Responding: com.hypergryph.arknights}
+  mFocusedApp=ActivityRecord{2b3c u0 com.hypergryph.arknights/com.u8.sdk.U8UnityContext t15}";
+        assert_eq!(
+            parse_foreground_package(dumpsys).as_deref(),
+            Some(ARKNIGHTS_PACKAGE)
+        );
+
+        // dumpsys activity activities
+        let dumpsys = "    mResumedActivity: ActivityRecord{4f2 u0 com.android.launcher3/.uioverrides.QuickstepLauncher t2}";
+        assert_eq!(
+            parse_foreground_package(dumpsys).as_deref(),
+            Some("com.android.launcher3")
+        );
+        let dumpsys = "  topResumedActivity=ActivityRecord{4f2 u0 com.YoStarEN.Arknights/com.u8.sdk.U8UnityContext t7}";
+        assert_eq!(
+            parse_foreground_package(dumpsys).as_deref(),
+            Some(GameClient::Global.package())
+        );
+
+        assert_eq!(parse_foreground_package("  mCurrentFocus=null"), None);
+        assert_eq!(parse_foreground_package(""), None);
+    }
+
+    #[test]
+    fn test_play_area_cache() {
+        let cache = PlayAreaCache::default();
+        assert_eq!(cache.get(), None);
+        assert_eq!(cache.calibration().screen_point_to_device((0, 0)), (0, 0));
+
+        // 左右各 20 的黑边
+        let letterboxed = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(200, 90, |x, _| {
+            image::Rgba([if (20..180).contains(&x) { 200 } else { 0 }, 0, 0, 255])
+        }));
+        let cropped = cache.crop(letterboxed.clone());
+        assert_eq!((cropped.width(), cropped.height()), (160, 90));
+        assert_eq!(cache.calibration().screen_point_to_device((0, 0)), (20, 0));
+
+        // 分辨率不变时使用缓存
+        let cropped = cache.crop(DynamicImage::new_rgba8(200, 90));
+        assert_eq!((cropped.width(), cropped.height()), (160, 90));
+
+        // 分辨率变化后重新检测
+        let cropped = cache.crop(DynamicImage::new_rgba8(160, 90));
+        assert_eq!((cropped.width(), cropped.height()), (160, 90));
+        assert_eq!(cache.calibration().screen_point_to_device((0, 0)), (0, 0));
+
+        cache.reset();
+        assert_eq!(cache.get(), None);
+    }
+}
@@ -0,0 +1,141 @@
+use std::{path::Path, sync::Mutex, time::Duration};
+
+use log::info;
+
+use crate::{
+    adb::MyError,
+    vision::{utils::Rect, Calibration},
+};
+
+use super::{
+    minitouch::{
+        toucher::{Easing, MiniTouchHeader, MiniTouchTransport, MiniToucher},
+        MiniTouchController,
+    },
+    Controller, KeyEvent, Toucher,
+};
+
+/// MuMu 模拟器的 [`Controller`]，见 [`Emulator::MuMu`](super::backend::Emulator::MuMu)
+///
+/// 截图、按键、应用管理等与 [`MiniTouchController`] 相同，点击、滑动和部署则通过
+/// [`MiniTouchTransport::Socket`] 的 minitouch 完成：minitouch 作为服务在模拟器中运行，
+/// 经由 adb server 直接连接它的 socket，每次点击只需写入几行命令。
+/// [`MiniTouchController`] 的点击和滑动每次都要为 `adb shell input` 启动一个进程，要慢上数百毫秒
+pub struct MuMuController {
+    inner: MiniTouchController,
+    toucher: Mutex<MiniToucher>,
+}
+
+impl MuMuController {
+    /// 连接设备并启动 minitouch 服务，minitouch 无法启动或连接时返回错误
+    pub fn connect<S: AsRef<str>>(device_serial: S) -> Result<Self, MyError> {
+        let device_serial = device_serial.as_ref();
+        let inner = MiniTouchController::connect(device_serial)?;
+
+        println!("[MuMuController]: connecting to the minitouch socket...");
+        let mut toucher =
+            MiniToucher::new(device_serial.to_string()).with_transport(MiniTouchTransport::Socket);
+        toucher.init().map_err(MyError::S)?;
+        println!("[MuMuController]: connected");
+
+        Ok(Self {
+            inner,
+            toucher: Mutex::new(toucher),
+        })
+    }
+
+    /// minitouch 的 header，包括最多同时按下的触点数和触摸坐标的范围
+    pub fn minitouch_header(&self) -> Option<MiniTouchHeader> {
+        self.toucher.lock().unwrap().header()
+    }
+}
+
+impl Controller for MuMuController {
+    fn screen_size(&self) -> (u32, u32) {
+        self.inner.screen_size()
+    }
+
+    fn calibration(&self) -> Calibration {
+        self.inner.calibration()
+    }
+
+    fn click(&self, x: u32, y: u32) -> Result<(), MyError> {
+        let (width, height) = self.screen_size();
+        if x > width || y > height {
+            return Err(MyError::S("coord out of screen".to_string()));
+        }
+        let (x, y) = self.calibration().screen_point_to_device((x, y));
+        info!("[Controller]: clicking ({}, {})", x, y);
+        self.toucher.lock().unwrap().click(x, y).map_err(MyError::S)
+    }
+
+    fn swipe(&self, start: (u32, u32), end: (i32, i32), duration: Duration) -> Result<(), MyError> {
+        info!(
+            "[Controller]: swiping from {:?} to {:?} for {:?}",
+            start, end, duration
+        );
+        let calibration = self.calibration();
+        let (start, end) = (
+            calibration.screen_point_to_device(start),
+            calibration.screen_offset_to_device(end),
+        );
+        // 与 `input swipe` 相同，匀速划动，到达终点后立即抬起
+        self.toucher
+            .lock()
+            .unwrap()
+            .swipe_curved(start, end, duration, Easing::Linear)
+            .map_err(MyError::S)
+    }
+
+    fn press_and_drag(
+        &self,
+        card_pos: (u32, u32),
+        tile_pos: (u32, u32),
+        direction_pos: (i32, i32),
+        hold: Duration,
+    ) -> Result<(), MyError> {
+        info!(
+            "[Controller]: dragging from {:?} to {:?}, then to {:?}",
+            card_pos, tile_pos, direction_pos
+        );
+        let calibration = self.calibration();
+        let (card_pos, tile_pos, direction_pos) = (
+            calibration.screen_point_to_device(card_pos),
+            calibration.screen_point_to_device(tile_pos),
+            calibration.screen_offset_to_device(direction_pos),
+        );
+        self.toucher
+            .lock()
+            .unwrap()
+            .press_and_drag(card_pos, tile_pos, direction_pos, hold)
+            .map_err(MyError::S)
+    }
+
+    fn screencap(&self) -> Result<image::DynamicImage, MyError> {
+        self.inner.screencap()
+    }
+
+    fn screencap_region(&self, rect: &Rect) -> Result<image::DynamicImage, MyError> {
+        self.inner.screencap_region(rect)
+    }
+
+    fn key_event(&self, key: KeyEvent) -> Result<(), MyError> {
+        self.inner.key_event(key)
+    }
+
+    fn current_foreground_package(&self) -> Result<String, MyError> {
+        self.inner.current_foreground_package()
+    }
+
+    fn launch_app(&self, package: &str) -> Result<(), MyError> {
+        self.inner.launch_app(package)
+    }
+
+    fn stop_app(&self, package: &str) -> Result<(), MyError> {
+        self.inner.stop_app(package)
+    }
+
+    fn ensure_minitouch(&self, res_dir: &Path) -> Result<(), MyError> {
+        self.inner.ensure_minitouch(res_dir)
+    }
+}
@@ -4,17 +4,57 @@
 use std::{
     error::Error,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
 };
 
-use config::{navigate::NavigateConfig, task::TaskConfig};
-use controller::{minitouch, Controller};
-use task::builtins::BuiltinTask;
-use vision::analyzer::{
-    deploy::{DeployAnalyzer, DeployAnalyzerOutput},
-    Analyzer,
+use config::{
+    navigate::{navigate_with, NavigateConfig},
+    popup::PopupConfig,
+    task::{ConfigError, TaskConfig},
+};
+use controller::{
+    backend::ControllerBackend,
+    dry_run::DryRunController,
+    minitouch::toucher::{Direction, DEFAULT_DEPLOY_HOLD_MS},
+    Controller, ScreenStream, ARKNIGHTS_PACKAGE,
+};
+use notify_debouncer_mini::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, Debouncer,
+};
+use ocrs::OcrEngine;
+use task::{builtins::BuiltinTask, popup_guard::PopupGuard};
+use vision::{
+    analyzer::{
+        battle::{read_battle_cost, BattleAnalyzer, BattleState},
+        battle_timeline::BattleTimeline,
+        best_match::BestMatchAnalyzer,
+        deploy::{DeployAnalyzer, DeployAnalyzerOutput},
+        multi_match::scale_template,
+        result::{ResultAnalyzer, ResultAnalyzerOutput},
+        sanity::SanityAnalyzer,
+        scene::{Scene, SceneAnalyzer},
+        stable::StableScreenAnalyzer,
+        unit_menu::{UnitMenuAnalyzer, UnitMenuAnalyzerOutput},
+        Analyzer,
+    },
+    map::TileTransform,
+    matcher::best_matcher::match_templates,
+    ocr::{
+        init_ocr_engine_with, ocr_region, parse_numbers, OcrConfig, OcrError, OcrInitError, DIGITS,
+    },
+    template_cache::TemplateCache,
+    utils::Rect,
+    Calibration,
 };
 
-use crate::task::Task;
+use crate::task::{RunOptions, Task, TaskEvt, TaskEvtBroadcaster, TaskStepTracker};
 
 pub mod adb;
 pub mod config;
@@ -22,40 +62,183 @@ pub mod controller;
 pub mod task;
 pub mod vision;
 
+/// [`AAH::deploy_operator`] 选择朝向时从地块划出的距离（1920x1080 下）
+pub const DEPLOY_FACING_DISTANCE: i32 = 200;
+/// [`AAH::open_unit_menu`] 点击干员后等待菜单弹出的时间
+pub const UNIT_MENU_OPEN_DELAY: Duration = Duration::from_millis(500);
+/// [`AAH::start_battle_analyzer`] 截取战斗画面的帧率
+pub const BATTLE_ANALYZER_FPS: f32 = 5.0;
+/// [`AAH::wait_until_stable`] 截取画面的帧率
+pub const STABLE_SCREEN_FPS: f32 = 5.0;
+/// [`AAH::launch_game`] 等待进入标题界面的时间
+pub const GAME_LAUNCH_TIMEOUT: Duration = Duration::from_secs(120);
+/// [`AAH::launch_game`] 识别场景的间隔
+pub const GAME_LAUNCH_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// [`AAH::ensure_game_foreground`] 启动游戏后等待其切换到前台的时间
+pub const GAME_FOREGROUND_TIMEOUT: Duration = Duration::from_secs(20);
+/// [`AAH::ensure_game_foreground`] 查询前台应用的间隔
+pub const GAME_FOREGROUND_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 /// AAH 的实例
 pub struct AAH {
     pub res_dir: PathBuf,
     /// [`controller`] 承担设备控制相关操作（比如触摸、截图等）
     pub controller: Box<dyn Controller + Sync + Send>,
     /// 由 `tasks.toml` 和 `tasks` 目录加载的任务配置
-    pub task_config: TaskConfig,
+    pub task_config: Arc<RwLock<TaskConfig>>,
     /// 由 `navigates.toml` 加载的导航配置
-    pub navigate_config: NavigateConfig,
+    pub navigate_config: Arc<RwLock<NavigateConfig>>,
+    /// 由 `popups.toml` 加载的弹窗配置，见 [`AAH::dismiss_popups`]
+    pub popup_config: Arc<RwLock<PopupConfig>>,
+    /// 当前配置中的错误，见 [`AAH::config_errors`]
+    config_errors: Arc<RwLock<Vec<ConfigError>>>,
     /// 屏幕内容的缓存
-    pub screen_cache: Option<image::DynamicImage>,
+    screen_cache: Mutex<Option<image::DynamicImage>>,
+    /// 缩放后的模板的缓存，见 [`AAH::get_template_scaled`]
+    template_cache: Mutex<TemplateCache>,
+    /// OCR 引擎，通过 [`AAH::connect_lenient`] 连接且模型不存在或无法加载时为 [`None`]，见 [`AAH::try_ocr_engine`]
+    pub ocr_engine: Option<OcrEngine>,
+    /// 初始化 OCR 引擎时的错误
+    ocr_init_error: Option<Arc<OcrInitError>>,
+    /// OCR 引擎的配置
+    pub ocr_config: OcrConfig,
+    /// 上一次成功读取的部署费用
+    last_battle_cost: Mutex<Option<u32>>,
+    /// 当前关卡地图的地块坐标转换，见 [`AAH::set_tile_transform`]
+    tile_transform: Mutex<Option<TileTransform>>,
+    /// 当前任务执行的选项，见 [`AAH::run_task_with`]
+    run_options: Mutex<RunOptions>,
+    /// 是否开启 dry run，见 [`AAH::set_dry_run`]
+    dry_run: Arc<AtomicBool>,
+    /// [`TaskEvt`] 的广播
+    task_evt: TaskEvtBroadcaster,
+    /// 当前正在执行的步骤的路径，见 [`TaskStepTracker`]
+    task_steps: TaskStepTracker,
+    /// [`AAH::watch_resources`] 创建的资源目录监听器
+    resources_watcher: Option<Debouncer<RecommendedWatcher>>,
+    /// 操作的游戏客户端的包名，见 [`AAH::set_game_package`]
+    game_package: Mutex<String>,
 }
 
 impl AAH {
     /// 连接到 `serial` 指定的设备（`serial` 就是 `adb devices` 里的序列号）
     /// - `serial`: 设备的序列号
     /// - `res_dir`: 资源目录的路径
+    ///
+    /// OCR 模型不存在或无法加载时返回 [`OcrInitError`]，需要在没有模型时连接请使用 [`AAH::connect_lenient`]
     pub fn connect<S: AsRef<str>, P: AsRef<Path>>(
         serial: S,
         res_dir: P,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::connect_with_ocr_config(serial, res_dir, OcrConfig::default())
+    }
+
+    /// 同 [`AAH::connect`]，但 OCR 模型不存在或无法加载时仍然连接，
+    /// 此时 OCR 相关的方法会返回 [`OcrError::Unavailable`]
+    pub fn connect_lenient<S: AsRef<str>, P: AsRef<Path>>(
+        serial: S,
+        res_dir: P,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::connect_inner(
+            serial,
+            res_dir,
+            OcrConfig::default(),
+            ControllerBackend::Auto,
+            false,
+        )
+    }
+
+    /// 列出 adb server 上的所有设备（即 `adb devices -l`），包括未授权、离线等状态的设备，
+    /// 只有 [`adb::DeviceInfo::is_available`] 的设备可以用于 [`AAH::connect`]
+    pub fn list_devices() -> Result<Vec<adb::DeviceInfo>, String> {
+        let mut host = adb::host::connect_default()?;
+        host.devices_long().map_err(|err| err.to_string())
+    }
+
+    /// 同 [`AAH::connect`]，连接后开启 dry run，见 [`AAH::set_dry_run`]
+    pub fn connect_dry_run<S: AsRef<str>, P: AsRef<Path>>(
+        serial: S,
+        res_dir: P,
+    ) -> Result<Self, Box<dyn Error>> {
+        let aah = Self::connect(serial, res_dir)?;
+        aah.set_dry_run(true);
+        Ok(aah)
+    }
+
+    /// 同 [`AAH::connect`]，使用 `ocr_config` 初始化 OCR 引擎
+    pub fn connect_with_ocr_config<S: AsRef<str>, P: AsRef<Path>>(
+        serial: S,
+        res_dir: P,
+        ocr_config: OcrConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::connect_with_backend(serial, res_dir, ocr_config, ControllerBackend::Auto)
+    }
+
+    /// 同 [`AAH::connect_with_ocr_config`]，使用 `backend` 连接设备
+    ///
+    /// 其他连接方法都使用 [`ControllerBackend::Auto`]，即在能识别出的模拟器上使用专用的后端
+    pub fn connect_with_backend<S: AsRef<str>, P: AsRef<Path>>(
+        serial: S,
+        res_dir: P,
+        ocr_config: OcrConfig,
+        backend: ControllerBackend,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::connect_inner(serial, res_dir, ocr_config, backend, true)
+    }
+
+    /// `require_ocr` 为 `true` 时 OCR 引擎初始化失败会返回错误，否则只记录该错误
+    fn connect_inner<S: AsRef<str>, P: AsRef<Path>>(
+        serial: S,
+        res_dir: P,
+        ocr_config: OcrConfig,
+        backend: ControllerBackend,
+        require_ocr: bool,
     ) -> Result<Self, Box<dyn Error>> {
         let res_dir = res_dir.as_ref().to_path_buf();
         let task_config =
             TaskConfig::load(&res_dir).map_err(|err| format!("task config not found: {err}"))?;
         let navigate_config = NavigateConfig::load(&res_dir)
             .map_err(|err| format!("navigate config not found: {err}"))?;
+        let popup_config = PopupConfig::load(&res_dir)
+            .map_err(|err| format!("failed to load popup config: {err}"))?;
+        let config_errors = validate_resources(&res_dir, &task_config, &navigate_config);
+        let (ocr_engine, ocr_init_error) = match init_ocr_engine_with(&res_dir, &ocr_config) {
+            Ok(engine) => (Some(engine), None),
+            Err(err) if require_ocr => return Err(err.into()),
+            Err(err) => {
+                println!("[AAH]: ocr engine is not available: {err}");
+                (None, Some(Arc::new(err)))
+            }
+        };
         // let controller = Box::new(AdbInputController::connect(serial)?);
-        let controller = Box::new(minitouch::MiniTouchController::connect(serial)?);
+        let controller = backend.connect(serial.as_ref())?;
+        let dry_run = Arc::new(AtomicBool::new(false));
+        let task_evt = TaskEvtBroadcaster::default();
+        let controller = Box::new(DryRunController::new(
+            controller,
+            dry_run.clone(),
+            task_evt.clone(),
+        ));
         Ok(Self {
             res_dir,
             controller,
-            task_config,
-            navigate_config,
-            screen_cache: None,
+            task_config: Arc::new(RwLock::new(task_config)),
+            navigate_config: Arc::new(RwLock::new(navigate_config)),
+            popup_config: Arc::new(RwLock::new(popup_config)),
+            config_errors: Arc::new(RwLock::new(config_errors)),
+            screen_cache: Mutex::new(None),
+            template_cache: Mutex::new(TemplateCache::default()),
+            ocr_engine,
+            ocr_init_error,
+            ocr_config,
+            last_battle_cost: Mutex::new(None),
+            tile_transform: Mutex::new(None),
+            run_options: Mutex::new(RunOptions::default()),
+            dry_run,
+            task_evt,
+            task_steps: TaskStepTracker::default(),
+            resources_watcher: None,
+            game_package: Mutex::new(ARKNIGHTS_PACKAGE.to_string()),
         })
     }
 
@@ -65,48 +248,211 @@ impl AAH {
 
         let task = self
             .task_config
+            .read()
+            .unwrap()
             .0
             .get(&name)
             .ok_or("failed to get task")?
             .clone();
         println!("executing {:?}", task);
 
-        task.run(self)?;
+        // Multi 会为每个子任务产生步骤事件，其他任务作为单个步骤
+        let steps = match &task {
+            BuiltinTask::Multi(multi) => multi.tasks().len(),
+            _ => 1,
+        };
+        self.task_evt.emit(TaskEvt::TaskStarted {
+            name: name.clone(),
+            steps,
+        });
+        let res = match &task {
+            BuiltinTask::Multi(_) => task.run(self),
+            _ => {
+                self.dismiss_popups();
+                let res = self
+                    .task_steps
+                    .run_step(&self.task_evt, 0, task.description(), || task.run(self));
+                self.dismiss_popups();
+                res
+            }
+        };
+        self.task_evt.emit(TaskEvt::TaskFinished {
+            name,
+            success: res.is_ok(),
+        });
+
+        res
+    }
+
+    /// 开启或关闭 dry run
+    ///
+    /// 开启后点击、滑动、按键等操作不会发送到设备，而是作为 [`TaskEvt::DryRunAction`] 发出，
+    /// 截图照常进行，可以用来检查任务的逻辑。见 [`DryRunController`]
+    pub fn set_dry_run(&self, dry_run: bool) {
+        self.dry_run.store(dry_run, Ordering::Relaxed);
+    }
+
+    /// 是否开启了 dry run
+    pub fn dry_run(&self) -> bool {
+        self.dry_run.load(Ordering::Relaxed)
+    }
+
+    /// 使用 `options` 运行名为 `name` 的任务，任务结束后恢复原来的选项
+    ///
+    /// 选项对任务中的所有步骤（包括通过 [`ByName`](task::builtins::ByName) 引用的任务）生效
+    pub fn run_task_with<S: AsRef<str>>(&self, name: S, options: RunOptions) -> Result<(), String> {
+        let prev = std::mem::replace(&mut *self.run_options.lock().unwrap(), options);
+        let res = self.run_task(name);
+        *self.run_options.lock().unwrap() = prev;
+        res
+    }
+
+    /// 识别并关闭 `popups.toml` 中配置的弹窗，返回关闭的弹窗的名称，见 [`PopupGuard`]
+    ///
+    /// 任务的每个步骤前后都会调用，识别失败时只输出日志，不影响任务的执行
+    pub fn dismiss_popups(&self) -> Vec<String> {
+        let popup_config = self.popup_config.read().unwrap().clone();
+        PopupGuard::new(popup_config)
+            .dismiss(self)
+            .unwrap_or_else(|err| {
+                println!("[AAH]: failed to dismiss popups: {err}");
+                vec![]
+            })
+    }
 
-        Ok(())
+    /// 当前任务执行的选项，见 [`AAH::run_task_with`]
+    pub fn run_options(&self) -> RunOptions {
+        self.run_options.lock().unwrap().clone()
     }
 
     // 更新屏幕缓存
-    pub fn update_screen(&mut self) -> Result<(), String> {
+    pub fn update_screen(&self) -> Result<(), String> {
+        self.screen_cap_and_cache().map(|_| ())
+    }
+
+    /// 获取缓存中的屏幕内容
+    /// 如果没有缓存，就通过 [`AAH::update_screen`] 更新，然后再返回
+    pub fn get_screen(&self) -> Result<image::DynamicImage, String> {
+        self.screen_cache_or_cap()
+    }
+
+    /// 以 `fps` 的帧率不断截取屏幕，见 [`ScreenStream`]
+    ///
+    /// 不会更新屏幕缓存
+    pub fn capture_stream(&self, fps: f32) -> ScreenStream<'_, dyn Controller + Sync + Send> {
+        ScreenStream::new(self.controller.as_ref(), fps)
+    }
+
+    /// 模板坐标（1920x1080 下）与屏幕、设备坐标之间的转换，见 [`Controller::calibration`]
+    pub fn calibration(&self) -> Calibration {
+        self.controller.calibration()
+    }
+
+    /// 截取当前帧的屏幕内容，更新屏幕缓存并返回
+    pub fn screen_cap_and_cache(&self) -> Result<image::DynamicImage, String> {
         let screen = self
             .controller
             .screencap()
             .map_err(|err| format!("{err}"))?;
-        self.screen_cache = Some(screen.clone());
-        Ok(())
+        *self.screen_cache.lock().unwrap() = Some(screen.clone());
+        Ok(screen)
     }
 
-    /// 获取缓存中的屏幕内容
-    /// 如果没有缓存，就通过 [`AAH::update_screen`] 更新，然后再返回
-    pub fn get_screen(&mut self) -> Result<image::DynamicImage, String> {
-        match &self.screen_cache {
-            Some(cache) => Ok(cache.clone()),
-            None => {
-                self.update_screen()?;
-                Ok(self.screen_cache.as_ref().unwrap().clone())
-            }
+    /// 只截取屏幕中 `rect`（1920x1080 下）区域的内容，见 [`Controller::screencap_region`]
+    ///
+    /// 返回与整个屏幕大小相同的图像，区域外的部分为黑色，因此区域内的坐标与完整截图一致。
+    /// 截图不完整，所以不会更新屏幕缓存
+    pub fn screen_cap_region(&self, rect: &Rect) -> Result<image::DynamicImage, String> {
+        let (width, height) = self.controller.screen_size();
+        let rect = self.calibration().template_rect_to_screen(rect);
+        let region = self
+            .controller
+            .screencap_region(&rect)
+            .map_err(|err| format!("{err}"))?;
+
+        let mut screen = image::DynamicImage::new_rgba8(width, height);
+        image::imageops::replace(&mut screen, &region, rect.x as i64, rect.y as i64);
+        Ok(screen)
+    }
+
+    /// 获取缓存中的屏幕内容，没有缓存时通过 [`AAH::screen_cap_and_cache`] 截取
+    pub fn screen_cache_or_cap(&self) -> Result<image::DynamicImage, String> {
+        let cache = self.screen_cache.lock().unwrap().clone();
+        match cache {
+            Some(cache) => Ok(cache),
+            None => self.screen_cap_and_cache(),
         }
     }
 
-    /// 重新加载 resources 中的配置
-    pub fn reload_resources(&mut self) -> Result<(), String> {
-        let task_config = TaskConfig::load(&self.res_dir)
-            .map_err(|err| format!("task config not found: {err}"))?;
-        let navigate_config = NavigateConfig::load(&self.res_dir)
-            .map_err(|err| format!("navigate config not found: {err}"))?;
-        self.task_config = task_config;
-        self.navigate_config = navigate_config;
-        Ok(())
+    /// 当前配置中的错误（比如引用了不存在的模板或任务），见 [`TaskConfig::validate`]
+    ///
+    /// 存在错误时仍然可以连接和执行其他任务，连接和每次重新加载配置时更新
+    pub fn config_errors(&self) -> Vec<ConfigError> {
+        self.config_errors.read().unwrap().clone()
+    }
+
+    /// 重新加载 resources 中的配置，返回新配置中的错误，见 [`AAH::config_errors`]
+    ///
+    /// 任一配置加载失败时不会替换当前的配置，缩放后的模板的缓存总会被清空
+    pub fn reload_resources(&self) -> Result<Vec<ConfigError>, String> {
+        self.template_cache.lock().unwrap().clear();
+        reload_resources(
+            &self.res_dir,
+            &self.task_config,
+            &self.navigate_config,
+            &self.popup_config,
+            &self.config_errors,
+            &self.task_evt,
+        )
+    }
+
+    /// 监听 `res_dir` 中的 `toml` 配置文件，文件变化时自动重新加载配置
+    ///
+    /// 每次重新加载后会产生 [`TaskEvt::ResourcesReloaded`]（附带新配置中的错误）或
+    /// [`TaskEvt::ResourcesReloadFailed`]，加载失败时保留当前的配置。
+    /// 返回开始监听时配置中的错误，见 [`AAH::config_errors`]
+    pub fn watch_resources(&mut self) -> Result<Vec<ConfigError>, String> {
+        let res_dir = self.res_dir.clone();
+        let task_config = self.task_config.clone();
+        let navigate_config = self.navigate_config.clone();
+        let popup_config = self.popup_config.clone();
+        let config_errors = self.config_errors.clone();
+        let task_evt = self.task_evt.clone();
+
+        let mut debouncer = new_debouncer(
+            Duration::from_millis(500),
+            move |res: DebounceEventResult| match res {
+                Ok(events) => {
+                    let config_changed = events.iter().any(|evt| {
+                        evt.path.extension().and_then(|ext| ext.to_str()) == Some("toml")
+                    });
+                    if config_changed {
+                        let _ = reload_resources(
+                            &res_dir,
+                            &task_config,
+                            &navigate_config,
+                            &popup_config,
+                            &config_errors,
+                            &task_evt,
+                        );
+                    }
+                }
+                Err(err) => println!("[AAH]: watch error: {:?}", err),
+            },
+        )
+        .map_err(|err| format!("failed to create watcher: {err}"))?;
+        debouncer
+            .watcher()
+            .watch(&self.res_dir, RecursiveMode::Recursive)
+            .map_err(|err| format!("failed to watch {:?}: {err}", self.res_dir))?;
+
+        self.resources_watcher = Some(debouncer);
+        Ok(self.config_errors())
+    }
+
+    /// 订阅 [`TaskEvt`]
+    pub fn subscribe_task_evt(&self) -> Receiver<TaskEvt> {
+        self.task_evt.subscribe()
     }
 
     /// 从 `{res_path}/resources/templates/1920x1080` 目录中根据文件名称获取模板
@@ -118,15 +464,678 @@ impl AAH {
         Ok(image)
     }
 
-    /// 截取当前帧的屏幕内容，分析部署卡片，返回 [`DeployAnalyzerOutput`]
-    pub fn analyze_deploy(&self) -> Result<DeployAnalyzerOutput, String> {
-        let mut analyzer = DeployAnalyzer;
+    /// 获取按屏幕高度 `height` 缩放后的模板（见 [`scale_template`]）
+    ///
+    /// 缩放结果会被缓存，同一模板在每种分辨率下只缩放一次，[`AAH::reload_resources`] 时清空缓存
+    pub fn get_template_scaled<S: AsRef<str>>(
+        &self,
+        name: S,
+        height: u32,
+    ) -> Result<image::DynamicImage, String> {
+        let name = name.as_ref();
+        if let Some(template) = self.template_cache.lock().unwrap().get(name, height) {
+            return Ok(template);
+        }
+        let template = scale_template(self.get_template(name)?, height);
+        self.template_cache
+            .lock()
+            .unwrap()
+            .insert(name, height, template.clone());
+        Ok(template)
+    }
+
+    /// 判断模板 `template_name`（见 [`AAH::get_template`]）是否出现在屏幕中，详见 [`vision::matcher::best_matcher::template_present`]
+    ///
+    /// - `threshold`: 最佳匹配值达到该值时视为出现
+    /// - `roi`: 只在该区域（1920x1080 下）内查找，[`None`] 时为全屏
+    ///
+    /// 优先使用缓存中的屏幕内容，没有缓存时截取当前帧
+    pub fn template_present<S: AsRef<str>>(
+        &self,
+        template_name: S,
+        threshold: f32,
+        roi: Option<Rect>,
+    ) -> Result<bool, String> {
+        let template = self.get_template(template_name)?;
+        let screen = self.screen_cache_or_cap()?;
+        vision::matcher::best_matcher::template_present(&screen, &template, threshold, roi.as_ref())
+    }
+
+    /// 将目录 `dir` 中的每张图片作为模板（1920x1080 下）与屏幕匹配，
+    /// 返回最佳匹配值高于 `threshold` 的 `(文件名, 位置, 匹配值)`，详见 [`match_templates`]
+    ///
+    /// 用于试探一组模板（比如所有弹窗的关闭按钮）中哪些出现在了屏幕上。模板与 [`AAH::get_template_scaled`]
+    /// 一样按屏幕高度缩放并缓存，没有缓存的模板会被并行地读取和缩放，无法读取的文件会被跳过并输出原因。
+    /// 优先使用缓存中的屏幕内容，没有缓存时截取当前帧
+    pub fn match_templates_in_dir<P: AsRef<Path>>(
+        &self,
+        dir: P,
+        threshold: f32,
+    ) -> Result<Vec<(String, Rect, f32)>, String> {
+        let dir = dir.as_ref();
+        let mut paths = std::fs::read_dir(dir)
+            .map_err(|err| format!("failed to read {}: {err}", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && image::ImageFormat::from_path(path).is_ok())
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let screen = self.screen_cache_or_cap()?;
+        let height = screen.height();
+
+        // 缓存以完整路径为键，以免与 templates 目录中的同名模板冲突
+        let mut templates = vec![];
+        let mut missing = vec![];
+        {
+            let mut template_cache = self.template_cache.lock().unwrap();
+            for path in paths {
+                let key = path.to_string_lossy().to_string();
+                match template_cache.get(&key, height) {
+                    Some(template) => templates.push((path, template)),
+                    None => missing.push(path),
+                }
+            }
+        }
+        let workers = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        let loaded = std::thread::scope(|s| {
+            missing
+                .chunks(missing.len().div_ceil(workers).max(1))
+                .map(|chunk| {
+                    s.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|path| {
+                                let template = image::open(path)
+                                    .map(|template| scale_template(template, height));
+                                (path.clone(), template)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        for (path, template) in loaded {
+            match template {
+                Ok(template) => {
+                    self.template_cache.lock().unwrap().insert(
+                        &path.to_string_lossy(),
+                        height,
+                        template.clone(),
+                    );
+                    templates.push((path, template));
+                }
+                Err(err) => println!("[AAH]: skipped {}: {err}", path.display()),
+            }
+        }
+
+        let templates = templates
+            .into_iter()
+            .filter_map(|(path, template)| {
+                Some((path.file_name()?.to_string_lossy().to_string(), template))
+            })
+            .collect::<Vec<_>>();
+        let matched = match_templates(&screen.to_luma32f(), &templates, threshold);
+        println!("[AAH]: matched templates in {}: {matched:?}", dir.display());
+        Ok(matched)
+    }
+
+    /// 从 `{res_path}/resources/avatars` 目录中获取干员 `name` 的所有头像（精英化、皮肤等），
+    /// 返回 `(变体, 头像)`，详见 [`vision::analyzer::deploy::get_oper_avatars`]
+    pub fn get_oper_avatars<S: AsRef<str>>(
+        &self,
+        name: S,
+    ) -> Result<Vec<(String, image::DynamicImage)>, String> {
+        vision::analyzer::deploy::get_oper_avatars(&self.res_dir, name)
+    }
+
+    /// 分析部署卡片，返回 [`DeployAnalyzerOutput`]
+    /// - `roi`: 只在该区域（1920x1080 下）内查找部署卡片，[`None`] 时为全屏
+    /// - `use_cache`: 是否使用缓存中的屏幕内容（见 [`AAH::screen_cache_or_cap`]），否则截取当前帧
+    pub fn analyze_deploy(
+        &self,
+        roi: Option<Rect>,
+        use_cache: bool,
+    ) -> Result<DeployAnalyzerOutput, String> {
+        let mut analyzer = DeployAnalyzer::new().use_cache(use_cache);
+        if let Some(roi) = roi {
+            analyzer = analyzer.roi(roi);
+        }
         analyzer.analyze(self)
     }
 
+    /// 设置当前关卡地图的地块坐标转换，用于 [`AAH::deploy_operator`]
+    ///
+    /// 部署时游戏处于侧视角，需要使用侧视角的转换（比如 `TileTransform::new(width, height, true)`）
+    pub fn set_tile_transform(&self, transform: TileTransform) {
+        *self.tile_transform.lock().unwrap() = Some(transform);
+    }
+
+    /// 将干员 `name` 部署到地块 `tile`（`(col, row)`），朝向 `facing`
+    ///
+    /// 通过 [`DeployAnalyzer`] 找到干员的部署卡片，按住卡片拖动到地块上，再划向朝向的方向。
+    /// 找不到卡片、卡片不可用或未设置地块坐标转换（见 [`AAH::set_tile_transform`]）时返回错误
+    pub fn deploy_operator<S: AsRef<str>>(
+        &self,
+        name: S,
+        tile: (u32, u32),
+        facing: Direction,
+    ) -> Result<(), String> {
+        let name = name.as_ref();
+
+        let tile_pos = self
+            .tile_transform
+            .lock()
+            .unwrap()
+            .as_ref()
+            .ok_or("[deploy_operator]: tile transform is not set".to_string())?
+            .tile_to_screen(tile);
+
+        let output = DeployAnalyzer::new()
+            .with_opers(vec![name])
+            .annotate(false)
+            .analyze(self)?;
+        let card = output
+            .deploy_cards
+            .iter()
+            .find(|card| card.oper_name.as_deref() == Some(name))
+            .ok_or(format!(
+                "[deploy_operator]: deploy card of {name:?} not found"
+            ))?;
+        if !card.available {
+            return Err(format!(
+                "[deploy_operator]: deploy card of {name:?} is not available"
+            ));
+        }
+
+        // 卡片位置为屏幕坐标，地块位置为 1920x1080 下的坐标
+        let calibration = self.calibration();
+        let card_pos = (
+            card.rect.x + card.rect.width / 2,
+            card.rect.y + card.rect.height / 2,
+        );
+        let (dx, dy) = match facing {
+            Direction::Up => (0, -DEPLOY_FACING_DISTANCE),
+            Direction::Down => (0, DEPLOY_FACING_DISTANCE),
+            Direction::Left => (-DEPLOY_FACING_DISTANCE, 0),
+            Direction::Right => (DEPLOY_FACING_DISTANCE, 0),
+        };
+        let tile_pos = calibration.template_point_to_screen(tile_pos);
+        let (dx, dy) = calibration.template_offset_to_screen((dx, dy));
+        let direction_pos = (tile_pos.0 as i32 + dx, tile_pos.1 as i32 + dy);
+
+        println!("[deploy_operator]: deploying {name:?} to {tile:?} facing {facing:?}");
+        self.controller
+            .press_and_drag(
+                card_pos,
+                tile_pos,
+                direction_pos,
+                Duration::from_millis(DEFAULT_DEPLOY_HOLD_MS),
+            )
+            .map_err(|err| format!("{err}"))
+    }
+
+    /// 点击位于 `pos`（1920x1080 下）的已部署干员，识别弹出的干员菜单中的按钮，见 [`UnitMenuAnalyzer`]
+    ///
+    /// 点击没有命中干员（菜单没有打开）时返回错误
+    pub fn open_unit_menu(&self, pos: (u32, u32)) -> Result<UnitMenuAnalyzerOutput, String> {
+        self.controller
+            .click_scaled(pos.0, pos.1)
+            .map_err(|err| format!("{err}"))?;
+        std::thread::sleep(UNIT_MENU_OPEN_DELAY);
+
+        let output = UnitMenuAnalyzer::new().around(pos).analyze(self)?;
+        if !output.is_open() {
+            return Err(format!(
+                "[open_unit_menu]: menu of the unit at {pos:?} didn't open"
+            ));
+        }
+        Ok(output)
+    }
+
+    /// 释放位于 `pos`（1920x1080 下）的已部署干员的技能
+    ///
+    /// 通过 [`AAH::open_unit_menu`] 打开干员菜单后点击技能按钮，
+    /// 没有技能按钮（技能未就绪或为被动技能）时关闭菜单并返回错误
+    pub fn use_skill(&self, pos: (u32, u32)) -> Result<(), String> {
+        let output = self.open_unit_menu(pos)?;
+        let Some(skill) = output.skill else {
+            self.controller
+                .press_esc()
+                .map_err(|err| format!("{err}"))?;
+            return Err(format!(
+                "[use_skill]: skill of the unit at {pos:?} is not ready"
+            ));
+        };
+        println!("[use_skill]: using skill of the unit at {pos:?}");
+        self.controller
+            .click_in_rect(skill)
+            .map_err(|err| format!("{err}"))
+    }
+
+    /// 撤退位于 `pos`（1920x1080 下）的已部署干员，通过 [`AAH::open_unit_menu`] 打开干员菜单后点击撤退按钮
+    pub fn retreat_unit(&self, pos: (u32, u32)) -> Result<(), String> {
+        let output = self.open_unit_menu(pos)?;
+        let retreat = output.retreat.ok_or(format!(
+            "[retreat_unit]: retreat button of the unit at {pos:?} not found"
+        ))?;
+        println!("[retreat_unit]: retreating the unit at {pos:?}");
+        self.controller
+            .click_in_rect(retreat)
+            .map_err(|err| format!("{err}"))
+    }
+
+    /// 截取当前帧的屏幕内容，识别当前所处的 [`Scene`]，无法识别时返回 [`None`]
+    pub fn current_scene(&self) -> Option<Scene> {
+        let mut analyzer = SceneAnalyzer::default();
+        analyzer.analyze(self).ok().and_then(|output| output.scene)
+    }
+
+    /// 等待画面停止变化（比如动画结束、加载完成），见 [`StableScreenAnalyzer`]
+    ///
+    /// 画面通过 [`AAH::capture_stream`] 以 [`STABLE_SCREEN_FPS`] 的帧率截取，每一帧都会更新屏幕缓存，
+    /// 超过 `timeout` 仍未静止时返回错误
+    pub fn wait_until_stable(&self, timeout: Duration) -> Result<(), String> {
+        let start = Instant::now();
+        let mut analyzer = StableScreenAnalyzer::new();
+        for screen in self.capture_stream(STABLE_SCREEN_FPS) {
+            let screen = screen.map_err(|err| format!("{err}"))?;
+            *self.screen_cache.lock().unwrap() = Some(screen.clone());
+            if analyzer.analyze_image(self, &screen)?.stable {
+                return Ok(());
+            }
+            if start.elapsed() >= timeout {
+                break;
+            }
+        }
+        Err(format!("[AAH]: screen is not stable after {:?}", timeout))
+    }
+
+    /// 设置操作的游戏客户端的包名，默认为国服的 [`ARKNIGHTS_PACKAGE`]，
+    /// 其他客户端的包名见 [`GameClient`](controller::GameClient)
+    pub fn set_game_package<S: AsRef<str>>(&self, package: S) {
+        *self.game_package.lock().unwrap() = package.as_ref().to_string();
+    }
+
+    /// 操作的游戏客户端的包名，见 [`AAH::set_game_package`]
+    pub fn game_package(&self) -> String {
+        self.game_package.lock().unwrap().clone()
+    }
+
+    /// 启动游戏 `package`，并等待进入标题界面（[`Scene::Start`]）
+    ///
+    /// 游戏已经在运行时只会切换到前台，此时识别到 [`Scene::Main`] 也视为启动完成。
+    /// 没有安装 `package` 或超过 [`GAME_LAUNCH_TIMEOUT`] 仍未进入时返回错误
+    pub fn launch_game<S: AsRef<str>>(&self, package: S) -> Result<(), String> {
+        let package = package.as_ref();
+        println!("[AAH]: launching {package}...");
+        self.controller
+            .launch_app(package)
+            .map_err(|err| format!("[AAH]: failed to launch {package}: {err}"))?;
+        // dry run 时不会真正启动
+        if self.dry_run() {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        loop {
+            if let Some(scene @ (Scene::Start | Scene::Main)) = self.current_scene() {
+                println!("[AAH]: {package} launched, current scene: {scene:?}");
+                return Ok(());
+            }
+            if start.elapsed() >= GAME_LAUNCH_TIMEOUT {
+                return Err(format!(
+                    "[AAH]: title screen of {package} not found after {:?}",
+                    GAME_LAUNCH_TIMEOUT
+                ));
+            }
+            std::thread::sleep(GAME_LAUNCH_POLL_INTERVAL);
+        }
+    }
+
+    /// 强制停止游戏（见 [`AAH::game_package`]）后重新启动，见 [`AAH::launch_game`]
+    pub fn restart_game(&self) -> Result<(), String> {
+        let package = self.game_package();
+        println!("[AAH]: restarting {package}...");
+        self.controller
+            .stop_app(&package)
+            .map_err(|err| format!("[AAH]: failed to stop {package}: {err}"))?;
+        self.launch_game(package)
+    }
+
+    /// 确保设备上有可以执行的 minitouch，需要时从 `res_dir/minitouch` 中推送设备 abi 对应的二进制文件，
+    /// 见 [`ensure_minitouch`](controller::minitouch::ensure_minitouch)
+    pub fn ensure_minitouch(&self) -> Result<(), String> {
+        self.controller
+            .ensure_minitouch(&self.res_dir)
+            .map_err(|err| format!("[AAH]: failed to ensure minitouch: {err}"))
+    }
+
+    /// 确认游戏（见 [`AAH::game_package`]）在前台，避免点击落在桌面或其他应用上
+    ///
+    /// 不在前台时（比如游戏闪退回到了桌面）启动游戏，并等待其切换到前台，
+    /// 超过 [`GAME_FOREGROUND_TIMEOUT`] 仍未切换时返回错误
+    pub fn ensure_game_foreground(&self) -> Result<(), String> {
+        let game_package = self.game_package();
+        let foreground_package = || {
+            self.controller
+                .current_foreground_package()
+                .map_err(|err| format!("[AAH]: failed to get the foreground package: {err}"))
+        };
+
+        let package = foreground_package()?;
+        if package == game_package {
+            return Ok(());
+        }
+        println!("[AAH]: foreground app is {package}, launching {game_package}...");
+        self.controller
+            .launch_app(&game_package)
+            .map_err(|err| format!("[AAH]: failed to launch {game_package}: {err}"))?;
+        // dry run 时不会真正启动
+        if self.dry_run() {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        while start.elapsed() < GAME_FOREGROUND_TIMEOUT {
+            std::thread::sleep(GAME_FOREGROUND_POLL_INTERVAL);
+            if foreground_package()? == game_package {
+                return Ok(());
+            }
+        }
+        Err(format!(
+            "[AAH]: {game_package} is not in the foreground after {:?}",
+            GAME_FOREGROUND_TIMEOUT
+        ))
+    }
+
+    /// 识别当前所处的页面（页面由 [`NavigateConfig`] 定义），无法识别时返回 [`None`]
+    pub fn current_page(&self) -> Option<String> {
+        let navigate_config = self.navigate_config.read().unwrap();
+        navigate_config.pages().into_iter().find(|page| {
+            navigate_config
+                .page_template(page)
+                .map(|template| BestMatchAnalyzer::new(template).analyze(self).is_ok())
+                .unwrap_or(false)
+        })
+    }
+
+    /// 从当前页面导航到 `target` 页面
+    ///
+    /// 在由 [`NavigateConfig`] 构建的导航图上寻找最短路径并逐步执行，
+    /// 每执行一步都会重新识别当前页面，若与预期不符则从识别到的页面重新寻路；
+    /// 没有配置模板的页面无法识别，此时认为已经到达了该页面，详见 [`navigate_with`]。
+    /// 无法识别页面时返回的错误中包含已经走过的路径
+    pub fn navigate_to<S: AsRef<str>>(&self, target: S) -> Result<(), String> {
+        navigate_with(
+            &self.navigate_config,
+            target.as_ref(),
+            |step| step.task.run(self).map(|_| ()),
+            || self.current_page(),
+        )
+        .map(|_| ())
+    }
+
+    /// 使用 `analyzer` 持续分析战斗画面，直到战斗结束、`cancel` 被置为 `true` 或超过 `max_duration`
+    ///
+    /// 画面通过 [`AAH::capture_stream`] 以 [`BATTLE_ANALYZER_FPS`] 的帧率截取，每一帧都会更新屏幕缓存。
+    ///
+    /// 画面中没有部署卡片时，会通过 [`ResultAnalyzer`] 检查是否已进入结算画面，
+    /// 识别到战斗结果后返回 [`BattleState::Completed`]；理智回复提示覆盖结算画面时会先将其关闭。
+    /// 资源目录中缺少结算画面的模板（见 [`ResultAnalyzer::missing_templates`]）时不会检查结算画面，
+    /// 只能通过 `cancel` 或 `max_duration` 结束
+    ///
+    /// 单帧分析失败时不会中断，而是产生 [`TaskEvt::BattleAnalyzerError`]。
+    /// 返回最后一次分析得到的 [`BattleState`]，超过 `max_duration` 时返回错误
+    pub fn start_battle_analyzer(
+        &self,
+        analyzer: BattleAnalyzer,
+        cancel: Arc<AtomicBool>,
+        max_duration: Duration,
+    ) -> Result<BattleState, String> {
+        self.start_battle_analyzer_with_timeline(analyzer, cancel, max_duration)
+            .map(|(battle_state, _)| battle_state)
+    }
+
+    /// 同 [`AAH::start_battle_analyzer`]，同时将每一帧的分析结果汇总为 [`BattleTimeline`] 一并返回
+    ///
+    /// 每个新的事件也会作为 [`TaskEvt::BattleEvent`] 发出
+    pub fn start_battle_analyzer_with_timeline(
+        &self,
+        mut analyzer: BattleAnalyzer,
+        cancel: Arc<AtomicBool>,
+        max_duration: Duration,
+    ) -> Result<(BattleState, BattleTimeline), String> {
+        let start = Instant::now();
+        let missing_templates = ResultAnalyzer::missing_templates(self);
+        let mut result_analyzer = if missing_templates.is_empty() {
+            Some(ResultAnalyzer::new())
+        } else {
+            println!(
+                "[AAH]: result templates {:?} are missing, result detection is disabled",
+                missing_templates
+            );
+            None
+        };
+        let mut battle_state = BattleState::Unknown;
+        let mut timeline = BattleTimeline::new();
+        let emit_events = |events: Vec<_>| {
+            for event in events {
+                self.task_evt.emit(TaskEvt::BattleEvent(event));
+            }
+        };
+        for screen in self.capture_stream(BATTLE_ANALYZER_FPS) {
+            if cancel.load(Ordering::Relaxed) {
+                println!("[AAH]: battle analyzer cancelled");
+                return Ok((battle_state, timeline));
+            }
+            if start.elapsed() >= max_duration {
+                return Err(format!(
+                    "[AAH]: battle analyzer exceeded max duration {:?}",
+                    max_duration
+                ));
+            }
+
+            let screen = match screen {
+                Ok(screen) => screen,
+                Err(err) => {
+                    println!("[AAH]: battle analyzer error: {err}");
+                    self.task_evt
+                        .emit(TaskEvt::BattleAnalyzerError(format!("{err}")));
+                    continue;
+                }
+            };
+            *self.screen_cache.lock().unwrap() = Some(screen.clone());
+
+            match analyzer.analyze_image(self, &screen) {
+                Ok(output) => {
+                    battle_state = output.battle_state;
+                    emit_events(timeline.record(start.elapsed(), &output));
+                }
+                Err(err) => {
+                    println!("[AAH]: battle analyzer error: {err}");
+                    self.task_evt.emit(TaskEvt::BattleAnalyzerError(err));
+                }
+            }
+
+            // 没有部署卡片时，可能已经进入了结算画面
+            if let (BattleState::Unknown, Some(result_analyzer)) =
+                (battle_state, result_analyzer.as_mut())
+            {
+                match result_analyzer.analyze_image(self, &screen) {
+                    Ok(ResultAnalyzerOutput {
+                        result: Some(result),
+                        ..
+                    }) => {
+                        battle_state = BattleState::Completed(result);
+                        emit_events(timeline.record_state(start.elapsed(), battle_state));
+                    }
+                    Ok(ResultAnalyzerOutput {
+                        sanity_prompt: true,
+                        ..
+                    }) => {
+                        // 关闭覆盖在结算画面上的理智回复提示
+                        if let Err(err) = self.controller.press_esc() {
+                            println!("[AAH]: failed to close sanity prompt: {err}");
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        println!("[AAH]: result analyzer error: {err}");
+                        self.task_evt.emit(TaskEvt::BattleAnalyzerError(err));
+                    }
+                }
+            }
+            if matches!(battle_state, BattleState::Completed(_)) {
+                break;
+            }
+        }
+        Ok((battle_state, timeline))
+    }
+
+    /// OCR 引擎，初始化失败时返回 [`OcrError::Unavailable`]，其中包含初始化时的 [`OcrInitError`]
+    pub fn try_ocr_engine(&self) -> Result<&OcrEngine, OcrError> {
+        match (&self.ocr_engine, &self.ocr_init_error) {
+            (Some(engine), _) => Ok(engine),
+            (None, Some(err)) => Err(OcrError::Unavailable(err.clone())),
+            (None, None) => Err(OcrError::Failed(
+                "ocr engine is not initialized".to_string(),
+            )),
+        }
+    }
+
+    /// 截取当前帧的屏幕内容，通过 OCR 读取战斗中的部署费用
+    ///
+    /// 费用回复动画中的帧可能无法被正确识别，此时返回上一次成功读取的值
+    pub fn read_battle_cost(&self) -> Result<u32, OcrError> {
+        let engine = self.try_ocr_engine()?;
+        let screen = self
+            .controller
+            .screencap()
+            .map_err(|err| format!("{err}"))?;
+
+        let mut last_battle_cost = self.last_battle_cost.lock().unwrap();
+        match read_battle_cost(engine, &screen) {
+            Ok(cost) => {
+                *last_battle_cost = Some(cost);
+                Ok(cost)
+            }
+            Err(err) => last_battle_cost.ok_or(OcrError::Failed(err)),
+        }
+    }
+
+    /// 截取当前帧的屏幕内容，通过 OCR 读取主页或关卡选择界面中的理智 `(当前, 上限)`，见 [`SanityAnalyzer`]
+    ///
+    /// 当前值可能超过上限（比如使用了理智药剂）
+    pub fn read_sanity(&self) -> Result<(u32, u32), OcrError> {
+        self.try_ocr_engine()?;
+        let output = SanityAnalyzer::new().analyze(self)?;
+        Ok((output.current, output.max))
+    }
+
+    /// 识别屏幕中 `rect`（1920x1080 下）区域内的文字
+    ///
+    /// 优先使用缓存中的屏幕内容，没有缓存时截取当前帧。
+    /// 若 [`OcrConfig`] 中指定了 `alphabet`，识别结果会经过其过滤
+    pub fn ocr_region(&self, rect: Rect) -> Result<String, OcrError> {
+        self.ocr_region_inner(&rect, self.ocr_config.alphabet.as_deref())
+    }
+
+    /// 同 [`AAH::ocr_region`]，但只保留 `whitelist` 中的字符，详见 [`vision::ocr::apply_whitelist`]
+    pub fn ocr_region_with_whitelist<S: AsRef<str>>(
+        &self,
+        rect: Rect,
+        whitelist: S,
+    ) -> Result<String, OcrError> {
+        self.ocr_region_inner(&rect, Some(whitelist.as_ref()))
+    }
+
+    /// 识别屏幕中 `rect`（1920x1080 下）区域内的所有整数
+    pub fn ocr_region_numbers(&self, rect: Rect) -> Result<Vec<i64>, OcrError> {
+        let text = self.ocr_region_inner(&rect, Some(&format!("{DIGITS}-")))?;
+        Ok(parse_numbers(&text))
+    }
+
+    fn ocr_region_inner(&self, rect: &Rect, whitelist: Option<&str>) -> Result<String, OcrError> {
+        let engine = self.try_ocr_engine()?;
+        let screen = self.screen_cache_or_cap()?;
+        Ok(ocr_region(engine, &screen, rect, whitelist)?)
+    }
+
     /// 获取所有任务名称
     pub fn get_tasks(&self) -> Vec<String> {
-        self.task_config.0.keys().map(|s| s.to_string()).collect()
+        self.task_config
+            .read()
+            .unwrap()
+            .0
+            .keys()
+            .map(|s| s.to_string())
+            .collect()
+    }
+}
+
+/// 检查 `task_config` 和 `navigate_config` 中的引用，输出并返回所有错误，见 [`TaskConfig::validate`]
+fn validate_resources(
+    res_dir: &Path,
+    task_config: &TaskConfig,
+    navigate_config: &NavigateConfig,
+) -> Vec<ConfigError> {
+    let errors = task_config
+        .validate(res_dir, navigate_config)
+        .err()
+        .unwrap_or_default();
+    if !errors.is_empty() {
+        println!("[AAH]: found {} errors in resources:", errors.len());
+        for err in &errors {
+            println!("  - {err}");
+        }
+    }
+    errors
+}
+
+/// 从 `res_dir` 加载配置，任务、导航和弹窗配置全部加载成功后才替换 `task_config`、`navigate_config` 和 `popup_config`
+///
+/// 新的任务配置和导航配置中的引用会一并检查，见 [`validate_resources`]
+fn reload_resources(
+    res_dir: &Path,
+    task_config: &RwLock<TaskConfig>,
+    navigate_config: &RwLock<NavigateConfig>,
+    popup_config: &RwLock<PopupConfig>,
+    config_errors: &RwLock<Vec<ConfigError>>,
+    task_evt: &TaskEvtBroadcaster,
+) -> Result<Vec<ConfigError>, String> {
+    let res = TaskConfig::load(res_dir)
+        .map_err(|err| format!("failed to load task config: {err}"))
+        .and_then(|new_task_config| {
+            let new_navigate_config = NavigateConfig::load(res_dir)
+                .map_err(|err| format!("failed to load navigate config: {err}"))?;
+            let new_popup_config = PopupConfig::load(res_dir)
+                .map_err(|err| format!("failed to load popup config: {err}"))?;
+            Ok((new_task_config, new_navigate_config, new_popup_config))
+        });
+    match res {
+        Ok((new_task_config, new_navigate_config, new_popup_config)) => {
+            let new_config_errors =
+                validate_resources(res_dir, &new_task_config, &new_navigate_config);
+            *task_config.write().unwrap() = new_task_config;
+            *navigate_config.write().unwrap() = new_navigate_config;
+            *popup_config.write().unwrap() = new_popup_config;
+            *config_errors.write().unwrap() = new_config_errors.clone();
+            println!("[AAH]: resources reloaded");
+            task_evt.emit(TaskEvt::ResourcesReloaded {
+                config_errors: new_config_errors.clone(),
+            });
+            Ok(new_config_errors)
+        }
+        Err(err) => {
+            println!("[AAH]: failed to reload resources: {err}");
+            task_evt.emit(TaskEvt::ResourcesReloadFailed(err.clone()));
+            Err(err)
+        }
     }
 }
 
@@ -142,6 +1151,159 @@ mod tests {
         println!("{:?}", aah.get_tasks());
     }
 
+    #[test]
+    fn test_connect_missing_ocr_model() {
+        let ocr_config = OcrConfig {
+            detection_model: Some("not-exist.rten".into()),
+            ..Default::default()
+        };
+        let Err(err) =
+            AAH::connect_with_ocr_config("127.0.0.1:16384", "../../resources", ocr_config)
+        else {
+            panic!("expected an error for a missing ocr model");
+        };
+        println!("{err}");
+        assert!(err
+            .downcast_ref::<OcrInitError>()
+            .is_some_and(|err| matches!(err, OcrInitError::ModelNotFound(path) if path.ends_with("not-exist.rten"))));
+    }
+
+    #[test]
+    fn test_match_templates_in_dir() {
+        let aah = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
+        let matched = aah
+            .match_templates_in_dir("../../resources/templates/1920x1080", 0.9)
+            .unwrap();
+        println!("{:?}", matched);
+        assert!(matched.windows(2).all(|w| w[0].2 >= w[1].2));
+
+        // 从当前屏幕截取 (800, 400) 处的一块作为模板（1920x1080 下），它一定能被找到
+        let screen = aah.screen_cap_and_cache().unwrap();
+        let scale = screen.height() as f32 / controller::DEFAULT_HEIGHT as f32;
+        let (x, y) = ((800.0 * scale) as u32, (400.0 * scale) as u32);
+        let template = screen
+            .crop_imm(x, y, (200.0 * scale) as u32, (150.0 * scale) as u32)
+            .resize_exact(200, 150, image::imageops::FilterType::Lanczos3);
+        let dir = std::env::temp_dir().join("aah_test_match_templates_in_dir");
+        std::fs::create_dir_all(&dir).unwrap();
+        template.save(dir.join("screen.png")).unwrap();
+
+        let matched = aah.match_templates_in_dir(&dir, 0.9).unwrap();
+        println!("{:?}", matched);
+        let (name, rect, _) = &matched[0];
+        assert_eq!(name, "screen.png");
+        assert!(rect.x.abs_diff(x) <= 2 && rect.y.abs_diff(y) <= 2);
+    }
+
+    #[test]
+    fn test_run_task_with() {
+        let aah = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
+        let rx = aah.subscribe_task_evt();
+        let res = aah.run_task_with(
+            "press_esc",
+            RunOptions {
+                max_retries: 3,
+                step_timeout: Some(Duration::from_secs(5)),
+                min_confidence: Some(0.9),
+            },
+        );
+        println!("{:?}", res);
+        println!("{:?}", rx.try_iter().collect::<Vec<_>>());
+        assert_eq!(aah.run_options().max_retries, 0);
+    }
+
+    #[test]
+    fn test_start_battle_analyzer() {
+        let aah = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
+        let rx = aah.subscribe_task_evt();
+        let cancel = Arc::new(AtomicBool::new(false));
+        {
+            let cancel = cancel.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_secs(5));
+                cancel.store(true, Ordering::Relaxed);
+            });
+        }
+        let (state, timeline) = aah
+            .start_battle_analyzer_with_timeline(
+                BattleAnalyzer::new(),
+                cancel,
+                Duration::from_secs(60),
+            )
+            .unwrap();
+        println!("{:?}", state);
+        println!("{}", timeline.to_json());
+        println!("{:?}", rx.try_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_deploy_operator() {
+        let aah = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
+        assert!(aah
+            .deploy_operator("char_102_texas", (3, 2), Direction::Right)
+            .is_err());
+        aah.set_tile_transform(TileTransform::new(9, 6, true));
+        println!(
+            "{:?}",
+            aah.deploy_operator("char_102_texas", (3, 2), Direction::Right)
+        );
+    }
+
+    #[test]
+    fn test_reload_resources_keeps_good_config() {
+        let res_dir = std::env::temp_dir().join("aah-test-reload-resources");
+        std::fs::create_dir_all(&res_dir).unwrap();
+        for file in ["tasks.toml", "navigates.toml", "popups.toml"] {
+            std::fs::copy(Path::new("../../resources").join(file), res_dir.join(file)).unwrap();
+        }
+
+        let task_config = RwLock::new(TaskConfig::load(&res_dir).unwrap());
+        let navigate_config = RwLock::new(NavigateConfig::load(&res_dir).unwrap());
+        let popup_config = RwLock::new(PopupConfig::default());
+        let config_errors = RwLock::new(vec![]);
+        let task_cnt = task_config.read().unwrap().0.len();
+        let task_evt = TaskEvtBroadcaster::default();
+        let rx = task_evt.subscribe();
+
+        // 临时目录中没有模板，引用的模板都不存在
+        let errors = reload_resources(
+            &res_dir,
+            &task_config,
+            &navigate_config,
+            &popup_config,
+            &config_errors,
+            &task_evt,
+        )
+        .unwrap();
+        assert!(errors
+            .iter()
+            .any(|err| matches!(err, ConfigError::TemplateNotFound { .. })));
+        assert_eq!(*config_errors.read().unwrap(), errors);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(TaskEvt::ResourcesReloaded { config_errors }) if config_errors == errors
+        ));
+        assert!(!popup_config.read().unwrap().popups.is_empty());
+
+        std::fs::write(res_dir.join("tasks.toml"), "[broken").unwrap();
+        assert!(reload_resources(
+            &res_dir,
+            &task_config,
+            &navigate_config,
+            &popup_config,
+            &config_errors,
+            &task_evt
+        )
+        .is_err());
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(TaskEvt::ResourcesReloadFailed(_))
+        ));
+        assert_eq!(task_config.read().unwrap().0.len(), task_cnt);
+
+        std::fs::remove_dir_all(&res_dir).unwrap();
+    }
+
     fn save_screenshot<P: AsRef<Path>, S: AsRef<str>>(path: P, name: S) {
         let path = path.as_ref();
         let name = name.as_ref();
@@ -149,7 +1311,7 @@ mod tests {
         let target_path = path.join(name);
         println!("saving screenshot to {:?}", target_path);
 
-        let mut aah = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
+        let aah = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
 
         aah.update_screen().unwrap();
         let screen = aah.get_screen().unwrap();
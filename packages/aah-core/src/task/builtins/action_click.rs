@@ -46,6 +46,11 @@ impl ActionClick {
     pub fn new(x: u32, y: u32, wrapper: Option<GenericTaskWrapper>) -> Self {
         Self { x, y, wrapper }
     }
+
+    /// 点击的位置（1920x1080 下）
+    pub fn pos(&self) -> (u32, u32) {
+        (self.x, self.y)
+    }
 }
 
 impl Task for ActionClick {
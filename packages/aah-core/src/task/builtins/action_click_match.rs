@@ -22,6 +22,10 @@ impl ActionClickMatch {
             wrapper,
         }
     }
+
+    pub fn match_task(&self) -> &MatchTask {
+        &self.match_task
+    }
 }
 
 impl Task for ActionClickMatch {
@@ -72,4 +76,4 @@ mod test {
             println!("{:?}", task);
         }
     }
-}
\ No newline at end of file
+}
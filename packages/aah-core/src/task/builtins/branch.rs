@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    task::{condition::TemplatePresent, Task},
+    AAH,
+};
+
+use super::BuiltinTask;
+
+#[cfg(test)]
+mod test {
+    use crate::task::builtins::ActionPressEsc;
+
+    use super::*;
+
+    #[test]
+    fn test_serde() {
+        // Without else
+        {
+            let task = If::new(
+                TemplatePresent::new("notice.png", None),
+                BuiltinTask::ActionPressEsc(ActionPressEsc::new(None)),
+                None,
+            );
+            let task = toml::to_string_pretty(&task).unwrap();
+            println!("{:?}", task);
+            let task = toml::from_str::<If>(&task).unwrap();
+            println!("{:?}", task);
+        }
+        // With else
+        {
+            let task = If::new(
+                TemplatePresent::new("notice.png", Some(0.9)),
+                BuiltinTask::ActionPressEsc(ActionPressEsc::new(None)),
+                Some(BuiltinTask::ByName(crate::task::builtins::ByName::new(
+                    "press_home",
+                    None,
+                ))),
+            );
+            let task = toml::to_string_pretty(&task).unwrap();
+            println!("{:?}", task);
+            let task = toml::from_str::<If>(&task).unwrap();
+            println!("{:?}", task);
+            assert!(task.else_task.is_some());
+        }
+    }
+}
+
+/// 根据条件执行不同的任务
+/// - `condition`: 判断条件
+/// - `then`: 条件成立时执行的任务
+/// - `else`: 条件不成立时执行的任务，未指定时不执行任何任务
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct If {
+    condition: TemplatePresent,
+    then: Box<BuiltinTask>,
+    #[serde(rename = "else", default)]
+    else_task: Option<Box<BuiltinTask>>,
+}
+
+impl If {
+    pub fn new(
+        condition: TemplatePresent,
+        then: BuiltinTask,
+        else_task: Option<BuiltinTask>,
+    ) -> Self {
+        Self {
+            condition,
+            then: Box::new(then),
+            else_task: else_task.map(Box::new),
+        }
+    }
+
+    pub fn condition(&self) -> &TemplatePresent {
+        &self.condition
+    }
+
+    pub fn then(&self) -> &BuiltinTask {
+        &self.then
+    }
+
+    pub fn else_task(&self) -> Option<&BuiltinTask> {
+        self.else_task.as_deref()
+    }
+}
+
+impl Task for If {
+    type Err = String;
+    fn run(&self, aah: &AAH) -> Result<Self::Res, Self::Err> {
+        let present = self
+            .condition
+            .eval(aah)
+            .map_err(|err| format!("[If]: failed to evaluate the condition: {err}"))?;
+        if present {
+            println!(
+                "[If]: {:?} is present, executing then",
+                self.condition.template
+            );
+            self.then.run(aah)
+        } else if let Some(else_task) = &self.else_task {
+            println!(
+                "[If]: {:?} is absent, executing else",
+                self.condition.template
+            );
+            else_task.run(aah)
+        } else {
+            Ok(())
+        }
+    }
+}
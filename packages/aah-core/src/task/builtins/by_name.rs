@@ -16,6 +16,10 @@ impl ByName {
         let name = name.as_ref().to_string();
         ByName { name, wrapper }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 impl Task for ByName {
@@ -3,23 +3,31 @@ mod action_click_match;
 mod action_press_esc;
 mod action_press_home;
 mod action_swipe;
+mod branch;
 mod by_name;
 
 mod multi;
 mod navigate;
+mod repeat;
+mod scroll_until;
+mod wait_for;
 
 pub use action_click::ActionClick;
 pub use action_click_match::ActionClickMatch;
 pub use action_press_esc::ActionPressEsc;
 pub use action_press_home::ActionPressHome;
 pub use action_swipe::ActionSwipe;
+pub use branch::If;
 pub use by_name::ByName;
 pub use multi::Multi;
 pub use navigate::Navigate;
+pub use repeat::Repeat;
+pub use scroll_until::{ScrollDirection, ScrollUntil};
 use serde::{Deserialize, Serialize};
+pub use wait_for::WaitFor;
 
 use crate::{
-    task::{match_task::MatchTask, wrapper::GenericTaskWrapper},
+    task::{condition::TemplatePresent, match_task::MatchTask, wrapper::GenericTaskWrapper},
     AAH,
 };
 
@@ -50,6 +58,20 @@ pub fn test_tasks() -> Vec<(&'static str, BuiltinTask)> {
                 None,
             )),
         ),
+        (
+            "wait_for",
+            BuiltinTask::WaitFor(WaitFor::new("main_base.png", true, 10.0, 0.5, None, None)),
+        ),
+        (
+            "scroll_until",
+            BuiltinTask::ScrollUntil(ScrollUntil::new(
+                "main_base.png",
+                ScrollDirection::Down,
+                10,
+                600,
+                None,
+            )),
+        ),
         ("navigate_in", BuiltinTask::NavigateIn("name".to_string())),
         ("navigate_out", BuiltinTask::NavigateIn("name".to_string())),
         (
@@ -70,6 +92,25 @@ pub fn test_tasks() -> Vec<(&'static str, BuiltinTask)> {
                 None,
             )),
         ),
+        (
+            "if",
+            BuiltinTask::If(If::new(
+                TemplatePresent::new("notice.png", None),
+                BuiltinTask::ActionPressEsc(ActionPressEsc::new(None)),
+                None,
+            )),
+        ),
+        (
+            "repeat",
+            BuiltinTask::Repeat(
+                Repeat::new(
+                    BuiltinTask::ByName(ByName::new("press_esc", None)),
+                    Some(3),
+                    Some(TemplatePresent::new("main_base.png", None)),
+                )
+                .unwrap(),
+            ),
+        ),
     ]
 }
 
@@ -77,28 +118,113 @@ pub fn test_tasks() -> Vec<(&'static str, BuiltinTask)> {
 pub enum BuiltinTask {
     ByName(ByName),
     Multi(Multi),
+    If(If),
+    Repeat(Repeat),
     // Action
     ActionPressEsc(ActionPressEsc),
     ActionPressHome(ActionPressHome),
     ActionClick(ActionClick),
     ActionSwipe(ActionSwipe),
     ActionClickMatch(ActionClickMatch),
+    WaitFor(WaitFor),
+    ScrollUntil(ScrollUntil),
     // Navigate
     NavigateIn(String),
     NavigateOut(String),
 }
 
+/// 任务中引用的外部资源
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskRef {
+    /// 模板文件名
+    Template(String),
+    /// 通过 [`ByName`] 引用的任务名
+    Task(String),
+    /// 通过 `NavigateIn`/`NavigateOut` 引用的导航名
+    Navigate(String),
+}
+
+impl BuiltinTask {
+    /// 递归收集任务（及其子任务）中引用的所有外部资源
+    pub fn refs(&self) -> Vec<TaskRef> {
+        match self {
+            BuiltinTask::ByName(task) => vec![TaskRef::Task(task.name().to_string())],
+            BuiltinTask::Multi(task) => task.tasks().iter().flat_map(|t| t.refs()).collect(),
+            BuiltinTask::If(task) => {
+                let mut refs = vec![TaskRef::Template(task.condition().template.clone())];
+                refs.extend(task.then().refs());
+                if let Some(else_task) = task.else_task() {
+                    refs.extend(else_task.refs());
+                }
+                refs
+            }
+            BuiltinTask::Repeat(task) => {
+                let mut refs = task.task().refs();
+                if let Some(until) = task.until() {
+                    refs.push(TaskRef::Template(until.template.clone()));
+                }
+                refs
+            }
+            BuiltinTask::ActionClickMatch(task) => match task.match_task() {
+                MatchTask::Template(template) => vec![TaskRef::Template(template.clone())],
+                MatchTask::Ocr(_) => vec![],
+            },
+            BuiltinTask::WaitFor(task) => vec![TaskRef::Template(task.template().to_string())],
+            BuiltinTask::ScrollUntil(task) => vec![TaskRef::Template(task.template().to_string())],
+            BuiltinTask::NavigateIn(name) | BuiltinTask::NavigateOut(name) => {
+                vec![TaskRef::Navigate(name.clone())]
+            }
+            BuiltinTask::ActionPressEsc(_)
+            | BuiltinTask::ActionPressHome(_)
+            | BuiltinTask::ActionClick(_)
+            | BuiltinTask::ActionSwipe(_) => vec![],
+        }
+    }
+}
+
+impl BuiltinTask {
+    /// 任务的简短描述，用于 [`TaskEvt::TaskStepStarted`](crate::task::TaskEvt::TaskStepStarted)
+    pub fn description(&self) -> String {
+        match self {
+            BuiltinTask::ByName(task) => format!("run task {:?}", task.name()),
+            BuiltinTask::Multi(task) => format!("run {} tasks", task.tasks().len()),
+            BuiltinTask::If(task) => format!("if {:?} is present", task.condition().template),
+            BuiltinTask::Repeat(task) => format!("repeat: {}", task.task().description()),
+            BuiltinTask::ActionPressEsc(_) => "press esc".to_string(),
+            BuiltinTask::ActionPressHome(_) => "press home".to_string(),
+            BuiltinTask::ActionClick(task) => format!("click {:?}", task.pos()),
+            BuiltinTask::ActionSwipe(_) => "swipe".to_string(),
+            BuiltinTask::ActionClickMatch(task) => match task.match_task() {
+                MatchTask::Template(template) => format!("click {template:?}"),
+                MatchTask::Ocr(text) => format!("click text {text:?}"),
+            },
+            BuiltinTask::WaitFor(task) => format!("wait for {:?}", task.template()),
+            BuiltinTask::ScrollUntil(task) => format!(
+                "scroll {:?} until {:?}",
+                task.direction(),
+                task.template()
+            ),
+            BuiltinTask::NavigateIn(name) => format!("navigate in {name:?}"),
+            BuiltinTask::NavigateOut(name) => format!("navigate out {name:?}"),
+        }
+    }
+}
+
 impl Task for BuiltinTask {
     type Err = String;
     fn run(&self, aah: &AAH) -> Result<Self::Res, Self::Err> {
         match self {
             BuiltinTask::ByName(task) => task.run(aah),
             BuiltinTask::Multi(task) => task.run(aah),
+            BuiltinTask::If(task) => task.run(aah),
+            BuiltinTask::Repeat(task) => task.run(aah),
             BuiltinTask::ActionPressEsc(task) => task.run(aah),
             BuiltinTask::ActionPressHome(task) => task.run(aah),
             BuiltinTask::ActionClick(task) => task.run(aah),
             BuiltinTask::ActionSwipe(task) => task.run(aah),
             BuiltinTask::ActionClickMatch(task) => task.run(aah),
+            BuiltinTask::WaitFor(task) => task.run(aah),
+            BuiltinTask::ScrollUntil(task) => task.run(aah).map(|_| ()),
             BuiltinTask::NavigateIn(navigate) => Navigate::NavigateIn(navigate.clone()).run(aah),
             BuiltinTask::NavigateOut(navigate) => Navigate::NavigateOut(navigate.clone()).run(aah),
         }
@@ -31,19 +31,29 @@ impl Multi {
             wrapper,
         }
     }
+
+    pub fn tasks(&self) -> &[BuiltinTask] {
+        &self.tasks
+    }
 }
 
 impl Task for Multi {
     type Err = String;
     fn run(&self, aah: &AAH) -> Result<Self::Res, Self::Err> {
         let mut res = Ok(());
-        for task in &self.tasks {
-            res = task.run(aah).map(|_| ());
+        for (index, task) in self.tasks.iter().enumerate() {
+            aah.dismiss_popups();
+            res = aah
+                .task_steps
+                .run_step(&aah.task_evt, index, task.description(), || {
+                    task.run(aah).map(|_| ())
+                });
             println!("{:?}", res);
             if res.is_err() && self.fail_fast {
                 break;
             }
         }
+        aah.dismiss_popups();
         res.map_err(|err| format!("[Multi]: error when executing task {:?}: {:?}", self, err))
     }
 }
@@ -16,7 +16,7 @@ impl Task for Navigate {
             Navigate::NavigateOut(name) => name,
         };
 
-        let navigate = aah.navigate_config.get_navigate(name)?;
+        let navigate = aah.navigate_config.read().unwrap().get_navigate(name)?;
 
         let task = match self {
             Navigate::NavigateIn(_) => navigate.enter_task,
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    task::{condition::TemplatePresent, Task},
+    AAH,
+};
+
+use super::BuiltinTask;
+
+#[cfg(test)]
+mod test {
+    use crate::task::builtins::ActionPressEsc;
+
+    use super::*;
+
+    #[test]
+    fn test_serde() {
+        let task = Repeat::new(
+            BuiltinTask::ActionPressEsc(ActionPressEsc::new(None)),
+            Some(3),
+            Some(TemplatePresent::new("main_base.png", None)),
+        )
+        .unwrap();
+        let task = toml::to_string_pretty(&task).unwrap();
+        println!("{:?}", task);
+        let task = toml::from_str::<Repeat>(&task).unwrap();
+        println!("{:?}", task);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Repeat::new(
+            BuiltinTask::ActionPressEsc(ActionPressEsc::new(None)),
+            None,
+            None
+        )
+        .is_err());
+
+        let res = toml::from_str::<Repeat>(
+            r#"
+            [task.ActionPressEsc]
+            "#,
+        );
+        println!("{:?}", res);
+        assert!(res.is_err());
+
+        let res = toml::from_str::<Repeat>(
+            r#"
+            times = 2
+            [task.ActionPressEsc]
+            "#,
+        );
+        println!("{:?}", res);
+        assert!(res.is_ok());
+    }
+}
+
+/// 重复执行任务，任务失败时直接返回错误
+/// - `task`: 要重复执行的任务
+/// - `times`: 最多执行的次数
+/// - `until`: 每次执行后判断的条件，条件成立时停止
+///
+/// `times` 与 `until` 至少需要指定一个
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(try_from = "RepeatDef")]
+pub struct Repeat {
+    task: Box<BuiltinTask>,
+    times: Option<u32>,
+    until: Option<TemplatePresent>,
+}
+
+#[derive(Deserialize)]
+struct RepeatDef {
+    task: Box<BuiltinTask>,
+    #[serde(default)]
+    times: Option<u32>,
+    #[serde(default)]
+    until: Option<TemplatePresent>,
+}
+
+impl TryFrom<RepeatDef> for Repeat {
+    type Error = String;
+
+    fn try_from(value: RepeatDef) -> Result<Self, Self::Error> {
+        let repeat = Self {
+            task: value.task,
+            times: value.times,
+            until: value.until,
+        };
+        repeat.validate()?;
+        Ok(repeat)
+    }
+}
+
+impl Repeat {
+    pub fn new(
+        task: BuiltinTask,
+        times: Option<u32>,
+        until: Option<TemplatePresent>,
+    ) -> Result<Self, String> {
+        let repeat = Self {
+            task: Box::new(task),
+            times,
+            until,
+        };
+        repeat.validate()?;
+        Ok(repeat)
+    }
+
+    /// 检查 `times` 与 `until` 至少指定了一个
+    pub fn validate(&self) -> Result<(), String> {
+        if self.times.is_none() && self.until.is_none() {
+            return Err("[Repeat]: at least one of `times` and `until` must be set".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn task(&self) -> &BuiltinTask {
+        &self.task
+    }
+
+    pub fn until(&self) -> Option<&TemplatePresent> {
+        self.until.as_ref()
+    }
+}
+
+impl Task for Repeat {
+    type Err = String;
+    fn run(&self, aah: &AAH) -> Result<Self::Res, Self::Err> {
+        self.validate()?;
+
+        let mut cnt = 0;
+        loop {
+            if self.times.map(|times| cnt >= times).unwrap_or(false) {
+                break;
+            }
+            self.task
+                .run(aah)
+                .map_err(|err| format!("[Repeat]: error at iteration {cnt}: {err}"))?;
+            cnt += 1;
+
+            if let Some(until) = &self.until {
+                let present = until
+                    .eval(aah)
+                    .map_err(|err| format!("[Repeat]: failed to evaluate until: {err}"))?;
+                if present {
+                    println!("[Repeat]: {:?} is present, stopped", until.template);
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
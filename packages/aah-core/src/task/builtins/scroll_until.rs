@@ -0,0 +1,409 @@
+use std::time::Duration;
+
+use aah_cv::{best_match, MatchTemplateMethod};
+use image::{DynamicImage, ImageBuffer, Luma};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controller::{DEFAULT_HEIGHT, DEFAULT_WIDTH},
+    task::{
+        wrapper::{GenericTaskWrapper, TaskWrapper},
+        Task,
+    },
+    vision::{
+        analyzer::{
+            multi_match::scale_template,
+            stable::{downsample, mean_abs_diff, DEFAULT_DIFF_THRESHOLD},
+        },
+        matcher::THRESHOLD,
+        utils::Rect,
+    },
+    AAH,
+};
+
+/// 每次滑动后等待列表停止滚动的时间
+pub const SCROLL_SETTLE_INTERVAL: Duration = Duration::from_millis(500);
+/// 每次滑动的时长
+pub const SCROLL_SWIPE_DURATION: Duration = Duration::from_millis(500);
+
+#[cfg(test)]
+mod test {
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    use super::*;
+
+    #[test]
+    fn test_serde() {
+        // Without wrapper
+        {
+            let task = ScrollUntil::new("oper_texas.png", ScrollDirection::Down, 5, 600, None);
+            let task = toml::to_string_pretty(&task).unwrap();
+            println!("{:?}", task);
+            let task = toml::from_str::<ScrollUntil>(&task).unwrap();
+            println!("{:?}", task);
+        }
+        // With wrapper
+        {
+            let task = ScrollUntil::new(
+                "oper_texas.png",
+                ScrollDirection::Left,
+                5,
+                600,
+                Some(GenericTaskWrapper::default()),
+            );
+            let task = toml::to_string_pretty(&task).unwrap();
+            println!("{:?}", task);
+            let task = toml::from_str::<ScrollUntil>(&task).unwrap();
+            println!("{:?}", task);
+        }
+        // Defaults
+        {
+            let task = toml::from_str::<ScrollUntil>(r#"template = "oper_texas.png""#).unwrap();
+            assert_eq!(task.direction, ScrollDirection::Down);
+            assert_eq!(task.max_swipes, default_max_swipes());
+            assert_eq!(task.swipe_distance, default_swipe_distance());
+        }
+    }
+
+    #[test]
+    fn test_swipe_points() {
+        let (start, end) = ScrollDirection::Down.swipe_points(600);
+        assert_eq!(start, (960, 840));
+        assert_eq!(end, (960, 240));
+        let (start, end) = ScrollDirection::Right.swipe_points(600);
+        assert_eq!(start, (1260, 540));
+        assert_eq!(end, (660, 540));
+        // 超出屏幕时限制在屏幕内
+        let (start, end) = ScrollDirection::Up.swipe_points(5000);
+        assert_eq!(start, (960, 0));
+        assert_eq!(end, (960, 1079));
+    }
+
+    /// 模拟一个纵向的列表，`target` 为目标在列表中的位置
+    struct List {
+        list: DynamicImage,
+        offset: u32,
+        target: Option<u32>,
+    }
+
+    impl List {
+        fn new(len: u32, target: Option<u32>) -> Self {
+            let list = RgbaImage::from_fn(64, len, |x, y| {
+                let v = ((x * 7 + y * 13) ^ (y / 3)) % 256;
+                Rgba([v as u8, v as u8, v as u8, 255])
+            });
+            Self {
+                list: DynamicImage::ImageRgba8(list),
+                offset: 0,
+                target,
+            }
+        }
+
+        fn capture(&self) -> DynamicImage {
+            self.list.view(0, self.offset, 64, 64).to_image().into()
+        }
+
+        fn find(&self) -> Option<Rect> {
+            self.target
+                .filter(|&target| target >= self.offset && target < self.offset + 64)
+                .map(|target| Rect {
+                    x: 0,
+                    y: target - self.offset,
+                    width: 10,
+                    height: 1,
+                })
+        }
+
+        fn swipe(&mut self, (start, end): ((u32, u32), (i32, i32))) {
+            let delta = (start.1 as i32 - end.1) / 10;
+            self.offset =
+                (self.offset as i32 + delta).clamp(0, self.list.height() as i32 - 64) as u32;
+        }
+    }
+
+    fn scroll(task: &ScrollUntil, list: List) -> (Result<Rect, String>, usize) {
+        let list = std::cell::RefCell::new(list);
+        let mut swipes = 0;
+        let res = task.scroll_until_with(
+            || Ok(list.borrow().capture()),
+            |_| Ok(list.borrow().find()),
+            |points| {
+                swipes += 1;
+                list.borrow_mut().swipe(points);
+                Ok(())
+            },
+        );
+        (res, swipes)
+    }
+
+    #[test]
+    fn test_scroll_until() {
+        // 每次滑动列表移动 60
+        let task = ScrollUntil::new("target.png", ScrollDirection::Down, 10, 600, None);
+
+        // 目标在第一屏
+        let (res, swipes) = scroll(&task, List::new(1000, Some(10)));
+        assert_eq!(res.unwrap().y, 10);
+        assert_eq!(swipes, 0);
+
+        // 滑动三次后出现
+        let (res, swipes) = scroll(&task, List::new(1000, Some(200)));
+        assert_eq!(res.unwrap().y, 200 - 180);
+        assert_eq!(swipes, 3);
+
+        // 列表到底后画面不再变化，提前结束
+        let (res, swipes) = scroll(&task, List::new(300, None));
+        println!("{:?}", res);
+        assert!(res.unwrap_err().contains("end of the list"));
+        assert_eq!(swipes, 5);
+
+        // 滑动次数用完
+        let (res, swipes) = scroll(&task, List::new(10000, None));
+        println!("{:?}", res);
+        assert!(res.unwrap_err().contains("10 swipes"));
+        assert_eq!(swipes, 10);
+
+        // 匹配出错时直接返回错误，不再滑动
+        let mut swipes = 0;
+        let res = task.scroll_until_with(
+            || Ok(List::new(1000, None).capture()),
+            |_| Err("match error".to_string()),
+            |_| {
+                swipes += 1;
+                Ok(())
+            },
+        );
+        assert_eq!(res.unwrap_err(), "match error");
+        assert_eq!(swipes, 0);
+    }
+
+    #[test]
+    fn test_find_template() {
+        let screen = List::new(1000, None).capture();
+        let template = screen.crop_imm(8, 20, 16, 12).to_luma32f();
+
+        let rect = find_template(&screen.to_luma32f(), &template, 0.9).unwrap();
+        assert_eq!(
+            rect,
+            Some(Rect {
+                x: 8,
+                y: 20,
+                width: 16,
+                height: 12
+            })
+        );
+
+        // 模板比画面大时返回错误，而不是视为没有找到
+        let template = ImageBuffer::new(128, 12);
+        let res = find_template(&screen.to_luma32f(), &template, 0.9);
+        println!("{:?}", res);
+        assert!(res.is_err());
+    }
+}
+
+/// 滚动列表的方向，即希望看到的内容所在的方向
+///
+/// 比如 `Down` 表示查看列表下方的内容，手指从下往上滑动
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScrollDirection {
+    Up,
+    #[default]
+    Down,
+    Left,
+    Right,
+}
+
+impl ScrollDirection {
+    /// 以屏幕中心为中点，滑动 `distance` 的起点和终点（1920x1080 下）
+    pub fn swipe_points(&self, distance: u32) -> ((u32, u32), (i32, i32)) {
+        let (cx, cy) = (DEFAULT_WIDTH as i32 / 2, DEFAULT_HEIGHT as i32 / 2);
+        let half = distance as i32 / 2;
+        let (dx, dy) = match self {
+            ScrollDirection::Up => (0, -half),
+            ScrollDirection::Down => (0, half),
+            ScrollDirection::Left => (-half, 0),
+            ScrollDirection::Right => (half, 0),
+        };
+        let clamp = |(x, y): (i32, i32)| {
+            (
+                x.clamp(0, DEFAULT_WIDTH as i32 - 1),
+                y.clamp(0, DEFAULT_HEIGHT as i32 - 1),
+            )
+        };
+        let start = clamp((cx + dx, cy + dy));
+        let end = clamp((cx - dx, cy - dy));
+        ((start.0 as u32, start.1 as u32), end)
+    }
+}
+
+/// 向 `direction` 滚动列表直到模板出现，返回模板的位置
+/// - `template`: 位于 `resources/templates/1920x1080` 下的模板文件名
+/// - `direction`: 滚动的方向，见 [`ScrollDirection`]
+/// - `max_swipes`: 最多滑动的次数，用完后返回错误
+/// - `swipe_distance`: 每次滑动的距离（1920x1080 下）
+/// - `threshold`: 匹配阈值，与 [`BestMatchAnalyzer`](crate::vision::analyzer::best_match::BestMatchAnalyzer) 一致，未指定时使用默认值
+///
+/// 模板在开始滑动前加载，加载失败时直接返回错误；只有没有匹配到模板时才会继续滑动。
+/// 滑动前后的画面没有变化（见 [`mean_abs_diff`]）时认为已经到达列表的尽头，直接返回错误
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScrollUntil {
+    template: String,
+    #[serde(default)]
+    direction: ScrollDirection,
+    #[serde(default = "default_max_swipes")]
+    max_swipes: usize,
+    #[serde(default = "default_swipe_distance")]
+    swipe_distance: u32,
+    threshold: Option<f32>,
+    wrapper: Option<GenericTaskWrapper>,
+}
+
+fn default_max_swipes() -> usize {
+    10
+}
+
+fn default_swipe_distance() -> u32 {
+    600
+}
+
+impl ScrollUntil {
+    pub fn new<S: AsRef<str>>(
+        template: S,
+        direction: ScrollDirection,
+        max_swipes: usize,
+        swipe_distance: u32,
+        wrapper: Option<GenericTaskWrapper>,
+    ) -> Self {
+        Self {
+            template: template.as_ref().to_string(),
+            direction,
+            max_swipes,
+            swipe_distance,
+            threshold: None,
+            wrapper,
+        }
+    }
+
+    /// 设置匹配阈值，未设置时使用 [`BestMatchAnalyzer`](crate::vision::analyzer::best_match::BestMatchAnalyzer) 的默认值
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn direction(&self) -> ScrollDirection {
+        self.direction
+    }
+
+    /// 截图、匹配、滑动分别由 `capture`、`find`、`swipe` 完成，`find` 返回 [`None`] 表示没有找到
+    fn scroll_until_with(
+        &self,
+        mut capture: impl FnMut() -> Result<DynamicImage, String>,
+        mut find: impl FnMut(&DynamicImage) -> Result<Option<Rect>, String>,
+        mut swipe: impl FnMut(((u32, u32), (i32, i32))) -> Result<(), String>,
+    ) -> Result<Rect, String> {
+        let points = self.direction.swipe_points(self.swipe_distance);
+        let mut prev = None;
+        let mut swipes = 0;
+        loop {
+            let screen = capture()?;
+            if let Some(rect) = find(&screen)? {
+                return Ok(rect);
+            }
+
+            let cur = downsample(&screen);
+            if let Some(prev) = &prev {
+                if mean_abs_diff(prev, &cur) < DEFAULT_DIFF_THRESHOLD {
+                    return Err(format!(
+                        "[ScrollUntil]: reached the end of the list after {swipes} swipes, {:?} not found",
+                        self.template
+                    ));
+                }
+            }
+            prev = Some(cur);
+
+            if swipes >= self.max_swipes {
+                return Err(format!(
+                    "[ScrollUntil]: {:?} not found after {swipes} swipes",
+                    self.template
+                ));
+            }
+            swipe(points)?;
+            swipes += 1;
+        }
+    }
+}
+
+/// 在 `image` 中查找 `template`，最佳匹配值（[`MatchTemplateMethod::CCOEFF_NORMED`]）未超过 `threshold` 时返回 [`None`]
+///
+/// 模板为空或比 `image` 大时返回错误
+fn find_template(
+    image: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    template: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    threshold: f32,
+) -> Result<Option<Rect>, String> {
+    if template.width() == 0
+        || template.height() == 0
+        || template.width() > image.width()
+        || template.height() > image.height()
+    {
+        return Err(format!(
+            "[ScrollUntil]: template {}x{} doesn't fit in the screen {}x{}",
+            template.width(),
+            template.height(),
+            image.width(),
+            image.height()
+        ));
+    }
+
+    let res = best_match(image, template, MatchTemplateMethod::CCOEFF_NORMED)?;
+    Ok((res.value > threshold).then(|| {
+        let (x, y) = res.location;
+        Rect {
+            x,
+            y,
+            width: template.width(),
+            height: template.height(),
+        }
+    }))
+}
+
+impl Task for ScrollUntil {
+    type Res = Rect;
+    type Err = String;
+    fn run(&self, aah: &AAH) -> Result<Self::Res, Self::Err> {
+        let task = || {
+            let template = aah.get_template(&self.template)?;
+            let threshold = self.threshold.unwrap_or(THRESHOLD);
+            // 按截图的高度缩放后的模板，分辨率不变时只缩放一次
+            let mut scaled = (0, ImageBuffer::new(0, 0));
+
+            self.scroll_until_with(
+                || aah.screen_cap_and_cache(),
+                |screen| {
+                    if scaled.0 != screen.height() {
+                        let template = scale_template(template.clone(), screen.height());
+                        scaled = (screen.height(), template.to_luma32f());
+                    }
+                    find_template(&screen.to_luma32f(), &scaled.1, threshold)
+                },
+                |(start, end)| {
+                    aah.controller
+                        .swipe_scaled(start, end, SCROLL_SWIPE_DURATION)
+                        .map_err(|err| format!("controller error: {:?}", err))?;
+                    std::thread::sleep(SCROLL_SETTLE_INTERVAL);
+                    Ok(())
+                },
+            )
+        };
+
+        if let Some(wrapper) = &self.wrapper {
+            wrapper.run(task)
+        } else {
+            task()
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    task::{
+        wrapper::{GenericTaskWrapper, TaskWrapper},
+        Task,
+    },
+    vision::matcher::DEFAULT_MULTI_MATCH_THRESHOLD,
+    AAH,
+};
+
+#[cfg(test)]
+mod test {
+    use crate::task::wrapper::GenericTaskWrapper;
+
+    use super::*;
+
+    #[test]
+    fn test_serde() {
+        // Without wrapper
+        {
+            let task = WaitFor::new("main_base.png", true, 10.0, 0.5, None, None);
+            let task = toml::to_string_pretty(&task).unwrap();
+            println!("{:?}", task);
+            let task = toml::from_str::<WaitFor>(&task).unwrap();
+            println!("{:?}", task);
+        }
+        // With wrapper
+        {
+            let task = WaitFor::new(
+                "main_base.png",
+                false,
+                10.0,
+                0.5,
+                Some(0.9),
+                Some(GenericTaskWrapper::default()),
+            );
+            let task = toml::to_string_pretty(&task).unwrap();
+            println!("{:?}", task);
+            let task = toml::from_str::<WaitFor>(&task).unwrap();
+            println!("{:?}", task);
+        }
+        // Defaults
+        {
+            let task = toml::from_str::<WaitFor>(r#"template = "main_base.png""#).unwrap();
+            assert!(task.appear);
+            assert_eq!(task.timeout, default_timeout());
+            assert_eq!(task.poll_interval, default_poll_interval());
+        }
+    }
+}
+
+/// 等待模板出现/消失
+/// - `template`: 位于 `resources/templates/1920x1080` 下的模板文件名
+/// - `appear`: 为 `true` 时等待模板出现，为 `false` 时等待模板消失
+/// - `timeout`: 超时时间（秒），超时后返回错误
+/// - `poll_interval`: 每次截图匹配之间的间隔（秒）
+/// - `threshold`: 匹配阈值，见 [`AAH::template_present`]，未指定时为 [`DEFAULT_MULTI_MATCH_THRESHOLD`]
+///
+/// 截图失败、模板文件不存在等错误会直接返回，不会被当作模板没有出现
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WaitFor {
+    template: String,
+    #[serde(default = "default_appear")]
+    appear: bool,
+    #[serde(default = "default_timeout")]
+    timeout: f32,
+    #[serde(default = "default_poll_interval")]
+    poll_interval: f32,
+    threshold: Option<f32>,
+    wrapper: Option<GenericTaskWrapper>,
+}
+
+fn default_appear() -> bool {
+    true
+}
+
+fn default_timeout() -> f32 {
+    10.0
+}
+
+fn default_poll_interval() -> f32 {
+    0.5
+}
+
+impl WaitFor {
+    pub fn new<S: AsRef<str>>(
+        template: S,
+        appear: bool,
+        timeout: f32,
+        poll_interval: f32,
+        threshold: Option<f32>,
+        wrapper: Option<GenericTaskWrapper>,
+    ) -> Self {
+        Self {
+            template: template.as_ref().to_string(),
+            appear,
+            timeout,
+            poll_interval,
+            threshold,
+            wrapper,
+        }
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+}
+
+impl Task for WaitFor {
+    type Err = String;
+    fn run(&self, aah: &AAH) -> Result<Self::Res, Self::Err> {
+        let task = || {
+            let threshold = self.threshold.unwrap_or(DEFAULT_MULTI_MATCH_THRESHOLD);
+
+            let start = Instant::now();
+            let timeout = Duration::from_secs_f32(self.timeout);
+            loop {
+                aah.update_screen()?;
+                let present = aah.template_present(&self.template, threshold, None)?;
+                if present == self.appear {
+                    return Ok(());
+                }
+                if start.elapsed() >= timeout {
+                    return Err(format!(
+                        "[WaitFor]: timeout after {}s waiting for {:?} to {}",
+                        self.timeout,
+                        self.template,
+                        if self.appear { "appear" } else { "disappear" }
+                    ));
+                }
+                std::thread::sleep(Duration::from_secs_f32(self.poll_interval));
+            }
+        };
+
+        if let Some(wrapper) = &self.wrapper {
+            wrapper.run(task)
+        } else {
+            task()
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{vision::matcher::DEFAULT_MULTI_MATCH_THRESHOLD, AAH};
+
+/// 条件：模板是否出现在当前屏幕中
+/// - `template`: 位于 `resources/templates/1920x1080` 下的模板文件名
+/// - `threshold`: 匹配阈值，见 [`AAH::template_present`]，未指定时为 [`DEFAULT_MULTI_MATCH_THRESHOLD`]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TemplatePresent {
+    pub template: String,
+    pub threshold: Option<f32>,
+}
+
+impl TemplatePresent {
+    pub fn new<S: AsRef<str>>(template: S, threshold: Option<f32>) -> Self {
+        Self {
+            template: template.as_ref().to_string(),
+            threshold,
+        }
+    }
+
+    /// 截取当前帧的屏幕内容，判断模板是否出现
+    ///
+    /// 截图失败、模板文件不存在等错误会返回 `Err`，而不是被当作模板没有出现
+    pub fn eval(&self, aah: &AAH) -> Result<bool, String> {
+        aah.update_screen()?;
+        aah.template_present(
+            &self.template,
+            self.threshold.unwrap_or(DEFAULT_MULTI_MATCH_THRESHOLD),
+            None,
+        )
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -6,7 +8,10 @@ use crate::{
     AAH,
 };
 
-use super::Task;
+use super::{Task, TaskEvt};
+
+/// 匹配失败后重试前等待的时间
+pub const MATCH_RETRY_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type", content = "template")]
@@ -24,8 +29,35 @@ impl Task for MatchTask {
 
         let res = match self {
             Self::Template(template_filename) => {
+                let options = aah.run_options();
                 let mut analyzer = BestMatchAnalyzer::new(template_filename.to_string());
-                analyzer.analyze(aah)?.rect
+                if let Some(threshold) = options.min_confidence {
+                    analyzer = analyzer.with_threshold(threshold);
+                }
+
+                let start = Instant::now();
+                let mut attempt = 0;
+                loop {
+                    match analyzer.analyze(aah) {
+                        Ok(output) => break output.rect,
+                        Err(err) => {
+                            let timeout = options
+                                .step_timeout
+                                .map(|timeout| start.elapsed() >= timeout)
+                                .unwrap_or(false);
+                            if attempt >= options.max_retries || timeout {
+                                return Err(err);
+                            }
+                            attempt += 1;
+                            aah.task_evt.emit(TaskEvt::StepRetry {
+                                step: format!("{:?}", self),
+                                attempt,
+                                err,
+                            });
+                            std::thread::sleep(MATCH_RETRY_INTERVAL);
+                        }
+                    }
+                }
             }
             Self::Ocr(text) => {
                 return Err("not implemented".to_string());
@@ -1,7 +1,17 @@
-use crate::AAH;
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crate::{config::task::ConfigError, vision::analyzer::battle_timeline::BattleEvent, AAH};
 
 pub mod builtins;
+pub mod condition;
 pub mod match_task;
+pub mod popup_guard;
 pub mod wrapper;
 
 pub trait Task {
@@ -9,3 +19,171 @@ pub trait Task {
     type Err = ();
     fn run(&self, aah: &AAH) -> Result<Self::Res, Self::Err>;
 }
+
+/// 任务执行的选项，见 [`AAH::run_task_with`]
+///
+/// - `max_retries`: 模板匹配步骤失败时的最大重试次数，用于等待界面动画等暂时性的失败
+/// - `step_timeout`: 单个匹配步骤（包括重试）的最长时间，超过后不再重试
+/// - `min_confidence`: 模板匹配的阈值，[`None`] 时使用默认值
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    pub max_retries: usize,
+    pub step_timeout: Option<Duration>,
+    pub min_confidence: Option<f32>,
+}
+
+/// 执行过程中产生的事件，通过 [`AAH::subscribe_task_evt`] 接收
+#[derive(Debug, Clone)]
+pub enum TaskEvt {
+    /// 资源配置重新加载成功，`config_errors` 为新配置中的错误，见 [`AAH::config_errors`]
+    ResourcesReloaded { config_errors: Vec<ConfigError> },
+    /// 资源配置重新加载失败（比如配置文件格式错误），当前的配置保持不变
+    ResourcesReloadFailed(String),
+    /// [`AAH::start_battle_analyzer`] 分析某一帧失败，分析会继续进行
+    BattleAnalyzerError(String),
+    /// [`AAH::start_battle_analyzer`] 识别到的战斗事件，见 [`BattleTimeline`](crate::vision::analyzer::battle_timeline::BattleTimeline)
+    BattleEvent(BattleEvent),
+    /// 匹配步骤失败，将进行第 `attempt` 次重试，见 [`RunOptions::max_retries`]
+    StepRetry {
+        step: String,
+        attempt: usize,
+        err: String,
+    },
+    /// 开始执行名为 `name` 的任务，`steps` 为任务的步骤数
+    TaskStarted { name: String, steps: usize },
+    /// 开始执行位于 `path` 的步骤，`description` 见 [`BuiltinTask::description`](builtins::BuiltinTask::description)
+    ///
+    /// 步骤为 [`Multi`](builtins::Multi) 中的每个子任务，其他任务只有一个步骤（`[0]`）。
+    /// `path` 由外到内依次为每一层 [`Multi`](builtins::Multi) 中子任务的下标，
+    /// 比如第 `1` 个步骤中嵌套的 [`Multi`](builtins::Multi) 的第 `0` 个子任务为 `[1, 0]`，见 [`TaskStepTracker`]
+    TaskStepStarted {
+        path: Vec<usize>,
+        description: String,
+    },
+    /// 位于 `path` 的步骤执行结束
+    TaskStepFinished {
+        path: Vec<usize>,
+        result: Result<(), String>,
+    },
+    /// 名为 `name` 的任务执行结束
+    TaskFinished { name: String, success: bool },
+    /// 步骤前后识别到名为 `name` 的弹窗并将其关闭，见 [`PopupGuard`](popup_guard::PopupGuard)
+    PopupDismissed { name: String },
+    /// dry run 模式下未实际执行的设备操作，见 [`AAH::set_dry_run`]
+    DryRunAction(String),
+}
+
+/// 将 [`TaskEvt`] 广播给所有订阅者，已断开的订阅者会被移除
+#[derive(Clone, Default)]
+pub struct TaskEvtBroadcaster {
+    senders: Arc<Mutex<Vec<Sender<TaskEvt>>>>,
+}
+
+impl TaskEvtBroadcaster {
+    pub fn subscribe(&self) -> Receiver<TaskEvt> {
+        let (tx, rx) = channel();
+        self.senders.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn emit(&self, evt: TaskEvt) {
+        self.senders
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(evt.clone()).is_ok());
+    }
+}
+
+/// 记录当前正在执行的步骤的路径，用于 [`TaskEvt::TaskStepStarted`] 和 [`TaskEvt::TaskStepFinished`]
+///
+/// 嵌套的 [`Multi`](builtins::Multi) 在外层步骤执行期间运行，其步骤的路径会以外层步骤的路径为前缀
+#[derive(Debug, Default)]
+pub struct TaskStepTracker {
+    path: Mutex<Vec<usize>>,
+}
+
+impl TaskStepTracker {
+    /// 将 `f` 作为当前步骤下的第 `index` 个步骤执行，并在前后通过 `task_evt` 产生步骤事件
+    pub fn run_step<F: FnOnce() -> Result<(), String>>(
+        &self,
+        task_evt: &TaskEvtBroadcaster,
+        index: usize,
+        description: String,
+        f: F,
+    ) -> Result<(), String> {
+        let path = {
+            let mut path = self.path.lock().unwrap();
+            path.push(index);
+            path.clone()
+        };
+        task_evt.emit(TaskEvt::TaskStepStarted {
+            path: path.clone(),
+            description,
+        });
+        let res = f();
+        self.path.lock().unwrap().pop();
+        task_evt.emit(TaskEvt::TaskStepFinished {
+            path,
+            result: res.clone(),
+        });
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_nested_step_path() {
+        let task_evt = TaskEvtBroadcaster::default();
+        let rx = task_evt.subscribe();
+        let tracker = TaskStepTracker::default();
+
+        // [outer, inner]，模拟第 1 个步骤为嵌套的 Multi
+        for outer in 0..2 {
+            tracker
+                .run_step(&task_evt, outer, format!("outer {outer}"), || {
+                    if outer == 1 {
+                        for inner in 0..2 {
+                            tracker
+                                .run_step(&task_evt, inner, format!("inner {inner}"), || Ok(()))?;
+                        }
+                    }
+                    Ok(())
+                })
+                .unwrap();
+        }
+        let err = tracker.run_step(
+            &task_evt,
+            2,
+            "failed".to_string(),
+            || Err("err".to_string()),
+        );
+        assert_eq!(err, Err("err".to_string()));
+
+        let events = rx
+            .try_iter()
+            .map(|evt| match evt {
+                TaskEvt::TaskStepStarted { path, .. } => ("started", path),
+                TaskEvt::TaskStepFinished { path, .. } => ("finished", path),
+                evt => panic!("unexpected event {evt:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                ("started", vec![0]),
+                ("finished", vec![0]),
+                ("started", vec![1]),
+                ("started", vec![1, 0]),
+                ("finished", vec![1, 0]),
+                ("started", vec![1, 1]),
+                ("finished", vec![1, 1]),
+                ("finished", vec![1]),
+                ("started", vec![2]),
+                ("finished", vec![2]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use crate::{
+    config::popup::{Popup, PopupConfig},
+    task::TaskEvt,
+    vision::{
+        analyzer::{any_match::AnyMatchAnalyzer, Analyzer},
+        utils::Rect,
+    },
+    AAH,
+};
+
+/// [`PopupGuard::dismiss`] 一次最多关闭的弹窗数，避免误识别时不断点击
+pub const MAX_POPUP_DISMISSALS: usize = 3;
+/// 点击关闭弹窗后等待其消失的时间
+pub const POPUP_DISMISS_INTERVAL: Duration = Duration::from_millis(800);
+
+/// 在任务的每个步骤前后识别并关闭打断任务的弹窗（比如升级、理智恢复、活动公告），
+/// 弹窗由 `popups.toml` 配置，见 [`PopupConfig`]
+///
+/// 通过 [`AnyMatchAnalyzer`] 识别所有弹窗的模板，匹配上时点击弹窗的关闭或确认按钮，
+/// 并产生 [`TaskEvt::PopupDismissed`]。弹窗可能接连出现（比如升级后的奖励），
+/// 因此点击后会再次识别，最多关闭 [`MAX_POPUP_DISMISSALS`] 个
+pub struct PopupGuard {
+    config: PopupConfig,
+    threshold: Option<f32>,
+}
+
+impl PopupGuard {
+    pub fn new(config: PopupConfig) -> Self {
+        Self {
+            config,
+            threshold: None,
+        }
+    }
+
+    /// 设置匹配值的下限，见 [`AnyMatchAnalyzer::with_threshold`]
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// 截取当前屏幕并识别其中的弹窗，返回弹窗与匹配到的位置（屏幕坐标）
+    pub fn detect(&self, aah: &AAH) -> Result<Option<(Popup, Rect)>, String> {
+        if self.config.popups.is_empty() {
+            return Ok(None);
+        }
+        let mut analyzer = AnyMatchAnalyzer::new(self.config.templates());
+        if let Some(threshold) = self.threshold {
+            analyzer = analyzer.with_threshold(threshold);
+        }
+        let Some(matched) = analyzer.analyze(aah)?.matched else {
+            return Ok(None);
+        };
+        let popup = self
+            .config
+            .get_by_template(&matched.template)
+            .ok_or(format!("no popup uses template {:?}", matched.template))?
+            .clone();
+        let rect = Rect {
+            x: matched.rect.x,
+            y: matched.rect.y,
+            width: matched.rect.width,
+            height: matched.rect.height,
+        };
+        Ok(Some((popup, rect)))
+    }
+
+    /// 关闭当前出现的弹窗，返回关闭的弹窗的名称，没有弹窗时返回空
+    pub fn dismiss(&self, aah: &AAH) -> Result<Vec<String>, String> {
+        let mut dismissed = vec![];
+        while dismissed.len() < MAX_POPUP_DISMISSALS {
+            let Some((popup, rect)) = self.detect(aah)? else {
+                break;
+            };
+            println!("[PopupGuard]: dismissing {:?}", popup.name);
+            match &popup.click {
+                Some(click) => aah.controller.click_in_rect_scaled(click.clone()),
+                None => aah.controller.click_in_rect(rect),
+            }
+            .map_err(|err| format!("failed to dismiss popup {:?}: {err}", popup.name))?;
+            aah.task_evt.emit(TaskEvt::PopupDismissed {
+                name: popup.name.clone(),
+            });
+            dismissed.push(popup.name);
+
+            // dry run 时不会实际点击，弹窗不会消失
+            if aah.dry_run() {
+                break;
+            }
+            std::thread::sleep(POPUP_DISMISS_INTERVAL);
+        }
+        Ok(dismissed)
+    }
+}
@@ -1,15 +1,30 @@
+use image::DynamicImage;
 use serde::Serialize;
 
 use crate::AAH;
 
 pub mod depot;
-// pub mod squad;
+pub mod squad;
 pub mod deploy;
+pub mod battle;
+pub mod battle_timeline;
+pub mod any_match;
 pub mod best_match;
 pub mod multi_match;
+pub mod result;
+pub mod sanity;
+pub mod scene;
+pub mod stable;
+pub mod unit_menu;
 
 /// [`Analyzer`] 接收图像，返回分析结果 [`Analyzer::Output`]
 pub trait Analyzer {
     type Output;
-    fn analyze(&mut self, aah: &AAH) -> Result<Self::Output, String>;
+    /// 截取当前帧的屏幕内容（同时更新屏幕缓存），通过 [`Analyzer::analyze_image`] 进行分析
+    fn analyze(&mut self, aah: &AAH) -> Result<Self::Output, String> {
+        let screen = aah.screen_cap_and_cache()?;
+        self.analyze_image(aah, &screen)
+    }
+    /// 分析 `image`（设备截图），可用于对保存下来的截图进行分析
+    fn analyze_image(&mut self, aah: &AAH, image: &DynamicImage) -> Result<Self::Output, String>;
 }
@@ -0,0 +1,195 @@
+use image::{math::Rect, DynamicImage};
+
+use crate::{
+    vision::{
+        analyzer::multi_match::crop_roi,
+        matcher::multi_matcher::MultiMatcher,
+        utils::{self, binarize_image, BinarizeThreshold},
+    },
+    AAH,
+};
+
+use super::Analyzer;
+
+/// [`AnyMatchAnalyzer`] 中匹配上的模板
+///
+/// - `template`: 模板文件名
+/// - `rect`: 匹配值最高的位置
+/// - `score`: 匹配值（CCOEFF_NORMED，越高越好）
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnyMatch {
+    pub template: String,
+    pub rect: Rect,
+    pub score: f32,
+}
+
+/// [`AnyMatchAnalyzer`] 的输出
+///
+/// - `matched`: 匹配值最高的模板，所有模板均低于阈值时为 [`None`]
+#[derive(Debug)]
+pub struct AnyMatchAnalyzerOutput {
+    pub matched: Option<AnyMatch>,
+}
+
+/// 将屏幕与多个候选模板进行匹配，返回匹配值最高的一个，用于判断"这几个模板中出现了哪个"，
+/// 比如场景识别、关闭出现的任意一种弹窗
+///
+/// 模板的缩放、`roi` 的裁剪和二值化与 [`MultiMatchAnalyzer`](super::multi_match::MultiMatchAnalyzer) 一致
+pub struct AnyMatchAnalyzer {
+    template_names: Vec<String>,
+    binarize_threshold: Option<BinarizeThreshold>,
+    threshold: Option<f32>,
+    roi: Option<utils::Rect>,
+    use_cache: bool,
+}
+
+impl AnyMatchAnalyzer {
+    /// `template_names`: 位于 `resources/templates/1920x1080` 下的候选模板文件名
+    pub fn new(template_names: Vec<String>) -> Self {
+        Self {
+            template_names,
+            binarize_threshold: None,
+            threshold: None,
+            roi: None,
+            use_cache: false,
+        }
+    }
+
+    /// 设置匹配值的下限，默认为 [`DEFAULT_MULTI_MATCH_THRESHOLD`](crate::vision::matcher::DEFAULT_MULTI_MATCH_THRESHOLD)
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// 匹配前对屏幕和模板进行二值化，见 [`MultiMatchAnalyzer::new`](super::multi_match::MultiMatchAnalyzer::new)
+    pub fn with_binarize_threshold(mut self, binarize_threshold: BinarizeThreshold) -> Self {
+        self.binarize_threshold = Some(binarize_threshold);
+        self
+    }
+
+    /// 只在 `roi`（1920x1080 下）区域内进行匹配，输出的位置仍为整个屏幕中的位置
+    pub fn roi(mut self, roi: utils::Rect) -> Self {
+        self.roi = Some(roi);
+        self
+    }
+
+    /// 使用缓存中的屏幕内容（见 [`AAH::screen_cache_or_cap`]），而不是截取当前帧
+    pub fn use_cache(mut self, use_cache: bool) -> Self {
+        self.use_cache = use_cache;
+        self
+    }
+
+    /// 将 `screen` 与 `templates`（`(模板文件名, 已按屏幕缩放的模板)`）逐个匹配，返回匹配值最高的一个
+    fn match_any(
+        &self,
+        screen: &DynamicImage,
+        templates: Vec<(String, DynamicImage)>,
+    ) -> Option<AnyMatch> {
+        let (offset_x, offset_y, mut image) = crop_roi(screen, self.roi.as_ref());
+        if let Some(threshold) = self.binarize_threshold {
+            image = binarize_image(&image, threshold.resolve(&image));
+        }
+        let image = image.to_luma32f();
+
+        let mut best: Option<AnyMatch> = None;
+        for (name, mut template) in templates {
+            if template.width() > image.width() || template.height() > image.height() {
+                println!("[AnyMatchAnalyzer]: skipped {name:?}: larger than the matching area");
+                continue;
+            }
+            if let Some(threshold) = self.binarize_threshold {
+                template = binarize_image(&template, threshold.resolve(&template));
+            }
+
+            let Some(res) =
+                MultiMatcher::template(image.clone(), template.to_luma32f(), self.threshold, None)
+                    .result()
+            else {
+                continue;
+            };
+            // 结果按匹配值从高到低排序
+            let (rect, score) = (res.rects[0], res.scores[0]);
+            if best.as_ref().map_or(true, |best| score > best.score) {
+                best = Some(AnyMatch {
+                    template: name,
+                    rect: Rect {
+                        x: rect.x + offset_x,
+                        y: rect.y + offset_y,
+                        ..rect
+                    },
+                    score,
+                });
+            }
+        }
+        best
+    }
+}
+
+impl Analyzer for AnyMatchAnalyzer {
+    type Output = AnyMatchAnalyzerOutput;
+    fn analyze(&mut self, core: &AAH) -> Result<Self::Output, String> {
+        let screen = match (&self.roi, self.use_cache) {
+            (_, true) => core.screen_cache_or_cap()?,
+            (Some(roi), false) => core.screen_cap_region(roi)?,
+            (None, false) => core.screen_cap_and_cache()?,
+        };
+        self.analyze_image(core, &screen)
+    }
+
+    fn analyze_image(&mut self, core: &AAH, screen: &DynamicImage) -> Result<Self::Output, String> {
+        let mut templates = vec![];
+        for name in &self.template_names {
+            match core.get_template_scaled(name, screen.height()) {
+                Ok(template) => templates.push((name.clone(), template)),
+                Err(err) => println!("[AnyMatchAnalyzer]: skipped {name:?}: {err}"),
+            }
+        }
+
+        let matched = self.match_any(screen, templates);
+        println!("[AnyMatchAnalyzer]: {:?}", matched);
+        Ok(AnyMatchAnalyzerOutput { matched })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::vision::matcher::test::{get_device_image, Device};
+
+    use super::*;
+
+    fn template(name: &str) -> (String, DynamicImage) {
+        let image = image::open(format!("../../resources/templates/1920x1080/{name}")).unwrap();
+        (name.to_string(), image)
+    }
+
+    #[test]
+    fn test_any_match() {
+        let screen = get_device_image(Device::MUMU, "battle0.png").unwrap();
+        // 部署卡片所在的区域
+        let analyzer = AnyMatchAnalyzer::new(vec![]).roi(utils::Rect {
+            x: 0,
+            y: 880,
+            width: 1920,
+            height: 200,
+        });
+
+        let matched = analyzer
+            .match_any(
+                &screen,
+                vec![
+                    template("main_base.png"),
+                    template("battle_deploy-card-cost-icon1.png"),
+                    template("start_start.png"),
+                ],
+            )
+            .unwrap();
+        println!("{matched:?}");
+        assert_eq!(matched.template, "battle_deploy-card-cost-icon1.png");
+        assert!(matched.score >= 0.8);
+        assert!(matched.rect.y >= 880);
+
+        // 没有任何模板出现
+        let matched = analyzer.match_any(&screen, vec![template("start_start.png")]);
+        assert_eq!(matched, None);
+    }
+}
@@ -0,0 +1,243 @@
+use image::DynamicImage;
+use ocrs::OcrEngine;
+use serde::Serialize;
+
+use crate::{
+    controller::DEFAULT_HEIGHT,
+    vision::{
+        ocr::{ocr_region, DIGITS},
+        utils::{average_hsv_v, Rect},
+    },
+    AAH,
+};
+
+use super::{
+    deploy::{DeployAnalyzer, DeployCard},
+    result::BattleResult,
+    Analyzer,
+};
+
+/// 技能图标区域相对于干员位置的默认偏移（1920x1080 下）
+pub const DEFAULT_SKILL_READY_OFFSET: (i32, i32) = (-20, -120);
+/// 技能图标区域的默认大小（1920x1080 下）
+pub const DEFAULT_SKILL_READY_SIZE: (u32, u32) = (40, 40);
+/// 技能图标区域平均 HSV V 值高于该值时认为技能就绪
+pub const DEFAULT_SKILL_READY_THRESHOLD: u8 = 150;
+
+/// 部署费用所在区域（1920x1080 下）
+pub const BATTLE_COST_RECT: Rect = Rect {
+    x: 1780,
+    y: 760,
+    width: 130,
+    height: 60,
+};
+
+/// 从 OCR 识别结果中解析部署费用，取第一段连续的数字
+pub fn parse_battle_cost(text: &str) -> Option<u32> {
+    let digits = text
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>();
+    digits.parse().ok()
+}
+
+/// 通过 OCR 读取战斗画面 `screen` 中的部署费用
+pub fn read_battle_cost(engine: &OcrEngine, screen: &DynamicImage) -> Result<u32, String> {
+    let text = ocr_region(engine, screen, &BATTLE_COST_RECT, Some(DIGITS))?;
+    parse_battle_cost(&text).ok_or(format!("failed to parse battle cost from {:?}", text))
+}
+
+/// 战斗状态
+///
+/// - `Completed`: 战斗结束，附带由 [`ResultAnalyzer`](super::result::ResultAnalyzer) 识别出的战斗结果
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum BattleState {
+    Unknown,
+    Running,
+    Completed(BattleResult),
+}
+
+/// 已部署干员的技能状态
+///
+/// - `pos`: 干员在屏幕上的位置（1920x1080 下）
+/// - `ready`: 技能是否就绪
+#[derive(Debug, Serialize, Clone)]
+pub struct SkillReady {
+    pub pos: (u32, u32),
+    pub ready: bool,
+}
+
+/// [`BattleAnalyzer`] 的输出
+///
+/// - `battle_state`: 战斗状态
+/// - `deploy_cards`: 所有部署卡片信息
+/// - `skill_ready`: 每个已部署干员的技能状态，与 [`BattleAnalyzer::with_deployed_units`] 的顺序一致
+#[derive(Debug)]
+pub struct BattleAnalyzerOutput {
+    pub battle_state: BattleState,
+    pub deploy_cards: Vec<DeployCard>,
+    pub skill_ready: Vec<SkillReady>,
+}
+
+pub struct BattleAnalyzer {
+    deploy_analyzer: DeployAnalyzer,
+    deployed_units: Vec<(u32, u32)>,
+    skill_ready_offset: (i32, i32),
+    skill_ready_size: (u32, u32),
+    skill_ready_threshold: u8,
+}
+
+impl BattleAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            // 战斗中循环分析，不需要标注后的屏幕
+            deploy_analyzer: DeployAnalyzer::new().annotate(false),
+            deployed_units: vec![],
+            skill_ready_offset: DEFAULT_SKILL_READY_OFFSET,
+            skill_ready_size: DEFAULT_SKILL_READY_SIZE,
+            skill_ready_threshold: DEFAULT_SKILL_READY_THRESHOLD,
+        }
+    }
+
+    /// 设置编队中的干员，用于识别部署卡片对应的干员，见 [`DeployAnalyzer::with_opers`]
+    pub fn with_opers<S: AsRef<str>>(mut self, opers: Vec<S>) -> Self {
+        self.deploy_analyzer = self.deploy_analyzer.with_opers(opers);
+        self
+    }
+
+    /// 只使用干员 `oper` 的 `variants` 变体进行匹配，见 [`DeployAnalyzer::with_oper_variants`]
+    pub fn with_oper_variants<S: AsRef<str>>(mut self, oper: S, variants: Vec<S>) -> Self {
+        self.deploy_analyzer = self.deploy_analyzer.with_oper_variants(oper, variants);
+        self
+    }
+
+    /// 设置已部署干员在屏幕上的位置（1920x1080 下），用于检测技能是否就绪
+    pub fn with_deployed_units(mut self, deployed_units: Vec<(u32, u32)>) -> Self {
+        self.deployed_units = deployed_units;
+        self
+    }
+
+    /// 设置技能图标区域相对于干员位置的偏移及大小（1920x1080 下）
+    ///
+    /// 技能图标的位置会随干员在地图上的位置变化，可根据实际情况调整
+    pub fn with_skill_ready_region(mut self, offset: (i32, i32), size: (u32, u32)) -> Self {
+        self.skill_ready_offset = offset;
+        self.skill_ready_size = size;
+        self
+    }
+
+    /// 设置技能就绪的 HSV V 阈值
+    pub fn with_skill_ready_threshold(mut self, threshold: u8) -> Self {
+        self.skill_ready_threshold = threshold;
+        self
+    }
+
+    /// 截取 `pos` 处干员的技能图标区域，通过平均 HSV V 值判断技能是否就绪
+    fn is_skill_ready(&self, screen: &DynamicImage, pos: (u32, u32)) -> bool {
+        let scale_factor = screen.height() as f32 / DEFAULT_HEIGHT as f32;
+
+        let x = (pos.0 as i32 + self.skill_ready_offset.0).max(0) as f32 * scale_factor;
+        let y = (pos.1 as i32 + self.skill_ready_offset.1).max(0) as f32 * scale_factor;
+        let rect = Rect {
+            x: (x as u32).min(screen.width() - 1),
+            y: (y as u32).min(screen.height() - 1),
+            width: (self.skill_ready_size.0 as f32 * scale_factor) as u32,
+            height: (self.skill_ready_size.1 as f32 * scale_factor) as u32,
+        };
+        let width = rect.width.min(screen.width() - rect.x).max(1);
+        let height = rect.height.min(screen.height() - rect.y).max(1);
+
+        let cropped = screen.crop_imm(rect.x, rect.y, width, height);
+        average_hsv_v(&cropped) > self.skill_ready_threshold
+    }
+}
+
+impl Default for BattleAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analyzer for BattleAnalyzer {
+    type Output = BattleAnalyzerOutput;
+    fn analyze_image(&mut self, core: &AAH, image: &DynamicImage) -> Result<Self::Output, String> {
+        let deploy_cards = match self.deploy_analyzer.analyze_image(core, image) {
+            Ok(output) => output.deploy_cards,
+            Err(_) => vec![],
+        };
+
+        let battle_state = if deploy_cards.is_empty() {
+            BattleState::Unknown
+        } else {
+            BattleState::Running
+        };
+
+        let skill_ready = self
+            .deployed_units
+            .iter()
+            .map(|&pos| SkillReady {
+                pos,
+                ready: self.is_skill_ready(image, pos),
+            })
+            .collect();
+
+        Ok(BattleAnalyzerOutput {
+            battle_state,
+            deploy_cards,
+            skill_ready,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        vision::{
+            analyzer::Analyzer,
+            matcher::test::{get_device_image, Device},
+            ocr::{init_ocr_engine, OcrInitError},
+        },
+        AAH,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_parse_battle_cost() {
+        assert_eq!(parse_battle_cost("12"), Some(12));
+        assert_eq!(parse_battle_cost(" 8\n"), Some(8));
+        assert_eq!(parse_battle_cost("a99b3"), Some(99));
+        assert_eq!(parse_battle_cost(""), None);
+        assert_eq!(parse_battle_cost("--"), None);
+    }
+
+    #[test]
+    fn test_read_battle_cost() {
+        // 仓库中不包含 OCR 模型，没有下载时跳过
+        let engine = match init_ocr_engine("../../resources") {
+            Ok(engine) => engine,
+            Err(err @ OcrInitError::ModelNotFound(_)) => {
+                println!("skipped: {err}");
+                return;
+            }
+            Err(err) => panic!("{err}"),
+        };
+        for (i, expected) in [16, 20, 32, 27, 12, 11].into_iter().enumerate() {
+            let image = get_device_image(Device::MUMU, format!("battle{i}.png")).unwrap();
+            assert_eq!(
+                read_battle_cost(&engine, &image),
+                Ok(expected),
+                "battle{i}.png"
+            );
+        }
+    }
+
+    #[test]
+    fn test_battle_analyzer() {
+        let core = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
+        let mut analyzer = BattleAnalyzer::new().with_deployed_units(vec![(960, 540)]);
+        let output = analyzer.analyze(&core).unwrap();
+        println!("{:?}", output);
+    }
+}
@@ -0,0 +1,245 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::{
+    battle::{BattleAnalyzerOutput, BattleState},
+    result::BattleResult,
+};
+
+/// 战斗中发生的事件
+///
+/// - `Started`: 第一次识别到部署卡片
+/// - `OperDeployed`: 干员的部署卡片消失，即干员被部署
+/// - `OperReturned`: 干员的部署卡片重新出现（撤退或被击倒后）
+/// - `SkillReady`: `pos` 处干员的技能就绪
+/// - `SkillUsed`: `pos` 处干员的技能从就绪变为未就绪，即技能被释放
+/// - `Completed`: 战斗结束
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BattleEvent {
+    Started,
+    OperDeployed { oper: String },
+    OperReturned { oper: String },
+    SkillReady { pos: (u32, u32) },
+    SkillUsed { pos: (u32, u32) },
+    Completed { result: BattleResult },
+}
+
+/// [`BattleTimeline`] 中的一条记录，`elapsed_ms` 为距离开始分析的毫秒数
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct BattleTimelineEntry {
+    pub elapsed_ms: u64,
+    #[serde(flatten)]
+    pub event: BattleEvent,
+}
+
+/// 由 [`BattleAnalyzer`](super::battle::BattleAnalyzer) 每一帧的输出汇总出的战斗过程，
+/// 见 [`AAH::start_battle_analyzer_with_timeline`](crate::AAH::start_battle_analyzer_with_timeline)
+///
+/// 部署卡片只在识别到干员（见 [`BattleAnalyzer::with_opers`](super::battle::BattleAnalyzer::with_opers)）时才能对应到干员，
+/// 没有部署卡片的帧（比如画面被遮挡）不会被视为所有干员都已部署
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BattleTimeline {
+    events: Vec<BattleTimelineEntry>,
+    #[serde(skip)]
+    last_state: Option<BattleState>,
+    /// 上一个有部署卡片的帧中识别出的干员
+    #[serde(skip)]
+    last_opers: Option<Vec<String>>,
+    /// 上一帧每个已部署干员的技能是否就绪
+    #[serde(skip)]
+    last_skill_ready: Vec<((u32, u32), bool)>,
+}
+
+impl BattleTimeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 所有记录，按时间顺序排列
+    pub fn events(&self) -> &[BattleTimelineEntry] {
+        &self.events
+    }
+
+    /// 序列化为 JSON，形如 `{"events":[{"elapsed_ms":0,"type":"started"},{"elapsed_ms":5200,"type":"oper_deployed","oper":"char_102_texas"}]}`
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("failed to serialize battle timeline")
+    }
+
+    fn push(&mut self, elapsed: Duration, event: BattleEvent, new_events: &mut Vec<BattleEvent>) {
+        self.events.push(BattleTimelineEntry {
+            elapsed_ms: elapsed.as_millis() as u64,
+            event: event.clone(),
+        });
+        new_events.push(event);
+    }
+
+    /// 记录战斗状态的变化，返回新产生的事件
+    ///
+    /// 用于由 [`ResultAnalyzer`](super::result::ResultAnalyzer) 识别出的战斗结束，
+    /// [`BattleTimeline::record`] 也会调用
+    pub fn record_state(&mut self, elapsed: Duration, state: BattleState) -> Vec<BattleEvent> {
+        let mut new_events = vec![];
+        if self.last_state == Some(state) {
+            return new_events;
+        }
+        match state {
+            BattleState::Running
+                if !self.events.iter().any(|e| e.event == BattleEvent::Started) =>
+            {
+                self.push(elapsed, BattleEvent::Started, &mut new_events)
+            }
+            BattleState::Completed(result) => {
+                self.push(elapsed, BattleEvent::Completed { result }, &mut new_events)
+            }
+            _ => {}
+        }
+        self.last_state = Some(state);
+        new_events
+    }
+
+    /// 记录距离开始 `elapsed` 时的一帧分析结果，与之前的帧比较得出事件，返回新产生的事件
+    pub fn record(&mut self, elapsed: Duration, output: &BattleAnalyzerOutput) -> Vec<BattleEvent> {
+        let mut new_events = self.record_state(elapsed, output.battle_state);
+
+        if output.battle_state == BattleState::Running {
+            let opers = output
+                .deploy_cards
+                .iter()
+                .filter_map(|card| card.oper_name.clone())
+                .collect::<Vec<_>>();
+            if let Some(last_opers) = self.last_opers.take() {
+                for oper in last_opers.iter().filter(|oper| !opers.contains(oper)) {
+                    let event = BattleEvent::OperDeployed { oper: oper.clone() };
+                    self.push(elapsed, event, &mut new_events);
+                }
+                for oper in opers.iter().filter(|oper| !last_opers.contains(oper)) {
+                    let event = BattleEvent::OperReturned { oper: oper.clone() };
+                    self.push(elapsed, event, &mut new_events);
+                }
+            }
+            self.last_opers = Some(opers);
+        }
+
+        let mut skill_ready = vec![];
+        for skill in &output.skill_ready {
+            let last_ready = self
+                .last_skill_ready
+                .iter()
+                .find(|(pos, _)| *pos == skill.pos)
+                .map(|(_, ready)| *ready);
+            match (last_ready, skill.ready) {
+                (Some(false) | None, true) => self.push(
+                    elapsed,
+                    BattleEvent::SkillReady { pos: skill.pos },
+                    &mut new_events,
+                ),
+                (Some(true), false) => self.push(
+                    elapsed,
+                    BattleEvent::SkillUsed { pos: skill.pos },
+                    &mut new_events,
+                ),
+                _ => {}
+            }
+            skill_ready.push((skill.pos, skill.ready));
+        }
+        self.last_skill_ready = skill_ready;
+
+        new_events
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::vision::{
+        analyzer::{battle::SkillReady, deploy::DeployCard},
+        utils::Rect,
+    };
+
+    use super::*;
+
+    fn output(opers: &[&str], skill_ready: &[bool]) -> BattleAnalyzerOutput {
+        let deploy_cards = opers
+            .iter()
+            .map(|oper| DeployCard {
+                rect: Rect {
+                    x: 0,
+                    y: 0,
+                    width: 10,
+                    height: 10,
+                },
+                score: 1.0,
+                available: true,
+                oper_name: Some(oper.to_string()),
+                oper_variant: None,
+            })
+            .collect::<Vec<_>>();
+        BattleAnalyzerOutput {
+            battle_state: if deploy_cards.is_empty() {
+                BattleState::Unknown
+            } else {
+                BattleState::Running
+            },
+            deploy_cards,
+            skill_ready: skill_ready
+                .iter()
+                .map(|&ready| SkillReady {
+                    pos: (960, 540),
+                    ready,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_battle_timeline() {
+        let mut timeline = BattleTimeline::new();
+        let secs = Duration::from_secs;
+
+        assert_eq!(
+            timeline.record(secs(0), &output(&["texas", "amiya"], &[])),
+            vec![BattleEvent::Started]
+        );
+        assert!(timeline
+            .record(secs(1), &output(&["texas", "amiya"], &[]))
+            .is_empty());
+        // 画面被遮挡，没有部署卡片
+        assert!(timeline.record(secs(2), &output(&[], &[])).is_empty());
+        assert_eq!(
+            timeline.record(secs(3), &output(&["amiya"], &[false])),
+            vec![BattleEvent::OperDeployed {
+                oper: "texas".to_string()
+            }]
+        );
+        assert_eq!(
+            timeline.record(secs(4), &output(&["amiya"], &[true])),
+            vec![BattleEvent::SkillReady { pos: (960, 540) }]
+        );
+        assert_eq!(
+            timeline.record(secs(5), &output(&["amiya", "texas"], &[false])),
+            vec![
+                BattleEvent::OperReturned {
+                    oper: "texas".to_string()
+                },
+                BattleEvent::SkillUsed { pos: (960, 540) }
+            ]
+        );
+        let result = BattleResult {
+            victory: true,
+            stars: 3,
+        };
+        assert_eq!(
+            timeline.record_state(secs(6), BattleState::Completed(result)),
+            vec![BattleEvent::Completed { result }]
+        );
+        assert_eq!(timeline.events().len(), 6);
+        assert_eq!(timeline.events()[1].elapsed_ms, 3000);
+
+        let json = timeline.to_json();
+        println!("{json}");
+        assert!(json.starts_with(r#"{"events":[{"elapsed_ms":0,"type":"started"}"#));
+        assert!(json.contains(r#"{"elapsed_ms":3000,"type":"oper_deployed","oper":"texas"}"#));
+        assert!(json.contains(r#""type":"completed","result":{"victory":true,"stars":3}"#));
+    }
+}
@@ -1,3 +1,5 @@
+use aah_cv::MatchTemplateMethod;
+use image::DynamicImage;
 use serde::Serialize;
 
 use crate::{controller::DEFAULT_HEIGHT, vision::{matcher::best_matcher::BestMatcher, utils::Rect}, AAH};
@@ -11,17 +13,27 @@ pub struct BestMatchAnalyzerOutput {
 
 pub struct BestMatchAnalyzer {
     template_filename: String,
+    threshold: Option<f32>,
 }
 
 impl BestMatchAnalyzer {
     pub fn new(template_filename: String) -> Self {
-        Self { template_filename }
+        Self {
+            template_filename,
+            threshold: None,
+        }
+    }
+
+    /// 设置匹配阈值，未设置时使用 [`BestMatcher`] 的默认值
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = Some(threshold);
+        self
     }
 }
 
 impl Analyzer for BestMatchAnalyzer {
     type Output = BestMatchAnalyzerOutput;
-    fn analyze(&mut self, core: &AAH) -> Result<Self::Output, String> {
+    fn analyze_image(&mut self, core: &AAH, image: &DynamicImage) -> Result<Self::Output, String> {
         // Make sure that we are in the operation-start page
         println!(
             "[TemplateMatchAnalyzer]: matching {:?}",
@@ -34,15 +46,9 @@ impl Analyzer for BestMatchAnalyzer {
         //     .controller
         //     .screencap_scaled()
         //     .map_err(|err| format!("{:?}", err))?;
-        let image = core
-            .controller
-            .screencap()
-            .map_err(|err| format!("{:?}", err))?;
-
         let image = image.to_luma32f();
         let template = core
-            .get_template(&self.template_filename)
-            .unwrap()
+            .get_template(&self.template_filename)?
             .to_luma32f();
 
         let template = if image.height() != DEFAULT_HEIGHT {
@@ -64,7 +70,8 @@ impl Analyzer for BestMatchAnalyzer {
         let res = BestMatcher::Template {
             image,
             template,
-            threshold: None,
+            method: MatchTemplateMethod::CCOEFF_NORMED,
+            threshold: self.threshold,
         }
         .result()
         .ok_or("match failed".to_string())?;
@@ -1,8 +1,20 @@
-use image::DynamicImage;
+use std::{
+    collections::HashMap,
+    fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use aah_cv::MatchTemplateMethod;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{DynamicImage, ImageFormat};
 use serde::Serialize;
 
 use crate::{
-    vision::utils::{average_hsv_v, draw_box, Rect},
+    vision::{
+        matcher::best_matcher::LabeledBestMatcher,
+        utils::{average_hsv_v, draw_box, draw_number, Rect},
+    },
     AAH,
 };
 
@@ -13,39 +25,396 @@ use super::{multi_match::MultiMatchAnalyzer, Analyzer};
 /// 部署卡片
 ///
 /// - `rect`: 位置信息
-/// - `available`: 是否可用
+/// - `score`: 费用图标的匹配值（CCOEFF_NORMED），见 [`DeployAnalyzer::with_card_threshold`]
+/// - `available`: 是否可用，见 [`DeployAnalyzer::with_availability_threshold`]
+/// - `oper_name`: 识别出的干员，未通过 [`DeployAnalyzer::with_opers`] 指定干员或无法识别时为 [`None`]
+/// - `oper_variant`: 匹配到的头像变体（精英化阶段、皮肤等），见 [`get_oper_avatars`]
 pub struct DeployCard {
     pub rect: Rect,
+    pub score: f32,
     pub available: bool,
+    pub oper_name: Option<String>,
+    pub oper_variant: Option<String>,
 }
 
 #[allow(unused)]
 #[derive(Debug)]
 /// [`DeployAnalyzer`] 的输出
 ///
-/// - `deploy_card`: 所有部署卡片信息
+/// - `screen`: 进行分析的屏幕
+/// - `deploy_card`: 所有部署卡片信息，按在屏幕上的位置从左到右排列，见 [`sort_deploy_cards`]
+/// - `res_screen`: 标注了部署卡片位置的屏幕
+///
+/// [`DeployAnalyzer::annotate`] 为 `false` 时 `screen` 和 `res_screen` 均为 [`None`]
 pub struct DeployAnalyzerOutput {
-    pub screen: DynamicImage,
+    pub screen: Option<DynamicImage>,
     pub deploy_cards: Vec<DeployCard>,
-    pub res_screen: DynamicImage,
+    pub res_screen: Option<DynamicImage>,
 }
 
-pub struct DeployAnalyzer;
+/// [`DeployAnalyzerOutput::to_json_with`] 输出的内容
+#[derive(Serialize)]
+struct DeployAnalyzerOutputJson<'a> {
+    deploy_cards: &'a [DeployCard],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    res_screen: Option<String>,
+}
 
-impl Analyzer for DeployAnalyzer {
-    type Output = DeployAnalyzerOutput;
-    fn analyze(&mut self, core: &AAH) -> Result<Self::Output, String> {
-        // Make sure that we are in the operation-start page
-        let res = MultiMatchAnalyzer::new("battle_deploy-card-cost1.png".to_string(), None, None)
-            .analyze(core)?;
+impl DeployAnalyzerOutput {
+    /// 将部署卡片信息序列化为 JSON，不包含屏幕图像，便于交给外部工具使用
+    ///
+    /// 形如 `{"deploy_cards":[{"rect":{...},"available":true,"oper_name":...,"oper_variant":...}]}`
+    pub fn to_json(&self) -> String {
+        self.to_json_with(false)
+    }
+
+    /// 同 [`DeployAnalyzerOutput::to_json`]，`embed_screen` 为 `true` 时会将标注后的屏幕
+    /// 编码为 PNG 后以 base64 放在 `res_screen` 字段中（没有 `res_screen` 时省略该字段）
+    ///
+    /// 一张 1920x1080 的屏幕编码后有数 MB，只在需要时开启
+    pub fn to_json_with(&self, embed_screen: bool) -> String {
+        let res_screen = self
+            .res_screen
+            .as_ref()
+            .filter(|_| embed_screen)
+            .and_then(|screen| {
+                let mut bytes = Vec::new();
+                screen
+                    .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+                    .ok()?;
+                Some(STANDARD.encode(bytes))
+            });
+        serde_json::to_string(&DeployAnalyzerOutputJson {
+            deploy_cards: &self.deploy_cards,
+            res_screen,
+        })
+        .expect("failed to serialize deploy analyzer output")
+    }
+}
+
+/// 将部署卡片按在屏幕上的位置排序：先按 `rect.x` 从左到右，`x` 相同时按 `rect.y` 从上到下
+///
+/// 匹配结果的顺序与部署栏上卡片的顺序无关，排序后下标即为卡片在部署栏中的位置（比如"第三张卡片"）。
+/// 排序是稳定的，位置相同的卡片保持原有顺序
+pub fn sort_deploy_cards(deploy_cards: &mut [DeployCard]) {
+    deploy_cards.sort_by_key(|card| (card.rect.x, card.rect.y));
+}
+
+/// 在 `{res_dir}/avatars` 中查找干员 `name` 的头像目录
+///
+/// 头像目录以完整的干员 id 命名（比如 `char_102_texas`），`name` 可以是：
+/// - 完整的干员 id：`char_102_texas`
+/// - 去掉 `char_` 前缀的 id：`102_texas`
+/// - 编号或名称：`102`、`texas`
+fn find_oper_avatar_dir(res_dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let avatars_dir = res_dir.join("avatars");
+    let dir = avatars_dir.join(name);
+    if dir.is_dir() {
+        return Ok(dir);
+    }
+
+    let read_dir = fs::read_dir(&avatars_dir)
+        .map_err(|err| format!("avatars directory {avatars_dir:?} not found: {err}"))?;
+    let mut candidates = read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter(|path| {
+            let Some(dir_name) = path.file_name().and_then(|s| s.to_str()) else {
+                return false;
+            };
+            let id = dir_name.strip_prefix("char_").unwrap_or(dir_name);
+            id == name || id.split('_').any(|component| component == name)
+        })
+        .collect::<Vec<_>>();
+    candidates.sort();
+
+    match candidates.len() {
+        0 => Err(format!("avatars of {name:?} not found in {avatars_dir:?}")),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(format!(
+            "ambiguous operator name {name:?}, candidates: {candidates:?}"
+        )),
+    }
+}
+
+/// 获取干员 `name` 的所有头像（精英化、皮肤等），返回 `(变体, 头像)`，按文件名排序，非图片文件会被跳过
+///
+/// 头像位于 `{res_dir}/avatars/<干员 id>` 目录下，`name` 可以是完整的干员 id（`char_102_texas`）、
+/// 去掉前缀的 id（`102_texas`）、编号（`102`）或名称（`texas`）。
+///
+/// 变体为去掉 `<干员 id>_` 前缀的文件名，比如 `char_102_texas_2.png` 为 `2`，
+/// `char_102_texas_epoque#2.png` 为 `epoque#2`，与干员 id 同名的文件为 [`DEFAULT_OPER_VARIANT`]
+pub fn get_oper_avatars<P: AsRef<Path>, S: AsRef<str>>(
+    res_dir: P,
+    name: S,
+) -> Result<Vec<(String, DynamicImage)>, String> {
+    let dir = find_oper_avatar_dir(res_dir.as_ref(), name.as_ref())?;
+
+    let mut paths = fs::read_dir(&dir)
+        .map_err(|err| format!("failed to read avatars directory {dir:?}: {err}"))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && ImageFormat::from_path(path).is_ok())
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let oper_id = dir.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+    paths
+        .iter()
+        .map(|path| {
+            let stem = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default();
+            let variant = if stem == oper_id {
+                DEFAULT_OPER_VARIANT
+            } else {
+                stem.strip_prefix(oper_id)
+                    .and_then(|s| s.strip_prefix('_'))
+                    .unwrap_or(stem)
+            };
+            let image = image::open(path)
+                .map_err(|err| format!("failed to open avatar {path:?}: {err}"))?;
+            Ok((variant.to_string(), image))
+        })
+        .collect()
+}
+
+/// 与干员 id 同名的头像文件的变体名
+pub const DEFAULT_OPER_VARIANT: &str = "default";
+
+/// 部署卡片费用图标匹配的默认阈值（CCOEFF_NORMED），低于该值的结果视为误识别
+pub const DEFAULT_CARD_THRESHOLD: f32 = 0.85;
+
+/// 干员头像匹配的默认阈值（CCOEFF_NORMED）
+pub const DEFAULT_OPER_THRESHOLD: f32 = 0.6;
+
+/// 部署卡片是否可用的默认亮度阈值，费用图标的平均 HSV 亮度高于该值时视为可用
+pub const DEFAULT_AVAILABILITY_THRESHOLD: u8 = 100;
+
+/// [`adaptive_availability_threshold`] 中可用与不可用的卡片之间亮度差距的下限
+pub const MIN_AVAILABILITY_GAP: u8 = 25;
+
+/// 根据当前帧中所有部署卡片费用图标的平均亮度 `brightness` 得到可用性的阈值，亮度高于阈值的卡片可用
+///
+/// 不可用的卡片是灰暗的，亮度明显低于可用的卡片，但整体的亮度随设备的显示、gamma 设置变化。
+/// 将亮度排序后取相邻两者差距最大的位置作为分界，差距不小于 [`MIN_AVAILABILITY_GAP`] 时返回其中点；
+/// 否则认为所有卡片同属一类（全部可用或全部不可用），由平均亮度是否高于 `fallback` 决定，
+/// 返回 `0` 或 `255`，使所有卡片的结果一致
+pub fn adaptive_availability_threshold(brightness: &[u8], fallback: u8) -> u8 {
+    let mut brightness = brightness.to_vec();
+    brightness.sort();
+    let gap = brightness
+        .windows(2)
+        .map(|w| (w[0], w[1]))
+        .max_by_key(|(dim, bright)| bright - dim)
+        .filter(|(dim, bright)| bright - dim >= MIN_AVAILABILITY_GAP);
+    if let Some((dim, bright)) = gap {
+        return dim + (bright - dim) / 2;
+    }
+
+    let sum = brightness.iter().map(|&v| v as u32).sum::<u32>();
+    if !brightness.is_empty() && sum / brightness.len() as u32 > fallback as u32 {
+        u8::MIN
+    } else {
+        u8::MAX
+    }
+}
+
+/// 分析战斗中的部署卡片
+///
+/// 通过 [`DeployAnalyzer::with_opers`] 指定编队中的干员后，会将每张部署卡片与干员头像进行匹配，
+/// 头像由 [`AAH::get_oper_avatars`] 从 `resources/avatars/<干员名>` 目录中加载
+pub struct DeployAnalyzer {
+    roi: Option<Rect>,
+    use_cache: bool,
+    annotate: bool,
+    annotate_scores: bool,
+    card_threshold: f32,
+    availability_threshold: Option<u8>,
+    opers: Vec<String>,
+    oper_variants: HashMap<String, Vec<String>>,
+    oper_method: MatchTemplateMethod,
+    oper_threshold: f32,
+    oper_prefilter: Option<usize>,
+    /// 已加载的头像，`((干员名, 变体), 头像)`，在第一次分析时加载
+    avatars: Option<Vec<((String, String), DynamicImage)>>,
+}
+
+impl DeployAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            roi: None,
+            use_cache: false,
+            annotate: true,
+            annotate_scores: false,
+            card_threshold: DEFAULT_CARD_THRESHOLD,
+            availability_threshold: None,
+            opers: vec![],
+            oper_variants: HashMap::new(),
+            oper_method: MatchTemplateMethod::CCOEFF_NORMED,
+            oper_threshold: DEFAULT_OPER_THRESHOLD,
+            oper_prefilter: None,
+            avatars: None,
+        }
+    }
+
+    /// 只在 `roi`（1920x1080 下）区域内查找部署卡片，比如屏幕下方的部署栏
+    ///
+    /// 不使用缓存时只截取 `roi` 区域（见 [`AAH::screen_cap_region`]），此时输出中的屏幕在区域外为黑色
+    pub fn roi(mut self, roi: Rect) -> Self {
+        self.roi = Some(roi);
+        self
+    }
+
+    /// 使用缓存中的屏幕内容（见 [`AAH::screen_cache_or_cap`]），而不是截取当前帧
+    pub fn use_cache(mut self, use_cache: bool) -> Self {
+        self.use_cache = use_cache;
+        self
+    }
+
+    /// 是否在输出中附带屏幕及标注了部署卡片的屏幕，默认为 `true`，见 [`DeployAnalyzerOutput`]
+    ///
+    /// 关闭后可以省去每次分析时复制、绘制屏幕的开销
+    pub fn annotate(mut self, annotate: bool) -> Self {
+        self.annotate = annotate;
+        self
+    }
+
+    /// 是否在标注的屏幕中每个部署卡片旁边绘制其匹配值，默认为 `false`
+    pub fn annotate_scores(mut self, annotate_scores: bool) -> Self {
+        self.annotate_scores = annotate_scores;
+        self
+    }
+
+    /// 设置费用图标匹配值的下限，默认为 [`DEFAULT_CARD_THRESHOLD`]，可以提高以去掉误识别的部署卡片
+    pub fn with_card_threshold(mut self, threshold: f32) -> Self {
+        self.card_threshold = threshold;
+        self
+    }
+
+    /// 使用固定的亮度阈值判断部署卡片是否可用，费用图标的平均 HSV 亮度高于 `threshold` 时视为可用
+    ///
+    /// 默认根据当前帧中各卡片的亮度自适应地选取阈值，见 [`adaptive_availability_threshold`]，
+    /// 所有卡片亮度接近时使用 [`DEFAULT_AVAILABILITY_THRESHOLD`]
+    pub fn with_availability_threshold(mut self, threshold: u8) -> Self {
+        self.availability_threshold = Some(threshold);
+        self
+    }
+
+    /// 设置需要识别的干员（比如 `char_102_texas`）
+    pub fn with_opers<S: AsRef<str>>(mut self, opers: Vec<S>) -> Self {
+        self.opers = opers.iter().map(|s| s.as_ref().to_string()).collect();
+        self.avatars = None;
+        self
+    }
+
+    /// 只使用干员 `oper` 的 `variants` 变体（比如已拥有的皮肤）进行匹配，变体名见 [`get_oper_avatars`]
+    pub fn with_oper_variants<S: AsRef<str>>(mut self, oper: S, variants: Vec<S>) -> Self {
+        self.oper_variants.insert(
+            oper.as_ref().to_string(),
+            variants.iter().map(|s| s.as_ref().to_string()).collect(),
+        );
+        self.avatars = None;
+        self
+    }
+
+    /// 设置干员头像匹配使用的方法，默认为 [`MatchTemplateMethod::CCOEFF_NORMED`]
+    ///
+    /// 误差类方法（如 [`MatchTemplateMethod::SumOfSquaredErrors`]）的匹配值越低越好，
+    /// 此时需要通过 [`DeployAnalyzer::with_oper_threshold`] 设置对应的阈值（上限）
+    pub fn with_oper_method(mut self, method: MatchTemplateMethod) -> Self {
+        self.oper_method = method;
+        self
+    }
+
+    /// 设置干员头像匹配的阈值，含义取决于匹配方法，见 [`DeployAnalyzer::with_oper_method`]
+    pub fn with_oper_threshold(mut self, threshold: f32) -> Self {
+        self.oper_threshold = threshold;
+        self
+    }
+
+    /// 头像很多时（比如识别全部干员），只对 dHash 最接近的 `top_k` 个头像进行匹配，
+    /// 见 [`LabeledBestMatcher::with_prefilter`]
+    pub fn with_oper_prefilter(mut self, top_k: usize) -> Self {
+        self.oper_prefilter = Some(top_k);
+        self
+    }
+
+    fn load_avatars(&mut self, core: &AAH) -> Result<(), String> {
+        if self.avatars.is_some() {
+            return Ok(());
+        }
+        let mut avatars = vec![];
+        for oper in &self.opers {
+            let owned_variants = self.oper_variants.get(oper);
+            for (variant, avatar) in core.get_oper_avatars(oper)? {
+                if owned_variants
+                    .map(|variants| variants.contains(&variant))
+                    .unwrap_or(true)
+                {
+                    avatars.push(((oper.clone(), variant), avatar));
+                }
+            }
+        }
+        self.avatars = Some(avatars);
+        Ok(())
+    }
+
+    /// 将部署卡片的图像与所有干员头像进行匹配，返回匹配值最好的 `(干员名, 变体)`
+    fn recognize_oper(&self, card: &DynamicImage) -> Option<(String, String)> {
+        let avatars = self.avatars.as_ref()?;
+        let card = card.to_luma32f();
+
+        // 头像缩放到卡片宽度，在卡片范围内匹配
+        let templates = avatars
+            .iter()
+            .map(|(label, avatar)| {
+                let template = avatar
+                    .resize(
+                        card.width(),
+                        card.width(),
+                        image::imageops::FilterType::Lanczos3,
+                    )
+                    .to_luma32f();
+                (label.clone(), template)
+            })
+            .collect();
+        let mut matcher = LabeledBestMatcher::new(&card, templates)
+            .with_method(self.oper_method)
+            .with_threshold(self.oper_threshold);
+        if let Some(top_k) = self.oper_prefilter {
+            matcher = matcher.with_prefilter(top_k);
+        }
+        matcher.result().map(|(label, _, _)| label)
+    }
 
-        let deploy_cards: Vec<DeployCard> = res
-            .rects
+    /// 由费用图标的匹配结果 `rects`、`scores` 得到部署卡片，按位置排序，见 [`sort_deploy_cards`]
+    fn deploy_cards(
+        &self,
+        image: &DynamicImage,
+        rects: Vec<image::math::Rect>,
+        scores: Vec<f32>,
+    ) -> Vec<DeployCard> {
+        let icons = rects
             .into_iter()
-            .map(|rect| {
-                let cropped = res.screen.crop_imm(rect.x, rect.y, rect.width, rect.height);
-                let avg_hsv_v = average_hsv_v(&cropped);
-                let available = avg_hsv_v > 100;
+            .zip(scores)
+            .filter(|(_, score)| *score >= self.card_threshold)
+            .map(|(rect, score)| {
+                let cropped = image.crop_imm(rect.x, rect.y, rect.width, rect.height);
+                (rect, score, average_hsv_v(&cropped))
+            })
+            .collect::<Vec<_>>();
+        let availability_threshold = self.availability_threshold.unwrap_or_else(|| {
+            let brightness = icons.iter().map(|(_, _, v)| *v).collect::<Vec<_>>();
+            adaptive_availability_threshold(&brightness, DEFAULT_AVAILABILITY_THRESHOLD)
+        });
+
+        let mut deploy_cards: Vec<DeployCard> = icons
+            .into_iter()
+            .map(|(rect, score, avg_hsv_v)| {
+                let available = avg_hsv_v > availability_threshold;
 
                 let rect = Rect {
                     x: rect.x - 45,
@@ -54,11 +423,67 @@ impl Analyzer for DeployAnalyzer {
                     height: 120,
                 };
 
-                DeployCard { rect, available }
+                let oper = if self.opers.is_empty() {
+                    None
+                } else {
+                    let card = image.crop_imm(rect.x, rect.y, rect.width, rect.height);
+                    self.recognize_oper(&card)
+                };
+                let (oper_name, oper_variant) = oper.unzip();
+
+                DeployCard {
+                    rect,
+                    score,
+                    available,
+                    oper_name,
+                    oper_variant,
+                }
             })
             .collect();
+        sort_deploy_cards(&mut deploy_cards);
+        deploy_cards
+    }
+}
+
+impl Default for DeployAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analyzer for DeployAnalyzer {
+    type Output = DeployAnalyzerOutput;
+    fn analyze(&mut self, core: &AAH) -> Result<Self::Output, String> {
+        let screen = match (&self.roi, self.use_cache) {
+            (_, true) => core.screen_cache_or_cap()?,
+            (Some(roi), false) => core.screen_cap_region(roi)?,
+            (None, false) => core.screen_cap_and_cache()?,
+        };
+        self.analyze_image(core, &screen)
+    }
+
+    fn analyze_image(&mut self, core: &AAH, image: &DynamicImage) -> Result<Self::Output, String> {
+        self.load_avatars(core)?;
+
+        // Make sure that we are in the operation-start page
+        let mut analyzer =
+            MultiMatchAnalyzer::new("battle_deploy-card-cost1.png".to_string(), None, None)
+                .annotate(false);
+        if let Some(roi) = &self.roi {
+            analyzer = analyzer.roi(roi.clone());
+        }
+        let res = analyzer.analyze_image(core, image)?;
+        let deploy_cards = self.deploy_cards(image, res.rects, res.scores);
 
-        let mut res_screen = res.screen.clone();
+        if !self.annotate {
+            return Ok(DeployAnalyzerOutput {
+                screen: None,
+                deploy_cards,
+                res_screen: None,
+            });
+        }
+
+        let mut res_screen = image.clone();
         for deploy_card in &deploy_cards {
             let color = if deploy_card.available {
                 [0, 255, 0, 255]
@@ -75,25 +500,245 @@ impl Analyzer for DeployAnalyzer {
                 rect.height,
                 color,
             );
+            if self.annotate_scores {
+                draw_number(
+                    &mut res_screen,
+                    rect.x as i32,
+                    rect.y as i32 - 14,
+                    &format!("{:.2}", deploy_card.score),
+                    2,
+                    color,
+                );
+            }
         }
 
         Ok(DeployAnalyzerOutput {
-            screen: res.screen,
+            screen: Some(image.clone()),
             deploy_cards,
-            res_screen,
+            res_screen: Some(res_screen),
         })
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{vision::analyzer::Analyzer, AAH};
+    use crate::{
+        vision::{
+            analyzer::Analyzer,
+            matcher::{
+                multi_matcher::MultiMatcher,
+                test::{get_device_image, Device},
+            },
+        },
+        AAH,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_deploy_analyzer_output_to_json() {
+        let output = DeployAnalyzerOutput {
+            screen: Some(DynamicImage::new_rgba8(8, 4)),
+            deploy_cards: vec![DeployCard {
+                rect: Rect {
+                    x: 10,
+                    y: 20,
+                    width: 75,
+                    height: 120,
+                },
+                score: 0.95,
+                available: true,
+                oper_name: Some("char_102_texas".to_string()),
+                oper_variant: None,
+            }],
+            res_screen: Some(DynamicImage::new_rgba8(8, 4)),
+        };
+
+        let json: serde_json::Value = serde_json::from_str(&output.to_json()).unwrap();
+        assert_eq!(json["deploy_cards"][0]["rect"]["x"], 10);
+        assert_eq!(json["deploy_cards"][0]["score"], 0.95);
+        assert_eq!(json["deploy_cards"][0]["oper_name"], "char_102_texas");
+        assert!(json.get("res_screen").is_none());
+
+        let json: serde_json::Value = serde_json::from_str(&output.to_json_with(true)).unwrap();
+        let bytes = STANDARD
+            .decode(json["res_screen"].as_str().unwrap())
+            .unwrap();
+        let screen = image::load_from_memory_with_format(&bytes, ImageFormat::Png).unwrap();
+        assert_eq!((screen.width(), screen.height()), (8, 4));
+    }
+
+    #[test]
+    fn test_adaptive_availability_threshold() {
+        let fallback = DEFAULT_AVAILABILITY_THRESHOLD;
+        assert_eq!(
+            adaptive_availability_threshold(&[109, 62, 106, 64, 113], fallback),
+            85
+        );
+        // 较暗的显示设置下，可用的卡片也低于默认阈值
+        assert_eq!(
+            adaptive_availability_threshold(&[69, 22, 66, 24, 73], fallback),
+            45
+        );
+        // 全部可用或全部不可用
+        assert_eq!(
+            adaptive_availability_threshold(&[106, 112, 118, 133, 147], fallback),
+            u8::MIN
+        );
+        assert_eq!(
+            adaptive_availability_threshold(&[96, 102, 104, 107], fallback),
+            u8::MIN
+        );
+        assert_eq!(
+            adaptive_availability_threshold(&[60, 58, 65], fallback),
+            u8::MAX
+        );
+        assert_eq!(adaptive_availability_threshold(&[120], fallback), u8::MIN);
+        assert_eq!(adaptive_availability_threshold(&[], fallback), u8::MAX);
+    }
+
+    #[test]
+    fn test_deploy_card_availability_on_frames() {
+        let template =
+            image::open("../../resources/templates/1920x1080/battle_deploy-card-cost-icon1.png")
+                .unwrap()
+                .to_luma32f();
+        // 从左到右每张卡片是否可用
+        for (filename, expected) in [
+            (
+                "battle0.png",
+                vec![true; 6]
+                    .into_iter()
+                    .chain([false; 4])
+                    .collect::<Vec<_>>(),
+            ),
+            ("battle3.png", vec![true; 11]),
+            (
+                "battle5.png",
+                vec![true; 3].into_iter().chain([false; 9]).collect(),
+            ),
+        ] {
+            let image = get_device_image(Device::MUMU, filename).unwrap();
+            let res = MultiMatcher::template(image.to_luma32f(), template.clone(), None, None)
+                .result()
+                .unwrap();
+            let availability = |analyzer: DeployAnalyzer, image: &DynamicImage| {
+                analyzer
+                    .deploy_cards(image, res.rects.clone(), res.scores.clone())
+                    .iter()
+                    .map(|card| card.available)
+                    .collect::<Vec<_>>()
+            };
+
+            assert_eq!(availability(DeployAnalyzer::new(), &image), expected);
+            assert_eq!(
+                availability(
+                    DeployAnalyzer::new().with_availability_threshold(100),
+                    &image
+                ),
+                expected
+            );
+
+            // 模拟较暗的显示设置，固定阈值会将可用的卡片识别为不可用
+            let dim = image.brighten(-40);
+            let dim_availability = availability(DeployAnalyzer::new(), &dim);
+            if expected.iter().all(|&available| available) {
+                // 没有不可用的卡片作为参照，只能保证结果一致
+                assert!(dim_availability.windows(2).all(|w| w[0] == w[1]));
+            } else {
+                assert_eq!(dim_availability, expected);
+            }
+            assert_ne!(
+                availability(DeployAnalyzer::new().with_availability_threshold(100), &dim),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_sort_deploy_cards() {
+        let card = |x, y| DeployCard {
+            rect: Rect {
+                x,
+                y,
+                width: 75,
+                height: 120,
+            },
+            score: 0.9,
+            available: true,
+            oper_name: None,
+            oper_variant: None,
+        };
+        let mut deploy_cards = vec![
+            card(1500, 920),
+            card(300, 920),
+            card(900, 930),
+            card(45, 920),
+            card(900, 920),
+            card(1200, 920),
+        ];
+        sort_deploy_cards(&mut deploy_cards);
+        assert_eq!(
+            deploy_cards
+                .iter()
+                .map(|card| (card.rect.x, card.rect.y))
+                .collect::<Vec<_>>(),
+            vec![
+                (45, 920),
+                (300, 920),
+                (900, 920),
+                (900, 930),
+                (1200, 920),
+                (1500, 920)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_oper_avatars() {
+        let res_dir = std::env::temp_dir().join("aah-test-get-oper-avatars");
+        let dir = res_dir.join("avatars").join("char_102_texas");
+        fs::create_dir_all(&dir).unwrap();
+        fs::create_dir_all(res_dir.join("avatars").join("char_103_angel")).unwrap();
+        for (filename, size) in [("char_102_texas_2.png", 2), ("char_102_texas.png", 1)] {
+            DynamicImage::new_rgba8(size, size)
+                .save(dir.join(filename))
+                .unwrap();
+        }
+        fs::write(dir.join("README.txt"), "not an image").unwrap();
+
+        for name in ["char_102_texas", "102_texas", "102", "texas"] {
+            let avatars = get_oper_avatars(&res_dir, name).unwrap();
+            assert_eq!(
+                avatars
+                    .iter()
+                    .map(|(variant, avatar)| (variant.as_str(), avatar.width()))
+                    .collect::<Vec<_>>(),
+                vec![(DEFAULT_OPER_VARIANT, 1), ("2", 2)]
+            );
+        }
+        assert!(get_oper_avatars(&res_dir, "exusiai").is_err());
+        assert!(get_oper_avatars("./not-exist", "texas").is_err());
+
+        fs::remove_dir_all(&res_dir).unwrap();
+    }
 
     #[test]
     fn test_deploy_analyzer() {
         let mut core = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
-        let mut analyzer = super::DeployAnalyzer {};
+        let mut analyzer = super::DeployAnalyzer::new();
         let output = analyzer.analyze(&mut core).unwrap();
         println!("{:?}", output);
     }
+
+    #[test]
+    fn test_deploy_analyzer_on_frames() {
+        let core = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
+        let mut analyzer = DeployAnalyzer::new();
+        for i in 0..=5 {
+            let image = get_device_image(Device::MUMU, format!("battle{i}.png")).unwrap();
+            let output = analyzer.analyze_image(&core, &image).unwrap();
+            println!("battle{i}.png: {:?}", output.deploy_cards);
+        }
+    }
 }
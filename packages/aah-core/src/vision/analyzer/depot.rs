@@ -1,6 +1,7 @@
 use std::f32::consts::PI;
 
-use crate::AAH;
+use crate::{controller::scale_to_default_height, AAH};
+use image::DynamicImage;
 use ndarray::{Array1, Array2, Axis};
 
 use super::Analyzer;
@@ -33,15 +34,12 @@ impl DepotAnalyzer {
 impl Analyzer for DepotAnalyzer {
     type Output = DepotAnalyzerOutput;
 
-    fn analyze(&mut self, aah: &AAH) -> Result<Self::Output, String> {
+    fn analyze_image(&mut self, _aah: &AAH, image: &DynamicImage) -> Result<Self::Output, String> {
         let crop_height = 128 + 30;
         let x_period = 312;
         let y_period = 380;
 
-        let mut screen = aah
-            .controller
-            .screencap_scaled()
-            .map_err(|err| format!("{:?}", err))?;
+        let mut screen = scale_to_default_height(image.clone());
 
         let screen = screen.crop(
             0,
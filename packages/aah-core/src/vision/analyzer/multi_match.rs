@@ -1,38 +1,125 @@
 use image::{math::Rect, DynamicImage};
 
-use crate::{controller::DEFAULT_HEIGHT, vision::{matcher::multi_matcher::MultiMatcher, utils::binarize_image}, AAH};
+use crate::{controller::DEFAULT_HEIGHT, vision::{matcher::multi_matcher::MultiMatcher, utils::{self, binarize_image, BinarizeThreshold}}, AAH};
 
 use super::Analyzer;
 
+/// 将 1920x1080 下的模板按 `screen_height` 等比缩放
+pub fn scale_template(template: DynamicImage, screen_height: u32) -> DynamicImage {
+    if screen_height == DEFAULT_HEIGHT {
+        return template;
+    }
+    let scale_factor = screen_height as f32 / DEFAULT_HEIGHT as f32;
+
+    let new_width = (template.width() as f32 * scale_factor) as u32;
+    let new_height = (template.height() as f32 * scale_factor) as u32;
+
+    DynamicImage::ImageRgba8(image::imageops::resize(
+        &template,
+        new_width,
+        new_height,
+        image::imageops::FilterType::Lanczos3,
+    ))
+}
+
+/// 裁剪出 `screen` 中的 `roi`（1920x1080 下）区域，超出屏幕的部分会被截掉，
+/// 返回 `(区域左上角的 x, 区域左上角的 y, 区域图像)`，`roi` 为 [`None`] 时为整个屏幕
+pub fn crop_roi(screen: &DynamicImage, roi: Option<&utils::Rect>) -> (u32, u32, DynamicImage) {
+    let Some(roi) = roi else {
+        return (0, 0, screen.clone());
+    };
+    let scale_factor = screen.height() as f32 / DEFAULT_HEIGHT as f32;
+    let x = ((roi.x as f32 * scale_factor) as u32).min(screen.width());
+    let y = ((roi.y as f32 * scale_factor) as u32).min(screen.height());
+    let width = ((roi.width as f32 * scale_factor) as u32).min(screen.width() - x);
+    let height = ((roi.height as f32 * scale_factor) as u32).min(screen.height() - y);
+    (x, y, screen.crop_imm(x, y, width, height))
+}
+
+/// [`MultiMatchAnalyzer`] 的输出
+///
+/// - `screen`: 进行匹配的屏幕，[`MultiMatchAnalyzer::annotate`] 为 `false` 时为 [`None`]
+/// - `rects`: 所有匹配结果的位置，按匹配值从高到低排序
+/// - `scores`: 与 `rects` 一一对应的匹配值，见 [`MultiMatcherResult`](crate::vision::matcher::multi_matcher::MultiMatcherResult)
 #[derive(Debug)]
 pub struct MultiMatchAnalyzerOutput {
-    pub screen: DynamicImage,
+    pub screen: Option<DynamicImage>,
     pub rects: Vec<Rect>,
+    pub scores: Vec<f32>,
 }
 
 pub struct MultiMatchAnalyzer {
     template_filename: String,
-    binarize_threshold: Option<u8>,
+    binarize_threshold: Option<BinarizeThreshold>,
     threshold: Option<f32>,
+    iou_threshold: Option<f32>,
+    roi: Option<utils::Rect>,
+    use_cache: bool,
+    annotate: bool,
 }
 
 impl MultiMatchAnalyzer {
+    /// - `binarize_threshold`: 匹配前对屏幕和模板进行二值化的阈值，为 [`BinarizeThreshold::Auto`]
+    ///   时分别对屏幕和模板使用 Otsu 法自动选取
+    /// - `threshold`: 匹配值（CCOEFF_NORMED，越高越好）的下限，见 [`MultiMatcher`]
     pub fn new(
         template_filename: String,
-        binarize_threshold: Option<u8>,
+        binarize_threshold: Option<BinarizeThreshold>,
         threshold: Option<f32>,
     ) -> Self {
         Self {
             template_filename,
             binarize_threshold,
             threshold,
+            iou_threshold: None,
+            roi: None,
+            use_cache: false,
+            annotate: true,
         }
     }
+
+    /// 只在 `roi`（1920x1080 下）区域内进行匹配，输出的位置仍为整个屏幕中的位置
+    ///
+    /// 不使用缓存时只截取 `roi` 区域（见 [`AAH::screen_cap_region`]），此时输出中的屏幕在区域外为黑色
+    pub fn roi(mut self, roi: utils::Rect) -> Self {
+        self.roi = Some(roi);
+        self
+    }
+
+    /// 设置去重时允许的最大 IoU，重叠超过该值的结果中只保留最好的一个，
+    /// 默认为 [`DEFAULT_IOU_THRESHOLD`](crate::vision::matcher::DEFAULT_IOU_THRESHOLD)
+    pub fn iou_threshold(mut self, iou_threshold: f32) -> Self {
+        self.iou_threshold = Some(iou_threshold);
+        self
+    }
+
+    /// 使用缓存中的屏幕内容（见 [`AAH::screen_cache_or_cap`]），而不是截取当前帧
+    pub fn use_cache(mut self, use_cache: bool) -> Self {
+        self.use_cache = use_cache;
+        self
+    }
+
+    /// 是否在输出中附带屏幕图像，默认为 `true`
+    ///
+    /// 不需要查看屏幕时（比如战斗中的循环分析）可以关闭，以免每次分析都复制一次屏幕
+    pub fn annotate(mut self, annotate: bool) -> Self {
+        self.annotate = annotate;
+        self
+    }
 }
 
 impl Analyzer for MultiMatchAnalyzer {
     type Output = MultiMatchAnalyzerOutput;
     fn analyze(&mut self, core: &AAH) -> Result<Self::Output, String> {
+        let screen = match (&self.roi, self.use_cache) {
+            (_, true) => core.screen_cache_or_cap()?,
+            (Some(roi), false) => core.screen_cap_region(roi)?,
+            (None, false) => core.screen_cap_and_cache()?,
+        };
+        self.analyze_image(core, &screen)
+    }
+
+    fn analyze_image(&mut self, core: &AAH, screen: &DynamicImage) -> Result<Self::Output, String> {
         // Make sure that we are in the operation-start page
         println!(
             "[TemplateMatchAnalyzer]: matching {:?}",
@@ -45,51 +132,52 @@ impl Analyzer for MultiMatchAnalyzer {
         //     .controller
         //     .screencap_scaled()
         //     .map_err(|err| format!("{:?}", err))?;
-        let screen = core
-            .controller
-            .screencap()
-            .map_err(|err| format!("{:?}", err))?;
-
-        let template = core.get_template(&self.template_filename).unwrap();
-
-        let template = if screen.height() != DEFAULT_HEIGHT {
-            let scale_factor = screen.height() as f32 / DEFAULT_HEIGHT as f32;
-
-            let new_width = (template.width() as f32 * scale_factor) as u32;
-            let new_height = (template.height() as f32 * scale_factor) as u32;
-
-            DynamicImage::ImageRgba8(image::imageops::resize(
-                &template,
-                new_width,
-                new_height,
-                image::imageops::FilterType::Lanczos3,
-            ))
-        } else {
-            template
-        };
+        let template = core.get_template_scaled(&self.template_filename, screen.height())?;
 
-        let mut image = screen.clone();
+        let (offset_x, offset_y, mut image) = crop_roi(screen, self.roi.as_ref());
+        if let Some(roi) = &self.roi {
+            if image.width() < template.width() || image.height() < template.height() {
+                return Err(format!("roi {:?} is smaller than the template", roi));
+            }
+        }
         let mut template = template;
         if let Some(threshold) = self.binarize_threshold {
-            image = binarize_image(&image, threshold);
-            template = binarize_image(&template, threshold);
+            image = binarize_image(&image, threshold.resolve(&image));
+            template = binarize_image(&template, threshold.resolve(&template));
         }
 
-        let rects = MultiMatcher::Template {
-            image: image.to_luma32f(),
-            template: template.to_luma32f(),
-            threshold: self.threshold,
-        }
+        let res = MultiMatcher::template(
+            image.to_luma32f(),
+            template.to_luma32f(),
+            self.threshold,
+            self.iou_threshold,
+        )
         .result()
         .ok_or("match failed".to_string())?;
-        Ok(Self::Output { screen, rects })
+        let rects = res
+            .rects
+            .into_iter()
+            .map(|rect| Rect {
+                x: rect.x + offset_x,
+                y: rect.y + offset_y,
+                ..rect
+            })
+            .collect();
+        Ok(Self::Output {
+            screen: self.annotate.then(|| screen.clone()),
+            rects,
+            scores: res.scores,
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
-        vision::analyzer::{multi_match::MultiMatchAnalyzer, Analyzer},
+        vision::{
+            analyzer::{multi_match::MultiMatchAnalyzer, Analyzer},
+            utils::BinarizeThreshold,
+        },
         AAH,
     };
 
@@ -98,7 +186,7 @@ mod test {
         let mut core = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
         let mut analyzer = MultiMatchAnalyzer::new(
             "battle_deploy-card-cost0".to_string(),
-            Some(127),
+            Some(BinarizeThreshold::Manual(127)),
             None,
         );
         let output = analyzer.analyze(&mut core).unwrap();
@@ -0,0 +1,299 @@
+use aah_cv::{best_match, MatchTemplateMethod};
+use image::{DynamicImage, ImageBuffer, Luma};
+use serde::Serialize;
+
+use crate::{controller::DEFAULT_HEIGHT, vision::utils::Rect, AAH};
+
+use super::Analyzer;
+
+/// 胜利横幅的模板
+pub const VICTORY_TEMPLATE: &str = "result_victory.png";
+/// 失败横幅的模板
+pub const DEFEAT_TEMPLATE: &str = "result_defeat.png";
+/// 点亮的星星的模板
+pub const STAR_TEMPLATE: &str = "result_star.png";
+/// 理智回复提示的模板，该提示有时会覆盖在结算画面上
+pub const SANITY_PROMPT_TEMPLATE: &str = "result_sanity-prompt.png";
+
+/// 结算画面中三颗星星所在的区域（1920x1080 下）
+pub const STAR_RECTS: [Rect; 3] = [
+    Rect {
+        x: 60,
+        y: 300,
+        width: 110,
+        height: 110,
+    },
+    Rect {
+        x: 170,
+        y: 300,
+        width: 110,
+        height: 110,
+    },
+    Rect {
+        x: 280,
+        y: 300,
+        width: 110,
+        height: 110,
+    },
+];
+
+/// 结算画面识别的默认阈值（CCOEFF_NORMED）
+pub const DEFAULT_RESULT_THRESHOLD: f32 = 0.8;
+
+/// 战斗结果
+///
+/// - `victory`: 是否胜利
+/// - `stars`: 点亮的星星数量，失败时为 0
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct BattleResult {
+    pub victory: bool,
+    pub stars: u8,
+}
+
+/// [`ResultAnalyzer`] 的输出
+///
+/// - `result`: 战斗结果，不在结算画面或结算画面被遮挡时为 [`None`]
+/// - `sanity_prompt`: 是否出现了理智回复提示，出现时需要先关闭提示才能读取结果
+#[derive(Debug, Serialize)]
+pub struct ResultAnalyzerOutput {
+    pub result: Option<BattleResult>,
+    pub sanity_prompt: bool,
+}
+
+/// [`ResultAnalyzer`] 使用的模板，均已按屏幕高度缩放并转换为灰度图
+pub struct ResultTemplates {
+    pub victory: ImageBuffer<Luma<f32>, Vec<f32>>,
+    pub defeat: ImageBuffer<Luma<f32>, Vec<f32>>,
+    pub star: ImageBuffer<Luma<f32>, Vec<f32>>,
+    pub sanity_prompt: ImageBuffer<Luma<f32>, Vec<f32>>,
+}
+
+impl ResultTemplates {
+    /// 通过 [`AAH::get_template_scaled`] 获取按 `height` 缩放后的模板，缩放结果会被缓存
+    pub fn load(core: &AAH, height: u32) -> Result<Self, String> {
+        let load = |name| {
+            core.get_template_scaled(name, height)
+                .map(|template| template.to_luma32f())
+        };
+        Ok(Self {
+            victory: load(VICTORY_TEMPLATE)?,
+            defeat: load(DEFEAT_TEMPLATE)?,
+            star: load(STAR_TEMPLATE)?,
+            sanity_prompt: load(SANITY_PROMPT_TEMPLATE)?,
+        })
+    }
+}
+
+/// 分析战斗结算画面，识别胜负以及星数
+///
+/// 需要 [`VICTORY_TEMPLATE`] 等模板，可以通过 [`ResultAnalyzer::missing_templates`] 检查它们是否存在
+pub struct ResultAnalyzer {
+    threshold: f32,
+}
+
+impl ResultAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            threshold: DEFAULT_RESULT_THRESHOLD,
+        }
+    }
+
+    /// 设置识别阈值
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// 返回资源目录中缺少的结算画面模板
+    pub fn missing_templates(core: &AAH) -> Vec<&'static str> {
+        [
+            VICTORY_TEMPLATE,
+            DEFEAT_TEMPLATE,
+            STAR_TEMPLATE,
+            SANITY_PROMPT_TEMPLATE,
+        ]
+        .into_iter()
+        .filter(|name| {
+            !core
+                .res_dir
+                .join("templates")
+                .join("1920x1080")
+                .join(name)
+                .exists()
+        })
+        .collect()
+    }
+
+    /// 在 `image` 中匹配 `template`，模板比 `image` 大时视为未匹配
+    fn matches(
+        &self,
+        image: &ImageBuffer<Luma<f32>, Vec<f32>>,
+        template: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    ) -> Result<bool, String> {
+        if template.width() == 0 || template.height() == 0 {
+            return Err("[ResultAnalyzer]: template is empty".to_string());
+        }
+        if template.width() > image.width() || template.height() > image.height() {
+            return Ok(false);
+        }
+        let res = best_match(image, template, MatchTemplateMethod::CCOEFF_NORMED)?;
+        Ok(res.value > self.threshold)
+    }
+
+    /// 在 [`STAR_RECTS`] 的每个区域中匹配点亮的星星，返回点亮的数量
+    fn count_stars(
+        &self,
+        image: &ImageBuffer<Luma<f32>, Vec<f32>>,
+        star: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    ) -> Result<u8, String> {
+        let scale_factor = image.height() as f32 / DEFAULT_HEIGHT as f32;
+        let mut stars = 0;
+        for rect in STAR_RECTS.iter() {
+            let x = ((rect.x as f32 * scale_factor) as u32).min(image.width());
+            let y = ((rect.y as f32 * scale_factor) as u32).min(image.height());
+            let width = ((rect.width as f32 * scale_factor) as u32).min(image.width() - x);
+            let height = ((rect.height as f32 * scale_factor) as u32).min(image.height() - y);
+            let cropped = image::imageops::crop_imm(image, x, y, width, height).to_image();
+            if self.matches(&cropped, star)? {
+                stars += 1;
+            }
+        }
+        Ok(stars)
+    }
+
+    /// 使用已经缩放好的 `templates` 分析灰度图 `image`
+    pub fn analyze_with(
+        &self,
+        image: &ImageBuffer<Luma<f32>, Vec<f32>>,
+        templates: &ResultTemplates,
+    ) -> Result<ResultAnalyzerOutput, String> {
+        if self.matches(image, &templates.sanity_prompt)? {
+            println!("[ResultAnalyzer]: sanity prompt is covering the result");
+            return Ok(ResultAnalyzerOutput {
+                result: None,
+                sanity_prompt: true,
+            });
+        }
+
+        let result = if self.matches(image, &templates.victory)? {
+            Some(BattleResult {
+                victory: true,
+                stars: self.count_stars(image, &templates.star)?,
+            })
+        } else if self.matches(image, &templates.defeat)? {
+            Some(BattleResult {
+                victory: false,
+                stars: 0,
+            })
+        } else {
+            None
+        };
+
+        println!("[ResultAnalyzer]: {:?}", result);
+        Ok(ResultAnalyzerOutput {
+            result,
+            sanity_prompt: false,
+        })
+    }
+}
+
+impl Default for ResultAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analyzer for ResultAnalyzer {
+    type Output = ResultAnalyzerOutput;
+    fn analyze_image(&mut self, core: &AAH, image: &DynamicImage) -> Result<Self::Output, String> {
+        let templates = ResultTemplates::load(core, image.height())?;
+        self.analyze_with(&image.to_luma32f(), &templates)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{ImageBuffer, Luma};
+
+    use crate::{vision::analyzer::Analyzer, AAH};
+
+    use super::{BattleResult, ResultAnalyzer, ResultTemplates};
+
+    /// 以 `seed` 生成的带纹理的图案，用作模板
+    fn pattern(width: u32, height: u32, seed: u32) -> ImageBuffer<Luma<f32>, Vec<f32>> {
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let v = (x * 7 + y * 13 + seed * 31) ^ (x * y + seed);
+            Luma([(v % 97) as f32 / 97.0])
+        })
+    }
+
+    fn paste(
+        screen: &mut ImageBuffer<Luma<f32>, Vec<f32>>,
+        patch: &ImageBuffer<Luma<f32>, Vec<f32>>,
+        (x, y): (u32, u32),
+    ) {
+        image::imageops::replace(screen, patch, x as i64, y as i64);
+    }
+
+    #[test]
+    fn test_analyze_with() {
+        // 240x135 的画面，即 1920x1080 缩小为 1/8，前两颗星星的区域为 (7, 37) 和 (21, 37)，大小为 13x13
+        let templates = ResultTemplates {
+            victory: pattern(40, 12, 1),
+            defeat: pattern(40, 12, 2),
+            star: pattern(10, 10, 3),
+            sanity_prompt: pattern(30, 15, 4),
+        };
+        let background = || ImageBuffer::from_pixel(240, 135, Luma([0.5f32]));
+        let analyzer = ResultAnalyzer::new();
+
+        // 胜利，点亮了前两颗星星
+        let mut screen = background();
+        paste(&mut screen, &templates.victory, (100, 10));
+        paste(&mut screen, &templates.star, (9, 39));
+        paste(&mut screen, &templates.star, (22, 39));
+        let output = analyzer.analyze_with(&screen, &templates).unwrap();
+        println!("{:?}", output);
+        assert_eq!(
+            output.result,
+            Some(BattleResult {
+                victory: true,
+                stars: 2
+            })
+        );
+        assert!(!output.sanity_prompt);
+
+        // 失败
+        let mut screen = background();
+        paste(&mut screen, &templates.defeat, (100, 10));
+        let output = analyzer.analyze_with(&screen, &templates).unwrap();
+        assert_eq!(
+            output.result,
+            Some(BattleResult {
+                victory: false,
+                stars: 0
+            })
+        );
+
+        // 理智回复提示覆盖了结算画面
+        let mut screen = background();
+        paste(&mut screen, &templates.victory, (100, 10));
+        paste(&mut screen, &templates.sanity_prompt, (150, 75));
+        let output = analyzer.analyze_with(&screen, &templates).unwrap();
+        assert_eq!(output.result, None);
+        assert!(output.sanity_prompt);
+
+        // 不在结算画面
+        let output = analyzer.analyze_with(&background(), &templates).unwrap();
+        assert_eq!(output.result, None);
+        assert!(!output.sanity_prompt);
+    }
+
+    #[test]
+    fn test_result_analyzer() {
+        let core = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
+        let mut analyzer = ResultAnalyzer::new();
+        let output = analyzer.analyze(&core).unwrap();
+        println!("{:?}", output);
+    }
+}
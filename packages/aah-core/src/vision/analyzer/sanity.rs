@@ -0,0 +1,137 @@
+use image::DynamicImage;
+use ocrs::OcrEngine;
+use serde::Serialize;
+
+use crate::{
+    vision::{
+        ocr::{ocr_region, DIGITS},
+        utils::Rect,
+    },
+    AAH,
+};
+
+use super::Analyzer;
+
+/// 识别理智时的白名单，数字和分隔当前值与上限的 `/`
+pub const SANITY_WHITELIST: &str = "0123456789/";
+
+/// 关卡选择等界面右上角 `当前/上限` 形式的理智所在区域（1920x1080 下）
+pub const SANITY_RECT: Rect = Rect {
+    x: 1700,
+    y: 10,
+    width: 210,
+    height: 80,
+};
+
+/// 主页终端按钮上的当前理智（大号数字）所在区域（1920x1080 下）
+pub const MAIN_SANITY_CURRENT_RECT: Rect = Rect {
+    x: 1140,
+    y: 195,
+    width: 190,
+    height: 125,
+};
+
+/// 主页终端按钮上的理智上限（`理智/上限`）所在区域（1920x1080 下）
+pub const MAIN_SANITY_MAX_RECT: Rect = Rect {
+    x: 1235,
+    y: 345,
+    width: 100,
+    height: 55,
+};
+
+/// 从 `当前/上限` 形式的 OCR 识别结果中解析理智，取 `/` 前最后一段数字和 `/` 后第一段数字
+///
+/// 当前值可能超过上限（比如使用了理智药剂），此时照常返回，由调用方判断。
+/// 没有 `/`、缺少任意一个数字或上限为 0 时返回 [`None`]
+pub fn parse_sanity(text: &str) -> Option<(u32, u32)> {
+    let (current, max) = text.split_once('/')?;
+    let digit_runs = |s: &str| {
+        s.split(|c: char| !c.is_ascii_digit())
+            .filter(|run| !run.is_empty())
+            .map(|run| run.to_string())
+            .collect::<Vec<_>>()
+    };
+    let current = digit_runs(current).pop()?.parse().ok()?;
+    let max = digit_runs(max).into_iter().next()?.parse().ok()?;
+    if max == 0 {
+        return None;
+    }
+    Some((current, max))
+}
+
+/// 通过 OCR 读取 `screen` 中的理智 `(当前, 上限)`
+///
+/// 先尝试右上角的 [`SANITY_RECT`]（关卡选择等界面），失败时按主页的布局分别读取当前值和上限
+pub fn read_sanity(engine: &OcrEngine, screen: &DynamicImage) -> Result<(u32, u32), String> {
+    let text = ocr_region(engine, screen, &SANITY_RECT, Some(SANITY_WHITELIST))?;
+    if let Some(sanity) = parse_sanity(&text) {
+        return Ok(sanity);
+    }
+
+    // 主页上当前值与上限分开显示，当前值的数字较大，识别结果中可能夹杂空格
+    let current = ocr_region(engine, screen, &MAIN_SANITY_CURRENT_RECT, Some(DIGITS))?
+        .split_whitespace()
+        .collect::<String>();
+    let max = ocr_region(
+        engine,
+        screen,
+        &MAIN_SANITY_MAX_RECT,
+        Some(SANITY_WHITELIST),
+    )?;
+    let main_text = format!("{current}{}", max.trim());
+    parse_sanity(&main_text).ok_or(format!(
+        "failed to parse sanity from {:?} or {:?}",
+        text, main_text
+    ))
+}
+
+/// [`SanityAnalyzer`] 的输出
+///
+/// - `current`: 当前理智，可能超过上限
+/// - `max`: 理智上限
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct SanityAnalyzerOutput {
+    pub current: u32,
+    pub max: u32,
+}
+
+/// 通过 OCR 读取主页或关卡选择界面中的理智，见 [`read_sanity`]
+#[derive(Default)]
+pub struct SanityAnalyzer;
+
+impl SanityAnalyzer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Analyzer for SanityAnalyzer {
+    type Output = SanityAnalyzerOutput;
+    fn analyze_image(&mut self, core: &AAH, image: &DynamicImage) -> Result<Self::Output, String> {
+        let (current, max) = read_sanity(core.try_ocr_engine()?, image)?;
+        println!("[SanityAnalyzer]: {current}/{max}");
+        Ok(SanityAnalyzerOutput { current, max })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_sanity() {
+        assert_eq!(parse_sanity("115/132"), Some((115, 132)));
+        assert_eq!(parse_sanity(" 82 / 135\n"), Some((82, 135)));
+        // 理智已满或溢出
+        assert_eq!(parse_sanity("135/135"), Some((135, 135)));
+        assert_eq!(parse_sanity("256/135"), Some((256, 135)));
+        // 前面的杂项数字（比如图标被识别为数字）被忽略
+        assert_eq!(parse_sanity("1 115/132"), Some((115, 132)));
+
+        assert_eq!(parse_sanity("115132"), None);
+        assert_eq!(parse_sanity("/132"), None);
+        assert_eq!(parse_sanity("115/"), None);
+        assert_eq!(parse_sanity("115/0"), None);
+        assert_eq!(parse_sanity(""), None);
+    }
+}
@@ -0,0 +1,199 @@
+use aah_cv::{best_match, MatchTemplateMethod};
+use image::{DynamicImage, ImageBuffer, Luma};
+use serde::Serialize;
+
+use crate::AAH;
+
+use super::Analyzer;
+
+/// 场景识别的默认阈值（CCOEFF_NORMED）
+pub const DEFAULT_SCENE_THRESHOLD: f32 = 0.8;
+
+/// 游戏中的场景
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Scene {
+    /// 标题界面（启动游戏后）
+    Start,
+    /// 主页
+    Main,
+    /// 关卡选择（作战开始前）
+    OperationSelect,
+    /// 编队
+    SquadEdit,
+    /// 战斗中
+    Battle,
+    /// 战斗结算
+    Result,
+}
+
+/// 默认的场景锚点模板，位于 `resources/templates/1920x1080` 下
+///
+/// [`Scene::SquadEdit`] 和 [`Scene::Result`] 暂时没有锚点模板，需要识别它们时可以通过 [`SceneAnalyzer::new`] 指定
+pub fn default_scene_anchors() -> Vec<(Scene, String)> {
+    vec![
+        (Scene::Start, "start_start.png".to_string()),
+        (Scene::Main, "main_base.png".to_string()),
+        (Scene::OperationSelect, "operation-start_start.png".to_string()),
+        (Scene::Battle, "battle_deploy-card-cost-icon1.png".to_string()),
+    ]
+}
+
+/// [`SceneAnalyzer`] 的输出
+///
+/// - `scene`: 匹配值最高的场景，所有锚点均低于阈值时为 [`None`]
+/// - `confidence`: 对应的匹配值
+#[derive(Debug, Serialize)]
+pub struct SceneAnalyzerOutput {
+    pub scene: Option<Scene>,
+    pub confidence: f32,
+}
+
+/// 将屏幕与一系列带标签的锚点模板进行匹配，得出当前所处的 [`Scene`]
+pub struct SceneAnalyzer {
+    anchors: Vec<(Scene, String)>,
+    threshold: f32,
+}
+
+impl SceneAnalyzer {
+    pub fn new(anchors: Vec<(Scene, String)>) -> Self {
+        Self {
+            anchors,
+            threshold: DEFAULT_SCENE_THRESHOLD,
+        }
+    }
+
+    /// 设置识别阈值，最佳匹配值低于该阈值时认为无法识别
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// 将灰度图 `image` 与已经按其高度缩放好的锚点模板 `anchors` 匹配
+    pub fn analyze_with(
+        &self,
+        image: &ImageBuffer<Luma<f32>, Vec<f32>>,
+        anchors: &[(Scene, DynamicImage)],
+    ) -> Result<SceneAnalyzerOutput, String> {
+        let mut res = SceneAnalyzerOutput {
+            scene: None,
+            confidence: 0.0,
+        };
+        for (scene, template) in anchors {
+            let template = template.to_luma32f();
+            if template.width() == 0
+                || template.height() == 0
+                || template.width() > image.width()
+                || template.height() > image.height()
+            {
+                return Err(format!(
+                    "[SceneAnalyzer]: anchor of {:?} {}x{} doesn't fit in the image {}x{}",
+                    scene,
+                    template.width(),
+                    template.height(),
+                    image.width(),
+                    image.height()
+                ));
+            }
+
+            let value = best_match(image, &template, MatchTemplateMethod::CCOEFF_NORMED)?.value;
+            if value > self.threshold && (res.scene.is_none() || value > res.confidence) {
+                res = SceneAnalyzerOutput {
+                    scene: Some(*scene),
+                    confidence: value,
+                };
+            }
+        }
+
+        println!("[SceneAnalyzer]: {:?}", res);
+        Ok(res)
+    }
+}
+
+impl Default for SceneAnalyzer {
+    fn default() -> Self {
+        Self::new(default_scene_anchors())
+    }
+}
+
+impl Analyzer for SceneAnalyzer {
+    type Output = SceneAnalyzerOutput;
+    fn analyze_image(&mut self, core: &AAH, image: &DynamicImage) -> Result<Self::Output, String> {
+        let anchors = self
+            .anchors
+            .iter()
+            .map(|(scene, template_filename)| {
+                core.get_template_scaled(template_filename, image.height())
+                    .map(|template| (*scene, template))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.analyze_with(&image.to_luma32f(), &anchors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::{
+        vision::{
+            analyzer::{multi_match::scale_template, Analyzer},
+            matcher::test::{get_device_image, Device},
+        },
+        AAH,
+    };
+
+    use super::{default_scene_anchors, Scene, SceneAnalyzer};
+
+    #[test]
+    fn test_default_scene_anchors_exist() {
+        let templates_dir = Path::new("../../resources/templates/1920x1080");
+        for (scene, template) in default_scene_anchors() {
+            assert!(
+                templates_dir.join(&template).exists(),
+                "anchor of {scene:?} {template:?} doesn't exist"
+            );
+        }
+    }
+
+    #[test]
+    fn test_analyze_with() {
+        // 只匹配截图中锚点附近 240x460 的区域（足够放下所有锚点），并缩小一半以加快匹配
+        let (width, height) = (240, 460);
+        let anchors = default_scene_anchors()
+            .into_iter()
+            .map(|(scene, template)| {
+                let template =
+                    image::open(format!("../../resources/templates/1920x1080/{template}")).unwrap();
+                (scene, scale_template(template, 540))
+            })
+            .collect::<Vec<_>>();
+        let analyzer = SceneAnalyzer::default();
+
+        // 截图，期望的场景，锚点在截图中的位置
+        for (filename, expected, (x, y)) in [
+            ("start.png", Scene::Start, (937, 1001)),
+            ("main.png", Scene::Main, (1388, 859)),
+            ("operation-start.png", Scene::OperationSelect, (1556, 551)),
+            ("battle0.png", Scene::Battle, (1844, 886)),
+        ] {
+            let x = x.min(1920 - width);
+            let y = y.min(1080 - height);
+            let image = get_device_image(Device::MUMU, filename)
+                .unwrap()
+                .crop_imm(x, y, width, height)
+                .resize_exact(width / 2, height / 2, image::imageops::FilterType::Lanczos3)
+                .to_luma32f();
+            let output = analyzer.analyze_with(&image, &anchors).unwrap();
+            println!("{filename}: {:?}", output);
+            assert_eq!(output.scene, Some(expected), "{filename}");
+        }
+    }
+
+    #[test]
+    fn test_scene_analyzer() {
+        let core = AAH::connect("127.0.0.1:16384", "../../resources").unwrap();
+        let mut analyzer = SceneAnalyzer::default();
+        let output = analyzer.analyze(&core).unwrap();
+        println!("{:?}", output);
+    }
+}
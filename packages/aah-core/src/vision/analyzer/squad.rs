@@ -1,21 +1,222 @@
-use crate::{task::{match_task::MatchTask, Task}, AAH};
+use image::DynamicImage;
+
+use crate::{
+    controller::DEFAULT_HEIGHT,
+    vision::{
+        analyzer::deploy::DEFAULT_OPER_THRESHOLD, matcher::best_matcher::best_match_labeled,
+        utils::Rect,
+    },
+    AAH,
+};
 
 use super::Analyzer;
 
+/// 编队界面每行的槽位数
+pub const SQUAD_SLOT_COLUMNS: u32 = 6;
+/// 编队界面的行数
+pub const SQUAD_SLOT_ROWS: u32 = 2;
+/// 第一个槽位左上角的位置（1920x1080 下）
+pub const SQUAD_SLOT_ORIGIN: (u32, u32) = (322, 144);
+/// 槽位的尺寸（1920x1080 下）
+pub const SQUAD_SLOT_SIZE: (u32, u32) = (180, 378);
+/// 相邻槽位左上角之间的距离（1920x1080 下）
+pub const SQUAD_SLOT_PITCH: (u32, u32) = (214, 437);
+
+/// 编队界面中所有槽位（1920x1080 下）的位置，按从左到右、从上到下的顺序
+pub fn default_squad_slots() -> Vec<Rect> {
+    (0..SQUAD_SLOT_ROWS)
+        .flat_map(|row| (0..SQUAD_SLOT_COLUMNS).map(move |col| (row, col)))
+        .map(|(row, col)| Rect {
+            x: SQUAD_SLOT_ORIGIN.0 + col * SQUAD_SLOT_PITCH.0,
+            y: SQUAD_SLOT_ORIGIN.1 + row * SQUAD_SLOT_PITCH.1,
+            width: SQUAD_SLOT_SIZE.0,
+            height: SQUAD_SLOT_SIZE.1,
+        })
+        .collect()
+}
+
 #[derive(Debug)]
+/// [`SquadAnalyzer`] 的输出
+///
+/// - `slots`: 每个槽位中的干员，按 [`SquadAnalyzer::with_slots`] 的顺序，空槽位或无法识别时为 [`None`]
 pub struct SquadAnalyzerOutput {
-
+    pub slots: Vec<Option<String>>,
 }
 
+/// 分析编队界面中每个槽位的干员，可以在部署前确认编队是否正确
+///
+/// 将每个槽位与 [`SquadAnalyzer::new`] 指定的干员的所有头像（见 [`AAH::get_oper_avatars`]）进行匹配，
+/// 匹配值最高且达到阈值的干员即为槽位中的干员，都没有达到阈值的槽位（比如空槽位）为 [`None`]
 pub struct SquadAnalyzer {
+    opers: Vec<String>,
+    slots: Vec<Rect>,
+    oper_threshold: f32,
+    /// 已加载的头像，`(干员名, 头像)`，在第一次分析时加载
+    avatars: Option<Vec<(String, DynamicImage)>>,
+}
+
+impl SquadAnalyzer {
+    /// `opers`: 可能出现在编队中的干员（比如 `char_102_texas`）
+    pub fn new<S: AsRef<str>>(opers: Vec<S>) -> Self {
+        Self {
+            opers: opers.iter().map(|s| s.as_ref().to_string()).collect(),
+            slots: default_squad_slots(),
+            oper_threshold: DEFAULT_OPER_THRESHOLD,
+            avatars: None,
+        }
+    }
+
+    /// 设置槽位的位置（1920x1080 下），默认为 [`default_squad_slots`]
+    pub fn with_slots(mut self, slots: Vec<Rect>) -> Self {
+        self.slots = slots;
+        self
+    }
+
+    /// 设置干员头像匹配的阈值，默认为 [`DEFAULT_OPER_THRESHOLD`]
+    pub fn with_oper_threshold(mut self, threshold: f32) -> Self {
+        self.oper_threshold = threshold;
+        self
+    }
+
+    fn load_avatars(&mut self, core: &AAH) -> Result<(), String> {
+        if self.avatars.is_some() {
+            return Ok(());
+        }
+        let mut avatars = vec![];
+        for oper in &self.opers {
+            for (_, avatar) in core.get_oper_avatars(oper)? {
+                avatars.push((oper.clone(), avatar));
+            }
+        }
+        self.avatars = Some(avatars);
+        Ok(())
+    }
+
+    /// 识别 `image` 中每个槽位的干员，槽位超出 `image` 时返回 `Err`
+    fn recognize_slots(&self, image: &DynamicImage) -> Result<Vec<Option<String>>, String> {
+        let avatars = self.avatars.as_deref().unwrap_or_default();
+        let scale_factor = image.height() as f32 / DEFAULT_HEIGHT as f32;
+        let scale = |v: u32| (v as f32 * scale_factor) as u32;
+
+        self.slots
+            .iter()
+            .map(|slot| {
+                let (x, y, width, height) = (
+                    scale(slot.x),
+                    scale(slot.y),
+                    scale(slot.width),
+                    scale(slot.height),
+                );
+                if x + width > image.width() || y + height > image.height() {
+                    return Err(format!(
+                        "squad slot {slot:?} is out of the image {}x{}",
+                        image.width(),
+                        image.height()
+                    ));
+                }
+                let slot = image.crop_imm(x, y, width, height).to_luma32f();
 
+                // 头像缩放到槽位宽度，在槽位范围内匹配
+                let templates = avatars
+                    .iter()
+                    .map(|(oper, avatar)| {
+                        let template = avatar
+                            .resize_exact(width, width, image::imageops::FilterType::Lanczos3)
+                            .to_luma32f();
+                        (oper.clone(), template)
+                    })
+                    .collect();
+                Ok(
+                    best_match_labeled(&slot, templates, Some(self.oper_threshold))
+                        .map(|(oper, _, _)| oper),
+                )
+            })
+            .collect()
+    }
 }
 
 impl Analyzer for SquadAnalyzer {
     type Output = SquadAnalyzerOutput;
-    fn analyze(&mut self, core: &AAH) -> Result<Self::Output, String> {
-        // Make sure that we are in the operation-start page
-        MatchTask::Template("operation-start_start.png".to_string()).run(core)?;
-        
+
+    fn analyze_image(&mut self, core: &AAH, image: &DynamicImage) -> Result<Self::Output, String> {
+        self.load_avatars(core)?;
+        Ok(SquadAnalyzerOutput {
+            slots: self.recognize_slots(image)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    /// 每个像素都不同的图像，作为干员头像
+    fn avatar(seed: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(64, 64, |x, y| {
+            let v = ((x * 7 + y * 13 + seed * 31) ^ (x * y + seed)) % 256;
+            Rgba([v as u8, (v * 3 % 256) as u8, (255 - v) as u8, 255])
+        }))
+    }
+
+    #[test]
+    fn test_squad_slots() {
+        let slots = default_squad_slots();
+        assert_eq!(slots.len(), (SQUAD_SLOT_COLUMNS * SQUAD_SLOT_ROWS) as usize);
+        let last = slots.last().unwrap();
+        assert!(last.x + last.width <= 1920 && last.y + last.height <= 1080);
+    }
+
+    #[test]
+    fn test_recognize_slots() {
+        // 较小的槽位，减少匹配的开销
+        let slots = (0..3)
+            .map(|i| Rect {
+                x: 100 + i * 120,
+                y: 200,
+                width: 60,
+                height: 120,
+            })
+            .collect::<Vec<_>>();
+        let mut analyzer =
+            SquadAnalyzer::new(vec!["char_102_texas", "char_103_angel"]).with_slots(slots.clone());
+        analyzer.avatars = Some(vec![
+            ("char_102_texas".to_string(), avatar(1)),
+            ("char_103_angel".to_string(), avatar(2)),
+        ]);
+
+        // 第一个槽位为 texas，第三个槽位为 angel，第二个为空
+        let mut screen = DynamicImage::new_rgba8(1920, 1080);
+        for (idx, seed) in [(0, 1), (2, 2)] {
+            let slot = &slots[idx];
+            let avatar = avatar(seed).resize_exact(
+                slot.width,
+                slot.width,
+                image::imageops::FilterType::Lanczos3,
+            );
+            image::imageops::overlay(&mut screen, &avatar, slot.x as i64, slot.y as i64 + 30);
+        }
+
+        let res = analyzer.recognize_slots(&screen).unwrap();
+        println!("{res:?}");
+        let mut expected = vec![None; slots.len()];
+        expected[0] = Some("char_102_texas".to_string());
+        expected[2] = Some("char_103_angel".to_string());
+        assert_eq!(res, expected);
+
+        // 按高度缩放槽位
+        let screen = screen.resize_exact(1280, 720, image::imageops::FilterType::Triangle);
+        assert_eq!(analyzer.recognize_slots(&screen).unwrap(), expected);
+
+        let analyzer = SquadAnalyzer::new(Vec::<String>::new()).with_slots(vec![Rect {
+            x: 1900,
+            y: 0,
+            width: 100,
+            height: 100,
+        }]);
+        assert!(analyzer
+            .recognize_slots(&DynamicImage::new_rgba8(1920, 1080))
+            .is_err());
     }
-}
\ No newline at end of file
+}
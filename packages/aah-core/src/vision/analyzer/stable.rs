@@ -0,0 +1,169 @@
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+
+use crate::AAH;
+
+use super::Analyzer;
+
+/// 判断画面静止的默认阈值，即相邻两帧灰度的平均绝对差（0~255）
+pub const DEFAULT_DIFF_THRESHOLD: f32 = 2.0;
+/// 默认需要连续多少帧的差异低于阈值才认为画面静止
+pub const DEFAULT_STABLE_FRAMES: usize = 3;
+/// 计算差异前将画面缩小到的宽度，高度按比例缩放
+pub const DIFF_WIDTH: u32 = 160;
+
+/// 将 `image` 缩小到 [`DIFF_WIDTH`] 宽的灰度图，用于计算帧间差异
+pub fn downsample(image: &DynamicImage) -> GrayImage {
+    let height = (image.height() as u64 * DIFF_WIDTH as u64 / image.width().max(1) as u64).max(1);
+    image
+        .resize_exact(DIFF_WIDTH, height as u32, FilterType::Triangle)
+        .to_luma8()
+}
+
+/// 两张同样尺寸的灰度图逐像素差的绝对值的平均值，尺寸不同时返回 [`f32::MAX`]
+pub fn mean_abs_diff(a: &GrayImage, b: &GrayImage) -> f32 {
+    if a.dimensions() != b.dimensions() || a.is_empty() {
+        return f32::MAX;
+    }
+    let sum: u64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&a, &b)| a.abs_diff(b) as u64)
+        .sum();
+    sum as f32 / a.as_raw().len() as f32
+}
+
+/// [`StableScreenAnalyzer`] 的输出
+///
+/// - `stable`: 是否已经连续 [`StableScreenAnalyzer::with_stable_frames`] 帧低于阈值
+/// - `diff`: 与上一帧的差异，第一帧时为 [`None`]
+/// - `stable_frames`: 当前连续低于阈值的帧数
+#[derive(Debug)]
+pub struct StableScreenAnalyzerOutput {
+    pub stable: bool,
+    pub diff: Option<f32>,
+    pub stable_frames: usize,
+}
+
+/// 对连续的画面进行分析，判断画面是否已经停止变化（比如动画结束、加载完成）
+///
+/// 每一帧缩小后（见 [`downsample`]）与上一帧比较，平均绝对差低于阈值的帧连续出现
+/// [`StableScreenAnalyzer::with_stable_frames`] 次后认为画面静止，见 [`AAH::wait_until_stable`]
+pub struct StableScreenAnalyzer {
+    threshold: f32,
+    stable_frames: usize,
+    prev: Option<GrayImage>,
+    cur_stable_frames: usize,
+}
+
+impl StableScreenAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            threshold: DEFAULT_DIFF_THRESHOLD,
+            stable_frames: DEFAULT_STABLE_FRAMES,
+            prev: None,
+            cur_stable_frames: 0,
+        }
+    }
+
+    /// 设置帧间平均绝对差（0~255）的阈值，默认为 [`DEFAULT_DIFF_THRESHOLD`]
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// 设置需要连续低于阈值的帧数，默认为 [`DEFAULT_STABLE_FRAMES`]
+    pub fn with_stable_frames(mut self, stable_frames: usize) -> Self {
+        self.stable_frames = stable_frames.max(1);
+        self
+    }
+
+    /// 清除之前的帧，重新开始计数
+    pub fn reset(&mut self) {
+        self.prev = None;
+        self.cur_stable_frames = 0;
+    }
+
+    /// 分析新的一帧，不需要 [`AAH`]
+    pub fn push_frame(&mut self, image: &DynamicImage) -> StableScreenAnalyzerOutput {
+        let cur = downsample(image);
+        let diff = self.prev.as_ref().map(|prev| mean_abs_diff(prev, &cur));
+        match diff {
+            Some(diff) if diff < self.threshold => self.cur_stable_frames += 1,
+            _ => self.cur_stable_frames = 0,
+        }
+        self.prev = Some(cur);
+
+        StableScreenAnalyzerOutput {
+            stable: self.cur_stable_frames >= self.stable_frames,
+            diff,
+            stable_frames: self.cur_stable_frames,
+        }
+    }
+}
+
+impl Default for StableScreenAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analyzer for StableScreenAnalyzer {
+    type Output = StableScreenAnalyzerOutput;
+
+    fn analyze_image(&mut self, _core: &AAH, image: &DynamicImage) -> Result<Self::Output, String> {
+        Ok(self.push_frame(image))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    fn frame(offset: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(1920, 1080, |x, y| {
+            let v = if (x + offset) % 400 < 200 { 255 } else { 0 };
+            Rgba([v, v, (y % 256) as u8, 255])
+        }))
+    }
+
+    #[test]
+    fn test_mean_abs_diff() {
+        let a = GrayImage::from_pixel(4, 4, image::Luma([10]));
+        let b = GrayImage::from_pixel(4, 4, image::Luma([14]));
+        assert_eq!(mean_abs_diff(&a, &b), 4.0);
+        assert_eq!(mean_abs_diff(&b, &a), 4.0);
+        assert_eq!(mean_abs_diff(&a, &GrayImage::new(2, 2)), f32::MAX);
+        assert_eq!(downsample(&frame(0)).dimensions(), (DIFF_WIDTH, 90));
+    }
+
+    #[test]
+    fn test_stable_screen_analyzer() {
+        let mut analyzer = StableScreenAnalyzer::new().with_stable_frames(2);
+
+        // 画面在移动
+        for offset in [0, 50, 100] {
+            let output = analyzer.push_frame(&frame(offset));
+            println!("{output:?}");
+            assert!(!output.stable);
+        }
+        assert_eq!(analyzer.cur_stable_frames, 0);
+
+        // 画面静止
+        let output = analyzer.push_frame(&frame(100));
+        assert_eq!(output.diff, Some(0.0));
+        assert!(!output.stable);
+        let output = analyzer.push_frame(&frame(100));
+        assert!(output.stable);
+
+        // 再次变化后重新计数
+        let output = analyzer.push_frame(&frame(150));
+        assert!(!output.stable);
+        assert_eq!(output.stable_frames, 0);
+
+        analyzer.reset();
+        assert_eq!(analyzer.push_frame(&frame(150)).diff, None);
+    }
+}
@@ -0,0 +1,185 @@
+use aah_cv::MatchTemplateMethod;
+use image::DynamicImage;
+use serde::Serialize;
+
+use crate::{
+    controller::{DEFAULT_HEIGHT, DEFAULT_WIDTH},
+    vision::{analyzer::multi_match::crop_roi, matcher::best_matcher::BestMatcher, utils::Rect},
+    AAH,
+};
+
+use super::Analyzer;
+
+/// 干员菜单中技能按钮的模板（位于 `resources/templates/1920x1080` 下）
+pub const SKILL_BUTTON_TEMPLATE: &str = "battle_unit-menu-skill.png";
+/// 干员菜单中撤退按钮的模板（位于 `resources/templates/1920x1080` 下）
+pub const RETREAT_BUTTON_TEMPLATE: &str = "battle_unit-menu-retreat.png";
+
+/// 干员菜单的按钮距离干员位置的最大距离（1920x1080 下），见 [`unit_menu_roi`]
+pub const UNIT_MENU_RADIUS: u32 = 300;
+
+/// 按钮匹配的默认阈值（CCOEFF_NORMED）
+pub const DEFAULT_UNIT_MENU_THRESHOLD: f32 = 0.8;
+
+/// 以干员位置 `pos`（1920x1080 下）为中心、边长为 `2 * radius` 的区域，限制在屏幕范围内
+pub fn unit_menu_roi(pos: (u32, u32), radius: u32) -> Rect {
+    let x = pos.0.saturating_sub(radius).min(DEFAULT_WIDTH);
+    let y = pos.1.saturating_sub(radius).min(DEFAULT_HEIGHT);
+    Rect {
+        x,
+        y,
+        width: (pos.0 + radius).min(DEFAULT_WIDTH).saturating_sub(x),
+        height: (pos.1 + radius).min(DEFAULT_HEIGHT).saturating_sub(y),
+    }
+}
+
+/// [`UnitMenuAnalyzer`] 的输出，位置均为屏幕坐标，可直接用于 [`Controller::click_in_rect`](crate::controller::Controller::click_in_rect)
+///
+/// - `skill`: 技能按钮的位置，技能未就绪或为被动技能时为 [`None`]
+/// - `retreat`: 撤退按钮的位置，菜单未打开时为 [`None`]
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct UnitMenuAnalyzerOutput {
+    pub skill: Option<Rect>,
+    pub retreat: Option<Rect>,
+}
+
+impl UnitMenuAnalyzerOutput {
+    /// 菜单是否已打开，打开的菜单中总会有撤退按钮
+    pub fn is_open(&self) -> bool {
+        self.retreat.is_some()
+    }
+}
+
+/// 点击已部署的干员后，识别弹出的干员菜单中的技能按钮和撤退按钮
+///
+/// 按钮的模板为 [`SKILL_BUTTON_TEMPLATE`] 和 [`RETREAT_BUTTON_TEMPLATE`]。
+/// 点击没有命中干员时菜单不会打开，此时输出中的按钮均为 [`None`]，见 [`UnitMenuAnalyzerOutput::is_open`]
+pub struct UnitMenuAnalyzer {
+    roi: Option<Rect>,
+    threshold: f32,
+}
+
+impl UnitMenuAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            roi: None,
+            threshold: DEFAULT_UNIT_MENU_THRESHOLD,
+        }
+    }
+
+    /// 只在干员位置 `pos`（1920x1080 下）附近查找按钮，见 [`unit_menu_roi`]
+    pub fn around(mut self, pos: (u32, u32)) -> Self {
+        self.roi = Some(unit_menu_roi(pos, UNIT_MENU_RADIUS));
+        self
+    }
+
+    /// 设置按钮匹配值的下限，默认为 [`DEFAULT_UNIT_MENU_THRESHOLD`]
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// 在 `screen` 中查找模板 `template_name`，返回匹配到的位置（屏幕坐标）
+    fn find_button(
+        &self,
+        core: &AAH,
+        screen: &DynamicImage,
+        template_name: &str,
+    ) -> Result<Option<Rect>, String> {
+        let template = core.get_template_scaled(template_name, screen.height())?;
+        let (offset_x, offset_y, image) = crop_roi(screen, self.roi.as_ref());
+        if template.width() > image.width() || template.height() > image.height() {
+            return Ok(None);
+        }
+
+        let res = BestMatcher::Template {
+            image: image.to_luma32f(),
+            template: template.to_luma32f(),
+            method: MatchTemplateMethod::CCOEFF_NORMED,
+            threshold: Some(self.threshold),
+        }
+        .result();
+        Ok(res.map(|rect| Rect {
+            x: rect.x + offset_x,
+            y: rect.y + offset_y,
+            ..rect
+        }))
+    }
+}
+
+impl Default for UnitMenuAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analyzer for UnitMenuAnalyzer {
+    type Output = UnitMenuAnalyzerOutput;
+    fn analyze_image(&mut self, core: &AAH, image: &DynamicImage) -> Result<Self::Output, String> {
+        let retreat = self.find_button(core, image, RETREAT_BUTTON_TEMPLATE)?;
+        // 菜单没有打开时不会有技能按钮，不必再匹配
+        let skill = if retreat.is_some() {
+            self.find_button(core, image, SKILL_BUTTON_TEMPLATE)?
+        } else {
+            None
+        };
+        let output = UnitMenuAnalyzerOutput { skill, retreat };
+        println!("[UnitMenuAnalyzer]: {:?}", output);
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unit_menu_roi() {
+        assert_eq!(
+            unit_menu_roi((960, 540), 300),
+            Rect {
+                x: 660,
+                y: 240,
+                width: 600,
+                height: 600,
+            }
+        );
+        // 靠近屏幕边缘的干员
+        assert_eq!(
+            unit_menu_roi((100, 1000), 300),
+            Rect {
+                x: 0,
+                y: 700,
+                width: 400,
+                height: 380,
+            }
+        );
+        assert_eq!(
+            unit_menu_roi((1900, 20), 300),
+            Rect {
+                x: 1600,
+                y: 0,
+                width: 320,
+                height: 320,
+            }
+        );
+    }
+
+    #[test]
+    fn test_unit_menu_output_is_open() {
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: 10,
+            height: 10,
+        };
+        let output = |skill: bool, retreat: bool| UnitMenuAnalyzerOutput {
+            skill: skill.then(|| rect.clone()),
+            retreat: retreat.then(|| rect.clone()),
+        };
+        assert!(output(true, true).is_open());
+        // 被动技能或技能未就绪
+        assert!(output(false, true).is_open());
+        assert!(!output(false, false).is_open());
+    }
+}
@@ -0,0 +1,192 @@
+//! 模板坐标（1920x1080 下）、屏幕坐标与设备坐标之间的转换
+
+use crate::{
+    controller::{DEFAULT_HEIGHT, DEFAULT_WIDTH},
+    vision::utils::Rect,
+};
+
+/// 模板坐标、屏幕坐标与设备坐标之间的转换
+///
+/// - 模板坐标：模板和配置中使用的 1920x1080 下的坐标
+/// - 屏幕坐标：[`Controller::screencap`](crate::controller::Controller::screencap) 返回的截图中的坐标，
+///   也是 [`Controller`](crate::controller::Controller) 的点击、滑动使用的坐标，只包含游戏画面
+/// - 设备坐标：设备上的实际坐标，有黑边时为屏幕坐标加上游戏画面的偏移（见 [`detect_play_area`](crate::vision::utils::detect_play_area)）
+///
+/// 明日方舟的界面按照高度缩放，因此缩放比例为游戏画面的高度除以 [`DEFAULT_HEIGHT`]。
+/// 通过 [`Controller::calibration`](crate::controller::Controller::calibration) 获取，
+/// 由匹配结果得到的点击位置都应该通过它转换
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    /// 游戏画面在设备上的区域
+    play_area: Rect,
+    /// 屏幕坐标 / 模板坐标
+    scale: f32,
+}
+
+impl Default for Calibration {
+    /// 1920x1080、没有黑边的设备
+    fn default() -> Self {
+        Self::from_screen_size((DEFAULT_WIDTH, DEFAULT_HEIGHT))
+    }
+}
+
+impl Calibration {
+    /// 游戏画面在设备上的区域为 `play_area`
+    ///
+    /// `play_area` 的高度为 0 时（比如还没有截图）缩放比例为 1
+    pub fn new(play_area: Rect) -> Self {
+        let scale = if play_area.height == 0 {
+            1.0
+        } else {
+            play_area.height as f32 / DEFAULT_HEIGHT as f32
+        };
+        Self { play_area, scale }
+    }
+
+    /// 没有黑边，游戏画面即整个 `(width, height)` 的屏幕
+    pub fn from_screen_size((width, height): (u32, u32)) -> Self {
+        Self::new(Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        })
+    }
+
+    /// 屏幕坐标与模板坐标的比例，`屏幕坐标 = 模板坐标 * scale`
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// 游戏画面在设备上的区域
+    pub fn play_area(&self) -> &Rect {
+        &self.play_area
+    }
+
+    /// 屏幕（即游戏画面）的尺寸
+    pub fn screen_size(&self) -> (u32, u32) {
+        (self.play_area.width, self.play_area.height)
+    }
+
+    pub fn template_point_to_screen(&self, (x, y): (u32, u32)) -> (u32, u32) {
+        (
+            (x as f32 * self.scale) as u32,
+            (y as f32 * self.scale) as u32,
+        )
+    }
+
+    pub fn screen_point_to_template(&self, (x, y): (u32, u32)) -> (u32, u32) {
+        (
+            (x as f32 / self.scale) as u32,
+            (y as f32 / self.scale) as u32,
+        )
+    }
+
+    /// 同 [`Calibration::template_point_to_screen`]，用于可能为负数或超出屏幕的坐标（比如滑动的终点）
+    pub fn template_offset_to_screen(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        (
+            (x as f32 * self.scale) as i32,
+            (y as f32 * self.scale) as i32,
+        )
+    }
+
+    pub fn template_rect_to_screen(&self, rect: &Rect) -> Rect {
+        let (x, y) = self.template_point_to_screen((rect.x, rect.y));
+        let (width, height) = self.template_point_to_screen((rect.width, rect.height));
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn screen_rect_to_template(&self, rect: &Rect) -> Rect {
+        let (x, y) = self.screen_point_to_template((rect.x, rect.y));
+        let (width, height) = self.screen_point_to_template((rect.width, rect.height));
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn screen_point_to_device(&self, (x, y): (u32, u32)) -> (u32, u32) {
+        (x + self.play_area.x, y + self.play_area.y)
+    }
+
+    /// 同 [`Calibration::screen_point_to_device`]，用于可能为负数或超出屏幕的坐标（比如滑动的终点）
+    pub fn screen_offset_to_device(&self, (x, y): (i32, i32)) -> (i32, i32) {
+        (x + self.play_area.x as i32, y + self.play_area.y as i32)
+    }
+
+    pub fn screen_rect_to_device(&self, rect: &Rect) -> Rect {
+        let (x, y) = self.screen_point_to_device((rect.x, rect.y));
+        Rect {
+            x,
+            y,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_calibration() {
+        let calibration = Calibration::default();
+        assert_eq!(calibration.scale(), 1.0);
+        assert_eq!(calibration.template_point_to_screen((960, 540)), (960, 540));
+
+        // 2560x1440
+        let calibration = Calibration::from_screen_size((2560, 1440));
+        assert_eq!(
+            calibration.template_point_to_screen((960, 540)),
+            (1280, 720)
+        );
+        assert_eq!(
+            calibration.screen_point_to_template((1280, 720)),
+            (960, 540)
+        );
+        assert_eq!(calibration.template_offset_to_screen((-30, 60)), (-40, 80));
+        assert_eq!(calibration.screen_point_to_device((10, 20)), (10, 20));
+
+        // 3440x1440，游戏画面为中间的 2560x1440
+        let calibration = Calibration::new(Rect {
+            x: 440,
+            y: 0,
+            width: 2560,
+            height: 1440,
+        });
+        assert_eq!(calibration.screen_size(), (2560, 1440));
+        let rect = Rect {
+            x: 300,
+            y: 600,
+            width: 150,
+            height: 90,
+        };
+        let screen_rect = calibration.template_rect_to_screen(&rect);
+        assert_eq!(
+            screen_rect,
+            Rect {
+                x: 400,
+                y: 800,
+                width: 200,
+                height: 120
+            }
+        );
+        assert_eq!(calibration.screen_rect_to_template(&screen_rect), rect);
+        assert_eq!(calibration.screen_rect_to_device(&screen_rect).x, 840);
+        assert_eq!(calibration.screen_point_to_device((0, 0)), (440, 0));
+        assert_eq!(calibration.screen_offset_to_device((-40, 80)), (400, 80));
+
+        assert_eq!(
+            Calibration::from_screen_size((0, 0)).screen_point_to_template((5, 5)),
+            (5, 5)
+        );
+    }
+}
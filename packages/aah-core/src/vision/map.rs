@@ -0,0 +1,273 @@
+//! 战斗地图中地块坐标与屏幕坐标（1920x1080 下）之间的转换
+
+use std::{fs, path::Path};
+
+use nalgebra::{DMatrix, DVector, Matrix3, Matrix4, Vector3};
+use serde::Deserialize;
+
+use crate::controller::{DEFAULT_HEIGHT, DEFAULT_WIDTH};
+
+/// 默认的相机位置，`[正视角, 侧视角]`，大部分关卡均为该值
+pub const DEFAULT_VIEW: [[f32; 3]; 2] = [
+    [0.0, -4.81, -7.76],
+    [0.5975098586953793, -5.31, -8.642108163374733],
+];
+
+/// 关卡地图的元数据，可以直接从 `resources/levels.json` 中的关卡反序列化
+///
+/// - `code`: 关卡代号，比如 `1-7`
+/// - `width`, `height`: 地图的列数和行数
+/// - `view`: 相机位置，`[正视角, 侧视角]`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageMap {
+    pub code: String,
+    pub stage_id: String,
+    pub width: u32,
+    pub height: u32,
+    pub view: [[f32; 3]; 2],
+}
+
+impl StageMap {
+    /// 从 `{res_dir}/levels.json` 中加载代号或 id 为 `stage` 的关卡
+    pub fn load<P: AsRef<Path>, S: AsRef<str>>(res_dir: P, stage: S) -> Result<Self, String> {
+        let (res_dir, stage) = (res_dir.as_ref(), stage.as_ref());
+        let path = res_dir.join("levels.json");
+        let levels =
+            fs::read_to_string(&path).map_err(|err| format!("failed to read {path:?}: {err}"))?;
+        let levels: Vec<StageMap> = serde_json::from_str(&levels)
+            .map_err(|err| format!("failed to parse {path:?}: {err}"))?;
+        levels
+            .into_iter()
+            .find(|level| level.code == stage || level.stage_id == stage)
+            .ok_or(format!("stage {stage:?} not found in {path:?}"))
+    }
+}
+
+/// 地块坐标 `(col, row)` 与屏幕坐标（1920x1080 下）之间的转换
+///
+/// `(0, 0)` 为地图左上角的地块，地块坐标为整数时对应地块的中心。
+/// 地块所在的平面到屏幕是一个单应变换，可以由相机位置计算（[`TileTransform::new`]、
+/// [`TileTransform::from_stage`]），也可以由几个已知位置的地块标定（[`TileTransform::from_anchors`]）
+pub struct TileTransform {
+    matrix: Matrix3<f32>,
+    inverse: Matrix3<f32>,
+}
+
+impl TileTransform {
+    /// 使用 [`DEFAULT_VIEW`] 创建 `map_width` x `map_height` 地图的转换
+    ///
+    /// - `side`: 是否为侧视角，拖动部署卡片时游戏会切换到侧视角
+    pub fn new(map_width: u32, map_height: u32, side: bool) -> Self {
+        Self::from_view(map_width, map_height, DEFAULT_VIEW, side)
+    }
+
+    /// 使用关卡自己的相机位置创建转换，见 [`StageMap::load`]
+    pub fn from_stage(stage: &StageMap, side: bool) -> Self {
+        Self::from_view(stage.width, stage.height, stage.view, side)
+    }
+
+    /// 使用相机位置 `view`（`[正视角, 侧视角]`）创建 `map_width` x `map_height` 地图的转换
+    pub fn from_view(map_width: u32, map_height: u32, view: [[f32; 3]; 2], side: bool) -> Self {
+        let (camera_pos, camera_euler) = if side {
+            (view[1], [30.0_f32.to_radians(), 10.0_f32.to_radians()])
+        } else {
+            (view[0], [30.0_f32.to_radians(), 0.0])
+        };
+        let camera = camera_matrix(
+            &Vector3::from(camera_pos),
+            camera_euler,
+            DEFAULT_HEIGHT as f32 / DEFAULT_WIDTH as f32,
+        );
+
+        // 地块位于 z = 0 的平面上，(x, y, 1) -> (clip.x, clip.y, clip.w)
+        #[rustfmt::skip]
+        let plane_to_clip = Matrix3::new(
+            camera[(0, 0)], camera[(0, 1)], camera[(0, 3)],
+            camera[(1, 0)], camera[(1, 1)], camera[(1, 3)],
+            camera[(3, 0)], camera[(3, 1)], camera[(3, 3)],
+        );
+        // 地图的中心位于世界坐标原点，行号增大时 y 减小
+        #[rustfmt::skip]
+        let tile_to_plane = Matrix3::new(
+            1.0, 0.0, -(map_width as f32 - 1.0) / 2.0,
+            0.0, -1.0, (map_height as f32 - 1.0) / 2.0,
+            0.0, 0.0, 1.0,
+        );
+        // NDC -> 屏幕，x 轴是反的
+        let (half_width, half_height) = (DEFAULT_WIDTH as f32 / 2.0, DEFAULT_HEIGHT as f32 / 2.0);
+        #[rustfmt::skip]
+        let clip_to_screen = Matrix3::new(
+            -half_width, 0.0, half_width,
+            0.0, half_height, half_height,
+            0.0, 0.0, 1.0,
+        );
+
+        Self::from_matrix(clip_to_screen * plane_to_clip * tile_to_plane)
+            .expect("camera projection of the map plane is invertible")
+    }
+
+    /// 由至少 4 个 `((col, row), (x, y))` 的对应关系（屏幕坐标为 1920x1080 下）标定转换，
+    /// 多于 4 个时使用最小二乘，适用于相机位置未知的关卡
+    ///
+    /// 对应关系不足或退化（比如有 3 个地块共线）时返回错误
+    pub fn from_anchors(anchors: &[((f32, f32), (f32, f32))]) -> Result<Self, String> {
+        if anchors.len() < 4 {
+            return Err(format!(
+                "at least 4 anchors are needed, got {}",
+                anchors.len()
+            ));
+        }
+
+        // 固定 h33 = 1，每个对应关系提供两个方程
+        let mut a = DMatrix::<f32>::zeros(anchors.len() * 2, 8);
+        let mut b = DVector::<f32>::zeros(anchors.len() * 2);
+        for (i, &((u, v), (x, y))) in anchors.iter().enumerate() {
+            let row = [u, v, 1.0, 0.0, 0.0, 0.0, -u * x, -v * x];
+            a.row_mut(i * 2).copy_from_slice(&row);
+            b[i * 2] = x;
+            let row = [0.0, 0.0, 0.0, u, v, 1.0, -u * y, -v * y];
+            a.row_mut(i * 2 + 1).copy_from_slice(&row);
+            b[i * 2 + 1] = y;
+        }
+        let h = (a.transpose() * &a)
+            .lu()
+            .solve(&(a.transpose() * b))
+            .ok_or("degenerate anchors".to_string())?;
+
+        #[rustfmt::skip]
+        let matrix = Matrix3::new(
+            h[0], h[1], h[2],
+            h[3], h[4], h[5],
+            h[6], h[7], 1.0,
+        );
+        Self::from_matrix(matrix).ok_or("degenerate anchors".to_string())
+    }
+
+    /// 直接使用从地块坐标到屏幕坐标的单应矩阵，矩阵不可逆时返回 [`None`]
+    pub fn from_matrix(matrix: Matrix3<f32>) -> Option<Self> {
+        let inverse = matrix.try_inverse()?;
+        Some(Self { matrix, inverse })
+    }
+
+    /// 地块 `(col, row)` 中心的屏幕坐标（1920x1080 下）
+    pub fn tile_to_screen(&self, tile: (u32, u32)) -> (u32, u32) {
+        let (x, y) = self.project(tile.0 as f32, tile.1 as f32);
+        (x.round().max(0.0) as u32, y.round().max(0.0) as u32)
+    }
+
+    /// 屏幕坐标（1920x1080 下）`(x, y)` 所在的地块坐标，四舍五入即为所在的地块
+    pub fn screen_to_tile(&self, pos: (u32, u32)) -> (f32, f32) {
+        let v = self.inverse * Vector3::new(pos.0 as f32, pos.1 as f32, 1.0);
+        (v.x / v.z, v.y / v.z)
+    }
+
+    fn project(&self, col: f32, row: f32) -> (f32, f32) {
+        let v = self.matrix * Vector3::new(col, row, 1.0);
+        (v.x / v.z, v.y / v.z)
+    }
+}
+
+/// 游戏相机的投影矩阵，`euler` 为绕 x 轴、y 轴的旋转角
+fn camera_matrix(pos: &Vector3<f32>, euler: [f32; 2], ratio: f32) -> Matrix4<f32> {
+    let (sin_x, cos_x) = euler[0].sin_cos();
+    let (sin_y, cos_y) = euler[1].sin_cos();
+    let tan_f = 20.0_f32.to_radians().tan();
+    let (far, near) = (1000.0, 0.3);
+
+    #[rustfmt::skip]
+    let translate = Matrix4::new(
+        1.0, 0.0, 0.0, -pos.x,
+        0.0, 1.0, 0.0, -pos.y,
+        0.0, 0.0, 1.0, -pos.z,
+        0.0, 0.0, 0.0, 1.0,
+    );
+    #[rustfmt::skip]
+    let rotate_y = Matrix4::new(
+        cos_y, 0.0, sin_y, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        -sin_y, 0.0, cos_y, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    );
+    #[rustfmt::skip]
+    let rotate_x = Matrix4::new(
+        1.0, 0.0, 0.0, 0.0,
+        0.0, cos_x, -sin_x, 0.0,
+        0.0, sin_x, cos_x, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    );
+    #[rustfmt::skip]
+    let proj = Matrix4::new(
+        ratio / tan_f, 0.0, 0.0, 0.0,
+        0.0, 1.0 / tan_f, 0.0, 0.0,
+        0.0, 0.0, -(far + near) / (far - near), -(2.0 * far * near) / (far - near),
+        0.0, 0.0, -1.0, 0.0,
+    );
+
+    proj * rotate_x * rotate_y * translate
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tile_to_screen() {
+        let transform = TileTransform::new(9, 6, false);
+        for row in 0..6 {
+            let (x, _) = transform.tile_to_screen((4, row));
+            assert!((x as i32 - 960).abs() <= 1, "{x}");
+        }
+        for col in 0..9 {
+            let screen = (0..6)
+                .map(|row| transform.tile_to_screen((col, row)))
+                .collect::<Vec<_>>();
+            println!("{col}: {screen:?}");
+            assert!(screen.windows(2).all(|w| w[0].1 < w[1].1));
+            assert!(screen.iter().all(|&(x, y)| x < 1920 && y < 1080));
+        }
+        for row in 0..6 {
+            let (left, _) = transform.tile_to_screen((0, row));
+            let (right, _) = transform.tile_to_screen((8, row));
+            assert!(left < right);
+        }
+    }
+
+    #[test]
+    fn test_screen_to_tile() {
+        let transform = TileTransform::new(9, 6, true);
+        for (col, row) in [(0, 0), (3, 2), (8, 5)] {
+            let (x, y) = transform.screen_to_tile(transform.tile_to_screen((col, row)));
+            assert!((x - col as f32).abs() < 0.05 && (y - row as f32).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_from_anchors() {
+        let transform = TileTransform::new(10, 7, false);
+        let anchors = [(0, 0), (9, 0), (0, 6), (9, 6), (4, 3)]
+            .map(|(col, row)| (col as f32, row as f32))
+            .map(|tile| (tile, transform.project(tile.0, tile.1)));
+        let calibrated = TileTransform::from_anchors(&anchors).unwrap();
+        for col in 0..10 {
+            for row in 0..7 {
+                let (x, y) = transform.tile_to_screen((col, row));
+                let (cx, cy) = calibrated.tile_to_screen((col, row));
+                assert!((x as i32 - cx as i32).abs() <= 1 && (y as i32 - cy as i32).abs() <= 1);
+            }
+        }
+
+        assert!(TileTransform::from_anchors(&anchors[..3]).is_err());
+        let collinear = [0.0, 1.0, 2.0, 3.0].map(|col| ((col, 0.0), (col * 100.0, 0.0)));
+        assert!(TileTransform::from_anchors(&collinear).is_err());
+    }
+
+    #[test]
+    fn test_from_stage() {
+        let stage = StageMap::load("../../resources", "0-1").unwrap();
+        assert_eq!((stage.width, stage.height), (9, 6));
+        let transform = TileTransform::from_stage(&stage, false);
+        println!("{:?}", transform.tile_to_screen((4, 3)));
+        assert!(StageMap::load("../../resources", "not-exist").is_err());
+    }
+}
@@ -1,16 +1,31 @@
-use std::time::Instant;
+use std::{collections::HashMap, time::Instant};
 
-use aah_cv::{find_extremes, match_template, MatchTemplateMethod};
+use aah_cv::{best_match, match_template_rgb, try_find_extremes, MatchTemplateMethod};
 use color_print::cprintln;
-use image::{ImageBuffer, Luma};
+use image::{DynamicImage, ImageBuffer, Luma, Rgb};
 
-use crate::vision::{matcher::{SSE_THRESHOLD, THRESHOLD}, utils::Rect};
+use crate::{
+    controller::{crop_clamped, DEFAULT_HEIGHT},
+    vision::{
+        matcher::{SSE_THRESHOLD, THRESHOLD},
+        utils::{dhash, hamming_distance, Rect},
+    },
+};
 
 /// 匹配器，目前只实现了模板匹配
 pub enum BestMatcher {
+    /// 灰度模板匹配，`threshold` 的含义取决于 `method`：
+    /// 越高越好的方法（见 [`MatchTemplateMethod::higher_is_better`]）要求匹配值高于阈值，其余方法要求低于阈值
     Template {
         image: ImageBuffer<Luma<f32>, Vec<f32>>,
         template: ImageBuffer<Luma<f32>, Vec<f32>>,
+        method: MatchTemplateMethod,
+        threshold: Option<f32>,
+    },
+    /// 彩色模板匹配，见 [`match_template_rgb`]，用于区分轮廓相近但颜色不同的目标（如干员头像）
+    TemplateRgb {
+        image: ImageBuffer<Rgb<f32>, Vec<f32>>,
+        template: ImageBuffer<Rgb<f32>, Vec<f32>>,
         threshold: Option<f32>,
     },
     // Ocr {
@@ -21,74 +36,419 @@ pub enum BestMatcher {
 }
 
 impl BestMatcher {
+    /// 使用 `method` 进行灰度模板匹配，见 [`BestMatcher::Template`]
+    pub fn new_with_method(
+        image: ImageBuffer<Luma<f32>, Vec<f32>>,
+        template: ImageBuffer<Luma<f32>, Vec<f32>>,
+        method: MatchTemplateMethod,
+        threshold: Option<f32>,
+    ) -> Self {
+        Self::Template {
+            image,
+            template,
+            method,
+            threshold,
+        }
+    }
+
     /// 执行匹配并获取结果
     pub fn result(&self) -> Option<Rect> {
+        self.result_with_value().map(|(rect, _)| rect)
+    }
+
+    /// 执行匹配并获取结果，以及该结果的匹配值
+    pub fn result_with_value(&self) -> Option<(Rect, f32)> {
         match self {
             Self::Template {
                 image,
                 template,
+                method,
                 threshold,
             } => {
-                // let down_scaled_template = template;
-                let method = MatchTemplateMethod::CCOEFF_NORMED;
+                let method = *method;
                 cprintln!("[BestMatcher::TemplateMatcher]: image: {}x{}, template: {}x{}, method: {:?}, matching...", image.width(), image.height(), template.width(), template.height(), method);
+                if template.width() == 0
+                    || template.height() == 0
+                    || template.width() > image.width()
+                    || template.height() > image.height()
+                {
+                    cprintln!("[BestMatcher::TemplateMatcher]: <red>failed</red>, template doesn't fit in the image");
+                    return None;
+                }
 
                 // TODO: deal with scale problem, maybe should do it when screen cap stage
                 let start_time = Instant::now();
-                let res = match_template(image, template, method);
-                // cprintln!("finding_extremes...");
-                let extrems = find_extremes(&res);
+                let res = match best_match(image, template, method) {
+                    Ok(res) => res,
+                    Err(err) => {
+                        cprintln!("[BestMatcher::TemplateMatcher]: <red>failed</red>, {err}");
+                        return None;
+                    }
+                };
                 cprintln!(
                     "[BestMatcher::TemplateMatcher]: cost: {}s, {:?}",
                     start_time.elapsed().as_secs_f32(),
-                    extrems
+                    res
                 );
 
-                match method {
-                    MatchTemplateMethod::SumOfSquaredErrors => {
-                        if extrems.min_value >= threshold.unwrap_or(SSE_THRESHOLD) {
-                            cprintln!("[BestMatcher::TemplateMatcher]: <red>failed</red>");
-                            return None;
-                        }
-                    }
-                    MatchTemplateMethod::CrossCorrelation => {
-                        if extrems.max_value <= threshold.unwrap_or(THRESHOLD) {
-                            cprintln!("[BestMatcher::TemplateMatcher]: <red>failed</red>");
-                            return None;
-                        }
-                    }
-                    MatchTemplateMethod::CCOEFF => {
-                        if extrems.max_value <= threshold.unwrap_or(THRESHOLD) {
-                            cprintln!("[BestMatcher::TemplateMatcher]: <red>failed</red>");
-                            return None;
-                        }
-                    }
-                    MatchTemplateMethod::CCOEFF_NORMED => {
-                        if extrems.max_value <= threshold.unwrap_or(THRESHOLD) {
-                            cprintln!("[BestMatcher::TemplateMatcher]: <red>failed</red>");
-                            return None;
-                        }
-                    }
-                    _ => ()
+                let passed = if method.higher_is_better() {
+                    res.value > threshold.unwrap_or(THRESHOLD)
+                } else {
+                    res.value < threshold.unwrap_or(SSE_THRESHOLD)
                 };
+                if !passed {
+                    cprintln!("[BestMatcher::TemplateMatcher]: <red>failed</red>");
+                    return None;
+                }
 
                 cprintln!("[BestMatcher::TemplateMatcher]: <green>success!</green>");
-                let (x, y) = match method {
-                    MatchTemplateMethod::SumOfSquaredErrors => extrems.min_value_location,
-                    MatchTemplateMethod::CrossCorrelation => extrems.max_value_location,
-                    MatchTemplateMethod::CCOEFF => extrems.max_value_location,
-                    MatchTemplateMethod::CCOEFF_NORMED => extrems.max_value_location,
-                    _ => panic!("not implemented")
+                let (x, y) = res.location;
+                Some((
+                    Rect {
+                        x,
+                        y,
+                        width: template.width(),
+                        height: template.height(),
+                    },
+                    res.value,
+                ))
+            }
+            Self::TemplateRgb {
+                image,
+                template,
+                threshold,
+            } => {
+                cprintln!("[BestMatcher::TemplateRgb]: image: {}x{}, template: {}x{}, matching...", image.width(), image.height(), template.width(), template.height());
+
+                let start_time = Instant::now();
+                let extrems = match match_template_rgb(image, template)
+                    .and_then(|res| try_find_extremes(&res))
+                {
+                    Ok(extrems) => extrems,
+                    Err(err) => {
+                        cprintln!("[BestMatcher::TemplateRgb]: <red>failed</red>, {err}");
+                        return None;
+                    }
                 };
-                Some(Rect {
+                cprintln!(
+                    "[BestMatcher::TemplateRgb]: cost: {}s, {:?}",
+                    start_time.elapsed().as_secs_f32(),
+                    extrems
+                );
+
+                if extrems.max_value <= threshold.unwrap_or(THRESHOLD) {
+                    cprintln!("[BestMatcher::TemplateRgb]: <red>failed</red>");
+                    return None;
+                }
+
+                cprintln!("[BestMatcher::TemplateRgb]: <green>success!</green>");
+                let (x, y) = extrems.max_value_location;
+                Some((
+                    Rect {
+                        x,
+                        y,
+                        width: template.width(),
+                        height: template.height(),
+                    },
+                    extrems.max_value,
+                ))
+            }
+        }
+    }
+}
+
+/// [`best_match_labeled_with_method`] 中相关系数类方法（[`MatchTemplateMethod::CCOEFF_NORMED`]）提前结束的匹配值
+pub const LABELED_EARLY_EXIT_CORRELATION: f32 = 0.99;
+/// [`best_match_labeled_with_method`] 中误差类方法提前结束的每像素平均误差
+pub const LABELED_EARLY_EXIT_ERROR: f32 = 1e-4;
+
+/// 匹配值是否已经足够好，不需要再尝试其他模板
+///
+/// 只有归一化的方法能够判断，[`MatchTemplateMethod::CrossCorrelation`] 和 [`MatchTemplateMethod::CCOEFF`] 不会提前结束
+fn is_near_perfect(method: MatchTemplateMethod, value: f32, template_pixels: u32) -> bool {
+    match method {
+        MatchTemplateMethod::CCOEFF_NORMED => value >= LABELED_EARLY_EXIT_CORRELATION,
+        MatchTemplateMethod::SumOfAbsoluteErrors | MatchTemplateMethod::SumOfSquaredErrors => {
+            value / template_pixels as f32 <= LABELED_EARLY_EXIT_ERROR
+        }
+        _ => false,
+    }
+}
+
+/// 将 `image` 与一系列带标签的模板逐一进行 [`MatchTemplateMethod::CCOEFF_NORMED`] 的 [`BestMatcher::Template`] 匹配，
+/// 见 [`best_match_labeled_with_method`]
+pub fn best_match_labeled<L>(
+    image: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    templates: Vec<(L, ImageBuffer<Luma<f32>, Vec<f32>>)>,
+    threshold: Option<f32>,
+) -> Option<(L, Rect, f32)> {
+    best_match_labeled_with_method(image, templates, MatchTemplateMethod::CCOEFF_NORMED, threshold)
+}
+
+/// 将 `image` 与一系列带标签的模板逐一使用 `method` 进行 [`BestMatcher::Template`] 匹配，
+/// 返回匹配值最好的模板的标签、位置以及匹配值，尺寸大于 `image` 的模板会被跳过
+///
+/// 某个模板的匹配值已经接近完美时（见 [`LABELED_EARLY_EXIT_CORRELATION`]、[`LABELED_EARLY_EXIT_ERROR`]）直接返回，
+/// 因此应当把更可能匹配上的模板放在前面
+pub fn best_match_labeled_with_method<L>(
+    image: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    templates: Vec<(L, ImageBuffer<Luma<f32>, Vec<f32>>)>,
+    method: MatchTemplateMethod,
+    threshold: Option<f32>,
+) -> Option<(L, Rect, f32)> {
+    let better = |value: f32, best: f32| {
+        if method.higher_is_better() {
+            value > best
+        } else {
+            value < best
+        }
+    };
+
+    let mut res: Option<(L, Rect, f32)> = None;
+    for (label, template) in templates {
+        if template.width() > image.width() || template.height() > image.height() {
+            continue;
+        }
+        let template_pixels = template.width() * template.height();
+        let matched = BestMatcher::new_with_method(image.clone(), template, method, threshold)
+            .result_with_value();
+        if let Some((rect, value)) = matched {
+            if res.as_ref().map(|(_, _, v)| better(value, *v)).unwrap_or(true) {
+                res = Some((label, rect, value));
+                if is_near_perfect(method, value, template_pixels) {
+                    break;
+                }
+            }
+        }
+    }
+    res
+}
+
+/// 带标签的多模板匹配，可以通过 [`LabeledBestMatcher::with_prefilter`] 在模板很多时（比如全部干员的头像）
+/// 先用感知哈希筛选出少量候选，再进行开销较大的模板匹配
+///
+/// 不设置预筛选时等同于 [`best_match_labeled_with_method`]
+pub struct LabeledBestMatcher<'a, L> {
+    image: &'a ImageBuffer<Luma<f32>, Vec<f32>>,
+    templates: Vec<(L, ImageBuffer<Luma<f32>, Vec<f32>>)>,
+    method: MatchTemplateMethod,
+    threshold: Option<f32>,
+    prefilter: Option<usize>,
+}
+
+impl<'a, L> LabeledBestMatcher<'a, L> {
+    pub fn new(
+        image: &'a ImageBuffer<Luma<f32>, Vec<f32>>,
+        templates: Vec<(L, ImageBuffer<Luma<f32>, Vec<f32>>)>,
+    ) -> Self {
+        Self {
+            image,
+            templates,
+            method: MatchTemplateMethod::CCOEFF_NORMED,
+            threshold: None,
+            prefilter: None,
+        }
+    }
+
+    /// 设置匹配方法，默认为 [`MatchTemplateMethod::CCOEFF_NORMED`]
+    pub fn with_method(mut self, method: MatchTemplateMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// 设置匹配阈值，含义见 [`BestMatcher::Template`]
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// 只对 dHash（见 [`dhash`]）与 `image` 最接近的 `top_k` 个模板进行模板匹配
+    ///
+    /// 为了避免剪掉正确的模板：
+    /// - 与第 `top_k` 个模板哈希距离相同的模板也会保留
+    /// - 保留的模板都没有通过阈值时，再对剩下的模板进行匹配
+    pub fn with_prefilter(mut self, top_k: usize) -> Self {
+        self.prefilter = Some(top_k.max(1));
+        self
+    }
+
+    /// 执行匹配，返回匹配值最好的模板的标签、位置以及匹配值
+    pub fn result(self) -> Option<(L, Rect, f32)> {
+        let Self {
+            image,
+            templates,
+            method,
+            threshold,
+            prefilter,
+        } = self;
+        let Some(top_k) = prefilter.filter(|&top_k| top_k < templates.len()) else {
+            return best_match_labeled_with_method(image, templates, method, threshold);
+        };
+
+        let total = templates.len();
+        let (candidates, pruned) = prefilter_by_dhash(image, templates, top_k);
+        cprintln!(
+            "[LabeledBestMatcher]: prefiltered {} of {} templates",
+            candidates.len(),
+            total
+        );
+        let res = best_match_labeled_with_method(image, candidates, method, threshold);
+        if res.is_none() && !pruned.is_empty() {
+            cprintln!("[LabeledBestMatcher]: <yellow>no match in the prefiltered templates</yellow>, matching the rest");
+            return best_match_labeled_with_method(image, pruned, method, threshold);
+        }
+        res
+    }
+}
+
+/// 按 dHash 距离将 `templates` 分为最接近的 `top_k` 个（以及距离相同的）和其余的，各自保持原来的顺序
+///
+/// 每个模板与 `image` 中央同样大小的区域比较
+fn prefilter_by_dhash<L>(
+    image: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    templates: Vec<(L, ImageBuffer<Luma<f32>, Vec<f32>>)>,
+    top_k: usize,
+) -> (
+    Vec<(L, ImageBuffer<Luma<f32>, Vec<f32>>)>,
+    Vec<(L, ImageBuffer<Luma<f32>, Vec<f32>>)>,
+) {
+    let mut image_hashes = HashMap::new();
+    let distances = templates
+        .iter()
+        .map(|(_, template)| {
+            let (width, height) = template.dimensions();
+            let image_hash = *image_hashes.entry((width, height)).or_insert_with(|| {
+                let x = image.width().saturating_sub(width) / 2;
+                let y = image.height().saturating_sub(height) / 2;
+                dhash(&image::imageops::crop_imm(image, x, y, width, height).to_image())
+            });
+            hamming_distance(image_hash, dhash(template))
+        })
+        .collect::<Vec<_>>();
+
+    let mut sorted = distances.clone();
+    sorted.sort_unstable();
+    let cutoff = sorted[top_k.clamp(1, sorted.len()) - 1];
+
+    let mut candidates = vec![];
+    let mut pruned = vec![];
+    for (template, distance) in templates.into_iter().zip(distances) {
+        if distance <= cutoff {
+            candidates.push(template);
+        } else {
+            pruned.push(template);
+        }
+    }
+    (candidates, pruned)
+}
+
+/// 判断 `template` 是否出现在 `image` 中，即 [`MatchTemplateMethod::CCOEFF_NORMED`] 的最佳匹配值是否达到 `threshold`
+///
+/// `template` 和 `roi` 均为 1920x1080 下的尺寸，会按 `image` 的高度缩放；`roi` 为 [`None`] 时在整个 `image` 中匹配
+pub fn template_present(
+    image: &DynamicImage,
+    template: &DynamicImage,
+    threshold: f32,
+    roi: Option<&Rect>,
+) -> Result<bool, String> {
+    let scale_factor = image.height() as f32 / DEFAULT_HEIGHT as f32;
+    let template = template.to_luma32f();
+    let template = if image.height() != DEFAULT_HEIGHT {
+        image::imageops::resize(
+            &template,
+            (template.width() as f32 * scale_factor) as u32,
+            (template.height() as f32 * scale_factor) as u32,
+            image::imageops::FilterType::Lanczos3,
+        )
+    } else {
+        template
+    };
+
+    let image = match roi {
+        Some(roi) => crop_clamped(
+            image,
+            &Rect {
+                x: (roi.x as f32 * scale_factor) as u32,
+                y: (roi.y as f32 * scale_factor) as u32,
+                width: (roi.width as f32 * scale_factor) as u32,
+                height: (roi.height as f32 * scale_factor) as u32,
+            },
+        ),
+        None => image.clone(),
+    };
+    if template.width() == 0
+        || template.height() == 0
+        || template.width() > image.width()
+        || template.height() > image.height()
+    {
+        return Err(format!(
+            "template {}x{} doesn't fit in the matched area {}x{}",
+            template.width(),
+            template.height(),
+            image.width(),
+            image.height()
+        ));
+    }
+
+    let method = MatchTemplateMethod::CCOEFF_NORMED;
+    let res = best_match(&image.to_luma32f(), &template, method)?;
+    cprintln!("[template_present]: {:?}", res);
+    Ok(if method.higher_is_better() {
+        res.value >= threshold
+    } else {
+        res.value <= threshold
+    })
+}
+
+/// 将 `templates` 中的每个 `(名称, 模板)` 分别与 `image` 匹配（[`MatchTemplateMethod::CCOEFF_NORMED`]），
+/// 返回最佳匹配值高于 `threshold` 的 `(名称, 位置, 匹配值)`，按匹配值从高到低排序
+///
+/// 模板不会被缩放。为空、比 `image` 大或匹配出错的模板会被跳过，并输出原因
+pub fn match_templates(
+    image: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    templates: &[(String, DynamicImage)],
+    threshold: f32,
+) -> Vec<(String, Rect, f32)> {
+    let mut matched = templates
+        .iter()
+        .filter_map(|(name, template)| {
+            let template = template.to_luma32f();
+            if template.width() == 0
+                || template.height() == 0
+                || template.width() > image.width()
+                || template.height() > image.height()
+            {
+                cprintln!(
+                    "[match_templates]: <red>skipped</red> {name}, template {}x{} doesn't fit in the image {}x{}",
+                    template.width(),
+                    template.height(),
+                    image.width(),
+                    image.height()
+                );
+                return None;
+            }
+
+            let res = match best_match(image, &template, MatchTemplateMethod::CCOEFF_NORMED) {
+                Ok(res) => res,
+                Err(err) => {
+                    cprintln!("[match_templates]: <red>failed</red> to match {name}, {err}");
+                    return None;
+                }
+            };
+            (res.value > threshold).then(|| {
+                let (x, y) = res.location;
+                let rect = Rect {
                     x,
                     y,
                     width: template.width(),
                     height: template.height(),
-                })
-            } 
-        }
-    }
+                };
+                (name.clone(), rect, res.value)
+            })
+        })
+        .collect::<Vec<_>>();
+    matched.sort_by(|a, b| b.2.total_cmp(&a.2));
+    matched
 }
 
 #[cfg(test)]
@@ -96,7 +456,182 @@ mod test {
 
     use crate::vision::matcher::test::{get_device_image, get_device_template_prepared, Device};
 
-    use super::BestMatcher;
+    use aah_cv::MatchTemplateMethod;
+    use image::{DynamicImage, ImageBuffer};
+
+    use super::{
+        best_match_labeled, best_match_labeled_with_method, match_templates, prefilter_by_dhash,
+        template_present, BestMatcher, LabeledBestMatcher,
+    };
+    use crate::vision::utils::Rect;
+
+    #[test]
+    fn test_best_match_labeled() {
+        let image = ImageBuffer::from_fn(32, 32, |x, y| {
+            image::Luma([((x * 7 + y * 13) % 17) as f32 / 17.0 + ((x * y) % 5) as f32 / 5.0])
+        });
+        let crop = |x, y| image::imageops::crop_imm(&image, x, y, 8, 8).to_image();
+        let templates = vec![
+            ("a", crop(3, 4)),
+            ("b", crop(20, 17)),
+            ("too-large", ImageBuffer::new(40, 40)),
+        ];
+        let (label, rect, _) = best_match_labeled(&image, templates, Some(0.9)).unwrap();
+        assert_eq!(label, "a");
+        assert_eq!((rect.x, rect.y), (3, 4));
+    }
+
+    /// 在合成的带标签头像集上比较各匹配方法的准确率
+    ///
+    /// 卡片为头像加上噪声，其中一半还整体调亮（模拟选中、技能就绪时的高亮）
+    #[test]
+    fn test_best_match_labeled_methods() {
+        let avatar = |seed: u32| {
+            ImageBuffer::from_fn(16, 16, move |x, y| {
+                let v = (x * (seed + 3) + y * (seed * 5 + 7) + (x ^ y) * seed) % 23;
+                image::Luma([v as f32 / 23.0])
+            })
+        };
+        let avatars = (1..=6).map(|seed| (seed, avatar(seed))).collect::<Vec<_>>();
+
+        let mut noise_state = 12345u32;
+        let mut noise = move || {
+            noise_state = noise_state.wrapping_mul(1103515245).wrapping_add(12345);
+            ((noise_state >> 16) % 100) as f32 / 100.0 * 0.1 - 0.05
+        };
+        let cards = avatars
+            .iter()
+            .flat_map(|(label, avatar)| [(*label, avatar, 0.0), (*label, avatar, 0.2)])
+            .map(|(label, avatar, brightness)| {
+                let mut card = ImageBuffer::from_pixel(20, 20, image::Luma([0.5f32]));
+                for (x, y, p) in avatar.enumerate_pixels() {
+                    card.put_pixel(x + 2, y + 2, image::Luma([p[0] + brightness + noise()]));
+                }
+                (label, card)
+            })
+            .collect::<Vec<_>>();
+
+        let accuracy = |method: MatchTemplateMethod, threshold: f32| {
+            let correct = cards
+                .iter()
+                .filter(|(label, card)| {
+                    best_match_labeled_with_method(card, avatars.clone(), method, Some(threshold))
+                        .map(|(matched, _, _)| matched == *label)
+                        .unwrap_or(false)
+                })
+                .count();
+            correct as f32 / cards.len() as f32
+        };
+
+        let ccoeff_normed = accuracy(MatchTemplateMethod::CCOEFF_NORMED, 0.6);
+        let sse = accuracy(MatchTemplateMethod::SumOfSquaredErrors, f32::MAX);
+        let sae = accuracy(MatchTemplateMethod::SumOfAbsoluteErrors, f32::MAX);
+        println!("accuracy: CCOEFF_NORMED {ccoeff_normed}, SSE {sse}, SAE {sae}");
+        // 相关系数对整体亮度的变化不敏感
+        assert_eq!(ccoeff_normed, 1.0);
+        assert!(sse >= 0.5);
+        assert!(sae >= 0.5);
+    }
+
+    #[test]
+    fn test_labeled_prefilter() {
+        let avatar = |seed: u32| {
+            ImageBuffer::from_fn(16, 16, move |x, y| {
+                let v = (x * (seed + 3) + y * (seed * 5 + 7) + (x ^ y) * seed) % 23;
+                image::Luma([v as f32 / 23.0])
+            })
+        };
+        let avatars = (1..=12).map(|seed| (seed, avatar(seed))).collect::<Vec<_>>();
+        let card = |seed: u32| {
+            let mut card = ImageBuffer::from_pixel(20, 20, image::Luma([0.5f32]));
+            for (x, y, p) in avatar(seed).enumerate_pixels() {
+                card.put_pixel(x + 2, y + 2, image::Luma([p[0] + 0.1]));
+            }
+            card
+        };
+
+        for seed in [1, 5, 12] {
+            let card = card(seed);
+            let (label, _, _) = LabeledBestMatcher::new(&card, avatars.clone())
+                .with_threshold(0.6)
+                .with_prefilter(3)
+                .result()
+                .unwrap();
+            assert_eq!(label, seed);
+
+            let (candidates, pruned) = prefilter_by_dhash(&card, avatars.clone(), 3);
+            assert!(candidates.len() >= 3);
+            assert_eq!(candidates.len() + pruned.len(), avatars.len());
+            assert!(candidates.iter().any(|(label, _)| *label == seed));
+        }
+
+        // 哈希距离相同的模板都会保留
+        let card = card(1);
+        let (candidates, _) = prefilter_by_dhash(
+            &card,
+            vec![(1, avatar(1)), (2, avatar(1)), (3, avatar(3))],
+            1,
+        );
+        assert_eq!(
+            candidates.iter().map(|(label, _)| *label).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn test_template_present() {
+        let image = get_device_image(Device::MUMU, "main.png").unwrap();
+        // 基建入口在 (1388, 859)
+        let roi = Rect {
+            x: 1300,
+            y: 800,
+            width: 300,
+            height: 220,
+        };
+        let template = get_device_template_prepared(Device::MUMU, "main_base.png").unwrap();
+        assert!(template_present(&image, &template, 0.9, Some(&roi)).unwrap());
+
+        let template = get_device_template_prepared(Device::MUMU, "start_start.png").unwrap();
+        assert!(!template_present(&image, &template, 0.9, Some(&roi)).unwrap());
+
+        let roi = Rect {
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 100,
+        };
+        let template = get_device_template_prepared(Device::MUMU, "main_base.png").unwrap();
+        assert!(template_present(&image, &template, 0.9, Some(&roi)).is_err());
+    }
+
+    #[test]
+    fn test_match_templates() {
+        // 基建入口在 (1388, 859)
+        let image = get_device_image(Device::MUMU, "main.png")
+            .unwrap()
+            .crop_imm(1300, 800, 300, 220)
+            .to_luma32f();
+        let template = |name: &str| {
+            let template = get_device_template_prepared(Device::MUMU, name).unwrap();
+            (name.to_string(), template)
+        };
+        let templates = vec![
+            template("start_start.png"),
+            template("main_base.png"),
+            // 比画面大的模板被跳过
+            (
+                "too_large.png".to_string(),
+                DynamicImage::new_luma8(400, 10),
+            ),
+        ];
+
+        let matched = match_templates(&image, &templates, 0.9);
+        println!("{:?}", matched);
+        assert_eq!(matched.len(), 1);
+        let (name, rect, _) = &matched[0];
+        assert_eq!(name, "main_base.png");
+        assert!(rect.x.abs_diff(88) <= 10 && rect.y.abs_diff(59) <= 10);
+    }
 
     #[test]
     fn test_devices() {
@@ -134,6 +669,7 @@ mod test {
         let res = BestMatcher::Template {
             image: image.to_luma32f(),
             template: template.to_luma32f(),
+            method: MatchTemplateMethod::CCOEFF_NORMED,
             threshold: None,
         }
         .result();
@@ -7,8 +7,13 @@ use image::DynamicImage;
 use rten_tensor::{NdTensorBase, NdTensorView};
 // use imageproc::template_matching::{find_extremes, match_template, MatchTemplateMethod};
 
-const THRESHOLD: f32 = 30.0;
+/// [`best_matcher::BestMatcher`] 默认的匹配值下限
+pub(crate) const THRESHOLD: f32 = 30.0;
 const SSE_THRESHOLD: f32 = 40.0;
+/// [`multi_matcher::MultiMatcher`] 默认的匹配值下限（CCOEFF_NORMED）
+pub const DEFAULT_MULTI_MATCH_THRESHOLD: f32 = 0.8;
+/// [`multi_matcher::MultiMatcher`] 去重时允许的最大 IoU
+pub const DEFAULT_IOU_THRESHOLD: f32 = 0.1;
 
 pub fn convert_image_to_ten(
     image: DynamicImage,
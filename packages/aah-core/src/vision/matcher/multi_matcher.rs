@@ -1,75 +1,214 @@
-use std::time::Instant;
+use std::{sync::Mutex, time::Instant};
 
-use aah_cv::{find_matches, match_template, MatchTemplateMethod};
+use aah_cv::{
+    find_matches_nms, match_template, non_max_suppression, types::Image, Match,
+    MatchTemplateMethod, TemplateMatcher,
+};
 use color_print::cprintln;
 use image::{math::Rect, ImageBuffer, Luma};
 
-use crate::vision::matcher::SSE_THRESHOLD;
+use crate::vision::matcher::{DEFAULT_IOU_THRESHOLD, DEFAULT_MULTI_MATCH_THRESHOLD};
 
+/// 图像面积（像素数）达到该值时 [`MultiMatcher::template`] 使用 [`MultiMatcher::TemplateGpu`]，
+/// 更小的图像上 GPU 调度的开销占主导
+pub const GPU_MIN_AREA: u32 = 320 * 180;
+
+/// [`MultiMatcher::TemplateGpu`] 使用的 [`TemplateMatcher`]，在第一次使用时创建，避免每次匹配都创建设备
+///
+/// 不使用 `thread_local`，线程退出时销毁 GPU 设备可能会 panic
+static GPU_MATCHER: Mutex<Option<TemplateMatcher>> = Mutex::new(None);
+
+/// 多目标匹配器
+///
+/// 使用归一化的相关系数（[`MatchTemplateMethod::CCOEFF_NORMED`]）进行匹配，
+/// 匹配值位于 `[-1, 1]`，越高越好
+///
+/// - `threshold`: 匹配值的下限，达到该值的位置才会作为结果，[`None`] 时为 [`DEFAULT_MULTI_MATCH_THRESHOLD`]
+/// - `iou_threshold`: 去重时允许的最大 IoU，与更好的结果重叠超过该值的结果会被去掉，
+///   [`None`] 时为 [`DEFAULT_IOU_THRESHOLD`]
 pub enum MultiMatcher {
+    /// 读回整个匹配结果，在 CPU 上逐个位置筛选
     Template {
         image: ImageBuffer<Luma<f32>, Vec<f32>>,
         template: ImageBuffer<Luma<f32>, Vec<f32>>,
         threshold: Option<f32>,
+        iou_threshold: Option<f32>,
+    },
+    /// 在 GPU 上匹配并筛选出达到阈值的位置（见 [`TemplateMatcher::match_and_threshold`]），
+    /// 只读回这些位置再去重，适合整个部署栏这样较大的图像
+    TemplateGpu {
+        image: ImageBuffer<Luma<f32>, Vec<f32>>,
+        template: ImageBuffer<Luma<f32>, Vec<f32>>,
+        threshold: Option<f32>,
+        iou_threshold: Option<f32>,
     },
 }
 
+/// [`MultiMatcher`] 的结果，按匹配值从高到低排序
+///
+/// - `rects`: 所有匹配结果的位置
+/// - `scores`: 与 `rects` 一一对应的匹配值
+#[derive(Debug, Clone, Default)]
+pub struct MultiMatcherResult {
+    pub rects: Vec<Rect>,
+    pub scores: Vec<f32>,
+}
+
 impl MultiMatcher {
-    /// 执行匹配并获取结果
-    pub fn result(&self) -> Option<Vec<Rect>> {
-        match self {
+    /// 根据 `image` 的大小选择 [`MultiMatcher::Template`] 或 [`MultiMatcher::TemplateGpu`]，见 [`GPU_MIN_AREA`]
+    pub fn template(
+        image: ImageBuffer<Luma<f32>, Vec<f32>>,
+        template: ImageBuffer<Luma<f32>, Vec<f32>>,
+        threshold: Option<f32>,
+        iou_threshold: Option<f32>,
+    ) -> Self {
+        if image.width() * image.height() >= GPU_MIN_AREA {
+            Self::TemplateGpu {
+                image,
+                template,
+                threshold,
+                iou_threshold,
+            }
+        } else {
             Self::Template {
                 image,
                 template,
                 threshold,
-            } => {
-                // let down_scaled_template = template;
-                let method = MatchTemplateMethod::SumOfSquaredErrors;
-                cprintln!("[Matcher::TemplateMatcher]: image: {}x{}, template: {}x{}, method: {:?}, matching...", image.width(), image.height(), template.width(), template.height(), method);
-
-                // TODO: deal with scale problem, maybe should do it when screen cap stage
-                let start_time = Instant::now();
-                let res = match_template(image, template, method);
-                cprintln!("finding_extremes...");
-
-                let matches = find_matches(
-                    &res,
-                    template.width(),
-                    template.height(),
-                    threshold.unwrap_or(SSE_THRESHOLD),
-                );
-                let matches: Vec<Rect> = matches
-                    .into_iter()
-                    .map(|m| Rect {
+                iou_threshold,
+            }
+        }
+    }
+
+    /// 执行匹配并获取结果，按匹配值从高到低排序，没有任何结果时返回 [`None`]
+    pub fn result(&self) -> Option<MultiMatcherResult> {
+        let (Self::Template {
+            image,
+            template,
+            threshold,
+            iou_threshold,
+        }
+        | Self::TemplateGpu {
+            image,
+            template,
+            threshold,
+            iou_threshold,
+        }) = self;
+        let gpu = matches!(self, Self::TemplateGpu { .. });
+        if template.width() > image.width() || template.height() > image.height() {
+            cprintln!("[MultiMatcher::TemplateMatcher]: <red>template is larger than the image</red>");
+            return None;
+        }
+        let method = MatchTemplateMethod::CCOEFF_NORMED;
+        let threshold = threshold.unwrap_or(DEFAULT_MULTI_MATCH_THRESHOLD);
+        let iou_threshold = iou_threshold.unwrap_or(DEFAULT_IOU_THRESHOLD);
+        cprintln!("[MultiMatcher::TemplateMatcher]: image: {}x{}, template: {}x{}, method: {:?}, gpu: {}, matching...", image.width(), image.height(), template.width(), template.height(), method, gpu);
+
+        // TODO: deal with scale problem, maybe should do it when screen cap stage
+        let start_time = Instant::now();
+        let matches = if gpu {
+            let candidates = GPU_MATCHER
+                .lock()
+                .unwrap()
+                .get_or_insert_with(TemplateMatcher::new)
+                .match_and_threshold(image.into(), template.into(), method, threshold);
+            let candidates = match candidates {
+                Ok(candidates) => candidates,
+                Err(err) => {
+                    cprintln!("[MultiMatcher::TemplateMatcher]: <red>{}</red>", err);
+                    return None;
+                }
+            };
+            non_max_suppression(
+                candidates,
+                template.width(),
+                template.height(),
+                method,
+                iou_threshold,
+            )
+        } else {
+            Self::cpu_matches(image, template, threshold, iou_threshold)
+        };
+
+        let (rects, scores) = matches
+            .into_iter()
+            .map(|m| {
+                (
+                    Rect {
                         x: m.location.0,
                         y: m.location.1,
                         width: template.width(),
                         height: template.height(),
-                    })
-                    .collect();
-                cprintln!(
-                    "[Matcher::TemplateMatcher]: cost: {}s,",
-                    start_time.elapsed().as_secs_f32(),
-                );
-
-                if matches.len() == 0 {
-                    cprintln!("[Matcher::TemplateMatcher]: <red>failed</red>");
-                    return None;
-                }
+                    },
+                    m.value,
+                )
+            })
+            .unzip();
+        let res = MultiMatcherResult { rects, scores };
+        cprintln!(
+            "[MultiMatcher::TemplateMatcher]: cost: {}s,",
+            start_time.elapsed().as_secs_f32(),
+        );
 
-                cprintln!(
-                    "[MultiMatcher::TemplateMatcher]: <green>{} matches</green>",
-                    matches.len()
-                );
-                Some(matches)
-            } // TODO: implement OcrMatcher
+        if res.rects.is_empty() {
+            cprintln!("[MultiMatcher::TemplateMatcher]: <red>failed</red>");
+            return None;
         }
+
+        cprintln!(
+            "[MultiMatcher::TemplateMatcher]: <green>{} matches</green>, scores: {:?}",
+            res.rects.len(),
+            res.scores
+        );
+        Some(res)
+        // TODO: implement OcrMatcher
+    }
+
+    /// [`MultiMatcher::Template`] 的匹配，读回整个结果后筛选并去重
+    fn cpu_matches(
+        image: &ImageBuffer<Luma<f32>, Vec<f32>>,
+        template: &ImageBuffer<Luma<f32>, Vec<f32>>,
+        threshold: f32,
+        iou_threshold: f32,
+    ) -> Vec<Match> {
+        let method = MatchTemplateMethod::CCOEFF_NORMED;
+        let res = match match_template(image, template, method) {
+            Ok(res) => res,
+            Err(err) => {
+                cprintln!("[MultiMatcher::TemplateMatcher]: <red>{err}</red>");
+                return vec![];
+            }
+        };
+        // 只保留模板完全位于图像内的位置，
+        // 超出 [-1, 1] 的值来自方差接近 0 的平坦区域的数值误差，视为不匹配
+        let (width, height) = (
+            image.width() - template.width() + 1,
+            image.height() - template.height() + 1,
+        );
+        let res = Image::new(
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| res.get(x, y))
+                .map(|v| if v.abs() <= 1.0 + 1e-3 { v } else { -1.0 })
+                .collect::<Vec<f32>>(),
+            width,
+            height,
+        );
+        cprintln!("finding_extremes...");
+
+        find_matches_nms(
+            &res,
+            template.width(),
+            template.height(),
+            method,
+            threshold,
+            iou_threshold,
+        )
     }
 }
 
 #[cfg(test)]
 mod test {
-    use image::math::Rect;
+    use image::{math::Rect, ImageBuffer, Luma};
 
     use crate::vision::{
         matcher::{
@@ -79,6 +218,68 @@ mod test {
         utils::{average_hsv_v, draw_box},
     };
 
+    #[test]
+    fn test_multi_matcher_template() {
+        // 不重复的图案作为模板，在图像中放置 5 个
+        let template = ImageBuffer::from_fn(16, 16, |x, y| {
+            Luma([((x * 7 + y * 13) ^ (x * y)) as f32 % 32.0 / 32.0])
+        });
+        let locations = [(3, 4), (40, 10), (90, 60), (150, 20), (170, 70)];
+        let mut image = ImageBuffer::from_pixel(200, 100, Luma([0.2f32]));
+        for &(x, y) in &locations {
+            image::imageops::replace(&mut image, &template, x, y);
+        }
+
+        let res = MultiMatcher::Template {
+            image: image.clone(),
+            template: template.clone(),
+            threshold: None,
+            iou_threshold: None,
+        }
+        .result()
+        .unwrap();
+        println!("{res:?}");
+        assert_eq!(res.rects.len(), locations.len());
+
+        // GPU 上筛选的结果与 CPU 一致
+        let res_gpu = MultiMatcher::TemplateGpu {
+            image: image.clone(),
+            template: template.clone(),
+            threshold: None,
+            iou_threshold: None,
+        }
+        .result()
+        .unwrap();
+        println!("{res_gpu:?}");
+        assert_eq!(res_gpu.rects, res.rects);
+        assert!(res_gpu
+            .scores
+            .iter()
+            .zip(&res.scores)
+            .all(|(a, b)| (a - b).abs() < 1e-3));
+        assert_eq!(res.scores.len(), locations.len());
+        let mut found = res
+            .rects
+            .iter()
+            .map(|rect| (rect.x as i64, rect.y as i64))
+            .collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(found, locations);
+        // 越高越好，从高到低排序
+        assert!(res.scores.windows(2).all(|w| w[0] >= w[1]));
+        assert!(res.scores.iter().all(|&score| score > 0.99));
+
+        // 阈值为下限
+        assert!(MultiMatcher::Template {
+            image,
+            template,
+            threshold: Some(1.01),
+            iou_threshold: None,
+        }
+        .result()
+        .is_none());
+    }
+
     #[test]
     fn test_devices() {
         test_device(Device::MUMU);
@@ -103,13 +304,14 @@ mod test {
             image: image.to_luma32f(),
             template: template.to_luma32f(),
             threshold: None,
+            iou_threshold: None,
         }
         .result()
         .unwrap();
-        println!("{} matches", res.len());
+        println!("{} matches, scores: {:?}", res.rects.len(), res.scores);
 
         let mut cnt = 0;
-        for rect in &res {
+        for rect in &res.rects {
             let cropped = image.crop_imm(rect.x, rect.y, rect.width, rect.width);
             let avg_hsv_v = average_hsv_v(&cropped);
             // println!("{avg_hsv_v}");
@@ -1,4 +1,9 @@
 pub mod analyzer;
+pub mod calibration;
+pub mod map;
 pub mod matcher;
 pub mod ocr;
+pub mod template_cache;
 pub mod utils;
+
+pub use calibration::Calibration;
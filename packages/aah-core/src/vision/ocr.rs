@@ -1 +1,334 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
+use image::DynamicImage;
+use ocrs::{OcrEngine, OcrEngineParams};
+use rten::Model;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    controller::DEFAULT_HEIGHT,
+    vision::{matcher::convert_image_to_ten, utils::Rect},
+};
+
+/// 数字字符的白名单
+pub const DIGITS: &str = "0123456789";
+
+/// 默认的文字检测模型
+pub const DEFAULT_DETECTION_MODEL: &str = "text-detection.rten";
+/// 默认的文字识别模型
+pub const DEFAULT_RECOGNITION_MODEL: &str = "text-recognition.rten";
+
+/// OCR 引擎的配置，未指定的项使用默认值
+///
+/// - `detection_model`: 文字检测模型的路径，默认为 [`DEFAULT_DETECTION_MODEL`]
+/// - `recognition_model`: 文字识别模型的路径，默认为 [`DEFAULT_RECOGNITION_MODEL`]
+/// - `alphabet`: 识别结果中允许出现的字符，未指定时不做过滤
+///
+/// 相对路径相对于 `{res_dir}/models/ocrs`，也可以使用绝对路径。
+/// 比如国服、日服需要使用对应语言的识别模型
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OcrConfig {
+    #[serde(default)]
+    pub detection_model: Option<PathBuf>,
+    #[serde(default)]
+    pub recognition_model: Option<PathBuf>,
+    #[serde(default)]
+    pub alphabet: Option<String>,
+}
+
+/// 初始化 OCR 引擎时的错误
+#[derive(Debug)]
+pub enum OcrInitError {
+    /// 模型文件不存在
+    ModelNotFound(PathBuf),
+    /// 读取模型文件失败
+    ReadModel { path: PathBuf, err: io::Error },
+    /// 模型文件无法被解析（比如文件损坏）
+    LoadModel { path: PathBuf, err: String },
+    /// 创建 [`OcrEngine`] 失败
+    CreateEngine(String),
+}
+
+impl Display for OcrInitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OcrInitError::ModelNotFound(path) => write!(f, "ocr model not found: {:?}", path),
+            OcrInitError::ReadModel { path, err } => {
+                write!(f, "failed to read ocr model {:?}: {}", path, err)
+            }
+            OcrInitError::LoadModel { path, err } => {
+                write!(f, "failed to load ocr model {:?}: {}", path, err)
+            }
+            OcrInitError::CreateEngine(err) => write!(f, "failed to create ocr engine: {}", err),
+        }
+    }
+}
+
+impl Error for OcrInitError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OcrInitError::ReadModel { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// 通过 [`AAH`](crate::AAH) 进行 OCR 识别时的错误，比如 [`AAH::ocr_region`](crate::AAH::ocr_region)
+#[derive(Debug, Clone)]
+pub enum OcrError {
+    /// OCR 引擎不可用，包含初始化时的错误（比如没有下载模型，仓库中不包含模型文件）
+    Unavailable(Arc<OcrInitError>),
+    /// 截图、识别或解析识别结果失败
+    Failed(String),
+}
+
+impl Display for OcrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OcrError::Unavailable(err) => write!(f, "ocr engine is not available: {}", err),
+            OcrError::Failed(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for OcrError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OcrError::Unavailable(err) => Some(err.as_ref()),
+            OcrError::Failed(_) => None,
+        }
+    }
+}
+
+impl From<String> for OcrError {
+    fn from(err: String) -> Self {
+        OcrError::Failed(err)
+    }
+}
+
+impl From<OcrError> for String {
+    fn from(err: OcrError) -> Self {
+        err.to_string()
+    }
+}
+
+/// 使用默认的 [`OcrConfig`] 初始化 OCR 引擎，见 [`init_ocr_engine_with`]
+pub fn init_ocr_engine<P: AsRef<Path>>(res_dir: P) -> Result<OcrEngine, OcrInitError> {
+    init_ocr_engine_with(res_dir, &OcrConfig::default())
+}
+
+/// 根据 `config` 初始化 OCR 引擎，模型文件不存在或无法加载时返回 [`OcrInitError`]
+pub fn init_ocr_engine_with<P: AsRef<Path>>(
+    res_dir: P,
+    config: &OcrConfig,
+) -> Result<OcrEngine, OcrInitError> {
+    println!("[OCR]: initializing ocr engine...");
+    let models_dir = res_dir.as_ref().join("models").join("ocrs");
+
+    let load_model = |path: &Path| {
+        let path = models_dir.join(path);
+        if !path.exists() {
+            return Err(OcrInitError::ModelNotFound(path));
+        }
+        let data = fs::read(&path).map_err(|err| OcrInitError::ReadModel {
+            path: path.clone(),
+            err,
+        })?;
+        Model::load(&data).map_err(|err| OcrInitError::LoadModel {
+            path,
+            err: format!("{:?}", err),
+        })
+    };
+    let detection_model = load_model(
+        config
+            .detection_model
+            .as_deref()
+            .unwrap_or(Path::new(DEFAULT_DETECTION_MODEL)),
+    )?;
+    let recognition_model = load_model(
+        config
+            .recognition_model
+            .as_deref()
+            .unwrap_or(Path::new(DEFAULT_RECOGNITION_MODEL)),
+    )?;
+
+    let engine = OcrEngine::new(OcrEngineParams {
+        detection_model: Some(detection_model),
+        recognition_model: Some(recognition_model),
+        ..Default::default()
+    })
+    .map_err(|err| OcrInitError::CreateEngine(format!("{:?}", err)))?;
+    println!("[OCR]: ocr engine initialized");
+    Ok(engine)
+}
+
+/// 识别 `image` 中的文字，多行文字以 `\n` 连接
+pub fn ocr_image(engine: &OcrEngine, image: &DynamicImage) -> Result<String, String> {
+    let tensor = convert_image_to_ten(image.clone())
+        .map_err(|err| format!("failed to convert image to tensor: {:?}", err))?;
+    let input = engine
+        .prepare_input(tensor.view())
+        .map_err(|err| format!("failed to prepare ocr input: {:?}", err))?;
+    engine
+        .get_text(&input)
+        .map_err(|err| format!("failed to recognize text: {:?}", err))
+}
+
+/// 识别 `screen` 中 `rect`（1920x1080 下）区域内的文字
+///
+/// 若提供了 `whitelist`，识别结果会经过 [`apply_whitelist`] 过滤
+pub fn ocr_region(
+    engine: &OcrEngine,
+    screen: &DynamicImage,
+    rect: &Rect,
+    whitelist: Option<&str>,
+) -> Result<String, String> {
+    let scale_factor = screen.height() as f32 / DEFAULT_HEIGHT as f32;
+    let x = ((rect.x as f32 * scale_factor) as u32).min(screen.width());
+    let y = ((rect.y as f32 * scale_factor) as u32).min(screen.height());
+    let width = ((rect.width as f32 * scale_factor) as u32).min(screen.width() - x);
+    let height = ((rect.height as f32 * scale_factor) as u32).min(screen.height() - y);
+    if width == 0 || height == 0 {
+        return Err(format!("region {:?} is out of the screen", rect));
+    }
+
+    let cropped = screen.crop_imm(x, y, width, height);
+    let text = ocr_image(engine, &cropped)?;
+    Ok(match whitelist {
+        Some(whitelist) => apply_whitelist(&text, whitelist),
+        None => text,
+    })
+}
+
+/// 只保留 `text` 中位于 `whitelist` 内的字符（空白字符会被保留，用于分隔）
+///
+/// 被过滤掉的字符会替换为一个空格，避免两侧的内容被拼接在一起（如 `"10/15"` 得到 `"10 15"`），
+/// 开头、结尾以及已有空白旁的被过滤字符不会产生额外的空格
+///
+/// 白名单包含数字时，会先将常见的误识别字符（如 `O`、`l`、`S`）替换为对应的数字
+pub fn apply_whitelist(text: &str, whitelist: &str) -> String {
+    let expect_digits = whitelist.chars().any(|c| c.is_ascii_digit());
+    let mut res = String::new();
+    let mut dropped = false;
+    for c in text.chars() {
+        let c = if !expect_digits || whitelist.contains(c) {
+            c
+        } else {
+            match c {
+                'O' | 'o' | 'D' | 'Q' => '0',
+                'I' | 'l' | 'i' | '|' | '!' => '1',
+                'Z' | 'z' => '2',
+                'S' | 's' => '5',
+                'G' | 'b' => '6',
+                'B' => '8',
+                'g' | 'q' => '9',
+                c => c,
+            }
+        };
+        if !c.is_whitespace() && !whitelist.contains(c) {
+            dropped = true;
+            continue;
+        }
+        if dropped && !c.is_whitespace() && res.chars().last().is_some_and(|c| !c.is_whitespace()) {
+            res.push(' ');
+        }
+        dropped = false;
+        res.push(c);
+    }
+    res
+}
+
+/// 解析 `text` 中所有的整数
+pub fn parse_numbers(text: &str) -> Vec<i64> {
+    let mut numbers = vec![];
+    let mut cur = String::new();
+    for c in text.chars().chain([' ']) {
+        if c.is_ascii_digit() || (c == '-' && cur.is_empty()) {
+            cur.push(c);
+            continue;
+        }
+        if let Ok(number) = cur.parse() {
+            numbers.push(number);
+        }
+        cur.clear();
+        if c == '-' {
+            cur.push(c);
+        }
+    }
+    numbers
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_init_ocr_engine_missing_model() {
+        let Err(err) = init_ocr_engine("./not-exist") else {
+            panic!("expected an error for a nonexistent res_dir");
+        };
+        println!("{err}");
+        match err {
+            OcrInitError::ModelNotFound(path) => {
+                assert_eq!(
+                    path,
+                    Path::new("./not-exist/models/ocrs").join(DEFAULT_DETECTION_MODEL)
+                )
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+
+        let config = OcrConfig {
+            detection_model: Some("not-exist.rten".into()),
+            ..Default::default()
+        };
+        let Err(err) = init_ocr_engine_with("../../resources", &config) else {
+            panic!("expected an error for a missing model");
+        };
+        assert!(
+            matches!(err, OcrInitError::ModelNotFound(path) if path.ends_with("not-exist.rten"))
+        );
+    }
+
+    #[test]
+    fn test_ocr_error() {
+        let Err(init_err) = init_ocr_engine("./not-exist") else {
+            panic!("expected an error for a nonexistent res_dir");
+        };
+        let err = OcrError::Unavailable(Arc::new(init_err));
+        assert!(err.to_string().starts_with("ocr engine is not available: "));
+        assert!(err
+            .source()
+            .and_then(|source| source.downcast_ref::<OcrInitError>())
+            .is_some_and(|source| matches!(source, OcrInitError::ModelNotFound(_))));
+
+        let err = OcrError::from("failed to parse".to_string());
+        assert_eq!(String::from(err), "failed to parse");
+    }
+
+    #[test]
+    fn test_apply_whitelist() {
+        assert_eq!(apply_whitelist("1O/l5", DIGITS), "10 15");
+        assert_eq!(apply_whitelist("/12,,34/", DIGITS), "12 34");
+        assert_eq!(apply_whitelist("12 / 34", DIGITS), "12  34");
+        assert_eq!(apply_whitelist("3 S\nB", DIGITS), "3 5\n8");
+        assert_eq!(apply_whitelist("1-7", "0123456789-"), "1-7");
+        assert_eq!(apply_whitelist("AB-c", "ABC"), "AB");
+        assert_eq!(apply_whitelist("A-B", "ABC"), "A B");
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        assert_eq!(parse_numbers("12 / 135"), vec![12, 135]);
+        assert_eq!(parse_numbers("-3x4"), vec![-3, 4]);
+        assert_eq!(parse_numbers("1-7"), vec![1, -7]);
+        assert_eq!(parse_numbers("--"), Vec::<i64>::new());
+        assert_eq!(parse_numbers(""), Vec::<i64>::new());
+    }
+}
@@ -0,0 +1,109 @@
+//! 按分辨率缩放后的模板的缓存，见 [`AAH::get_template_scaled`](crate::AAH::get_template_scaled)
+
+use std::collections::HashMap;
+
+use image::DynamicImage;
+
+/// [`TemplateCache`] 默认最多缓存的模板数
+pub const DEFAULT_TEMPLATE_CACHE_CAPACITY: usize = 64;
+
+/// 以 `(模板文件名, 目标高度)` 为键缓存缩放后的模板，超出容量时淘汰最久未使用的模板
+///
+/// 设备分辨率不是 1920x1080 时，每次匹配都需要重新缩放模板（Lanczos3），
+/// 在战斗这类逐帧分析的场景下开销较大，缓存后每种分辨率只需要缩放一次
+pub struct TemplateCache {
+    capacity: usize,
+    /// `(模板, 最后一次使用的时间)`
+    entries: HashMap<(String, u32), (DynamicImage, u64)>,
+    tick: u64,
+}
+
+impl TemplateCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 获取缓存的模板，同时将其标记为最近使用
+    pub fn get(&mut self, name: &str, height: u32) -> Option<DynamicImage> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.entries
+            .get_mut(&(name.to_string(), height))
+            .map(|(template, last_used)| {
+                *last_used = tick;
+                template.clone()
+            })
+    }
+
+    /// 缓存模板，超出容量时淘汰最久未使用的模板
+    pub fn insert(&mut self, name: &str, height: u32, template: DynamicImage) {
+        self.tick += 1;
+        let key = (name.to_string(), height);
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (template, self.tick));
+    }
+
+    /// 清空缓存，比如资源重新加载后
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl Default for TemplateCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TEMPLATE_CACHE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_template_cache() {
+        let template = |width| DynamicImage::new_luma8(width, 10);
+        let mut cache = TemplateCache::new(2);
+        assert!(cache.get("a.png", 720).is_none());
+
+        cache.insert("a.png", 720, template(1));
+        cache.insert("a.png", 1440, template(2));
+        assert_eq!(cache.get("a.png", 720).unwrap().width(), 1);
+        assert_eq!(cache.len(), 2);
+
+        // 淘汰最久未使用的 ("a.png", 1440)
+        cache.insert("b.png", 720, template(3));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("a.png", 1440).is_none());
+        assert!(cache.get("a.png", 720).is_some());
+        assert!(cache.get("b.png", 720).is_some());
+
+        // 替换已有的模板不会淘汰其他模板
+        cache.insert("b.png", 720, template(4));
+        assert_eq!(cache.get("b.png", 720).unwrap().width(), 4);
+        assert!(cache.get("a.png", 720).is_some());
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
@@ -1,7 +1,8 @@
-use image::{DynamicImage, GenericImage, Luma, Rgba};
-use serde::Serialize;
+use aah_cv::types::Image;
+use image::{DynamicImage, GenericImage, ImageBuffer, Luma, Rgba};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Rect {
     pub x: u32,
     pub y: u32,
@@ -28,6 +29,228 @@ pub fn average_hsv_v(image: &DynamicImage) -> u8 {
     (sum / count) as u8
 }
 
+/// 二值化阈值
+///
+/// - `Manual`: 手动指定的阈值
+/// - `Auto`: 使用 [`otsu_threshold`] 根据图像的灰度直方图自动选取
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinarizeThreshold {
+    Manual(u8),
+    Auto,
+}
+
+impl From<u8> for BinarizeThreshold {
+    fn from(threshold: u8) -> Self {
+        BinarizeThreshold::Manual(threshold)
+    }
+}
+
+impl BinarizeThreshold {
+    /// 得到对 `image` 进行二值化时实际使用的阈值
+    pub fn resolve(&self, image: &DynamicImage) -> u8 {
+        match self {
+            BinarizeThreshold::Manual(threshold) => *threshold,
+            BinarizeThreshold::Auto => otsu_threshold(image),
+        }
+    }
+}
+
+/// `image` 灰度图的直方图，第 `v` 项为灰度值为 `v` 的像素数
+///
+/// 用于调整 [`binarize_image`] 的阈值时查看灰度的分布，[`otsu_threshold`] 也基于它计算
+pub fn luma_histogram(image: &DynamicImage) -> [u32; 256] {
+    let mut histogram = [0; 256];
+    for Luma([gray]) in image.to_luma8().pixels() {
+        histogram[*gray as usize] += 1;
+    }
+    histogram
+}
+
+/// 使用大津法（Otsu）计算 `image` 灰度图的二值化阈值，可直接用于 [`binarize_image`]
+///
+/// 返回值为使类间方差最大的分割点，灰度值大于等于该值的像素属于前景
+pub fn otsu_threshold(image: &DynamicImage) -> u8 {
+    let histogram = luma_histogram(image);
+
+    let total = histogram.iter().map(|&cnt| cnt as f64).sum::<f64>();
+    let sum_total = histogram
+        .iter()
+        .enumerate()
+        .map(|(v, &cnt)| v as f64 * cnt as f64)
+        .sum::<f64>();
+
+    let mut best_threshold = 0;
+    let mut best_variance = 0.0;
+    let (mut weight_bg, mut sum_bg) = (0.0, 0.0);
+    // 背景为 [0, t)，前景为 [t, 255]
+    for t in 1..256 {
+        weight_bg += histogram[t - 1] as f64;
+        sum_bg += (t - 1) as f64 * histogram[t - 1] as f64;
+        let weight_fg = total - weight_bg;
+        if weight_bg == 0.0 || weight_fg == 0.0 {
+            continue;
+        }
+
+        let mean_bg = sum_bg / weight_bg;
+        let mean_fg = (sum_total - sum_bg) / weight_fg;
+        let variance = weight_bg * weight_fg * (mean_bg - mean_fg).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best_threshold = t;
+        }
+    }
+    best_threshold as u8
+}
+
+/// 匹配结果的统计量，见 [`score_stats`]
+///
+/// - `count`: 参与统计的值（有限值）的个数
+/// - `min`、`max`、`mean`、`stddev`: 最小值、最大值、平均值、标准差，`count` 为 0 时均为 0
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct ScoreStats {
+    pub count: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub stddev: f32,
+}
+
+/// 统计匹配结果 `result`（比如 [`aah_cv::match_template`] 的返回值）中匹配值的分布，
+/// 用于选取匹配阈值：阈值应明显高于 `mean`，并低于正确位置的匹配值
+///
+/// NaN 和无穷大会被忽略
+pub fn score_stats(result: &Image) -> ScoreStats {
+    let values = result.data.iter().filter(|v| v.is_finite());
+    let (mut count, mut min, mut max, mut sum, mut sq_sum) = (0, f32::MAX, f32::MIN, 0.0, 0.0);
+    for &v in values {
+        count += 1;
+        min = min.min(v);
+        max = max.max(v);
+        sum += v as f64;
+        sq_sum += v as f64 * v as f64;
+    }
+    if count == 0 {
+        return ScoreStats {
+            count,
+            min: 0.0,
+            max: 0.0,
+            mean: 0.0,
+            stddev: 0.0,
+        };
+    }
+
+    let mean = sum / count as f64;
+    // 舍入误差可能使方差略小于 0
+    let variance = (sq_sum / count as f64 - mean * mean).max(0.0);
+    ScoreStats {
+        count,
+        min,
+        max,
+        mean: mean as f32,
+        stddev: variance.sqrt() as f32,
+    }
+}
+
+/// 将 RGB 转换为 HSV，H 的范围为 `[0, 360)`，S 和 V 的范围为 `[0, 255]`
+pub fn rgb_to_hsv(pixel: &Rgba<u8>) -> (f32, u8, u8) {
+    let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let s = if max == 0.0 { 0.0 } else { delta / max * 255.0 };
+    (h, s as u8, max as u8)
+}
+
+/// 在 `image` 中查找颜色位于指定 HSV 范围内的连通区域，返回各区域的包围盒
+///
+/// - `hue_range`: H 的范围（角度，`[0, 360)`），起点大于终点时表示跨越 0 度（比如红色的 `(340, 20)`）
+/// - `sat_min`: S 的最小值
+/// - `val_min`: V 的最小值
+/// - `min_area`: 区域的最小像素数，用于过滤噪点
+///
+/// 可用于估计战场上敌人、干员的数量及大致位置
+pub fn count_colored_blobs(
+    image: &DynamicImage,
+    hue_range: (f32, f32),
+    sat_min: u8,
+    val_min: u8,
+    min_area: u32,
+) -> Vec<Rect> {
+    let image = image.to_rgba8();
+    let (width, height) = image.dimensions();
+    let in_hue_range = |h: f32| {
+        let (start, end) = hue_range;
+        if start <= end {
+            start <= h && h <= end
+        } else {
+            h >= start || h <= end
+        }
+    };
+    let mask = image
+        .pixels()
+        .map(|p| {
+            let (h, s, v) = rgb_to_hsv(p);
+            s >= sat_min && v >= val_min && in_hue_range(h)
+        })
+        .collect::<Vec<_>>();
+
+    // 四连通的连通域标记
+    let mut visited = vec![false; mask.len()];
+    let mut blobs = vec![];
+    let mut stack = vec![];
+    for start in 0..mask.len() {
+        if !mask[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        stack.push(start);
+
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, height, 0, 0);
+        let mut area = 0;
+        while let Some(idx) = stack.pop() {
+            let (x, y) = (idx as u32 % width, idx as u32 / width);
+            area += 1;
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+
+            let neighbors = [
+                (x > 0).then(|| idx - 1),
+                (x + 1 < width).then(|| idx + 1),
+                (y > 0).then(|| idx - width as usize),
+                (y + 1 < height).then(|| idx + width as usize),
+            ];
+            for neighbor in neighbors.into_iter().flatten() {
+                if mask[neighbor] && !visited[neighbor] {
+                    visited[neighbor] = true;
+                    stack.push(neighbor);
+                }
+            }
+        }
+
+        if area >= min_area {
+            blobs.push(Rect {
+                x: min_x,
+                y: min_y,
+                width: max_x - min_x + 1,
+                height: max_y - min_y + 1,
+            });
+        }
+    }
+    blobs
+}
+
 pub fn binarize_image(image: &DynamicImage, threshold: u8) -> DynamicImage {
     let mut image = image.to_luma8();
     for (x, y, pixel) in image.enumerate_pixels_mut() {
@@ -86,6 +309,135 @@ pub fn draw_box(
     // }
 }
 
+/// 3x5 点阵字形，每行的低 3 位从左到右为像素
+fn glyph(c: char) -> Option<[u8; 5]> {
+    let glyph = match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => return None,
+    };
+    Some(glyph)
+}
+
+/// 以 3x5 点阵字体在 `(x, y)` 处绘制数字 `text`，每个点放大为 `scale`x`scale` 的方块，
+/// 只支持数字、`.` 和 `-`，其他字符会被跳过，超出图像的部分不会绘制
+pub fn draw_number(
+    image: &mut DynamicImage,
+    x: i32,
+    y: i32,
+    text: &str,
+    scale: u32,
+    rgba_u8: [u8; 4],
+) {
+    let scale = scale.max(1) as i32;
+    let glyphs = text.chars().filter_map(glyph);
+    for (idx, glyph) in glyphs.enumerate() {
+        // 字形宽 3，间隔 1
+        let glyph_x = x + idx as i32 * 4 * scale;
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = glyph_x + col * scale + dx;
+                        let py = y + row as i32 * scale + dy;
+                        if px >= 0
+                            && py >= 0
+                            && px < image.width() as i32
+                            && py < image.height() as i32
+                        {
+                            image.put_pixel(px as u32, py as u32, Rgba(rgba_u8));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 亮度不超过该值的像素视为黑边
+pub const BLACK_BAR_LUMA: u8 = 8;
+
+/// 检测 `image` 中游戏画面（非黑边）所在的区域
+///
+/// 部分模拟器在超宽屏上会将 16:9 的游戏画面居中显示，两侧（或上下）留有黑边。
+/// 从四边向内找到第一个存在非黑像素（亮度大于 [`BLACK_BAR_LUMA`]）的行/列，
+/// 两侧黑边宽度相差超过 1% 时（比如画面本身的边缘较暗）认为没有黑边，
+/// 没有黑边或整个画面都是黑色时返回整个 `image` 的区域
+pub fn detect_play_area(image: &DynamicImage) -> Rect {
+    let full = Rect {
+        x: 0,
+        y: 0,
+        width: image.width(),
+        height: image.height(),
+    };
+    let luma = image.to_luma8();
+    let (width, height) = luma.dimensions();
+    let col_is_black = |x: u32| (0..height).all(|y| luma.get_pixel(x, y)[0] <= BLACK_BAR_LUMA);
+    let row_is_black = |y: u32| (0..width).all(|x| luma.get_pixel(x, y)[0] <= BLACK_BAR_LUMA);
+
+    let Some(left) = (0..width).find(|&x| !col_is_black(x)) else {
+        return full;
+    };
+    let right = (0..width).rev().find(|&x| !col_is_black(x)).unwrap_or(left);
+    let top = (0..height).find(|&y| !row_is_black(y)).unwrap_or(0);
+    let bottom = (0..height).rev().find(|&y| !row_is_black(y)).unwrap_or(top);
+
+    // 黑边总是对称的
+    let symmetric = |a: u32, b: u32, len: u32| a.abs_diff(b) <= (len / 100).max(1);
+    let (x, w) = if symmetric(left, width - 1 - right, width) {
+        (left, right - left + 1)
+    } else {
+        (0, width)
+    };
+    let (y, h) = if symmetric(top, height - 1 - bottom, height) {
+        (top, bottom - top + 1)
+    } else {
+        (0, height)
+    };
+    Rect {
+        x,
+        y,
+        width: w,
+        height: h,
+    }
+}
+
+/// 计算 `image` 的差异哈希（dHash）
+///
+/// 将图像缩放到 9x8，每一位表示同一行中相邻两个像素的亮度是否递增。
+/// 只与图像的结构有关，对整体亮度、缩放不敏感，两个哈希之间的距离见 [`hamming_distance`]
+pub fn dhash(image: &ImageBuffer<Luma<f32>, Vec<f32>>) -> u64 {
+    let small = image::imageops::resize(image, 9, 8, image::imageops::FilterType::Triangle);
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x + 1, y)[0] > small.get_pixel(x, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// 两个哈希不同的位数，越小越相似
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
 pub fn save_image(image: &DynamicImage, path: &str) {
     let mut path = path.to_string();
     if !path.ends_with(".png") {
@@ -124,3 +476,223 @@ pub fn save_image(image: &DynamicImage, path: &str) {
 //     })?;
 //     Ok(engine)
 // }
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, GrayImage, Luma, RgbaImage};
+
+    use super::*;
+
+    #[test]
+    fn test_draw_number() {
+        let mut image = DynamicImage::new_rgba8(20, 10);
+        draw_number(&mut image, 1, 1, "1.0", 1, [255, 0, 0, 255]);
+        let image = image.to_rgba8();
+        let lit = |x, y| image.get_pixel(x, y)[0] == 255;
+        // "1" 的第一行为 .#.
+        assert!(!lit(1, 1) && lit(2, 1) && !lit(3, 1));
+        // "." 只有最后一行的中间
+        assert!(lit(6, 5) && !lit(6, 1));
+        // "0" 的第三行为 #.#
+        assert!(lit(9, 3) && !lit(10, 3) && lit(11, 3));
+
+        // 超出图像的部分不绘制
+        let mut image = DynamicImage::new_rgba8(4, 4);
+        draw_number(&mut image, -2, 2, "88", 2, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_rect_serde() {
+        let rect = Rect {
+            x: 1780,
+            y: 760,
+            width: 130,
+            height: 60,
+        };
+        let json = serde_json::to_string(&rect).unwrap();
+        assert_eq!(json, r#"{"x":1780,"y":760,"width":130,"height":60}"#);
+        assert_eq!(serde_json::from_str::<Rect>(&json).unwrap(), rect);
+    }
+
+    #[test]
+    fn test_count_colored_blobs() {
+        let mut image = RgbaImage::from_pixel(100, 80, Rgba([30, 30, 30, 255]));
+        let mut fill = |x0: u32, y0: u32, size: u32, color: [u8; 4]| {
+            for y in y0..y0 + size {
+                for x in x0..x0 + size {
+                    image.put_pixel(x, y, Rgba(color));
+                }
+            }
+        };
+        // 两个红色方块、一个蓝色方块和一个红色噪点
+        fill(10, 10, 12, [220, 20, 20, 255]);
+        fill(60, 40, 8, [230, 40, 30, 255]);
+        fill(40, 60, 10, [20, 20, 220, 255]);
+        fill(90, 5, 2, [220, 20, 20, 255]);
+        let image = DynamicImage::ImageRgba8(image);
+
+        let mut red = count_colored_blobs(&image, (340.0, 20.0), 100, 100, 10);
+        red.sort_by_key(|rect| rect.x);
+        assert_eq!(red.len(), 2);
+        assert_eq!(
+            (red[0].x, red[0].y, red[0].width, red[0].height),
+            (10, 10, 12, 12)
+        );
+        assert_eq!(
+            (red[1].x, red[1].y, red[1].width, red[1].height),
+            (60, 40, 8, 8)
+        );
+
+        let blue = count_colored_blobs(&image, (200.0, 260.0), 100, 100, 10);
+        assert_eq!(blue.len(), 1);
+        assert_eq!(
+            (blue[0].x, blue[0].y, blue[0].width, blue[0].height),
+            (40, 60, 10, 10)
+        );
+    }
+
+    #[test]
+    fn test_otsu_threshold() {
+        // 左半边暗、右半边亮的双峰图像，带有少量噪声
+        let image = GrayImage::from_fn(64, 32, |x, y| {
+            let noise = ((x * 7 + y * 13) % 11) as u8;
+            Luma([if x < 32 { 40 + noise } else { 190 + noise }])
+        });
+        let image = DynamicImage::ImageLuma8(image);
+
+        let threshold = otsu_threshold(&image);
+        println!("otsu threshold: {threshold}");
+        assert!(threshold > 50 && threshold <= 190);
+        assert_eq!(BinarizeThreshold::Auto.resolve(&image), threshold);
+        assert_eq!(BinarizeThreshold::from(127).resolve(&image), 127);
+
+        let binarized = binarize_image(&image, threshold).to_luma8();
+        for (x, _, Luma([v])) in binarized.enumerate_pixels() {
+            assert_eq!(*v, if x < 32 { 0 } else { 255 });
+        }
+    }
+
+    #[test]
+    fn test_luma_histogram() {
+        let image = GrayImage::from_fn(16, 8, |x, _| Luma([if x < 4 { 10 } else { 200 }]));
+        let histogram = luma_histogram(&DynamicImage::ImageLuma8(image));
+        assert_eq!(histogram[10], 32);
+        assert_eq!(histogram[200], 96);
+        assert_eq!(histogram.iter().sum::<u32>(), 128);
+
+        assert_eq!(luma_histogram(&DynamicImage::new_rgba8(3, 2))[0], 6);
+    }
+
+    #[test]
+    fn test_score_stats() {
+        let result = Image::new(vec![0.2, 0.4, 0.4, 0.4, 0.5, 0.5, 0.7, 0.9], 4, 2);
+        let stats = score_stats(&result);
+        assert_eq!(stats.count, 8);
+        assert_eq!((stats.min, stats.max), (0.2, 0.9));
+        assert!((stats.mean - 0.5).abs() < 1e-6);
+        assert!((stats.stddev - 0.2).abs() < 1e-6);
+
+        // NaN 和无穷大不参与统计
+        let result = Image::new(vec![0.5, f32::NAN, 0.5, f32::INFINITY], 2, 2);
+        let stats = score_stats(&result);
+        assert_eq!((stats.count, stats.min, stats.max), (2, 0.5, 0.5));
+        assert_eq!((stats.mean, stats.stddev), (0.5, 0.0));
+
+        let stats = score_stats(&Image::filled(2, 2, f32::NAN));
+        assert_eq!((stats.count, stats.mean, stats.stddev), (0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_dhash() {
+        let image = ImageBuffer::from_fn(36, 32, |x, y| {
+            Luma([((x * 7 + y * 13) % 17) as f32 / 68.0 + ((x * y) % 5) as f32 / 20.0])
+        });
+        let hash = dhash(&image);
+        assert_ne!(hash, 0);
+
+        // 整体调亮、缩放后哈希几乎不变
+        let brighter = ImageBuffer::from_fn(36, 32, |x, y| Luma([image.get_pixel(x, y)[0] + 0.3]));
+        assert_eq!(hamming_distance(hash, dhash(&brighter)), 0);
+        let resized =
+            image::imageops::resize(&image, 72, 64, image::imageops::FilterType::Triangle);
+        assert!(hamming_distance(hash, dhash(&resized)) <= 8);
+
+        // 从左到右变亮的渐变，左右翻转后每一位都不同
+        let gradient = ImageBuffer::from_fn(36, 32, |x, _| Luma([x as f32 / 36.0]));
+        assert_eq!(dhash(&gradient), u64::MAX);
+        let flipped = image::imageops::flip_horizontal(&gradient);
+        assert_eq!(hamming_distance(dhash(&gradient), dhash(&flipped)), 64);
+    }
+
+    #[test]
+    fn test_detect_play_area() {
+        use crate::vision::matcher::test::{get_device_image, Device};
+
+        let full = |width, height| Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+
+        // 2560x1080 的屏幕，中间为 1920x1080 的画面
+        let content = |x: u32, y: u32| Rgba([(x % 200 + 20) as u8, (y % 200 + 20) as u8, 80, 255]);
+        let image = RgbaImage::from_fn(2560, 1080, |x, y| {
+            if (320..2240).contains(&x) {
+                content(x, y)
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        });
+        assert_eq!(
+            detect_play_area(&DynamicImage::ImageRgba8(image)),
+            Rect {
+                x: 320,
+                y: 0,
+                width: 1920,
+                height: 1080
+            }
+        );
+
+        // 上下黑边
+        let image = RgbaImage::from_fn(100, 80, |x, y| {
+            if (10..70).contains(&y) {
+                content(x, y)
+            } else {
+                Rgba([2, 2, 2, 255])
+            }
+        });
+        assert_eq!(
+            detect_play_area(&DynamicImage::ImageRgba8(image)),
+            Rect {
+                x: 0,
+                y: 10,
+                width: 100,
+                height: 60
+            }
+        );
+
+        // 只有一侧较暗，不是黑边
+        let image = RgbaImage::from_fn(200, 100, |x, y| {
+            if x >= 30 {
+                content(x, y)
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        });
+        assert_eq!(
+            detect_play_area(&DynamicImage::ImageRgba8(image)),
+            full(200, 100)
+        );
+
+        // 全黑
+        assert_eq!(
+            detect_play_area(&DynamicImage::new_rgba8(64, 36)),
+            full(64, 36)
+        );
+
+        // 正常的截图
+        let image = get_device_image(Device::MUMU, "battle0.png").unwrap();
+        assert_eq!(detect_play_area(&image), full(1920, 1080));
+    }
+}
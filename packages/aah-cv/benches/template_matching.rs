@@ -0,0 +1,211 @@
+//! Compares the GPU [TemplateMatcher], the CPU [template_matching::match_template]
+//! and [imageproc::template_matching::match_template] across a few input/template sizes.
+//!
+//! Run with `cargo bench -p aah-cv`. The GPU device is initialized once outside of the
+//! measurements, and criterion's warmup absorbs the pipeline and buffer creation, so only
+//! the steady-state matching cost is measured.
+//!
+//! The `workgroup size` group compares the workgroup sizes of [TemplateMatcher], see
+//! [aah_cv::TemplateMatcherBuilder::workgroup_size], and `u8 input` compares the upload of an
+//! `f32` input to [TemplateMatcher::match_template_u8] on a 1080p screen.
+
+use aah_cv::{template_matching, types::Image, MatchTemplateMethod, TemplateMatcher};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use image024::{GrayImage, Luma};
+use imageproc::template_matching::MatchTemplateMethod as ImageprocMethod;
+use ndarray::Array2;
+
+/// `(input width, input height, template size)`
+const SIZES: [(u32, u32, u32); 4] = [
+    (480, 270, 16),
+    (480, 270, 48),
+    (960, 540, 16),
+    (960, 540, 48),
+];
+
+/// A deterministic pseudo-random pixel value, so that the inputs have no periodic structure
+fn pixel(x: u32, y: u32) -> u8 {
+    let mut h = x.wrapping_mul(0x9e37_79b1) ^ y.wrapping_mul(0x85eb_ca77);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    (h >> 24) as u8
+}
+
+fn gray_image(width: u32, height: u32, offset: u32) -> GrayImage {
+    GrayImage::from_fn(width, height, |x, y| Luma([pixel(x + offset, y + offset)]))
+}
+
+fn to_f32(image: &GrayImage) -> Vec<f32> {
+    image.pixels().map(|p| p.0[0] as f32 / 255.0).collect()
+}
+
+fn to_array2(image: &GrayImage) -> Array2<f32> {
+    Array2::from_shape_vec(
+        (image.height() as usize, image.width() as usize),
+        to_f32(image),
+    )
+    .unwrap()
+}
+
+fn bench_methods(
+    c: &mut Criterion,
+    name: &str,
+    method: MatchTemplateMethod,
+    imageproc_method: ImageprocMethod,
+) {
+    let mut group = c.benchmark_group(name);
+    group.sample_size(20);
+
+    let mut matcher = TemplateMatcher::new();
+    for (width, height, size) in SIZES {
+        let input = gray_image(width, height, 0);
+        let template = gray_image(size, size, 7);
+        let (input_data, template_data) = (to_f32(&input), to_f32(&template));
+        let id = format!("{width}x{height}/{size}x{size}");
+
+        group.bench_function(BenchmarkId::new("aah-cv gpu", &id), |b| {
+            b.iter(|| {
+                matcher
+                    .match_template(
+                        Image::new(input_data.as_slice(), width, height),
+                        Image::new(template_data.as_slice(), size, size),
+                        method,
+                        false,
+                    )
+                    .unwrap();
+                matcher.wait_for_result().unwrap()
+            })
+        });
+        group.bench_function(BenchmarkId::new("imageproc", &id), |b| {
+            b.iter(|| {
+                imageproc::template_matching::match_template(&input, &template, imageproc_method)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_gpu_vs_imageproc(c: &mut Criterion) {
+    bench_methods(
+        c,
+        "sum of squared errors",
+        MatchTemplateMethod::SumOfSquaredErrors,
+        ImageprocMethod::SumOfSquaredErrors,
+    );
+    bench_methods(
+        c,
+        "cross correlation",
+        MatchTemplateMethod::CrossCorrelation,
+        ImageprocMethod::CrossCorrelation,
+    );
+}
+
+fn bench_workgroup_sizes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("workgroup size");
+    group.sample_size(20);
+
+    for (x, y) in [(8, 8), (16, 16), (32, 8)] {
+        // One device at a time
+        let mut matcher = TemplateMatcher::builder()
+            .workgroup_size(x, y)
+            .build()
+            .unwrap();
+        for (width, height, size) in SIZES {
+            let input = to_f32(&gray_image(width, height, 0));
+            let template = to_f32(&gray_image(size, size, 7));
+            let id = format!("{width}x{height}/{size}x{size}");
+
+            group.bench_function(BenchmarkId::new(format!("{x}x{y}"), &id), |b| {
+                b.iter(|| {
+                    matcher
+                        .match_template(
+                            Image::new(input.as_slice(), width, height),
+                            Image::new(template.as_slice(), size, size),
+                            MatchTemplateMethod::SumOfSquaredErrors,
+                            false,
+                        )
+                        .unwrap();
+                    matcher.wait_for_result().unwrap()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_u8_input(c: &mut Criterion) {
+    let mut group = c.benchmark_group("u8 input");
+    group.sample_size(20);
+
+    // A battle screen and a deploy card sized template
+    let (width, height, size) = (1920, 1080, 16);
+    let input = gray_image(width, height, 0);
+    let input_f32 = to_f32(&input);
+    let input_u8 = image::GrayImage::from_raw(width, height, input.into_raw()).unwrap();
+    let template = to_f32(&gray_image(size, size, 7));
+    let id = format!("{width}x{height}/{size}x{size}");
+
+    let mut matcher = TemplateMatcher::new();
+    group.bench_function(BenchmarkId::new("f32", &id), |b| {
+        b.iter(|| {
+            matcher
+                .match_template(
+                    Image::new(input_f32.as_slice(), width, height),
+                    Image::new(template.as_slice(), size, size),
+                    MatchTemplateMethod::SumOfSquaredErrors,
+                    false,
+                )
+                .unwrap();
+            matcher.wait_for_result().unwrap()
+        })
+    });
+    group.bench_function(BenchmarkId::new("u8", &id), |b| {
+        b.iter(|| {
+            matcher
+                .match_template_u8(
+                    &input_u8,
+                    Image::new(template.as_slice(), size, size),
+                    MatchTemplateMethod::SumOfSquaredErrors,
+                )
+                .unwrap();
+            matcher.wait_for_result().unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn bench_cpu_vs_imageproc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("normalized cross correlation");
+    group.sample_size(10);
+
+    for (width, height, size) in SIZES {
+        let input = gray_image(width, height, 0);
+        let template = gray_image(size, size, 7);
+        let id = format!("{width}x{height}/{size}x{size}");
+
+        let ctx = template_matching::MatchContext::new(to_array2(&input));
+        let kernel = to_array2(&template);
+        group.bench_function(BenchmarkId::new("aah-cv cpu", &id), |b| {
+            b.iter(|| ctx.match_template(&kernel).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("imageproc", &id), |b| {
+            b.iter(|| {
+                imageproc::template_matching::match_template(
+                    &input,
+                    &template,
+                    ImageprocMethod::CrossCorrelationNormalized,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_gpu_vs_imageproc,
+    bench_workgroup_sizes,
+    bench_u8_input,
+    bench_cpu_vs_imageproc
+);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+struct Params {
+    width: u32,
+    height: u32,
+};
+
+// Packed RGBA8 pixels for `main_rgba`, or 4 packed luma8 pixels per value for `main_gray`
+@group(0)
+@binding(0)
+var<storage, read> raw: array<u32>;
+
+@group(0)
+@binding(1)
+var<storage, read_write> luma: array<f32>;
+
+@group(0)
+@binding(2)
+var<uniform> params: Params;
+
+@compute
+@workgroup_size(16, 16, 1)
+// Converts packed RGBA8 pixels to luma, with the same coefficients as `image`'s `to_luma32f`
+fn main_rgba(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    var x = global_id.x;
+    var y = global_id.y;
+
+    if x >= params.width || y >= params.height {
+        return;
+    }
+
+    var idx = y * params.width + x;
+    var color = unpack4x8unorm(raw[idx]);
+    luma[idx] = 0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b;
+}
+
+@compute
+@workgroup_size(16, 16, 1)
+// Converts packed luma8 pixels to f32
+fn main_gray(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    var x = global_id.x;
+    var y = global_id.y;
+
+    if x >= params.width || y >= params.height {
+        return;
+    }
+
+    var idx = y * params.width + x;
+    var value = (raw[idx / 4u] >> (8u * (idx % 4u))) & 0xffu;
+    luma[idx] = f32(value) / 255.0;
+}
@@ -1,8 +1,19 @@
+// `WORKGROUP_SIZE_X` and `WORKGROUP_SIZE_Y` are prepended by the `TemplateMatcher`,
+// see `TemplateMatcherBuilder::workgroup_size`
+
 struct Uniforms {
     input_width: u32,
     input_height: u32,
     template_width: u32,
     template_height: u32,
+    // Norm of the zero-mean template, only used by CCOEFF_NORMED
+    template_norm: f32,
+    // Top left corner of the matched region of the input, and the size of the result (the
+    // locations of the template inside the region). The whole input is matched by default
+    roi_x: u32,
+    roi_y: u32,
+    result_width: u32,
+    result_height: u32,
 };
 
 @group(0)
@@ -21,107 +32,135 @@ var<storage, read_write> result_buf: array<f32>;
 @binding(3)
 var<uniform> uniforms: Uniforms;
 
-@compute
-@workgroup_size(16, 16, 1)
-// Sum of Absolute Error
-fn main_sae(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    var x = global_id.x;
-    var y = global_id.y;
-
-    var input_width = uniforms.input_width;
-    var input_height = uniforms.input_height;
-
-    var template_width = uniforms.template_width;
-    var template_height = uniforms.template_height;
-
-    var match_width = min(template_width, input_width - x);
-    var match_height = min(template_height, input_height - y);
-
-    var total_sum = 0.0;
-    for (var i = 0u; i < match_width; i++) {
-        for (var j = 0u; j < match_height; j++) {
-            var input_idx = (y + j) * input_width + (i + x);
-            var template_idx = j * template_width + i;
+const TILE_SIZE: u32 = WORKGROUP_SIZE_X * WORKGROUP_SIZE_Y;
 
-            var input_val = input_buf[input_idx];
-            var template_val = template_buf[template_idx];
+// The template is read tile by tile into workgroup memory, shared by all the invocations
+var<workgroup> template_tile: array<f32, TILE_SIZE>;
 
-            var abdiff = abs(input_val - template_val);
+const METHOD_SAE: u32 = 0u;
+const METHOD_SSE: u32 = 1u;
+const METHOD_CC: u32 = 2u;
+// The template is uploaded with its mean subtracted, so the cross correlation with the window
+// is the numerator of the correlation coefficient
+const METHOD_CCOEFF_NORMED: u32 = 3u;
 
-            total_sum += abdiff;
+fn score(method: u32, input_val: f32, template_val: f32) -> f32 {
+    switch method {
+        case METHOD_SAE: {
+            return abs(input_val - template_val);
+        }
+        case METHOD_SSE: {
+            var diff = input_val - template_val;
+            return diff * diff;
+        }
+        default: {
+            return input_val * template_val;
         }
     }
-
-    var result_idx = y * (input_width - template_width + 1u) + x;
-    result_buf[result_idx] = total_sum;
 }
 
-@compute
-@workgroup_size(16, 16, 1)
-// Sum of Squared Error
-fn main_sse(@builtin(global_invocation_id) global_id: vec3<u32>) {
+fn sliding_window(global_id: vec3<u32>, local_index: u32, method: u32) {
     var x = global_id.x;
     var y = global_id.y;
 
     var input_width = uniforms.input_width;
-    var input_height = uniforms.input_height;
 
     var template_width = uniforms.template_width;
-    var template_height = uniforms.template_height;
+    var template_len = template_width * uniforms.template_height;
 
-    var match_width = min(template_width, input_width - x);
-    var match_height = min(template_height, input_height - y);
+    var result_width = uniforms.result_width;
+    var result_height = uniforms.result_height;
+    // Out of bounds invocations still have to help loading the tiles
+    var in_bounds = x < result_width && y < result_height;
 
     var total_sum = 0.0;
-    for (var i = 0u; i < match_width; i++) {
-        for (var j = 0u; j < match_height; j++) {
-            var input_idx = (y + j) * input_width + (i + x);
-            var template_idx = j * template_width + i;
-
-            var input_val = input_buf[input_idx];
-            var template_val = template_buf[template_idx];
-
-            var sqdiff = pow(input_val - template_val, 2.0);
+    // Sum and squared sum of the input window, for CCOEFF_NORMED
+    var window_sum = 0.0;
+    var window_sqsum = 0.0;
+    for (var tile_start = 0u; tile_start < template_len; tile_start += TILE_SIZE) {
+        var load_idx = tile_start + local_index;
+        if load_idx < template_len {
+            template_tile[local_index] = template_buf[load_idx];
+        }
+        workgroupBarrier();
+
+        if in_bounds {
+            var tile_len = min(TILE_SIZE, template_len - tile_start);
+            var i = tile_start % template_width;
+            var j = tile_start / template_width;
+            for (var k = 0u; k < tile_len; k++) {
+                var input_val = input_buf[(uniforms.roi_y + y + j) * input_width + (uniforms.roi_x + x + i)];
+                total_sum += score(method, input_val, template_tile[k]);
+                if method == METHOD_CCOEFF_NORMED {
+                    window_sum += input_val;
+                    window_sqsum += input_val * input_val;
+                }
+
+                i++;
+                if i == template_width {
+                    i = 0u;
+                    j++;
+                }
+            }
+        }
+        workgroupBarrier();
+    }
 
-            total_sum += sqdiff;
+    if in_bounds {
+        if method == METHOD_CCOEFF_NORMED {
+            total_sum = normalize(total_sum, window_sum, window_sqsum, f32(template_len));
         }
+        result_buf[y * result_width + x] = total_sum;
     }
+}
 
-    var result_idx = y * (input_width - template_width + 1u) + x;
-    result_buf[result_idx] = total_sum;
+// Divides the cross correlation with the zero-mean template by the norms of the zero-mean window
+// and template, flat windows or templates score 0
+fn normalize(ccorr: f32, window_sum: f32, window_sqsum: f32, len: f32) -> f32 {
+    var window_var = window_sqsum - window_sum * window_sum / len;
+    var denom = sqrt(max(window_var, 0.0)) * uniforms.template_norm;
+    if window_var <= 1e-6 * len || denom <= 0.0 {
+        return 0.0;
+    }
+    return clamp(ccorr / denom, -1.0, 1.0);
 }
 
 @compute
-@workgroup_size(16, 16, 1)
-// Cross Correleation
-fn main_cc(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    var x = global_id.x;
-    var y = global_id.y;
-
-    var input_width = uniforms.input_width;
-    var input_height = uniforms.input_height;
-
-    var template_width = uniforms.template_width;
-    var template_height = uniforms.template_height;
-
-    var match_width = min(template_width, input_width - x);
-    var match_height = min(template_height, input_height - y);
-
-    var total_sum = 0.0;
-    for (var i = 0u; i < match_width; i++) {
-        for (var j = 0u; j < match_height; j++) {
-            var input_idx = (y + j) * input_width + (i + x);
-            var template_idx = j * template_width + i;
-
-            var input_val = input_buf[input_idx];
-            var template_val = template_buf[template_idx];
+@workgroup_size(WORKGROUP_SIZE_X, WORKGROUP_SIZE_Y, 1)
+// Sum of Absolute Error
+fn main_sae(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    sliding_window(global_id, local_index, METHOD_SAE);
+}
 
-            var cc = input_val * template_val;
+@compute
+@workgroup_size(WORKGROUP_SIZE_X, WORKGROUP_SIZE_Y, 1)
+// Sum of Squared Error
+fn main_sse(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    sliding_window(global_id, local_index, METHOD_SSE);
+}
 
-            total_sum += cc;
-        }
-    }
+@compute
+@workgroup_size(WORKGROUP_SIZE_X, WORKGROUP_SIZE_Y, 1)
+// Cross Correleation
+fn main_cc(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    sliding_window(global_id, local_index, METHOD_CC);
+}
 
-    var result_idx = y * (input_width - template_width + 1u) + x;
-    result_buf[result_idx] = total_sum;
-}
\ No newline at end of file
+@compute
+@workgroup_size(WORKGROUP_SIZE_X, WORKGROUP_SIZE_Y, 1)
+// Normalized Correlation Coefficient
+fn main_ccoeff_normed(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    sliding_window(global_id, local_index, METHOD_CCOEFF_NORMED);
+}
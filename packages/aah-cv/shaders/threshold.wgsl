@@ -0,0 +1,56 @@
+// `WORKGROUP_SIZE_X` and `WORKGROUP_SIZE_Y` are prepended by the `TemplateMatcher`,
+// see `TemplateMatcherBuilder::workgroup_size`
+
+struct Params {
+    width: u32,
+    height: u32,
+    threshold: f32,
+    higher_is_better: u32,
+};
+
+struct Match {
+    x: u32,
+    y: u32,
+    score: f32,
+};
+
+@group(0)
+@binding(0)
+var<storage, read> scores: array<f32>;
+
+@group(0)
+@binding(1)
+var<storage, read_write> match_count: atomic<u32>;
+
+@group(0)
+@binding(2)
+var<storage, read_write> matches: array<Match>;
+
+@group(0)
+@binding(3)
+var<uniform> params: Params;
+
+@compute
+@workgroup_size(WORKGROUP_SIZE_X, WORKGROUP_SIZE_Y, 1)
+// Appends every score passing the threshold to `matches`
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    var x = global_id.x;
+    var y = global_id.y;
+
+    if x >= params.width || y >= params.height {
+        return;
+    }
+
+    var score = scores[y * params.width + x];
+    var passed = false;
+    if params.higher_is_better == 1u {
+        passed = score >= params.threshold;
+    } else {
+        passed = score <= params.threshold;
+    }
+
+    if passed {
+        var idx = atomicAdd(&match_count, 1u);
+        matches[idx] = Match(x, y, score);
+    }
+}
@@ -1,14 +1,19 @@
+#[cfg(feature = "gpu")]
 use std::borrow::Cow;
+#[cfg(feature = "gpu")]
 use std::time::Instant;
 
 use imageproc::filter::Kernel;
 use ndarray::Zip;
 use ndarray::{Array2, ArrayView2, ArrayViewMut2, Axis};
+#[cfg(feature = "gpu")]
 use wgpu::util::DeviceExt;
 
+#[cfg(feature = "gpu")]
 use crate::gpu::{
     BindGroupEntriesBuilder, BindGroupLayoutEntriesBuilder, Context, GpuTask, GpuTaskWrapper,
 };
+use crate::types::Image;
 
 fn convolve(image: &Array2<f32>, kernel: &Array2<f32>) -> Array2<f32> {
     let (image_height, image_width) = image.dim();
@@ -37,8 +42,44 @@ fn convolve(image: &Array2<f32>, kernel: &Array2<f32>) -> Array2<f32> {
     result
 }
 
+/// Valid-mode correlation of `image` with `kernel` (the kernel is not flipped), the result has size
+/// `(W - w + 1) x (H - h + 1)`.
+///
+/// Runs on the GPU with the `gpu` feature, and through FFT on the CPU otherwise.
+/// Returns [None] if the kernel is larger than the image.
+pub fn correlate(image: &Array2<f32>, kernel: &Array2<f32>) -> Option<Array2<f32>> {
+    #[cfg(feature = "gpu")]
+    {
+        gpu_convolve_block(image, kernel)
+    }
+    #[cfg(not(feature = "gpu"))]
+    {
+        let (image_height, image_width) = image.dim();
+        let (kernel_height, kernel_width) = kernel.dim();
+        if kernel_height > image_height || kernel_width > image_width {
+            return None;
+        }
+
+        let to_image = |array: &Array2<f32>| {
+            let (height, width) = array.dim();
+            Image::new(
+                array.iter().copied().collect::<Vec<_>>(),
+                width as u32,
+                height as u32,
+            )
+        };
+        let res = crate::fft_matching::fft_ccorr(&to_image(image), &to_image(kernel));
+        Array2::from_shape_vec(
+            (res.height as usize, res.width as usize),
+            res.data.into_owned(),
+        )
+        .ok()
+    }
+}
+
 // gpu
 
+#[cfg(feature = "gpu")]
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct ShaderUniforms {
@@ -48,15 +89,21 @@ struct ShaderUniforms {
     kernel_height: u32,
 }
 
+#[cfg(feature = "gpu")]
 pub fn gpu_convolve_block(image: &Array2<f32>, kernel: &Array2<f32>) -> Option<Array2<f32>> {
     pollster::block_on(gpu_convolve(image, kernel))
 }
 
+/// Returns [None] if the kernel is larger than the image.
+#[cfg(feature = "gpu")]
 pub async fn gpu_convolve(image: &Array2<f32>, kernel: &Array2<f32>) -> Option<Array2<f32>> {
     let image_height = image.shape()[0];
     let image_width = image.shape()[1];
     let kernel_height = kernel.shape()[0];
     let kernel_width = kernel.shape()[1];
+    if kernel_height > image_height || kernel_width > image_width {
+        return None;
+    }
 
     let result_width = image_width - kernel_width + 1;
     let result_height = image_height - kernel_height + 1;
@@ -74,6 +121,7 @@ pub async fn gpu_convolve(image: &Array2<f32>, kernel: &Array2<f32>) -> Option<A
     res.map(|res| Array2::from_shape_vec((result_height, result_width), res).unwrap())
 }
 
+#[cfg(feature = "gpu")]
 pub struct GpuConvolveTask {
     image: Array2<f32>,
     kernel: Array2<f32>,
@@ -82,6 +130,7 @@ pub struct GpuConvolveTask {
     kernel_buffer: wgpu::Buffer,
 }
 
+#[cfg(feature = "gpu")]
 impl GpuConvolveTask {
     pub async fn new(context: &Context, image: &Array2<f32>, kernel: &Array2<f32>) -> Self {
         // Gets the size in bytes of the buffer.
@@ -145,6 +194,7 @@ impl GpuConvolveTask {
     }
 }
 
+#[cfg(feature = "gpu")]
 impl GpuTask for GpuConvolveTask {
     fn build_bind_group_entries<'a>(
         &'a self,
@@ -188,14 +238,148 @@ impl GpuTask for GpuConvolveTask {
         ); // Number of cells to run, the (x,y,z) size of item being processed
     }
 }
+
+/// Correlates the image with the outer product of `kernel_x` and `kernel_y`,
+/// as a horizontal pass followed by a vertical pass on the CPU.
+///
+/// The border is clamped to the edge, so the result has the same size as the input.
+/// For a `k x k` kernel this costs `2k` multiplications per pixel instead of `k * k`.
+///
+/// Returns an error if either kernel or the image is empty.
+pub fn separable(
+    image: &Image<'_>,
+    kernel_x: &[f32],
+    kernel_y: &[f32],
+) -> Result<Image<'static>, String> {
+    if kernel_x.is_empty() || kernel_y.is_empty() {
+        return Err(format!(
+            "kernel is empty: {}x{}",
+            kernel_x.len(),
+            kernel_y.len()
+        ));
+    }
+    if image.width == 0 || image.height == 0 {
+        return Err(format!("image is empty: {}x{}", image.width, image.height));
+    }
+
+    let (width, height) = (image.width as usize, image.height as usize);
+    let (left, top) = ((kernel_x.len() - 1) / 2, (kernel_y.len() - 1) / 2);
+    let horizontal = (0..height)
+        .flat_map(|y| {
+            let row = image.row(y as u32);
+            (0..width).map(move |x| {
+                kernel_x
+                    .iter()
+                    .enumerate()
+                    .map(|(i, k)| k * row[(x + i).saturating_sub(left).min(width - 1)])
+                    .sum::<f32>()
+            })
+        })
+        .collect::<Vec<_>>();
+    let res = (0..height)
+        .flat_map(|y| {
+            let horizontal = &horizontal;
+            (0..width).map(move |x| {
+                kernel_y
+                    .iter()
+                    .enumerate()
+                    .map(|(i, k)| {
+                        k * horizontal[(y + i).saturating_sub(top).min(height - 1) * width + x]
+                    })
+                    .sum::<f32>()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Image::new(res, width as u32, height as u32))
+}
+
+/// Builds a normalized 1D Gaussian kernel with a radius of `ceil(3 * sigma)`.
+///
+/// # Panics
+///
+/// Panics if `sigma <= 0.0`.
+pub fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    assert!(sigma > 0.0, "sigma must be > 0.0");
+    let radius = (3.0 * sigma).ceil() as i32;
+    let kernel = (-radius..=radius)
+        .map(|x| (-(x * x) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect::<Vec<_>>();
+    let sum: f32 = kernel.iter().sum();
+    kernel.into_iter().map(|v| v / sum).collect()
+}
+
+/// Blurs an image using a Gaussian of standard deviation `sigma`, see [separable].
+///
+/// Useful for reducing anti-aliasing noise in screenshots before matching.
+/// Returns an error if the image is empty.
+pub fn gaussian_blur(image: &Image<'_>, sigma: f32) -> Result<Image<'static>, String> {
+    let kernel = gaussian_kernel(sigma);
+    separable(image, &kernel, &kernel)
+}
+
 #[cfg(test)]
 mod test {
+    #[cfg(feature = "gpu")]
     use std::time::Instant;
 
     use ndarray::Array2;
 
-    use crate::convolve::{convolve, gpu_convolve, gpu_convolve_block};
+    #[cfg(feature = "gpu")]
+    use crate::convolve::gpu_convolve;
+    use crate::convolve::{convolve, correlate, gaussian_blur, gaussian_kernel, separable};
+    use crate::types::Image;
+
+    #[test]
+    fn test_gaussian_blur() {
+        let sigma = 1.5;
+        let (width, height) = (64, 48);
+        let data = (0..width * height)
+            .map(|i| ((i % width * 7 + i / width * 13) % 17) as f32 / 16.0)
+            .collect::<Vec<_>>();
+        let res = gaussian_blur(&Image::new(data.as_slice(), width, height), sigma).unwrap();
+        assert_eq!((res.width, res.height), (width, height));
+
+        // `gaussian_blur_f32` of imageproc uses an unnormalized kernel with a radius of `2 * sigma`,
+        // so run its separable filter (which it is built on) with the same kernel
+        let kernel = gaussian_kernel(sigma);
+        assert!((kernel.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        let image =
+            image024::ImageBuffer::<image024::Luma<f32>, _>::from_raw(width, height, data).unwrap();
+        let expected = imageproc::filter::separable_filter_equal(&image, &kernel);
+        for (a, b) in res.data.iter().zip(expected.as_raw().iter()) {
+            assert!((a - b).abs() < 1e-5, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    fn test_separable_empty() {
+        let image = Image::new(vec![0.0; 12], 4, 3);
+        let err = separable(&image, &[], &[1.0]).unwrap_err();
+        assert_eq!(err, "kernel is empty: 0x1");
+        let err = separable(&Image::new(vec![], 0, 3), &[1.0], &[1.0]).unwrap_err();
+        assert_eq!(err, "image is empty: 0x3");
+
+        // A single-pixel kernel keeps the image as is
+        let image = Image::new((0..12).map(|v| v as f32).collect::<Vec<_>>(), 4, 3);
+        let res = separable(&image, &[1.0], &[1.0]).unwrap();
+        assert_eq!(res.data, image.data);
+    }
+
+    #[test]
+    fn test_correlate() {
+        let image = Array2::from_shape_fn((40, 50), |(y, x)| ((x * 7 + y * 13) % 17) as f32);
+        let kernel = Array2::from_shape_fn((5, 3), |(y, x)| ((x + y * 3) % 4) as f32);
+        let res = correlate(&image, &kernel).unwrap();
+        let expected = convolve(&image, &kernel);
+        assert_eq!(res.dim(), expected.dim());
+        for (a, b) in res.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-3, "{a} != {b}");
+        }
+        assert!(correlate(&kernel, &image).is_none());
+    }
 
+    #[cfg(feature = "gpu")]
     fn test_convolve_with_size(image_size: usize, kernel_size: usize) {
         println!("testing in image_size {image_size} and kernel_size {kernel_size}...");
         #[rustfmt::skip]
@@ -222,6 +406,7 @@ mod test {
         println!("{:?}", result);
     }
 
+    #[cfg(feature = "gpu")]
     #[test]
     fn test_main() {
         /*
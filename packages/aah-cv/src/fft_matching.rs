@@ -0,0 +1,125 @@
+//! FFT-based cross correlation, used by [MatchTemplateMethod::FftCrossCorrelation](crate::MatchTemplateMethod::FftCrossCorrelation).
+//!
+//! The naive sliding-window shaders cost `O(W * H * w * h)` for an input of `W x H` and a template
+//! of `w x h`, while the FFT path costs `O(W * H * log(W * H))` regardless of the template size.
+//! On a 1920x1080 input the FFT path starts winning at templates around 64x64, and is
+//! dramatically faster for larger ones. For small templates (icons, digits) stick to
+//! [MatchTemplateMethod::CrossCorrelation](crate::MatchTemplateMethod::CrossCorrelation).
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::sync::Arc;
+
+use crate::types::Image;
+
+/// Template side length (in pixels) above which [fft_ccorr] is expected to beat the naive path.
+pub const FFT_TEMPLATE_SIZE_THRESHOLD: u32 = 64;
+
+/// Computes the cross correlation of `input` and `template` through FFT.
+///
+/// Same as the `main_cc` shader: the result has size `(W - w + 1) x (H - h + 1)`,
+/// and each value is `sum(input[x + i, y + j] * template[i, j])`.
+pub fn fft_ccorr(input: &Image<'_>, template: &Image<'_>) -> Image<'static> {
+    let (width, height) = (input.width as usize, input.height as usize);
+    let (t_width, t_height) = (template.width as usize, template.height as usize);
+    assert!(t_width <= width && t_height <= height);
+
+    let mut planner = FftPlanner::new();
+    let row_fft = planner.plan_fft_forward(width);
+    let col_fft = planner.plan_fft_forward(height);
+    let row_ifft = planner.plan_fft_inverse(width);
+    let col_ifft = planner.plan_fft_inverse(height);
+
+    let mut input_freq = input
+        .data
+        .iter()
+        .map(|&v| Complex::new(v as f64, 0.0))
+        .collect::<Vec<_>>();
+    fft2d(&mut input_freq, width, height, &row_fft, &col_fft);
+
+    // The template is zero padded to the input size, the valid region of the
+    // circular correlation never wraps around so no extra padding is needed.
+    let mut template_freq = vec![Complex::new(0.0, 0.0); width * height];
+    for y in 0..t_height {
+        for x in 0..t_width {
+            template_freq[y * width + x] =
+                Complex::new(template.get(x as u32, y as u32) as f64, 0.0);
+        }
+    }
+    fft2d(&mut template_freq, width, height, &row_fft, &col_fft);
+
+    // Correlation theorem: corr(I, T) = IFFT(FFT(I) * conj(FFT(T)))
+    let mut res = input_freq
+        .iter()
+        .zip(template_freq.iter())
+        .map(|(i, t)| i * t.conj())
+        .collect::<Vec<_>>();
+    fft2d(&mut res, width, height, &row_ifft, &col_ifft);
+
+    let scale = (width * height) as f64;
+    let (res_w, res_h) = (width - t_width + 1, height - t_height + 1);
+    let mut data = Vec::with_capacity(res_w * res_h);
+    for y in 0..res_h {
+        for x in 0..res_w {
+            data.push((res[y * width + x].re / scale) as f32);
+        }
+    }
+    Image::new(data, res_w as u32, res_h as u32)
+}
+
+/// In-place 2D FFT of a row-major `width x height` buffer
+fn fft2d(
+    data: &mut [Complex<f64>],
+    width: usize,
+    height: usize,
+    row_fft: &Arc<dyn Fft<f64>>,
+    col_fft: &Arc<dyn Fft<f64>>,
+) {
+    row_fft.process(data);
+
+    let mut col = vec![Complex::new(0.0, 0.0); height];
+    for x in 0..width {
+        for y in 0..height {
+            col[y] = data[y * width + x];
+        }
+        col_fft.process(&mut col);
+        for y in 0..height {
+            data[y * width + x] = col[y];
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fft_ccorr() {
+        let input = Image::new(
+            (0..13 * 9)
+                .map(|i| ((i * 7) % 11) as f32)
+                .collect::<Vec<_>>(),
+            13,
+            9,
+        );
+        let template = Image::new(
+            (0..4 * 3).map(|i| ((i * 5) % 7) as f32).collect::<Vec<_>>(),
+            4,
+            3,
+        );
+        let res = fft_ccorr(&input, &template);
+        assert_eq!((res.width, res.height), (10, 7));
+
+        for y in 0..res.height {
+            for x in 0..res.width {
+                let mut expected = 0.0;
+                for j in 0..template.height {
+                    for i in 0..template.width {
+                        expected += input.get(x + i, y + j) * template.get(i, j);
+                    }
+                }
+                let value = res.get(x, y);
+                assert!((value - expected).abs() < 1e-3, "{value} != {expected}");
+            }
+        }
+    }
+}
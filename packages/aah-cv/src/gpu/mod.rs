@@ -10,7 +10,72 @@ pub struct Context {
     pub queue: wgpu::Queue,
 }
 
+/// Options for selecting the wgpu backend and adapter of a [Context].
+#[derive(Clone, Debug)]
+pub struct ContextOptions {
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    /// Only use the adapter whose name contains this string (case insensitive)
+    pub adapter_name: Option<String>,
+}
+
+impl Default for ContextOptions {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            adapter_name: None,
+        }
+    }
+}
+
 impl Context {
+    /// Creates a context with the given options, fails if no matching adapter is found.
+    pub async fn with_options(options: &ContextOptions) -> Result<Self, String> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: options.backends,
+            ..Default::default()
+        });
+
+        let adapter = match &options.adapter_name {
+            Some(name) => {
+                let name = name.to_lowercase();
+                instance
+                    .enumerate_adapters(options.backends)
+                    .into_iter()
+                    .find(|adapter| adapter.get_info().name.to_lowercase().contains(&name))
+                    .ok_or(format!("no adapter named {:?} found", name))?
+            }
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: options.power_preference,
+                    compatible_surface: None,
+                    force_fallback_adapter: false,
+                })
+                .await
+                .ok_or(format!("no adapter found for {:?}", options.backends))?,
+        };
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::downlevel_defaults(),
+                },
+                None,
+            )
+            .await
+            .map_err(|err| format!("failed to request device: {err}"))?;
+
+        Ok(Self {
+            instance,
+            adapter,
+            device,
+            queue,
+        })
+    }
+
     pub async fn new() -> Self {
         // Instantiates instance of WebGPU
         let instance = wgpu::Instance::default();
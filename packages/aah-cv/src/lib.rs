@@ -1,36 +1,75 @@
 //! GPU-accelerated template matching.
 //!
 //! Faster alternative to [imageproc::template_matching](https://docs.rs/imageproc/latest/imageproc/template_matching/index.html).
+//!
+//! The GPU path (`TemplateMatcher`, the `gpu` and `fft` modules) is behind the default-on `gpu`
+//! feature. With `default-features = false` the crate doesn't depend on `wgpu`, and [match_template]
+//! and [template_matching] run every method on the CPU instead.
+//!
+//! The tests of the GPU path are behind the feature too, run the CPU-only ones with
+//! `cargo test -p aah-cv --no-default-features`.
 
 #![deny(clippy::all)]
 // #![allow(dead_code)]
 // #![allow(unused_variables)]
 
 pub mod convolve;
+#[cfg(feature = "gpu")]
 pub mod fft;
+pub mod fft_matching;
+#[cfg(feature = "gpu")]
 pub mod gpu;
+#[cfg(feature = "gpu")]
+pub mod pool;
 pub mod template_matching;
 pub mod types;
 pub mod utils;
 
-use gpu::Context;
-use image::{ImageBuffer, Luma};
+#[cfg(feature = "gpu")]
+use gpu::{Context, ContextOptions};
+#[cfg(feature = "gpu")]
+use image::{math::Rect, GrayImage, RgbaImage};
+use image::{DynamicImage, ImageBuffer, Luma, Rgb};
 use imageproc::template_matching::Extremes;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "gpu")]
+use std::collections::HashMap;
 use std::{
     borrow::Cow,
-    mem::size_of,
     ops::{Add, Div, Mul},
 };
+#[cfg(feature = "gpu")]
+use std::{
+    cell::RefCell,
+    future::Future,
+    mem::size_of,
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
 use types::Image;
 use utils::{image_mean, square_sum};
+#[cfg(feature = "gpu")]
 use wgpu::util::DeviceExt;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// Variance per pixel below which a window or template counts as flat in the normalized methods
+/// (e.g. [MatchTemplateMethod::CCOEFF_NORMED]). Flat windows and templates score 0, instead of
+/// dividing by a (numerically) zero norm into NaN, infinite or arbitrary values.
+pub const FLAT_VARIANCE_EPSILON: f32 = 1e-6;
+
+/// Serialized in kebab-case, e.g. `"sum-of-squared-errors"` or `"ccoeff-normed"`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum MatchTemplateMethod {
     SumOfAbsoluteErrors,
     SumOfSquaredErrors,
     CrossCorrelation,
+    /// Same result as [MatchTemplateMethod::CrossCorrelation], computed through FFT on CPU.
+    /// Much faster for templates larger than [fft_matching::FFT_TEMPLATE_SIZE_THRESHOLD].
+    FftCrossCorrelation,
+    #[serde(rename = "ccoeff")]
     CCOEFF,
+    #[serde(rename = "ccoeff-normed")]
     CCOEFF_NORMED,
 }
 
@@ -38,66 +77,1499 @@ pub enum MatchTemplateMethod {
 ///
 /// This is a shorthand for:
 /// ```ignore
-/// let mut matcher = TemplateMatcher::new();
 /// matcher.match_template(input, template, method);
 /// matcher.wait_for_result().unwrap()
 /// ```
+/// where `matcher` is a [TemplateMatcher] kept by the current thread, created on its first call.
+/// Creating a [TemplateMatcher] (and its wgpu device) costs far more than a small matching, so it
+/// is reused by every later call of [match_template], [ccoeff] and [ccorr] on the same thread.
+/// Callers that need their own matcher (e.g. with a custom [TemplateMatcherBuilder]) can use
+/// [match_template_with] instead, or take one from a [pool::TemplateMatcherPool].
+///
 /// You can use  [find_extremes] to find minimum and maximum values, and their locations in the result image.
+///
+/// Without the `gpu` feature, every method runs on the CPU.
+///
+/// Returns an error if the GPU failed to return the result (e.g. a flaky driver failing to map the
+/// result buffer), instead of a result full of zeros that looks like valid scores. Callers can
+/// fall back to a CPU implementation then.
 pub fn match_template<'a>(
     input: &ImageBuffer<Luma<f32>, Vec<f32>>,
     template: &ImageBuffer<Luma<f32>, Vec<f32>>,
     method: MatchTemplateMethod,
-) -> Image<'static> {
+) -> Result<Image<'static>, String> {
     match method {
         MatchTemplateMethod::CCOEFF => ccoeff(input, template, false),
         MatchTemplateMethod::CCOEFF_NORMED => ccoeff(input, template, true),
-        _ => {
-            let mut matcher = TemplateMatcher::new();
-            matcher.match_template(input.into(), template.into(), method, true);
-            matcher.wait_for_result().unwrap()
+        _ => sliding_window(input.into(), template.into(), method, true),
+    }
+}
+
+/// Same as [match_template], but runs on `matcher` instead of the one of the current thread.
+#[cfg(feature = "gpu")]
+pub fn match_template_with(
+    matcher: &mut TemplateMatcher,
+    input: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    template: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    method: MatchTemplateMethod,
+) -> Result<Image<'static>, String> {
+    match method {
+        MatchTemplateMethod::CCOEFF => ccoeff_with(matcher, input, template, false),
+        MatchTemplateMethod::CCOEFF_NORMED => ccoeff_with(matcher, input, template, true),
+        _ => sliding_window_with(matcher, input.into(), template.into(), method, true),
+    }
+}
+
+/// Runs one of the sliding window methods through the matcher of the current thread, see
+/// [match_template].
+///
+/// Returns an error if the result couldn't be read back, see [TemplateMatcher::try_wait_for_result].
+#[cfg(feature = "gpu")]
+fn sliding_window<'a>(
+    input: Image<'a>,
+    template: Image<'a>,
+    method: MatchTemplateMethod,
+    padding: bool,
+) -> Result<Image<'static>, String> {
+    with_thread_matcher(|matcher| sliding_window_with(matcher, input, template, method, padding))
+}
+
+#[cfg(feature = "gpu")]
+thread_local! {
+    /// The matcher behind [match_template], [ccoeff] and [ccorr], see [with_thread_matcher].
+    static THREAD_MATCHER: RefCell<Option<TemplateMatcher>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` on the [TemplateMatcher] of the current thread, which is created on the first call
+/// and reused by the later ones, see [match_template].
+#[cfg(feature = "gpu")]
+fn with_thread_matcher<T>(f: impl FnOnce(&mut TemplateMatcher) -> T) -> T {
+    THREAD_MATCHER.with(|matcher| {
+        let mut matcher = matcher.borrow_mut();
+        f(matcher.get_or_insert_with(TemplateMatcher::new))
+    })
+}
+
+/// Runs one of the sliding window methods through `matcher`.
+#[cfg(feature = "gpu")]
+fn sliding_window_with<'a>(
+    matcher: &mut TemplateMatcher,
+    input: Image<'a>,
+    template: Image<'a>,
+    method: MatchTemplateMethod,
+    padding: bool,
+) -> Result<Image<'static>, String> {
+    matcher.match_template(input, template, method, padding)?;
+    matcher
+        .try_wait_for_result()
+        .ok_or("the matching was not started".to_string())?
+}
+
+/// Runs one of the sliding window methods on the CPU, cross correlations go through FFT.
+///
+/// Returns an error for the same sizes as [TemplateMatcher::match_template] does, and for the
+/// methods that are not sliding window methods.
+#[cfg(not(feature = "gpu"))]
+fn sliding_window<'a>(
+    input: Image<'a>,
+    template: Image<'a>,
+    method: MatchTemplateMethod,
+    padding: bool,
+) -> Result<Image<'static>, String> {
+    check_sizes((input.width, input.height), &template, padding)?;
+    let input = if padding {
+        pad_input(&input, template.width, template.height)
+    } else {
+        input
+    };
+
+    let (res_w, res_h) = (
+        input.width - template.width + 1,
+        input.height - template.height + 1,
+    );
+    let score = |f: &dyn Fn(f32, f32) -> f32| {
+        let mut data = Vec::with_capacity(res_w as usize * res_h as usize);
+        for y in 0..res_h {
+            for x in 0..res_w {
+                let mut sum = 0.0;
+                for j in 0..template.height {
+                    let input_row = &input.row(y + j)[x as usize..];
+                    for (&i, &t) in input_row.iter().zip(template.row(j)) {
+                        sum += f(i, t);
+                    }
+                }
+                data.push(sum);
+            }
         }
+        Image::new(data, res_w, res_h)
+    };
+    Ok(match method {
+        MatchTemplateMethod::SumOfAbsoluteErrors => score(&|i, t| (i - t).abs()),
+        MatchTemplateMethod::SumOfSquaredErrors => score(&|i, t| (i - t) * (i - t)),
+        MatchTemplateMethod::CrossCorrelation | MatchTemplateMethod::FftCrossCorrelation => {
+            fft_matching::fft_ccorr(&input, &template)
+        }
+        MatchTemplateMethod::CCOEFF | MatchTemplateMethod::CCOEFF_NORMED => {
+            return Err(format!("{method:?} is not a sliding window method"))
+        }
+    })
+}
+
+/// Same as [match_template], but converts `input` and `template` to grayscale internally.
+///
+/// Prefer [match_template] with preconverted images when matching against the same input repeatedly.
+pub fn match_template_dynamic(
+    input: &DynamicImage,
+    template: &DynamicImage,
+    method: MatchTemplateMethod,
+) -> Result<Image<'static>, String> {
+    match_template(&input.to_luma32f(), &template.to_luma32f(), method)
+}
+
+/// Color template matching, the average of [MatchTemplateMethod::CCOEFF_NORMED] over the three channels.
+///
+/// Tells apart templates with similar luma but different colors, at roughly three times the cost of
+/// grayscale matching. A channel that is flat in the template scores 0 everywhere, which lowers the
+/// best possible value below 1.0 accordingly.
+pub fn match_template_rgb(
+    input: &ImageBuffer<Rgb<f32>, Vec<f32>>,
+    template: &ImageBuffer<Rgb<f32>, Vec<f32>>,
+) -> Result<Image<'static>, String> {
+    let channel = |image: &ImageBuffer<Rgb<f32>, Vec<f32>>, c: usize| {
+        ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+            Luma([image.get_pixel(x, y)[c]])
+        })
+    };
+
+    let mut sum = ccoeff(&channel(input, 0), &channel(template, 0), true)?;
+    for c in 1..3 {
+        sum = sum + ccoeff(&channel(input, c), &channel(template, c), true)?;
     }
+    Ok(sum / 3.0)
+}
+
+impl MatchTemplateMethod {
+    /// Whether a higher value means a better match for this method.
+    pub fn higher_is_better(&self) -> bool {
+        !matches!(
+            self,
+            MatchTemplateMethod::SumOfAbsoluteErrors | MatchTemplateMethod::SumOfSquaredErrors
+        )
+    }
+}
+
+/// Runs [match_template] and returns the best location and value: the maximum for methods where
+/// [higher is better](MatchTemplateMethod::higher_is_better), the minimum for the others.
+///
+/// Only the locations where the template is fully inside the input are considered.
+///
+/// Returns an error if the template is empty or larger than the input, if [match_template] fails,
+/// or if the result holds NaN or infinite values (see [try_find_extremes]).
+pub fn best_match(
+    input: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    template: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    method: MatchTemplateMethod,
+) -> Result<Match, String> {
+    check_sizes(input.dimensions(), &template.into(), false)?;
+    let (width, height) = template.dimensions();
+
+    let res = match_template(input, template, method)?;
+    // Ignore the padded area, where the template is not fully inside the input
+    let res = Image::new(
+        (0..=input.height() - height)
+            .flat_map(|y| (0..=input.width() - width).map(move |x| (x, y)))
+            .map(|(x, y)| res.get(x, y))
+            .collect::<Vec<f32>>(),
+        input.width() - width + 1,
+        input.height() - height + 1,
+    );
+    let extremes = try_find_extremes(&res)?;
+    Ok(if method.higher_is_better() {
+        Match {
+            location: extremes.max_value_location,
+            value: extremes.max_value,
+        }
+    } else {
+        Match {
+            location: extremes.min_value_location,
+            value: extremes.min_value,
+        }
+    })
+}
+
+/// The best match found by [match_template_multiscale].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MultiScaleMatch {
+    /// The scale applied to the template
+    pub scale: f32,
+    /// Top left location of the match in the input
+    pub location: (u32, u32),
+    pub value: f32,
+}
+
+/// Runs [match_template] with the template resized by each of the `scales`, and returns the best match.
+///
+/// Scales that make the template empty or larger than the input are skipped.
+/// If `early_exit` is set, returns as soon as a scale reaches it (at least for methods where
+/// higher is better, at most for the others), so put the most likely scales first.
+///
+/// Only normalized methods (e.g. [MatchTemplateMethod::CCOEFF_NORMED]) produce values that are
+/// comparable across scales.
+///
+/// Returns an error if matching any of the scales fails, see [match_template].
+pub fn match_template_multiscale(
+    input: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    template: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    scales: &[f32],
+    method: MatchTemplateMethod,
+    early_exit: Option<f32>,
+) -> Result<Option<MultiScaleMatch>, String> {
+    let is_better = |a: f32, b: f32| {
+        if method.higher_is_better() {
+            a > b
+        } else {
+            a < b
+        }
+    };
+
+    let mut best: Option<MultiScaleMatch> = None;
+    for &scale in scales {
+        let width = (template.width() as f32 * scale).round() as u32;
+        let height = (template.height() as f32 * scale).round() as u32;
+        if width == 0 || height == 0 || width > input.width() || height > input.height() {
+            continue;
+        }
+
+        let scaled = image::imageops::resize(
+            template,
+            width,
+            height,
+            image::imageops::FilterType::Lanczos3,
+        );
+        let Match { location, value } = best_match(input, &scaled, method)?;
+
+        if best.map_or(true, |best| is_better(value, best.value)) {
+            best = Some(MultiScaleMatch {
+                scale,
+                location,
+                value,
+            });
+        }
+        if let Some(threshold) = early_exit {
+            if value == threshold || is_better(value, threshold) {
+                break;
+            }
+        }
+    }
+    Ok(best)
 }
 
 #[cfg(test)]
 mod test {
-    use image::{ImageBuffer, Luma};
+    use std::time::Instant;
+
+    #[cfg(feature = "gpu")]
+    use image::math::Rect;
+    use image::{DynamicImage, ImageBuffer, Luma, Rgb};
+
+    use crate::{
+        best_match, ccoeff, ccorr, find_extremes, find_matches, find_matches_nms, match_template,
+        match_template_dynamic, match_template_multiscale, match_template_rgb, sliding_window,
+        try_find_extremes, types::Image, MatchTemplateMethod,
+    };
+    #[cfg(feature = "gpu")]
+    use crate::{
+        fft_matching, match_template_with, PollStrategy, TemplateMatcher, DEFAULT_WORKGROUP_SIZE,
+        THREAD_MATCHER,
+    };
+    #[cfg(feature = "gpu")]
+    use std::time::Duration;
+
+    #[test]
+    fn test_method_serde() {
+        for (method, name) in [
+            (
+                MatchTemplateMethod::SumOfSquaredErrors,
+                "\"sum-of-squared-errors\"",
+            ),
+            (
+                MatchTemplateMethod::FftCrossCorrelation,
+                "\"fft-cross-correlation\"",
+            ),
+            (MatchTemplateMethod::CCOEFF, "\"ccoeff\""),
+            (MatchTemplateMethod::CCOEFF_NORMED, "\"ccoeff-normed\""),
+        ] {
+            let json = serde_json::to_string(&method).unwrap();
+            assert_eq!(json, name);
+            assert_eq!(
+                serde_json::from_str::<MatchTemplateMethod>(&json).unwrap(),
+                method
+            );
+        }
+    }
+
+    #[test]
+    fn test_match_template_rgb() {
+        let pattern = |x: u32, y: u32| ((x * 7919 + y * 104729) ^ (x * y * 31)) % 251;
+        // The same luma pattern, but the blue channel is inverted in the template
+        let template = ImageBuffer::from_fn(16, 16, |x, y| {
+            let v = pattern(x, y) as f32 / 255.0;
+            Rgb([v, v, 1.0 - v])
+        });
+        let lookalike = ImageBuffer::from_fn(16, 16, |x, y| {
+            let v = pattern(x, y) as f32 / 255.0;
+            Rgb([v, v, v])
+        });
+        let mut input = ImageBuffer::from_fn(64, 48, |x, y| {
+            let v = pattern(x + 100, y + 100) as f32 / 255.0;
+            Rgb([v, 1.0 - v, v * v])
+        });
+        image::imageops::replace(&mut input, &template, 8, 8);
+        image::imageops::replace(&mut input, &lookalike, 40, 24);
+
+        let gray_input = DynamicImage::ImageRgb32F(input.clone()).to_luma32f();
+        let gray_template = DynamicImage::ImageRgb32F(template.clone()).to_luma32f();
+        let t = Instant::now();
+        let gray = match_template(
+            &gray_input,
+            &gray_template,
+            MatchTemplateMethod::CCOEFF_NORMED,
+        )
+        .unwrap();
+        let gray_cost = t.elapsed();
+
+        let t = Instant::now();
+        let rgb = match_template_rgb(&input, &template).unwrap();
+        let rgb_cost = t.elapsed();
+        println!("grayscale cost: {gray_cost:?}, rgb cost: {rgb_cost:?}");
+
+        // Grayscale can't tell the lookalike apart, rgb can
+        println!("gray: {} {}", gray.get(8, 8), gray.get(40, 24));
+        println!("rgb: {} {}", rgb.get(8, 8), rgb.get(40, 24));
+        assert!(gray.get(40, 24) > 0.99);
+        assert!(rgb.get(8, 8) > 0.99);
+        assert!(rgb.get(40, 24) < 0.5);
+        assert_eq!(find_extremes(&rgb).max_value_location, (8, 8));
+    }
+
+    #[test]
+    fn test_find_matches() {
+        // Two peaks in each direction, with weaker neighbours that should be merged
+        let mut data = vec![0.5; 20 * 10];
+        for (x, y, v) in [(2, 2, 0.9), (3, 2, 0.8), (12, 6, 0.95)] {
+            data[y * 20 + x] = v;
+        }
+        for (x, y, v) in [(6, 1, 0.1), (6, 2, 0.2), (16, 8, 0.05)] {
+            data[y * 20 + x] = v;
+        }
+        let res = Image::new(data, 20, 10);
+
+        let high = find_matches(&res, 4, 4, MatchTemplateMethod::CCOEFF_NORMED, 0.7);
+        assert_eq!(
+            high.iter()
+                .map(|m| (m.location, m.value))
+                .collect::<Vec<_>>(),
+            vec![((2, 2), 0.9), ((12, 6), 0.95)]
+        );
+
+        let low = find_matches(&res, 4, 4, MatchTemplateMethod::SumOfSquaredErrors, 0.3);
+        assert_eq!(
+            low.iter()
+                .map(|m| (m.location, m.value))
+                .collect::<Vec<_>>(),
+            vec![((6, 1), 0.1), ((16, 8), 0.05)]
+        );
+    }
+
+    #[test]
+    fn test_find_matches_nms() {
+        let mut data = vec![0.0; 30 * 10];
+        // Overlapping detections: (2, 2) and (4, 2) overlap by half (IoU 1/3), (12, 2) doesn't overlap
+        for (x, y, v) in [(2, 2, 0.9), (3, 2, 0.85), (4, 2, 0.8), (12, 2, 0.95)] {
+            data[y * 30 + x] = v;
+        }
+        let res = Image::new(data, 30, 10);
+
+        let matches = find_matches_nms(&res, 4, 4, MatchTemplateMethod::CCOEFF_NORMED, 0.5, 0.0);
+        assert_eq!(
+            matches.iter().map(|m| m.location).collect::<Vec<_>>(),
+            vec![(12, 2), (2, 2)]
+        );
+        let matches = find_matches_nms(&res, 4, 4, MatchTemplateMethod::CCOEFF_NORMED, 0.5, 0.4);
+        assert_eq!(
+            matches.iter().map(|m| m.location).collect::<Vec<_>>(),
+            vec![(12, 2), (2, 2), (4, 2)]
+        );
+
+        let res = Image::new(res.data.iter().map(|v| 1.0 - v).collect::<Vec<_>>(), 30, 10);
+        let matches = find_matches_nms(
+            &res,
+            4,
+            4,
+            MatchTemplateMethod::SumOfSquaredErrors,
+            0.5,
+            0.0,
+        );
+        assert_eq!(
+            matches.iter().map(|m| m.location).collect::<Vec<_>>(),
+            vec![(12, 2), (2, 2)]
+        );
+    }
+
+    #[test]
+    fn test_match_template_dynamic() {
+        let input = DynamicImage::ImageRgb8(ImageBuffer::from_fn(48, 48, |x, y| {
+            let v = (x * 7919 + y * 104729) ^ (x * y * 31);
+            Rgb([(v % 251) as u8, (v / 3 % 241) as u8, (v / 7 % 239) as u8])
+        }));
+        let template = input.crop_imm(10, 20, 17, 17);
+
+        let expected = match_template(
+            &input.to_luma32f(),
+            &template.to_luma32f(),
+            MatchTemplateMethod::CCOEFF_NORMED,
+        )
+        .unwrap();
+        let res =
+            match_template_dynamic(&input, &template, MatchTemplateMethod::CCOEFF_NORMED).unwrap();
+        assert_eq!(res.data, expected.data);
+        assert_eq!(find_extremes(&res).max_value_location, (10, 20));
+    }
+
+    /// Runs `f` on a new thread, so that the matcher kept by [match_template] and friends is
+    /// dropped before the test creates matchers of its own. Some drivers (e.g. GLES through EGL)
+    /// break the other wgpu devices of a thread when one of them is dropped.
+    #[cfg(feature = "gpu")]
+    fn on_new_thread<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+        std::thread::scope(|s| s.spawn(f).join().unwrap())
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_thread_matcher() {
+        let input = ImageBuffer::from_fn(40, 30, |x, y| Luma([((x * 7 + y * 3) % 11) as f32]));
+        let template = ImageBuffer::from_fn(6, 5, |x, y| Luma([((x + y * 2) % 5) as f32]));
+
+        // Every call of a thread runs on its matcher, which keeps one pipeline per method
+        assert!(THREAD_MATCHER.with(|matcher| matcher.borrow().is_none()));
+        match_template(&input, &template, MatchTemplateMethod::CrossCorrelation).unwrap();
+        ccoeff(&input, &template, true).unwrap();
+        match_template(&input, &template, MatchTemplateMethod::SumOfSquaredErrors).unwrap();
+        let pipelines = THREAD_MATCHER.with(|matcher| {
+            matcher
+                .borrow()
+                .as_ref()
+                .map(|matcher| matcher.pipelines.len())
+        });
+        assert_eq!(pipelines, Some(2));
+
+        on_new_thread(|| assert!(THREAD_MATCHER.with(|matcher| matcher.borrow().is_none())));
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_match_template_with() {
+        let input = ImageBuffer::from_fn(40, 30, |x, y| Luma([((x * 7 + y * 3) % 11) as f32]));
+        let template = ImageBuffer::from_fn(6, 5, |x, y| Luma([((x + y * 2) % 5) as f32]));
+        let methods = [
+            MatchTemplateMethod::SumOfSquaredErrors,
+            MatchTemplateMethod::CrossCorrelation,
+            MatchTemplateMethod::CCOEFF,
+            MatchTemplateMethod::CCOEFF_NORMED,
+        ];
+        let expected = on_new_thread(|| {
+            methods
+                .map(|method| match_template(&input, &template, method).unwrap())
+                .to_vec()
+        });
+
+        // One matcher serves every method, including the cross correlations composing CCOEFF
+        let mut matcher = TemplateMatcher::new();
+        for (method, expected) in methods.into_iter().zip(expected) {
+            let res = match_template_with(&mut matcher, &input, &template, method).unwrap();
+            assert_eq!(res.data, expected.data, "{method:?}");
+        }
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_read_result_unmapped() {
+        let mut matcher = TemplateMatcher::new();
+        assert!(matcher.try_wait_for_result().is_none());
+
+        matcher.last_result_size = (16, 8);
+        let err = matcher.read_result(false).unwrap_err();
+        assert_eq!(err, "failed to map the 16x8 result buffer");
+        let zeros = matcher.zero_result();
+        assert_eq!((zeros.width, zeros.height), (16, 8));
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_template_matcher_builder() {
+        let input = ImageBuffer::from_fn(32, 32, |x, y| Luma([((x * 3 + y) % 5) as f32]));
+        let template = ImageBuffer::from_fn(4, 4, |x, y| Luma([((x + y) % 3) as f32]));
+        let expected = on_new_thread(|| {
+            match_template(&input, &template, MatchTemplateMethod::CrossCorrelation).unwrap()
+        });
+
+        let mut matcher = TemplateMatcher::builder()
+            .power_preference(wgpu::PowerPreference::LowPower)
+            .build()
+            .unwrap();
+        matcher
+            .match_template(
+                (&input).into(),
+                (&template).into(),
+                MatchTemplateMethod::CrossCorrelation,
+                true,
+            )
+            .unwrap();
+        let res = matcher.wait_for_result().unwrap();
+        assert_eq!(res.data, expected.data);
+
+        // Async retrieval gives the same result
+        matcher
+            .match_template(
+                (&input).into(),
+                (&template).into(),
+                MatchTemplateMethod::CrossCorrelation,
+                true,
+            )
+            .unwrap();
+        let res = pollster::block_on(matcher.wait_for_result_async()).unwrap();
+        assert_eq!(res.data, expected.data);
+        assert!(pollster::block_on(matcher.wait_for_result_async()).is_none());
+        drop(matcher);
+
+        assert!(TemplateMatcher::builder()
+            .adapter_name("not-exist-adapter")
+            .build()
+            .is_err());
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_try_result_and_poll_strategy() {
+        let input = ImageBuffer::from_fn(48, 40, |x, y| Luma([((x * 3 + y) % 5) as f32]));
+        let template = ImageBuffer::from_fn(6, 5, |x, y| Luma([((x + y) % 3) as f32]));
+        let expected = on_new_thread(|| {
+            match_template(&input, &template, MatchTemplateMethod::CrossCorrelation).unwrap()
+        });
+
+        let mut matcher = TemplateMatcher::builder()
+            .poll_strategy(PollStrategy::Poll {
+                interval: Duration::from_micros(100),
+            })
+            .build()
+            .unwrap();
+        assert_ne!(matcher.poll_strategy(), PollStrategy::Wait);
+        assert_eq!(matcher.try_result().map(|res| res.is_none()), Some(true));
+
+        let mut start = |matcher: &mut TemplateMatcher| {
+            matcher
+                .match_template(
+                    (&input).into(),
+                    (&template).into(),
+                    MatchTemplateMethod::CrossCorrelation,
+                    true,
+                )
+                .unwrap()
+        };
+        start(&mut matcher);
+        let res = loop {
+            match matcher.try_result() {
+                Some(res) => break res.unwrap(),
+                None => std::thread::sleep(Duration::from_micros(100)),
+            }
+        };
+        assert_eq!(res.data, expected.data);
+        assert_eq!(matcher.try_result().map(|res| res.is_none()), Some(true));
+
+        // Polling retrieval
+        start(&mut matcher);
+        assert_eq!(matcher.wait_for_result().unwrap().data, expected.data);
+
+        // A blocking retrieval picks up the mapping requested by try_result
+        matcher.set_poll_strategy(PollStrategy::Wait);
+        start(&mut matcher);
+        let _ = matcher.try_result();
+        let _ = matcher.try_result();
+        if let Some(res) = matcher.wait_for_result() {
+            assert_eq!(res.data, expected.data);
+        }
+        assert!(matcher.wait_for_result().is_none());
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_pipeline_cache() {
+        let input = ImageBuffer::from_fn(35, 19, |x, y| Luma([((x * 7 + y * 3) % 13) as f32]));
+        let template = ImageBuffer::from_fn(4, 4, |x, y| Luma([((x + y * 5) % 7) as f32]));
+
+        let mut matcher = TemplateMatcher::new();
+        let mut results = vec![];
+        for method in [
+            MatchTemplateMethod::CrossCorrelation,
+            MatchTemplateMethod::SumOfSquaredErrors,
+        ]
+        .repeat(3)
+        {
+            matcher
+                .match_template((&input).into(), (&template).into(), method, false)
+                .unwrap();
+            results.push(matcher.wait_for_result().unwrap());
+        }
+        // One pipeline per method, and the results don't depend on the switches
+        assert_eq!(matcher.pipelines.len(), 2);
+        for (i, res) in results.iter().enumerate().skip(2) {
+            assert_eq!(res.data, results[i % 2].data);
+        }
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_match_template_rgba() {
+        let input = image::RgbaImage::from_fn(67, 51, |x, y| {
+            image::Rgba([
+                (x * 3 % 256) as u8,
+                (y * 5 % 256) as u8,
+                ((x + y) * 7 % 256) as u8,
+                255,
+            ])
+        });
+        let template = DynamicImage::ImageRgba8(input.clone())
+            .crop_imm(20, 10, 4, 4)
+            .to_luma32f();
+        let luma = DynamicImage::ImageRgba8(input.clone()).to_luma32f();
+
+        let mut matcher = TemplateMatcher::new();
+        for method in [
+            MatchTemplateMethod::SumOfSquaredErrors,
+            MatchTemplateMethod::CrossCorrelation,
+            MatchTemplateMethod::FftCrossCorrelation,
+        ] {
+            matcher
+                .match_template((&luma).into(), (&template).into(), method, false)
+                .unwrap();
+            let expected = matcher.wait_for_result().unwrap();
+            matcher
+                .match_template_rgba(&input, (&template).into(), method)
+                .unwrap();
+            let res = matcher.wait_for_result().unwrap();
+            assert_eq!((res.width, res.height), (expected.width, expected.height));
+            for (a, b) in res.data.iter().zip(expected.data.iter()) {
+                assert!((a - b).abs() < 1e-3, "{method:?}: {a} != {b}");
+            }
+        }
+
+        // Upload + conversion on the GPU vs conversion on the CPU at 1080p
+        let input = image::RgbaImage::from_fn(1920, 1080, |x, y| {
+            image::Rgba([(x % 256) as u8, (y % 256) as u8, ((x * y) % 256) as u8, 255])
+        });
+        let template = ImageBuffer::from_fn(17, 9, |x, y| Luma([((x + y) % 7) as f32 / 7.0]));
+        let method = MatchTemplateMethod::SumOfSquaredErrors;
+        // Warm up the buffers and pipelines
+        matcher
+            .match_template_rgba(&input, (&template).into(), method)
+            .unwrap();
+        matcher.wait_for_result().unwrap();
+
+        let t = Instant::now();
+        let luma = DynamicImage::ImageRgba8(input.clone()).to_luma32f();
+        let convert_cost = t.elapsed();
+        matcher
+            .match_template((&luma).into(), (&template).into(), method, false)
+            .unwrap();
+        matcher.wait_for_result().unwrap();
+        let cpu_cost = t.elapsed();
+
+        let t = Instant::now();
+        matcher
+            .match_template_rgba(&input, (&template).into(), method)
+            .unwrap();
+        matcher.wait_for_result().unwrap();
+        let gpu_cost = t.elapsed();
+        println!(
+            "1080p, CPU conversion: {cpu_cost:?} (convert {convert_cost:?}), GPU conversion: {gpu_cost:?}"
+        );
+    }
+
+    /// Sum of squared errors without padding, one pixel at a time
+    #[cfg(feature = "gpu")]
+    fn naive_sse(
+        input: &ImageBuffer<Luma<f32>, Vec<f32>>,
+        template: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    ) -> ImageBuffer<Luma<f32>, Vec<f32>> {
+        let width = input.width() - template.width() + 1;
+        let height = input.height() - template.height() + 1;
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let mut sum = 0.0;
+            for (i, j, t) in template.enumerate_pixels() {
+                sum += (input.get_pixel(x + i, y + j).0[0] - t.0[0]).powi(2);
+            }
+            Luma([sum])
+        })
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_match_template_u8() {
+        let template = ImageBuffer::from_fn(4, 4, |x, y| Luma([((x + y * 5) % 7) as f32 / 7.0]));
+
+        let mut matcher = TemplateMatcher::new();
+        // 65x51 isn't a multiple of 4 bytes
+        for (width, height) in [(64, 48), (65, 51)] {
+            let input = image::GrayImage::from_fn(width, height, |x, y| {
+                Luma([((x * 37 + y * 91) % 256) as u8])
+            });
+            let input_f32 = ImageBuffer::from_fn(width, height, |x, y| {
+                Luma([input.get_pixel(x, y).0[0] as f32 / 255.0])
+            });
+            for method in [
+                MatchTemplateMethod::SumOfAbsoluteErrors,
+                MatchTemplateMethod::SumOfSquaredErrors,
+            ] {
+                matcher
+                    .match_template((&input_f32).into(), (&template).into(), method, false)
+                    .unwrap();
+                let expected = matcher.wait_for_result().unwrap();
+                matcher
+                    .match_template_u8(&input, (&template).into(), method)
+                    .unwrap();
+                let res = matcher.wait_for_result().unwrap();
+                assert_eq!((res.width, res.height), (expected.width, expected.height));
+                for (a, b) in res.data.iter().zip(expected.data.iter()) {
+                    assert!((a - b).abs() < 1e-4, "{method:?}: {a} != {b}");
+                }
+            }
+        }
+
+        let input = image::GrayImage::new(64, 48);
+        let err = matcher
+            .match_template_u8(
+                &input,
+                (&template).into(),
+                MatchTemplateMethod::CrossCorrelation,
+            )
+            .unwrap_err();
+        println!("{err}");
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_release_buffers() {
+        let input = ImageBuffer::from_fn(48, 40, |x, y| Luma([((x * 7 + y * 3) % 13) as f32]));
+        let template = ImageBuffer::from_fn(5, 4, |x, y| Luma([((x + y * 5) % 7) as f32]));
+        let expected = naive_sse(&input, &template);
+        let method = MatchTemplateMethod::SumOfSquaredErrors;
+
+        let mut matcher = TemplateMatcher::new();
+        matcher
+            .match_template((&input).into(), (&template).into(), method, false)
+            .unwrap();
+        // The uncollected result is discarded
+        matcher.release_buffers();
+        assert!(matcher.input_buffer.is_none() && matcher.result_buffer.is_none());
+        assert!(matcher.wait_for_result().is_none());
+
+        // Same sizes as before the release
+        matcher
+            .match_template((&input).into(), (&template).into(), method, false)
+            .unwrap();
+        let res = matcher.wait_for_result().unwrap();
+        assert_eq!(res.data, expected.as_raw().as_slice());
+
+        matcher.release_buffers();
+        let input_u8 = image::GrayImage::from_fn(48, 40, |x, y| Luma([(x * 5 + y) as u8]));
+        matcher
+            .match_template_u8(&input_u8, (&template).into(), method)
+            .unwrap();
+        let res = matcher.wait_for_result().unwrap();
+        assert_eq!((res.width, res.height), (44, 37));
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_template_size_changes() {
+        let input = ImageBuffer::from_fn(48, 40, |x, y| Luma([((x * 7 + y * 3) % 13) as f32]));
+
+        let mut matcher = TemplateMatcher::new();
+        for (width, height) in [(4, 4), (9, 3), (4, 4), (1, 1), (17, 9), (9, 3)] {
+            let template =
+                ImageBuffer::from_fn(width, height, |x, y| Luma([((x + y * 5) % 7) as f32]));
+            matcher
+                .match_template(
+                    (&input).into(),
+                    (&template).into(),
+                    MatchTemplateMethod::SumOfSquaredErrors,
+                    false,
+                )
+                .unwrap();
+            let res = matcher.wait_for_result().unwrap();
+
+            let expected = naive_sse(&input, &template);
+            assert_eq!((res.width, res.height), expected.dimensions());
+            assert_eq!(res.data, expected.as_raw().as_slice(), "{width}x{height}");
+        }
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_input_size_changes() {
+        let template = ImageBuffer::from_fn(5, 4, |x, y| Luma([((x + y * 5) % 7) as f32]));
+
+        let mut matcher = TemplateMatcher::new();
+        for (width, height) in [(48, 40), (21, 13), (48, 40), (5, 4), (33, 40)] {
+            let input =
+                ImageBuffer::from_fn(width, height, |x, y| Luma([((x * 7 + y * 3) % 13) as f32]));
+            matcher
+                .match_template(
+                    (&input).into(),
+                    (&template).into(),
+                    MatchTemplateMethod::SumOfSquaredErrors,
+                    false,
+                )
+                .unwrap();
+            let res = matcher.wait_for_result().unwrap();
+
+            let expected = naive_sse(&input, &template);
+            assert_eq!((res.width, res.height), expected.dimensions());
+            assert_eq!(res.data, expected.as_raw().as_slice(), "{width}x{height}");
+        }
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_match_template_roi() {
+        let input_buffer = ImageBuffer::from_fn(67, 51, |x, y| {
+            Luma([((x * 7 + y * 3) % 13) as f32 / 13.0 + ((x ^ y) % 5) as f32 * 0.1])
+        });
+        let input: Image = (&input_buffer).into();
+        let template_buffer =
+            ImageBuffer::from_fn(9, 7, |x, y| Luma([((x + y * 5) % 7) as f32 / 7.0]));
+        let template: Image = (&template_buffer).into();
+
+        let rois = [
+            Rect {
+                x: 10,
+                y: 5,
+                width: 30,
+                height: 20,
+            },
+            Rect {
+                x: 0,
+                y: 0,
+                width: 67,
+                height: 51,
+            },
+            Rect {
+                x: 58,
+                y: 44,
+                width: 9,
+                height: 7,
+            },
+        ];
+        let methods = [
+            MatchTemplateMethod::SumOfSquaredErrors,
+            MatchTemplateMethod::CCOEFF_NORMED,
+            MatchTemplateMethod::FftCrossCorrelation,
+        ];
+        // Same as matching against the cropped regions
+        let expected = on_new_thread(|| {
+            let mut expected = vec![];
+            for method in methods {
+                for roi in rois {
+                    let crop = input.crop(roi.x, roi.y, roi.width, roi.height);
+                    let crop_buffer =
+                        ImageBuffer::from_raw(roi.width, roi.height, crop.data.to_vec()).unwrap();
+                    expected.push(match method {
+                        MatchTemplateMethod::SumOfSquaredErrors => {
+                            Image::from(naive_sse(&crop_buffer, &template_buffer))
+                        }
+                        MatchTemplateMethod::CCOEFF_NORMED => {
+                            ccoeff(&crop_buffer, &template_buffer, true).unwrap()
+                        }
+                        _ => fft_matching::fft_ccorr(&crop, &template),
+                    });
+                }
+            }
+            expected
+        });
 
-    use crate::ccoeff;
+        let mut matcher = TemplateMatcher::new();
+        let mut expected = expected.into_iter();
+        for method in methods {
+            for (i, roi) in rois.into_iter().enumerate() {
+                if i == 0 || method == MatchTemplateMethod::FftCrossCorrelation {
+                    matcher
+                        .match_template_roi(input.clone(), template.clone(), roi, method)
+                        .unwrap();
+                } else {
+                    // The input is still on the GPU
+                    matcher
+                        .match_uploaded_roi(template.clone(), roi, method)
+                        .unwrap();
+                }
+                let res = matcher.wait_for_result().unwrap();
+                let expected = expected.next().unwrap();
+                assert_eq!(
+                    (res.width, res.height),
+                    (roi.width - 8, roi.height - 6),
+                    "{method:?} {roi:?}"
+                );
+                for y in 0..res.height {
+                    for x in 0..res.width {
+                        let (a, b) = (res.get(x, y), expected.get(x, y));
+                        assert!(
+                            (a - b).abs() < 1e-3 * b.abs().max(1.0),
+                            "{method:?} {roi:?} ({x}, {y}): {a} != {b}"
+                        );
+                    }
+                }
+            }
+        }
+
+        // The template is at (23, 11) of the input, i.e. (13, 6) of the roi
+        let template = input.crop(23, 11, 9, 7);
+        matcher
+            .match_template_roi(
+                input.clone(),
+                template.clone(),
+                rois[0],
+                MatchTemplateMethod::SumOfSquaredErrors,
+            )
+            .unwrap();
+        let res = matcher.wait_for_result().unwrap();
+        assert_eq!(find_extremes(&res).min_value_location, (13, 6));
+
+        // Out of bounds rois and templates larger than the roi
+        for roi in [
+            Rect {
+                x: 60,
+                y: 0,
+                width: 9,
+                height: 7,
+            },
+            Rect {
+                x: 0,
+                y: 0,
+                width: 8,
+                height: 51,
+            },
+            Rect {
+                x: u32::MAX,
+                y: 0,
+                width: 9,
+                height: 7,
+            },
+        ] {
+            let err = matcher
+                .match_template_roi(
+                    input.clone(),
+                    template.clone(),
+                    roi,
+                    MatchTemplateMethod::SumOfSquaredErrors,
+                )
+                .unwrap_err();
+            println!("{err}");
+        }
+
+        // FFT runs on the CPU and doesn't upload the input
+        matcher
+            .match_template(
+                input.clone(),
+                template.clone(),
+                MatchTemplateMethod::FftCrossCorrelation,
+                false,
+            )
+            .unwrap();
+        assert!(matcher
+            .match_uploaded_roi(template, rois[0], MatchTemplateMethod::SumOfSquaredErrors)
+            .is_err());
+    }
+
+    #[test]
+    fn test_match_template_empty_template() {
+        let input = ImageBuffer::from_fn(20, 10, |x, y| Luma([((x + y) % 5) as f32]));
+        let template = ImageBuffer::<Luma<f32>, Vec<f32>>::new(0, 4);
+        let err =
+            match_template(&input, &template, MatchTemplateMethod::SumOfSquaredErrors).unwrap_err();
+        assert!(err.contains("empty"), "{err}");
+
+        // Without padding, a template larger than the input is an error on both paths
+        let template = ImageBuffer::from_fn(24, 4, |x, y| Luma([((x + y) % 3) as f32]));
+        let err = sliding_window(
+            (&input).into(),
+            (&template).into(),
+            MatchTemplateMethod::SumOfSquaredErrors,
+            false,
+        )
+        .unwrap_err();
+        assert!(err.contains("larger"), "{err}");
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_workgroup_size() {
+        // The result is 45x29, not a multiple of any workgroup size
+        let input = ImageBuffer::from_fn(50, 40, |x, y| Luma([((x * 7 + y * 3) % 13) as f32]));
+        let template = ImageBuffer::from_fn(6, 12, |x, y| Luma([((x + y * 5) % 7) as f32]));
+        let expected = naive_sse(&input, &template);
+
+        for (x, y) in [(8, 8), (16, 16), (32, 8)] {
+            let mut matcher = TemplateMatcher::builder()
+                .workgroup_size(x, y)
+                .build()
+                .unwrap();
+            assert_eq!(matcher.workgroup_size(), (x, y));
+            matcher
+                .match_template(
+                    (&input).into(),
+                    (&template).into(),
+                    MatchTemplateMethod::SumOfSquaredErrors,
+                    false,
+                )
+                .unwrap();
+            let res = matcher.wait_for_result().unwrap();
+            assert_eq!((res.width, res.height), expected.dimensions());
+            assert_eq!(res.data, expected.as_raw().as_slice(), "{x}x{y}");
+        }
+
+        for (x, y) in [(0, 16), (64, 64)] {
+            let err = TemplateMatcher::builder()
+                .workgroup_size(x, y)
+                .build()
+                .err()
+                .unwrap();
+            println!("{err}");
+        }
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_template_larger_than_input() {
+        let input = Image::new(vec![0.0; 25], 5, 5);
+        let template = Image::new(vec![1.0; 100], 10, 10);
+
+        let mut matcher = TemplateMatcher::new();
+        for method in [
+            MatchTemplateMethod::SumOfSquaredErrors,
+            MatchTemplateMethod::FftCrossCorrelation,
+        ] {
+            let err = matcher
+                .match_template(input.clone(), template.clone(), method, false)
+                .unwrap_err();
+            println!("{err}");
+            assert!(matcher.wait_for_result().is_none());
+        }
+        assert!(matcher
+            .match_and_threshold(
+                input.clone(),
+                template.clone(),
+                MatchTemplateMethod::CrossCorrelation,
+                0.0
+            )
+            .is_err());
+
+        // With padding the result has the size of the input
+        matcher
+            .match_template(input, template, MatchTemplateMethod::CrossCorrelation, true)
+            .unwrap();
+        let res = matcher.wait_for_result().unwrap();
+        assert_eq!((res.width, res.height), (5, 5));
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_ccoeff_normed_gpu() {
+        let input = ImageBuffer::from_fn(67, 51, |x, y| {
+            Luma([((x * 7 + y * 3) % 13) as f32 / 13.0 + ((x ^ y) % 5) as f32 * 0.1])
+        });
+        let template = ImageBuffer::from_fn(9, 7, |x, y| Luma([((x + y * 5) % 7) as f32 / 7.0]));
+
+        let expected = on_new_thread(|| ccoeff(&input, &template, true).unwrap());
+        let mut matcher = TemplateMatcher::new();
+        matcher
+            .match_template(
+                (&input).into(),
+                (&template).into(),
+                MatchTemplateMethod::CCOEFF_NORMED,
+                false,
+            )
+            .unwrap();
+        let res = matcher.wait_for_result().unwrap();
+        assert_eq!((res.width, res.height), (59, 45));
+        for y in 0..res.height {
+            for x in 0..res.width {
+                assert!(
+                    (res.get(x, y) - expected.get(x, y)).abs() < 1e-3,
+                    "({x}, {y}): {} != {}",
+                    res.get(x, y),
+                    expected.get(x, y)
+                );
+            }
+        }
+
+        // Flat windows score 0 instead of dividing by zero
+        let flat = ImageBuffer::from_pixel(20, 20, Luma([0.5f32]));
+        matcher
+            .match_template(
+                (&flat).into(),
+                (&template).into(),
+                MatchTemplateMethod::CCOEFF_NORMED,
+                false,
+            )
+            .unwrap();
+        let res = matcher.wait_for_result().unwrap();
+        assert!(res.data.iter().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn test_try_find_extremes() {
+        let res = Image::new(vec![0.1, 0.9, -0.4, 0.2, 0.5, 0.3], 3, 2);
+        let extremes = try_find_extremes(&res).unwrap();
+        assert_eq!(extremes.max_value_location, (1, 0));
+        assert_eq!(extremes.min_value_location, (2, 0));
+
+        // find_extremes skips NaN, and falls back to the sentinels if every value is NaN
+        let nan = Image::filled(4, 3, f32::NAN);
+        let extremes = find_extremes(&nan);
+        assert_eq!(
+            (extremes.min_value, extremes.max_value),
+            (f32::MAX, f32::MIN)
+        );
+        assert_eq!(
+            try_find_extremes(&nan).unwrap_err(),
+            "12 of the 4x3 values are NaN or infinite, the first one at (0, 0)"
+        );
+
+        let res = Image::new(vec![0.1, 0.9, -0.4, f32::NAN, 0.5, f32::INFINITY], 3, 2);
+        assert_eq!(find_extremes(&res).max_value, f32::INFINITY);
+        assert_eq!(
+            try_find_extremes(&res).unwrap_err(),
+            "2 of the 3x2 values are NaN or infinite, the first one at (0, 1)"
+        );
+        assert!(try_find_extremes(&Image::new(vec![], 0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_ccoeff_normed_flat() {
+        // Textured on the left, flat on the right
+        let input = ImageBuffer::from_fn(48, 32, |x, y| {
+            Luma([if x < 24 {
+                ((x * 7 + y * 3) % 13) as f32 / 13.0
+            } else {
+                0.7
+            }])
+        });
+        let constant = ImageBuffer::from_pixel(6, 5, Luma([0.3f32]));
+        let template = ImageBuffer::from_fn(6, 5, |x, y| Luma([((x + y * 2) % 5) as f32 / 5.0]));
+
+        // Dropped before `match_template` creates the matcher of this thread, see [on_new_thread]
+        #[cfg(feature = "gpu")]
+        {
+            let mut matcher = TemplateMatcher::new();
+            matcher
+                .match_template(
+                    (&input).into(),
+                    (&constant).into(),
+                    MatchTemplateMethod::CCOEFF_NORMED,
+                    false,
+                )
+                .unwrap();
+            let res = matcher.wait_for_result().unwrap();
+            assert!(res.data.iter().all(|&v| v == 0.0));
+        }
+
+        // A constant-color template has no variance, every window scores 0
+        let res = match_template(&input, &constant, MatchTemplateMethod::CCOEFF_NORMED).unwrap();
+        assert!(res.data.iter().all(|&v| v == 0.0));
+
+        let res = match_template(&input, &template, MatchTemplateMethod::CCOEFF_NORMED).unwrap();
+        assert!(res.data.iter().all(|v| v.is_finite() && v.abs() <= 1.0));
+        // Windows fully inside the flat area score 0
+        for y in 0..=32 - 5 {
+            for x in 24..=48 - 6 {
+                assert_eq!(res.get(x, y), 0.0, "({x}, {y})");
+            }
+        }
+        let extremes = find_extremes(&res);
+        assert!(extremes.max_value_location.0 < 24);
+    }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn test_match_and_threshold() {
+        // 67x51 with a 4x4 template gives a 64x48 result
+        let input = ImageBuffer::from_fn(67, 51, |x, y| Luma([((x * 7 + y * 3) % 13) as f32]));
+        let template = ImageBuffer::from_fn(4, 4, |x, y| Luma([((x + y * 5) % 7) as f32]));
+
+        // The threshold pass follows the workgroup size of the matcher
+        for (x, y) in [DEFAULT_WORKGROUP_SIZE, (8, 4)] {
+            let mut matcher = TemplateMatcher::builder()
+                .workgroup_size(x, y)
+                .build()
+                .unwrap();
+            for method in [
+                MatchTemplateMethod::CrossCorrelation,
+                MatchTemplateMethod::SumOfSquaredErrors,
+                MatchTemplateMethod::FftCrossCorrelation,
+                MatchTemplateMethod::CCOEFF_NORMED,
+            ] {
+                matcher
+                    .match_template((&input).into(), (&template).into(), method, false)
+                    .unwrap();
+                let res = matcher.wait_for_result().unwrap();
+                // Keep roughly the best 10% of the score range
+                let extremes = find_extremes(&res);
+                let range = extremes.max_value - extremes.min_value;
+                let threshold = if method.higher_is_better() {
+                    extremes.max_value - range * 0.1
+                } else {
+                    extremes.min_value + range * 0.1
+                };
+
+                let mut expected = vec![];
+                for y in 0..res.height {
+                    for x in 0..res.width {
+                        let value = res.get(x, y);
+                        let passed = if method.higher_is_better() {
+                            value >= threshold
+                        } else {
+                            value <= threshold
+                        };
+                        if passed {
+                            expected.push(((x, y), value));
+                        }
+                    }
+                }
+                assert!(!expected.is_empty());
+
+                let matches = matcher
+                    .match_and_threshold((&input).into(), (&template).into(), method, threshold)
+                    .unwrap();
+                let matches = matches
+                    .iter()
+                    .map(|m| (m.location, m.value))
+                    .collect::<Vec<_>>();
+                assert_eq!(matches.len(), expected.len(), "{:?} {x}x{y}", method);
+                for (a, b) in matches.iter().zip(expected.iter()) {
+                    assert_eq!(a.0, b.0);
+                    assert!((a.1 - b.1).abs() < 1e-2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let input = Image::new(
+            (0..19 * 13)
+                .map(|i| ((i * 7) % 11) as f32)
+                .collect::<Vec<_>>(),
+            19,
+            13,
+        );
+        let template = Image::new(
+            (0..4 * 3).map(|i| ((i * 5) % 7) as f32).collect::<Vec<_>>(),
+            4,
+            3,
+        );
+        for (method, f) in [
+            (
+                MatchTemplateMethod::SumOfAbsoluteErrors,
+                (|i: f32, t: f32| (i - t).abs()) as fn(f32, f32) -> f32,
+            ),
+            (MatchTemplateMethod::SumOfSquaredErrors, |i, t| {
+                (i - t) * (i - t)
+            }),
+            (MatchTemplateMethod::CrossCorrelation, |i, t| i * t),
+        ] {
+            let res = sliding_window(input.clone(), template.clone(), method, false).unwrap();
+            assert_eq!((res.width, res.height), (16, 11));
+            for y in 0..res.height {
+                for x in 0..res.width {
+                    let mut expected = 0.0;
+                    for j in 0..template.height {
+                        for i in 0..template.width {
+                            expected += f(input.get(x + i, y + j), template.get(i, j));
+                        }
+                    }
+                    let value = res.get(x, y);
+                    assert!(
+                        (value - expected).abs() < 1e-2,
+                        "{method:?}: {value} != {expected}"
+                    );
+                }
+            }
+        }
+
+        let res = sliding_window(
+            input.clone(),
+            template,
+            MatchTemplateMethod::CrossCorrelation,
+            true,
+        )
+        .unwrap();
+        assert_eq!((res.width, res.height), (input.width, input.height));
+    }
+
+    #[test]
+    fn test_fft_cross_correlation() {
+        let input = ImageBuffer::from_fn(128, 96, |x, y| Luma([((x * 7 + y * 3) % 13) as f32]));
+        let template = ImageBuffer::from_fn(20, 10, |x, y| Luma([((x + y * 5) % 7) as f32]));
+        let naive =
+            match_template(&input, &template, MatchTemplateMethod::CrossCorrelation).unwrap();
+        let fft =
+            match_template(&input, &template, MatchTemplateMethod::FftCrossCorrelation).unwrap();
+        assert_eq!((naive.width, naive.height), (fft.width, fft.height));
+        for (a, b) in naive.data.iter().zip(fft.data.iter()) {
+            assert!((a - b).abs() < 1e-2, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    fn test_best_match() {
+        let input = ImageBuffer::from_fn(40, 30, |x, y| {
+            Luma([((x * x * 7 + y * y * 13 + x * y) % 31) as f32])
+        });
+        let template = image::imageops::crop_imm(&input, 21, 9, 6, 5).to_image();
+
+        for method in [
+            MatchTemplateMethod::SumOfSquaredErrors,
+            MatchTemplateMethod::CCOEFF_NORMED,
+        ] {
+            let m = best_match(&input, &template, method).unwrap();
+            println!("{method:?}: {m:?}");
+            assert_eq!(m.location, (21, 9));
+        }
+        let m = best_match(&input, &template, MatchTemplateMethod::SumOfSquaredErrors).unwrap();
+        assert_eq!(m.value, 0.0);
+
+        let empty = ImageBuffer::new(0, 5);
+        let err = best_match(&input, &empty, MatchTemplateMethod::CCOEFF_NORMED).unwrap_err();
+        assert_eq!(err, "template is empty: 0x5");
+        let err = best_match(&template, &input, MatchTemplateMethod::CCOEFF_NORMED).unwrap_err();
+        assert_eq!(err, "template 40x30 is larger than the input 6x5");
+    }
+
+    #[test]
+    fn test_match_template_multiscale() {
+        let template = ImageBuffer::from_fn(16, 16, |x, y| {
+            Luma([if (x / 4 + y / 4) % 2 == 0 {
+                1.0f32
+            } else {
+                0.0
+            }])
+        });
+        // The template appears at (40, 24) in 1.5x
+        let scaled =
+            image::imageops::resize(&template, 24, 24, image::imageops::FilterType::Lanczos3);
+        let mut input = ImageBuffer::from_pixel(96, 64, Luma([0.5f32]));
+        image::imageops::replace(&mut input, &scaled, 40, 24);
+
+        let res = match_template_multiscale(
+            &input,
+            &template,
+            &[1.0, 1.25, 1.5, 1.75],
+            MatchTemplateMethod::CCOEFF_NORMED,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        println!("{:?}", res);
+        assert_eq!(res.scale, 1.5);
+        assert_eq!(res.location, (40, 24));
+
+        let res = match_template_multiscale(
+            &input,
+            &template,
+            &[1.5, 1.0],
+            MatchTemplateMethod::CCOEFF_NORMED,
+            Some(0.9),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(res.scale, 1.5);
+    }
 
     #[test]
     fn test_ccoeff() {
         let input = ImageBuffer::from_fn(7, 7, |x, y| Luma([x as f32 + y as f32]));
         let template = ImageBuffer::from_fn(2, 2, |x, y| Luma([x as f32 + y as f32]));
-        let res = ccoeff(&input, &template, false);
+        let res = ccoeff(&input, &template, false).unwrap();
         println!("{:?}", res);
-        let res_normed = ccoeff(&input, &template, true);
+        let res_normed = ccoeff(&input, &template, true).unwrap();
         println!("{:?}", res_normed);
+
+        let err = ccorr((&template).into(), (&input).into(), false).unwrap_err();
+        assert_eq!(err, "template 7x7 is larger than the input 2x2");
     }
 }
 
-pub fn ccoeff<'a>(
+/// Correlation coefficient of `input` and `template`, normalized if `normed` is `true`, see
+/// [MatchTemplateMethod::CCOEFF] and [MatchTemplateMethod::CCOEFF_NORMED].
+///
+/// Composed of several cross correlations, which all run on the [TemplateMatcher] of the current
+/// thread with the `gpu` feature (see [match_template]), or on `matcher` with [ccoeff_with].
+#[cfg(feature = "gpu")]
+pub fn ccoeff(
+    input: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    template: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    normed: bool,
+) -> Result<Image<'static>, String> {
+    with_thread_matcher(|matcher| ccoeff_with(matcher, input, template, normed))
+}
+
+/// Correlation coefficient of `input` and `template`, normalized if `normed` is `true`, see
+/// [MatchTemplateMethod::CCOEFF] and [MatchTemplateMethod::CCOEFF_NORMED].
+#[cfg(not(feature = "gpu"))]
+pub fn ccoeff(
+    input: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    template: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    normed: bool,
+) -> Result<Image<'static>, String> {
+    ccoeff_by(input, template, normed, |i, t| ccorr(i, t, true))
+}
+
+/// Same as [ccoeff], but runs the cross correlations on `matcher` instead of the one of the
+/// current thread.
+#[cfg(feature = "gpu")]
+pub fn ccoeff_with(
+    matcher: &mut TemplateMatcher,
+    input: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    template: &ImageBuffer<Luma<f32>, Vec<f32>>,
+    normed: bool,
+) -> Result<Image<'static>, String> {
+    ccoeff_by(input, template, normed, |i, t| {
+        ccorr_with(matcher, i, t, true)
+    })
+}
+
+/// Composes [ccoeff] from the padded cross correlations computed by `ccorr`.
+fn ccoeff_by(
     input: &ImageBuffer<Luma<f32>, Vec<f32>>,
     template: &ImageBuffer<Luma<f32>, Vec<f32>>,
     normed: bool,
-) -> Image<'static> {
-    let mask = ImageBuffer::from_pixel(template.width(), template.height(), Luma([1.0f32]));
+    mut ccorr_padded: impl FnMut(Image<'_>, Image<'_>) -> Result<Image<'static>, String>,
+) -> Result<Image<'static>, String> {
     let i: Image = input.into();
-    let m: Image = (&mask).into();
+    let m = Image::filled(template.width(), template.height(), 1.0);
     let t: Image = (template).into();
 
     // T' * M where T' = M * (T - 1/sum(M)*sum(M*T))
     let tc = t.clone() - (t.clone() * m.clone()).sum() / m.sum();
 
-    let ccorr_i_tcm = ccorr(i.clone(), tc.clone() * m.clone(), true);
-    let ccorr_i_m = ccorr(i.clone(), m.clone(), true);
+    let ccorr_i_tcm = ccorr_padded(i.clone(), tc.clone() * m.clone())?;
+    let ccorr_i_m = ccorr_padded(i.clone(), m.clone())?;
 
     // CCorr(I', T') = CCorr(I, T'*M) - sum(T'*M)/sum(M)*CCorr(I, M)
     let res = ccorr_i_tcm - (tc.clone() * m.clone()).sum() / m.sum() * ccorr_i_m.clone();
 
     if normed {
-        // norm(T')
-        let norm_templ = tc.square().sum().sqrt();
+        // norm(T')^2
+        let norm_templ_sq = tc.square().sum();
         // norm(I') = sqrt{ CCorr(I^2, M^2) - 2*CCorr(I, M^2)/sum(M)*CCorr(I, M)
         //                  + sum(M^2)*CCorr(I, M)^2/sum(M)^2 }
         //          = sqrt{ CCorr(I^2, M^2)
@@ -105,56 +1577,109 @@ pub fn ccoeff<'a>(
         //                  - 2 * CCorr(I, M^2) } }
         let i_sq = i.square();
         let m_sq = m.square();
-        let ccorr_i_sq_m_sq = ccorr(i_sq.clone(), m_sq.clone(), true);
-        let ccorr_i_m_sq = ccorr(i.clone(), m_sq.clone(), true);
-        let norm_input = ccorr_i_sq_m_sq
+        let ccorr_i_sq_m_sq = ccorr_padded(i_sq.clone(), m_sq.clone())?;
+        let ccorr_i_m_sq = ccorr_padded(i.clone(), m_sq.clone())?;
+        let norm_input_sq = ccorr_i_sq_m_sq
             + ccorr_i_m.clone() / m.sum() * (m_sq.sum() / m.sum() * ccorr_i_m - 2.0 * ccorr_i_m_sq);
-        let norm_input = norm_input.sqrt();
 
-        res / (norm_input * norm_templ).replace_zero(1.0)
+        // Flat windows or templates score 0, see [FLAT_VARIANCE_EPSILON], and the rounding
+        // errors of nearly flat ones are clamped
+        let flat = FLAT_VARIANCE_EPSILON * m.sum();
+        let data = res
+            .data
+            .iter()
+            .zip(norm_input_sq.data.iter())
+            .map(|(&v, &norm_input_sq)| {
+                if norm_templ_sq <= flat || norm_input_sq.is_nan() || norm_input_sq <= flat {
+                    return 0.0;
+                }
+                let score = v / (norm_input_sq.sqrt() * norm_templ_sq.sqrt());
+                if score.is_finite() {
+                    score.clamp(-1.0, 1.0)
+                } else {
+                    0.0
+                }
+            })
+            .collect::<Vec<f32>>();
+        Ok(Image::new(data, res.width, res.height))
     } else {
-        res
+        Ok(res)
     }
 }
 
-pub fn ccorr<'a>(input: Image<'a>, template: Image<'a>, padding: bool) -> Image<'static> {
-    let mut matcher = TemplateMatcher::new();
-    matcher.match_template(
+/// Cross correlation of `input` and `template`, on the GPU with the `gpu` feature (through the
+/// [TemplateMatcher] of the current thread, see [match_template]).
+///
+/// # Errors
+///
+/// Returns an error if the template is empty, if `padding` is `false` and the template is larger
+/// than the input, or if the GPU failed to return the result (see [match_template]).
+pub fn ccorr<'a>(
+    input: Image<'a>,
+    template: Image<'a>,
+    padding: bool,
+) -> Result<Image<'static>, String> {
+    sliding_window(
         input,
         template,
         MatchTemplateMethod::CrossCorrelation,
         padding,
-    );
-    matcher.wait_for_result().unwrap()
+    )
 }
 
+/// Same as [ccorr], but runs on `matcher` instead of the one of the current thread.
+#[cfg(feature = "gpu")]
+pub fn ccorr_with<'a>(
+    matcher: &mut TemplateMatcher,
+    input: Image<'a>,
+    template: Image<'a>,
+    padding: bool,
+) -> Result<Image<'static>, String> {
+    sliding_window_with(
+        matcher,
+        input,
+        template,
+        MatchTemplateMethod::CrossCorrelation,
+        padding,
+    )
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Match {
     pub location: (u32, u32),
     pub value: f32,
 }
 
+/// Finds the locations in a [match_template] result that pass `threshold`, keeping only the best
+/// one among locations closer than the template size.
+///
+/// Whether a value passes depends on the `method` used to produce the result: it must be above
+/// `threshold` for methods where [higher is better](MatchTemplateMethod::higher_is_better) (e.g.
+/// [MatchTemplateMethod::CCOEFF_NORMED]), and below it for the others (e.g.
+/// [MatchTemplateMethod::SumOfSquaredErrors]).
 pub fn find_matches(
     input: &Image<'_>,
     template_width: u32,
     template_height: u32,
+    method: MatchTemplateMethod,
     threshold: f32,
 ) -> Vec<Match> {
+    let higher_is_better = method.higher_is_better();
+    let is_better = |a: f32, b: f32| if higher_is_better { a > b } else { a < b };
+
     let mut matches: Vec<Match> = Vec::new();
 
     let input_width = input.width;
     let input_height = input.height;
 
     for y in 0..input_height {
-        for x in 0..input_width {
-            let idx = (y * input.width) + x;
-            let value = input.data[idx as usize];
-
-            if value < threshold {
+        for (x, &value) in (0..input_width).zip(input.row(y)) {
+            if is_better(value, threshold) {
                 if let Some(m) = matches.iter_mut().rev().find(|m| {
                     ((m.location.0 as i32 - x as i32).abs() as u32) < template_width
                         && ((m.location.1 as i32 - y as i32).abs() as u32) < template_height
                 }) {
-                    if value > m.value {
+                    if is_better(value, m.value) {
                         m.location = (x, y);
                         m.value = value;
                     }
@@ -172,7 +1697,87 @@ pub fn find_matches(
     matches
 }
 
+/// Like [find_matches], but de-duplicates with non-maximum suppression: every location passing
+/// `threshold` is a candidate, and a candidate is dropped if its rect overlaps an already kept
+/// better one with an IoU (intersection over union) above `iou_threshold`.
+///
+/// The result is sorted from the best match to the worst. An `iou_threshold` of `0.0` keeps only
+/// rects that don't overlap at all.
+pub fn find_matches_nms(
+    input: &Image<'_>,
+    template_width: u32,
+    template_height: u32,
+    method: MatchTemplateMethod,
+    threshold: f32,
+    iou_threshold: f32,
+) -> Vec<Match> {
+    let higher_is_better = method.higher_is_better();
+    let is_better = |a: f32, b: f32| if higher_is_better { a > b } else { a < b };
+
+    let mut candidates = Vec::new();
+    for y in 0..input.height {
+        for (x, &value) in (0..input.width).zip(input.row(y)) {
+            if is_better(value, threshold) {
+                candidates.push(Match {
+                    location: (x, y),
+                    value,
+                });
+            }
+        }
+    }
+    non_max_suppression(
+        candidates,
+        template_width,
+        template_height,
+        method,
+        iou_threshold,
+    )
+}
+
+/// The non-maximum suppression of [find_matches_nms] on already thresholded `candidates`, e.g.
+/// the ones returned by [TemplateMatcher::match_and_threshold].
+///
+/// The result is sorted from the best match to the worst.
+pub fn non_max_suppression(
+    mut candidates: Vec<Match>,
+    template_width: u32,
+    template_height: u32,
+    method: MatchTemplateMethod,
+    iou_threshold: f32,
+) -> Vec<Match> {
+    let higher_is_better = method.higher_is_better();
+    candidates.sort_by(|a, b| {
+        let ord = a.value.total_cmp(&b.value);
+        if higher_is_better {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+
+    // All the rects have the template size
+    let area = (template_width * template_height) as f32;
+    let iou = |a: &Match, b: &Match| {
+        let overlap_w = template_width.saturating_sub(a.location.0.abs_diff(b.location.0));
+        let overlap_h = template_height.saturating_sub(a.location.1.abs_diff(b.location.1));
+        let intersection = (overlap_w * overlap_h) as f32;
+        intersection / (2.0 * area - intersection)
+    };
+
+    let mut matches: Vec<Match> = Vec::new();
+    for candidate in candidates {
+        if matches.iter().all(|m| iou(m, &candidate) <= iou_threshold) {
+            matches.push(candidate);
+        }
+    }
+    matches
+}
+
 /// Finds the smallest and largest values and their locations in an image.
+///
+/// NaN values are skipped, and infinite ones count as the smallest or largest value. If the whole
+/// image is NaN (or empty), the values are `f32::MAX` and `f32::MIN` at `(0, 0)`. Use
+/// [try_find_extremes] to detect these cases, which usually mean an upstream numerical problem.
 pub fn find_extremes(input: &Image<'_>) -> Extremes<f32> {
     let mut min_value = f32::MAX;
     let mut min_value_location = (0, 0);
@@ -180,10 +1785,7 @@ pub fn find_extremes(input: &Image<'_>) -> Extremes<f32> {
     let mut max_value_location = (0, 0);
 
     for y in 0..input.height {
-        for x in 0..input.width {
-            let idx = (y * input.width) + x;
-            let value = input.data[idx as usize];
-
+        for (x, &value) in (0..input.width).zip(input.row(y)) {
             if value < min_value {
                 min_value = value;
                 min_value_location = (x, y);
@@ -204,6 +1806,27 @@ pub fn find_extremes(input: &Image<'_>) -> Extremes<f32> {
     }
 }
 
+/// Same as [find_extremes], but fails if the image is empty or holds NaN or infinite values, so
+/// that an untrustworthy result isn't mistaken for a match.
+pub fn try_find_extremes(input: &Image<'_>) -> Result<Extremes<f32>, String> {
+    if input.data.is_empty() {
+        return Err("can't find the extremes of an empty image".to_string());
+    }
+    let non_finite = input.data.iter().filter(|v| !v.is_finite()).count();
+    if non_finite > 0 {
+        let idx = input.data.iter().position(|v| !v.is_finite()).unwrap() as u32;
+        return Err(format!(
+            "{non_finite} of the {}x{} values are NaN or infinite, the first one at ({}, {})",
+            input.width,
+            input.height,
+            idx % input.width,
+            idx / input.width
+        ));
+    }
+    Ok(find_extremes(input))
+}
+
+#[cfg(feature = "gpu")]
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct ShaderUniforms {
@@ -211,20 +1834,55 @@ struct ShaderUniforms {
     input_height: u32,
     template_width: u32,
     template_height: u32,
+    template_norm: f32,
+    roi_x: u32,
+    roi_y: u32,
+    result_width: u32,
+    result_height: u32,
+    _padding: [u32; 3],
+}
+
+/// Format of the raw inputs converted to luma on the GPU, see `luma.wgsl`
+#[cfg(feature = "gpu")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RawFormat {
+    Rgba8,
+    Luma8,
 }
 
+#[cfg(feature = "gpu")]
+struct LumaPipeline {
+    bind_group_layout: wgpu::BindGroupLayout,
+    rgba: wgpu::ComputePipeline,
+    gray: wgpu::ComputePipeline,
+}
+
+/// Matches templates on the GPU through wgpu.
+///
+/// A matcher owns its own wgpu device, creating one is expensive, so reuse it for many matchings.
+/// `TemplateMatcher` is `Send` and `Sync`, the wgpu handles it holds are thread-safe on native
+/// backends. Matching takes `&mut self`, so a matcher serves one thread at a time, use a
+/// [pool::TemplateMatcherPool] to share warmed-up matchers between threads.
+#[cfg(feature = "gpu")]
 pub struct TemplateMatcher {
     ctx: gpu::Context,
     shader: wgpu::ShaderModule,
     bind_group_layout: wgpu::BindGroupLayout,
     pipeline_layout: wgpu::PipelineLayout,
 
-    last_pipeline: Option<wgpu::ComputePipeline>,
+    /// Compiled pipelines of the GPU methods, so that switching between methods doesn't recompile.
+    /// Holds at most one entry per sliding window method.
+    pipelines: HashMap<MatchTemplateMethod, wgpu::ComputePipeline>,
     last_method: Option<MatchTemplateMethod>,
 
     last_input_size: (u32, u32),
     last_template_size: (u32, u32),
     last_result_size: (u32, u32),
+    /// Matched region of the input, see [TemplateMatcher::match_template_roi]
+    last_roi: Rect,
+    /// Whether the input buffer holds the input of the latest matching, see
+    /// [TemplateMatcher::match_uploaded_roi]
+    input_uploaded: bool,
 
     uniform_buffer: wgpu::Buffer,
     input_buffer: Option<wgpu::Buffer>,
@@ -233,22 +1891,152 @@ pub struct TemplateMatcher {
     staging_buffer: Option<wgpu::Buffer>,
     bind_group: Option<wgpu::BindGroup>,
 
+    /// Result of the CPU path ([MatchTemplateMethod::FftCrossCorrelation])
+    cpu_result: Option<Image<'static>>,
+
+    /// Workgroup size of the matching shaders
+    workgroup_size: (u32, u32),
+
+    /// Layout and pipeline of the threshold pass, created on first use
+    threshold_pipeline: Option<(wgpu::BindGroupLayout, wgpu::ComputePipeline)>,
+    /// Layout and pipelines of the raw input to luma pass, created on first use
+    luma_pipeline: Option<LumaPipeline>,
+    /// Raw upload buffer and bind group of the luma pass, recreated with the input buffer
+    luma_pass: Option<(RawFormat, wgpu::Buffer, wgpu::BindGroup)>,
+
+    /// How the blocking retrievals wait for the GPU, see [PollStrategy]
+    poll_strategy: PollStrategy,
+    /// Receives the mapping of the staging buffer once it was requested, see
+    /// [TemplateMatcher::try_result]
+    map_receiver: Option<flume::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+
     matching_ongoing: bool,
 }
 
+#[cfg(feature = "gpu")]
 impl Default for TemplateMatcher {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Default workgroup size of the matching shaders, see [TemplateMatcherBuilder::workgroup_size].
+#[cfg(feature = "gpu")]
+pub const DEFAULT_WORKGROUP_SIZE: (u32, u32) = (16, 16);
+
+/// How [TemplateMatcher::wait_for_result] waits for the GPU to finish a matching.
+#[cfg(feature = "gpu")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PollStrategy {
+    /// Blocks in [wgpu::Maintain::Wait] until the GPU is done.
+    #[default]
+    Wait,
+    /// Polls with [wgpu::Maintain::Poll], sleeping `interval` between polls. On some platforms
+    /// this wakes up sooner than a blocking wait.
+    Poll { interval: Duration },
+}
+
+/// Builder of [TemplateMatcher], for selecting the wgpu backend and adapter.
+///
+/// ```ignore
+/// let matcher = TemplateMatcher::builder()
+///     .backends(wgpu::Backends::VULKAN)
+///     .power_preference(wgpu::PowerPreference::LowPower)
+///     .build()?;
+/// ```
+#[cfg(feature = "gpu")]
+#[derive(Clone, Debug, Default)]
+pub struct TemplateMatcherBuilder {
+    options: ContextOptions,
+    workgroup_size: Option<(u32, u32)>,
+    poll_strategy: PollStrategy,
+}
+
+#[cfg(feature = "gpu")]
+impl TemplateMatcherBuilder {
+    /// Backends to use, defaults to [wgpu::Backends::all].
+    pub fn backends(mut self, backends: wgpu::Backends) -> Self {
+        self.options.backends = backends;
+        self
+    }
+
+    /// Defaults to [wgpu::PowerPreference::HighPerformance].
+    pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.options.power_preference = power_preference;
+        self
+    }
+
+    /// Only use the adapter whose name contains `name` (case insensitive), ignores the power preference.
+    pub fn adapter_name<S: AsRef<str>>(mut self, name: S) -> Self {
+        self.options.adapter_name = Some(name.as_ref().to_string());
+        self
+    }
+
+    /// Workgroup size `(x, y)` of the matching shaders, defaults to [DEFAULT_WORKGROUP_SIZE].
+    ///
+    /// Each workgroup reads the template in tiles of `x * y` values into workgroup memory, so
+    /// larger workgroups read the template less often, at the cost of idle invocations on the
+    /// borders of small results.
+    pub fn workgroup_size(mut self, x: u32, y: u32) -> Self {
+        self.workgroup_size = Some((x, y));
+        self
+    }
+
+    /// How the matcher waits for results, defaults to [PollStrategy::Wait].
+    pub fn poll_strategy(mut self, poll_strategy: PollStrategy) -> Self {
+        self.poll_strategy = poll_strategy;
+        self
+    }
+
+    /// Fails if no adapter matches the options, or if the workgroup size exceeds the device limits.
+    pub fn build(self) -> Result<TemplateMatcher, String> {
+        let ctx = pollster::block_on(Context::with_options(&self.options))?;
+        let workgroup_size = self.workgroup_size.unwrap_or(DEFAULT_WORKGROUP_SIZE);
+        check_workgroup_size(workgroup_size, &ctx.device.limits())?;
+        let mut matcher = TemplateMatcher::with_context(ctx, workgroup_size);
+        matcher.poll_strategy = self.poll_strategy;
+        Ok(matcher)
+    }
+}
+
+#[cfg(feature = "gpu")]
 impl TemplateMatcher {
     pub fn new() -> Self {
-        let ctx = pollster::block_on(Context::new());
+        Self::with_context(pollster::block_on(Context::new()), DEFAULT_WORKGROUP_SIZE)
+    }
+
+    pub fn builder() -> TemplateMatcherBuilder {
+        TemplateMatcherBuilder::default()
+    }
+
+    /// Workgroup size of the matching shaders, see [TemplateMatcherBuilder::workgroup_size].
+    pub fn workgroup_size(&self) -> (u32, u32) {
+        self.workgroup_size
+    }
+
+    /// How the matcher waits for results, see [TemplateMatcherBuilder::poll_strategy].
+    pub fn poll_strategy(&self) -> PollStrategy {
+        self.poll_strategy
+    }
+
+    /// Changes how the matcher waits for results, takes effect from the next retrieval.
+    pub fn set_poll_strategy(&mut self, poll_strategy: PollStrategy) {
+        self.poll_strategy = poll_strategy;
+    }
 
+    fn with_context(ctx: Context, workgroup_size: (u32, u32)) -> Self {
+        let source = format!(
+            "const WORKGROUP_SIZE_X: u32 = {}u;\nconst WORKGROUP_SIZE_Y: u32 = {}u;\n{}",
+            workgroup_size.0,
+            workgroup_size.1,
+            include_str!("../shaders/matching.wgsl")
+        );
         let shader = ctx
             .device
-            .create_shader_module(wgpu::include_wgsl!("../shaders/matching.wgsl"));
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("matching.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
 
         let bind_group_layout =
             ctx.device
@@ -322,142 +2110,603 @@ impl TemplateMatcher {
             shader,
             pipeline_layout,
             bind_group_layout,
-            last_pipeline: None,
+            pipelines: HashMap::new(),
             last_method: None,
             last_input_size: (0, 0),
             last_template_size: (0, 0),
             last_result_size: (0, 0),
+            last_roi: Rect {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            },
+            input_uploaded: false,
             uniform_buffer,
             input_buffer: None,
             template_buffer: None,
             result_buffer: None,
             staging_buffer: None,
             bind_group: None,
+            cpu_result: None,
+            workgroup_size,
+            threshold_pipeline: None,
+            luma_pipeline: None,
+            luma_pass: None,
+            poll_strategy: PollStrategy::default(),
+            map_receiver: None,
             matching_ongoing: false,
         }
     }
 
+    /// Drops the cached input, template, result and staging buffers, e.g. between bursts of work
+    /// to not hold the VRAM of large inputs while idle. The pipelines are kept.
+    ///
+    /// The buffers are recreated by the next [TemplateMatcher::match_template]. A result that
+    /// was not collected yet is discarded.
+    pub fn release_buffers(&mut self) {
+        if self.matching_ongoing {
+            self.wait_for_result();
+        }
+        self.input_buffer = None;
+        self.input_uploaded = false;
+        self.template_buffer = None;
+        self.result_buffer = None;
+        self.staging_buffer = None;
+        self.bind_group = None;
+        self.luma_pass = None;
+    }
+
     /// Waits for the latest [match_template] execution and returns the result.
     /// Returns [None] if no matching was started.
+    ///
+    /// If the result buffer fails to be mapped, returns a result full of zeros, which looks like
+    /// valid scores. Use [TemplateMatcher::try_wait_for_result] to detect that case.
     pub fn wait_for_result(&mut self) -> Option<Image<'static>> {
+        self.try_wait_for_result()
+            .map(|res| res.unwrap_or_else(|_| self.zero_result()))
+    }
+
+    /// Same as [TemplateMatcher::wait_for_result], but returns an error instead of a result full
+    /// of zeros if the result buffer fails to be mapped (e.g. on a flaky driver).
+    ///
+    /// Waits according to the [PollStrategy] of the matcher.
+    pub fn try_wait_for_result(&mut self) -> Option<Result<Image<'static>, String>> {
         if !self.matching_ongoing {
             return None;
         }
         self.matching_ongoing = false;
 
-        let (result_width, result_height) = self.last_result_size;
+        if let Some(result) = self.cpu_result.take() {
+            return Some(Ok(result));
+        }
+
+        let receiver = self.request_map();
+        let mapped = match self.poll_strategy {
+            PollStrategy::Wait => {
+                self.ctx.device.poll(wgpu::Maintain::Wait);
+                matches!(receiver.recv(), Ok(Ok(())))
+            }
+            PollStrategy::Poll { interval } => loop {
+                self.ctx.device.poll(wgpu::Maintain::Poll);
+                match receiver.try_recv() {
+                    Ok(res) => break res.is_ok(),
+                    Err(flume::TryRecvError::Empty) => std::thread::sleep(interval),
+                    Err(flume::TryRecvError::Disconnected) => break false,
+                }
+            },
+        };
+        Some(self.read_result(mapped))
+    }
+
+    /// Returns the result of the latest [match_template] execution if the GPU is done with it,
+    /// without blocking, e.g. to check on it once per frame from an event loop.
+    ///
+    /// - [None]: the GPU is still busy, call again later.
+    /// - `Some(None)`: no matching was started, or its result was already collected.
+    /// - `Some(Some(result))`: the result, which is then collected. As with
+    ///   [TemplateMatcher::wait_for_result], a result that fails to be mapped is full of zeros.
+    ///
+    /// Polls the device with [wgpu::Maintain::Poll] whatever the [PollStrategy] is. Mixing with
+    /// the blocking retrievals is fine, they pick up the same pending result.
+    pub fn try_result(&mut self) -> Option<Option<Image<'static>>> {
+        if !self.matching_ongoing {
+            return Some(None);
+        }
+        if let Some(result) = self.cpu_result.take() {
+            self.matching_ongoing = false;
+            return Some(Some(result));
+        }
+
+        let receiver = self.request_map();
+        self.ctx.device.poll(wgpu::Maintain::Poll);
+        let mapped = match receiver.try_recv() {
+            Ok(res) => res.is_ok(),
+            Err(flume::TryRecvError::Empty) => {
+                // Keep the receiver for the next call
+                self.map_receiver = Some(receiver);
+                return None;
+            }
+            Err(flume::TryRecvError::Disconnected) => false,
+        };
+        self.matching_ongoing = false;
+        Some(Some(
+            self.read_result(mapped)
+                .unwrap_or_else(|_| self.zero_result()),
+        ))
+    }
 
+    /// Requests the mapping of the staging buffer, or takes the pending request of an earlier
+    /// [TemplateMatcher::try_result] call, since a buffer can only be mapped once at a time.
+    fn request_map(&mut self) -> flume::Receiver<Result<(), wgpu::BufferAsyncError>> {
+        if let Some(receiver) = self.map_receiver.take() {
+            return receiver;
+        }
         let buffer_slice = self.staging_buffer.as_ref().unwrap().slice(..);
-        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        let (sender, receiver) = flume::bounded(1);
         buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+        receiver
+    }
 
-        self.ctx.device.poll(wgpu::Maintain::Wait);
+    /// Same as [TemplateMatcher::wait_for_result], but doesn't block the calling thread.
+    ///
+    /// The device is polled with [wgpu::Maintain::Poll], yielding to the executor between polls
+    /// until the result is mapped, so it can be awaited from an async runtime (e.g. Tokio).
+    pub async fn wait_for_result_async(&mut self) -> Option<Image<'static>> {
+        if !self.matching_ongoing {
+            return None;
+        }
+        self.matching_ongoing = false;
 
-        pollster::block_on(async {
-            let result;
+        if let Some(result) = self.cpu_result.take() {
+            return Some(result);
+        }
 
-            if let Some(Ok(())) = receiver.receive().await {
-                let data = buffer_slice.get_mapped_range();
-                result = bytemuck::cast_slice(&data).to_vec();
-                drop(data);
-                self.staging_buffer.as_ref().unwrap().unmap();
-            } else {
-                result = vec![0.0; (result_width * result_height) as usize]
-            };
+        let receiver = self.request_map();
+        let mapped = loop {
+            self.ctx.device.poll(wgpu::Maintain::Poll);
+            match receiver.try_recv() {
+                Ok(res) => break res.is_ok(),
+                Err(flume::TryRecvError::Empty) => YieldNow(false).await,
+                Err(flume::TryRecvError::Disconnected) => break false,
+            }
+        };
+        Some(
+            self.read_result(mapped)
+                .unwrap_or_else(|_| self.zero_result()),
+        )
+    }
 
-            Some(Image::new(result, result_width as _, result_height as _))
-        })
+    /// Reads the result from the staging buffer, returns an error if the buffer failed to be mapped.
+    fn read_result(&self, mapped: bool) -> Result<Image<'static>, String> {
+        let (result_width, result_height) = self.last_result_size;
+        if !mapped {
+            return Err(format!(
+                "failed to map the {result_width}x{result_height} result buffer"
+            ));
+        }
+
+        let staging_buffer = self.staging_buffer.as_ref().unwrap();
+        let data = staging_buffer.slice(..).get_mapped_range();
+        let result = bytemuck::cast_slice(&data).to_vec();
+        drop(data);
+        staging_buffer.unmap();
+        Ok(Image::new(result, result_width as _, result_height as _))
+    }
+
+    /// A result full of zeros, returned by [TemplateMatcher::wait_for_result] when the result
+    /// buffer failed to be mapped.
+    fn zero_result(&self) -> Image<'static> {
+        let (result_width, result_height) = self.last_result_size;
+        Image::zeros(result_width, result_height)
     }
 
     /// Slides a template over the input and scores the match at each point using the requested method.
     /// To get the result of the matching, call [wait_for_result].
     /// Anchor on top left (0, 0)
+    ///
+    /// Without `padding`, returns an error if the template is larger than the input.
     pub fn match_template<'a>(
         &mut self,
         input: Image<'a>,
         template: Image<'a>,
         method: MatchTemplateMethod,
         padding: bool,
-    ) {
+    ) -> Result<(), String> {
+        check_sizes((input.width, input.height), &template, padding)?;
+
+        if self.matching_ongoing {
+            // Discard previous result if not collected.
+            self.wait_for_result();
+        }
+
+        let input = if padding {
+            pad_input(&input, template.width, template.height)
+        } else {
+            input
+        };
+
+        if method == MatchTemplateMethod::FftCrossCorrelation {
+            self.cpu_result = Some(fft_matching::fft_ccorr(&input, &template));
+            self.matching_ongoing = true;
+            // The input buffer still holds an older input
+            self.input_uploaded = false;
+            return Ok(());
+        }
+
+        let input_changed = self.upload_input(&input);
+        self.dispatch(
+            template,
+            method,
+            input_changed,
+            full_roi(input.width, input.height),
+        );
+        Ok(())
+    }
+
+    /// Same as [TemplateMatcher::match_template] without padding, but only matches the template
+    /// inside `roi` of the input. The whole input is uploaded, and only the workgroups covering
+    /// `roi` are dispatched.
+    ///
+    /// The result holds the locations of the template inside `roi`, its size is
+    /// `(roi.width - template.width + 1, roi.height - template.height + 1)`. The value at `(x, y)`
+    /// of the result is the score of the template at `(roi.x + x, roi.y + y)` of the input, so add
+    /// the offset of `roi` to the locations found in the result (e.g. by [find_extremes]) to get
+    /// input coordinates. This is the same result as matching against
+    /// `input.crop(roi.x, roi.y, roi.width, roi.height)`, without copying the region.
+    ///
+    /// To match other regions of the same input without uploading it again, see
+    /// [TemplateMatcher::match_uploaded_roi].
+    ///
+    /// Returns an error if `roi` is not inside the input, or if the template is larger than `roi`.
+    pub fn match_template_roi<'a>(
+        &mut self,
+        input: Image<'a>,
+        template: Image<'a>,
+        roi: Rect,
+        method: MatchTemplateMethod,
+    ) -> Result<(), String> {
+        check_roi((input.width, input.height), roi)?;
+        check_sizes((roi.width, roi.height), &template, false)?;
+
+        if method == MatchTemplateMethod::FftCrossCorrelation {
+            let input = input.crop(roi.x, roi.y, roi.width, roi.height);
+            return self.match_template(input, template, method, false);
+        }
+
         if self.matching_ongoing {
             // Discard previous result if not collected.
             self.wait_for_result();
         }
 
-        if self.last_pipeline.is_none() || self.last_method != Some(method) {
-            self.last_method = Some(method);
+        let input_changed = self.upload_input(&input);
+        self.dispatch(template, method, input_changed, roi);
+        Ok(())
+    }
+
+    /// Same as [TemplateMatcher::match_template_roi], but matches against the input that is
+    /// already on the GPU, i.e. the input of the latest matching. For repeated matching of regions
+    /// of a static screen, this uploads the screen only once:
+    ///
+    /// ```ignore
+    /// matcher.match_template_roi(screen, template, roi, method)?;
+    /// let first = matcher.wait_for_result().unwrap();
+    /// matcher.match_uploaded_roi(other_template, other_roi, method)?;
+    /// let second = matcher.wait_for_result().unwrap();
+    /// ```
+    ///
+    /// The result coordinates are offset by `roi` in the same way. Returns an error if the latest
+    /// matching didn't upload its input ([MatchTemplateMethod::FftCrossCorrelation] runs on the
+    /// CPU) or the buffers were released, and for [MatchTemplateMethod::FftCrossCorrelation].
+    pub fn match_uploaded_roi(
+        &mut self,
+        template: Image<'_>,
+        roi: Rect,
+        method: MatchTemplateMethod,
+    ) -> Result<(), String> {
+        if method == MatchTemplateMethod::FftCrossCorrelation {
+            return Err(format!("{method:?} is not supported on uploaded inputs"));
+        }
+        if !self.input_uploaded {
+            return Err("no input is uploaded".to_string());
+        }
+        check_roi(self.last_input_size, roi)?;
+        check_sizes((roi.width, roi.height), &template, false)?;
+
+        if self.matching_ongoing {
+            // Discard previous result if not collected.
+            self.wait_for_result();
+        }
+
+        self.dispatch(template, method, false, roi);
+        Ok(())
+    }
+
+    /// Same as [TemplateMatcher::match_template] without padding, but takes an RGBA input and
+    /// converts it to luma on the GPU, with the same coefficients as [DynamicImage::to_luma32f].
+    ///
+    /// The CPU only uploads the raw bytes, which saves the conversion of the whole screen on every
+    /// frame. [MatchTemplateMethod::FftCrossCorrelation] runs on the CPU and still converts there.
+    pub fn match_template_rgba<'a>(
+        &mut self,
+        input: &RgbaImage,
+        template: Image<'a>,
+        method: MatchTemplateMethod,
+    ) -> Result<(), String> {
+        check_sizes(input.dimensions(), &template, false)?;
+
+        if method == MatchTemplateMethod::FftCrossCorrelation {
+            let input = DynamicImage::ImageRgba8(input.clone()).to_luma32f();
+            return self.match_template(input.into(), template, method, false);
+        }
+
+        if self.matching_ongoing {
+            // Discard previous result if not collected.
+            self.wait_for_result();
+        }
+
+        let input_changed = self.prepare_input_buffer(input.dimensions());
+        self.convert_to_luma(input.as_raw(), input.dimensions(), RawFormat::Rgba8);
+        self.dispatch(
+            template,
+            method,
+            input_changed,
+            full_roi(input.width(), input.height()),
+        );
+        Ok(())
+    }
+
+    /// Same as [TemplateMatcher::match_template] without padding, but takes a `u8` grayscale
+    /// input, converted to `f32` (`/ 255.0`) on the GPU. This uploads a quarter of the bytes of an
+    /// `f32` input.
+    ///
+    /// Only [MatchTemplateMethod::SumOfAbsoluteErrors] and [MatchTemplateMethod::SumOfSquaredErrors]
+    /// are supported, for which `u8` precision is enough. Returns an error for the other methods,
+    /// use [TemplateMatcher::match_template] for them.
+    pub fn match_template_u8<'a>(
+        &mut self,
+        input: &GrayImage,
+        template: Image<'a>,
+        method: MatchTemplateMethod,
+    ) -> Result<(), String> {
+        if !matches!(
+            method,
+            MatchTemplateMethod::SumOfAbsoluteErrors | MatchTemplateMethod::SumOfSquaredErrors
+        ) {
+            return Err(format!("{method:?} is not supported on u8 inputs"));
+        }
+        check_sizes(input.dimensions(), &template, false)?;
+
+        if self.matching_ongoing {
+            // Discard previous result if not collected.
+            self.wait_for_result();
+        }
+
+        let input_changed = self.prepare_input_buffer(input.dimensions());
+        self.convert_to_luma(input.as_raw(), input.dimensions(), RawFormat::Luma8);
+        self.dispatch(
+            template,
+            method,
+            input_changed,
+            full_roi(input.width(), input.height()),
+        );
+        Ok(())
+    }
+
+    /// Writes `input` into the input buffer, returns whether the buffer was recreated.
+    fn upload_input(&mut self, input: &Image<'_>) -> bool {
+        let input_changed = self.prepare_input_buffer((input.width, input.height));
+        self.ctx.queue.write_buffer(
+            self.input_buffer.as_ref().unwrap(),
+            0,
+            bytemuck::cast_slice(&input.data),
+        );
+        input_changed
+    }
+
+    /// (Re)creates the input buffer if the input size changed, returns whether it was recreated.
+    ///
+    /// Called before every upload, so the input buffer holds the latest input afterwards.
+    fn prepare_input_buffer(&mut self, input_size: (u32, u32)) -> bool {
+        self.input_uploaded = true;
+        if self.input_buffer.is_some() && self.last_input_size == input_size {
+            return false;
+        }
+        self.last_input_size = input_size;
+        self.input_buffer = Some(self.ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("input_buffer"),
+            size: (input_size.0 * input_size.1) as u64 * size_of::<f32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+        // The luma pass writes into the old input buffer
+        self.luma_pass = None;
+        true
+    }
+
+    /// Uploads the `raw` input and converts it to luma into the input buffer, see `luma.wgsl`.
+    fn convert_to_luma(&mut self, raw: &[u8], size: (u32, u32), format: RawFormat) {
+        let (width, height) = size;
+        // Buffer writes must be 4 bytes aligned, which 4 bytes per pixel always are
+        let padded;
+        let raw = if raw.len() % 4 != 0 {
+            padded = [raw, &[0; 3][..4 - raw.len() % 4]].concat();
+            &padded
+        } else {
+            raw
+        };
+
+        let device = &self.ctx.device;
+        let luma_pipeline = self.luma_pipeline.get_or_insert_with(|| {
+            let bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("luma_bind_group_layout"),
+                    entries: gpu::BindGroupLayoutEntriesBuilder::new()
+                        .add_buffer(wgpu::BufferBindingType::Storage { read_only: true })
+                        .add_buffer(wgpu::BufferBindingType::Storage { read_only: false })
+                        .add_buffer(wgpu::BufferBindingType::Uniform)
+                        .build(),
+                });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            let module = device.create_shader_module(wgpu::include_wgsl!("../shaders/luma.wgsl"));
+            let create_pipeline = |entry_point| {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&pipeline_layout),
+                    module: &module,
+                    entry_point,
+                })
+            };
+            LumaPipeline {
+                rgba: create_pipeline("main_rgba"),
+                gray: create_pipeline("main_gray"),
+                bind_group_layout,
+            }
+        });
+        let pipeline = match format {
+            RawFormat::Rgba8 => &luma_pipeline.rgba,
+            RawFormat::Luma8 => &luma_pipeline.gray,
+        };
+
+        if self
+            .luma_pass
+            .as_ref()
+            .is_some_and(|(last_format, _, _)| *last_format != format)
+        {
+            self.luma_pass = None;
+        }
+        let (_, raw_buffer, bind_group) = self.luma_pass.get_or_insert_with(|| {
+            let raw_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("raw_buffer"),
+                size: raw.len() as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("luma_params_buffer"),
+                contents: bytemuck::cast_slice(&[width, height]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &luma_pipeline.bind_group_layout,
+                entries: gpu::BindGroupEntriesBuilder::new()
+                    .add_buffer(&raw_buffer)
+                    .add_buffer(self.input_buffer.as_ref().unwrap())
+                    .add_buffer(&params_buffer)
+                    .build(),
+            });
+            (format, raw_buffer, bind_group)
+        });
+        self.ctx.queue.write_buffer(raw_buffer, 0, raw);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("luma_encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("luma_pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                (width as f32 / 16.0).ceil() as u32,
+                (height as f32 / 16.0).ceil() as u32,
+                1,
+            );
+        }
+        // Submissions run in order, so the matching pass sees the converted input
+        self.ctx.queue.submit(std::iter::once(encoder.finish()));
+    }
 
+    /// Runs the `method` pass on the `roi` of the input buffer, the result is read by
+    /// [TemplateMatcher::wait_for_result].
+    fn dispatch(
+        &mut self,
+        template: Image<'_>,
+        method: MatchTemplateMethod,
+        input_changed: bool,
+        roi: Rect,
+    ) {
+        self.last_method = Some(method);
+        if !self.pipelines.contains_key(&method) {
             let entry_point = match method {
                 MatchTemplateMethod::SumOfAbsoluteErrors => "main_sae",
                 MatchTemplateMethod::SumOfSquaredErrors => "main_sse",
                 MatchTemplateMethod::CrossCorrelation => "main_cc",
+                MatchTemplateMethod::CCOEFF_NORMED => "main_ccoeff_normed",
                 _ => panic!("not implemented yet"),
             };
 
-            self.last_pipeline = Some(self.ctx.device.create_compute_pipeline(
-                &wgpu::ComputePipelineDescriptor {
-                    label: None,
-                    layout: Some(&self.pipeline_layout),
-                    module: &self.shader,
-                    entry_point,
-                },
-            ));
+            let pipeline =
+                self.ctx
+                    .device
+                    .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: None,
+                        layout: Some(&self.pipeline_layout),
+                        module: &self.shader,
+                        entry_point,
+                    });
+            self.pipelines.insert(method, pipeline);
         }
 
-        let mut buffers_changed = false;
+        let input_size = self.last_input_size;
 
-        let input = if padding {
-            let padded_w = input.width + template.width - 1;
-            let padded_h = input.height + template.height - 1;
-
-            let mut padded_input = vec![0.0; padded_w as usize * padded_h as usize];
-            for y in 0..input.height {
-                for x in 0..input.width {
-                    let idx = (y * input.width) + x;
-                    let padded_idx = (y * padded_w) + x;
-                    padded_input[padded_idx as usize] = input.data[idx as usize];
-                }
-            }
-            Image::new(padded_input, padded_w, padded_h)
+        // CCOEFF_NORMED correlates with the zero-mean template, and needs its norm
+        let (template, template_norm) = if method == MatchTemplateMethod::CCOEFF_NORMED {
+            let len = template.data.len() as f32;
+            let template = template.clone() - template.sum() / len;
+            let norm_sq = template.square().sum();
+            // A zero norm makes the shader score every window 0, see [FLAT_VARIANCE_EPSILON]
+            let norm = if norm_sq <= FLAT_VARIANCE_EPSILON * len {
+                0.0
+            } else {
+                norm_sq.sqrt()
+            };
+            (template, norm)
         } else {
-            input
+            (template, 0.0)
         };
 
-        let input_size = (input.width, input.height);
-        if self.input_buffer.is_none() || self.last_input_size != input_size {
-            buffers_changed = true;
-
-            self.last_input_size = input_size;
-
-            self.input_buffer = Some(self.ctx.device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
-                    label: Some("input_buffer"),
-                    contents: bytemuck::cast_slice(&input.data),
-                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-                },
-            ));
-        } else {
-            self.ctx.queue.write_buffer(
-                self.input_buffer.as_ref().unwrap(),
-                0,
-                bytemuck::cast_slice(&input.data),
-            );
-        }
-
         let template_size = (template.width, template.height);
-        if self.template_buffer.is_none() || self.last_template_size != template_size {
+        let template_changed =
+            self.template_buffer.is_none() || self.last_template_size != template_size;
+        let roi_changed = self.last_roi != roi;
+        self.last_roi = roi;
+        let res_w = roi.width - template.width + 1;
+        let res_h = roi.height - template.height + 1;
+        // The uniforms hold both sizes, e.g. switching between the full screen and a cropped roi
+        // with the same template only changes the input size. The template norm depends on the
+        // template content
+        if input_changed
+            || template_changed
+            || roi_changed
+            || method == MatchTemplateMethod::CCOEFF_NORMED
+        {
             self.ctx.queue.write_buffer(
                 &self.uniform_buffer,
                 0,
                 bytemuck::cast_slice(&[ShaderUniforms {
-                    input_width: input.width,
-                    input_height: input.height,
+                    input_width: input_size.0,
+                    input_height: input_size.1,
                     template_width: template.width,
                     template_height: template.height,
+                    template_norm,
+                    roi_x: roi.x,
+                    roi_y: roi.y,
+                    result_width: res_w,
+                    result_height: res_h,
+                    _padding: [0; 3],
                 }]),
             );
-            buffers_changed = true;
+        }
 
+        if template_changed {
             self.last_template_size = template_size;
 
             self.template_buffer = Some(self.ctx.device.create_buffer_init(
@@ -475,11 +2724,12 @@ impl TemplateMatcher {
             );
         }
 
-        let res_w = input.width - template.width + 1;
-        let res_h = input.height - template.height + 1;
         let res_buf_sz = (res_w * res_h) as u64 * size_of::<f32>() as u64;
 
-        if buffers_changed {
+        // The result size depends on both the input and the template sizes
+        let result_changed =
+            self.result_buffer.is_none() || self.last_result_size != (res_w, res_h);
+        if result_changed {
             self.last_result_size = (res_w, res_h);
 
             self.result_buffer = Some(self.ctx.device.create_buffer(&wgpu::BufferDescriptor {
@@ -497,7 +2747,10 @@ impl TemplateMatcher {
                 size: res_buf_sz,
                 mapped_at_creation: false,
             }));
+        }
 
+        // Any recreated buffer has to be bound again
+        if input_changed || template_changed || result_changed {
             self.bind_group = Some(
                 self.ctx
                     .device
@@ -542,11 +2795,11 @@ impl TemplateMatcher {
                 label: Some("compute_pass"),
                 timestamp_writes: None,
             });
-            compute_pass.set_pipeline(self.last_pipeline.as_ref().unwrap());
+            compute_pass.set_pipeline(&self.pipelines[&method]);
             compute_pass.set_bind_group(0, self.bind_group.as_ref().unwrap(), &[]);
             compute_pass.dispatch_workgroups(
-                (res_w as f32 / 16.0).ceil() as u32,
-                (res_h as f32 / 16.0).ceil() as u32,
+                res_w.div_ceil(self.workgroup_size.0),
+                res_h.div_ceil(self.workgroup_size.1),
                 1,
             );
         }
@@ -562,4 +2815,315 @@ impl TemplateMatcher {
         self.ctx.queue.submit(std::iter::once(encoder.finish()));
         self.matching_ongoing = true;
     }
+
+    /// Matches the template (without padding) and returns only the locations whose score passes
+    /// `threshold`: at least `threshold` for methods where higher is better, at most for the others.
+    ///
+    /// The scores are filtered on the GPU and only the passing `(x, y, score)` tuples are read back,
+    /// instead of the whole score map. The matches are sorted by location (row-major).
+    ///
+    /// Returns an error if the template is larger than the input, or if the matches couldn't be
+    /// read back.
+    pub fn match_and_threshold<'a>(
+        &mut self,
+        input: Image<'a>,
+        template: Image<'a>,
+        method: MatchTemplateMethod,
+        threshold: f32,
+    ) -> Result<Vec<Match>, String> {
+        let higher_is_better = method.higher_is_better();
+        self.match_template(input, template, method, false)?;
+
+        if self.cpu_result.is_some() {
+            let res = self.wait_for_result().unwrap();
+            return Ok((0..res.height)
+                .flat_map(|y| (0..res.width).map(move |x| (x, y)))
+                .map(|(x, y)| Match {
+                    location: (x, y),
+                    value: res.get(x, y),
+                })
+                .filter(|m| {
+                    if higher_is_better {
+                        m.value >= threshold
+                    } else {
+                        m.value <= threshold
+                    }
+                })
+                .collect());
+        }
+        // The scores are consumed on the GPU
+        self.matching_ongoing = false;
+
+        let device = &self.ctx.device;
+        let workgroup_size = self.workgroup_size;
+        let (bind_group_layout, pipeline) = self.threshold_pipeline.get_or_insert_with(|| {
+            let bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("threshold_bind_group_layout"),
+                    entries: gpu::BindGroupLayoutEntriesBuilder::new()
+                        .add_buffer(wgpu::BufferBindingType::Storage { read_only: true })
+                        .add_buffer(wgpu::BufferBindingType::Storage { read_only: false })
+                        .add_buffer(wgpu::BufferBindingType::Storage { read_only: false })
+                        .add_buffer(wgpu::BufferBindingType::Uniform)
+                        .build(),
+                });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("threshold_pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some("threshold.wgsl"),
+                    source: wgpu::ShaderSource::Wgsl(
+                        format!(
+                            "const WORKGROUP_SIZE_X: u32 = {}u;\nconst WORKGROUP_SIZE_Y: u32 = {}u;\n{}",
+                            workgroup_size.0,
+                            workgroup_size.1,
+                            include_str!("../shaders/threshold.wgsl")
+                        )
+                        .into(),
+                    ),
+                }),
+                entry_point: "main",
+            });
+            (bind_group_layout, pipeline)
+        });
+
+        let (res_w, res_h) = self.last_result_size;
+        let match_size = size_of::<GpuMatch>() as u64;
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("threshold_params_buffer"),
+            contents: bytemuck::cast_slice(&[ThresholdUniforms {
+                width: res_w,
+                height: res_h,
+                threshold,
+                higher_is_better: higher_is_better as u32,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let count_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("threshold_count_buffer"),
+            size: size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let matches_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("threshold_matches_buffer"),
+            size: (res_w * res_h) as u64 * match_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: bind_group_layout,
+            entries: gpu::BindGroupEntriesBuilder::new()
+                .add_buffer(self.result_buffer.as_ref().unwrap())
+                .add_buffer(&count_buffer)
+                .add_buffer(&matches_buffer)
+                .add_buffer(&params_buffer)
+                .build(),
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("threshold_encoder"),
+        });
+        encoder.clear_buffer(&count_buffer, 0, None);
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("threshold_pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                res_w.div_ceil(self.workgroup_size.0),
+                res_h.div_ceil(self.workgroup_size.1),
+                1,
+            );
+        }
+        self.ctx.queue.submit(std::iter::once(encoder.finish()));
+
+        // Read back the number of matches first, then only the matches
+        let count = read_buffer::<u32>(&self.ctx, &count_buffer, size_of::<u32>() as u64)?
+            .first()
+            .copied()
+            .unwrap_or(0) as u64;
+        if count == 0 {
+            return Ok(vec![]);
+        }
+        let mut matches = read_buffer::<GpuMatch>(&self.ctx, &matches_buffer, count * match_size)?
+            .into_iter()
+            .map(|m| Match {
+                location: (m.x, m.y),
+                value: m.score,
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|m| (m.location.1, m.location.0));
+        Ok(matches)
+    }
+}
+
+#[cfg(feature = "gpu")]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ThresholdUniforms {
+    width: u32,
+    height: u32,
+    threshold: f32,
+    higher_is_better: u32,
+}
+
+#[cfg(feature = "gpu")]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuMatch {
+    x: u32,
+    y: u32,
+    score: f32,
+}
+
+/// Copies the first `size` bytes of `buffer` into a staging buffer and reads them back.
+/// Returns an error if the staging buffer fails to be mapped.
+#[cfg(feature = "gpu")]
+fn read_buffer<T: bytemuck::Pod>(
+    ctx: &Context,
+    buffer: &wgpu::Buffer,
+    size: u64,
+) -> Result<Vec<T>, String> {
+    let staging_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("read_staging_buffer"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, size);
+    ctx.queue.submit(std::iter::once(encoder.finish()));
+
+    let buffer_slice = staging_buffer.slice(..);
+    let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+    ctx.device.poll(wgpu::Maintain::Wait);
+
+    if pollster::block_on(receiver.receive()) != Some(Ok(())) {
+        return Err(format!("failed to map the {size} bytes staging buffer"));
+    }
+    let data = buffer_slice.get_mapped_range();
+    let result = bytemuck::cast_slice(&data).to_vec();
+    drop(data);
+    staging_buffer.unmap();
+    Ok(result)
+}
+
+/// Checks a workgroup size of the matching shaders against the compute `limits` of the device, see
+/// [TemplateMatcherBuilder::workgroup_size].
+///
+/// Returns an error if the workgroup size is empty or exceeds the limits, including the workgroup
+/// memory used by the template tile.
+#[cfg(feature = "gpu")]
+fn check_workgroup_size(size: (u32, u32), limits: &wgpu::Limits) -> Result<(), String> {
+    let (x, y) = size;
+    if x == 0 || y == 0 {
+        return Err(format!("workgroup size is empty: {x}x{y}"));
+    }
+    if x > limits.max_compute_workgroup_size_x || y > limits.max_compute_workgroup_size_y {
+        return Err(format!(
+            "workgroup size {x}x{y} exceeds the device limit {}x{}",
+            limits.max_compute_workgroup_size_x, limits.max_compute_workgroup_size_y
+        ));
+    }
+    if x * y > limits.max_compute_invocations_per_workgroup {
+        return Err(format!(
+            "workgroup size {x}x{y} exceeds the device limit of {} invocations",
+            limits.max_compute_invocations_per_workgroup
+        ));
+    }
+    let tile_size = x * y * size_of::<f32>() as u32;
+    if tile_size > limits.max_compute_workgroup_storage_size {
+        return Err(format!(
+            "workgroup size {x}x{y} needs {tile_size} bytes of workgroup memory, the device limit is {}",
+            limits.max_compute_workgroup_storage_size
+        ));
+    }
+    Ok(())
+}
+
+/// The whole input of `width`x`height`, see [TemplateMatcher::match_template_roi].
+#[cfg(feature = "gpu")]
+fn full_roi(width: u32, height: u32) -> Rect {
+    Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    }
+}
+
+/// Returns an error if `roi` is not inside the input.
+#[cfg(feature = "gpu")]
+fn check_roi(input_size: (u32, u32), roi: Rect) -> Result<(), String> {
+    let fits =
+        |start: u32, len: u32, max: u32| start.checked_add(len).is_some_and(|end| end <= max);
+    if !fits(roi.x, roi.width, input_size.0) || !fits(roi.y, roi.height, input_size.1) {
+        return Err(format!(
+            "roi {}x{} at ({}, {}) is out of bounds of the input {}x{}",
+            roi.width, roi.height, roi.x, roi.y, input_size.0, input_size.1
+        ));
+    }
+    Ok(())
+}
+
+/// Returns an error if the template is empty, or larger than the input without `padding`.
+fn check_sizes(input_size: (u32, u32), template: &Image<'_>, padding: bool) -> Result<(), String> {
+    if template.width == 0 || template.height == 0 {
+        return Err(format!(
+            "template is empty: {}x{}",
+            template.width, template.height
+        ));
+    }
+    if !padding && (template.width > input_size.0 || template.height > input_size.1) {
+        return Err(format!(
+            "template {}x{} is larger than the input {}x{}",
+            template.width, template.height, input_size.0, input_size.1
+        ));
+    }
+    Ok(())
+}
+
+/// Pads the input with zeros on the right and bottom, so that the result has the same size as the input.
+fn pad_input(input: &Image<'_>, template_width: u32, template_height: u32) -> Image<'static> {
+    let padded_w = input.width + template_width - 1;
+    let padded_h = input.height + template_height - 1;
+
+    let mut padded_input = vec![0.0; padded_w as usize * padded_h as usize];
+    for y in 0..input.height {
+        let start = (y * padded_w) as usize;
+        padded_input[start..start + input.width as usize].copy_from_slice(input.row(y));
+    }
+    Image::new(padded_input, padded_w, padded_h)
+}
+
+/// A future that yields to the executor once before completing.
+#[cfg(feature = "gpu")]
+struct YieldNow(bool);
+
+#[cfg(feature = "gpu")]
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
 }
@@ -0,0 +1,203 @@
+//! A pool of [TemplateMatcher]s shared between threads.
+//!
+//! Creating a [TemplateMatcher] requests a new wgpu device and compiles the matching shaders,
+//! which costs far more than a matching itself. A [TemplateMatcherPool] keeps idle matchers
+//! around so that threads doing many small matchings reuse warmed-up devices, pipelines and
+//! buffers instead.
+//!
+//! ```ignore
+//! let pool = TemplateMatcherPool::new(4);
+//! std::thread::scope(|s| {
+//!     for (input, template) in jobs {
+//!         s.spawn(|| {
+//!             let mut matcher = pool.get().unwrap();
+//!             matcher.match_template(input, template, MatchTemplateMethod::CCOEFF_NORMED, true)?;
+//!             matcher.try_wait_for_result().unwrap()
+//!         });
+//!     }
+//! });
+//! ```
+
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{TemplateMatcher, TemplateMatcherBuilder};
+
+// The pool hands matchers out to other threads, keep it from silently breaking.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<TemplateMatcher>();
+    assert_send_sync::<TemplateMatcherPool>();
+};
+
+/// A pool of [TemplateMatcher]s, see the [module docs](self).
+///
+/// [TemplateMatcherPool::get] hands out an idle matcher, or builds a new one if every matcher is
+/// in use. The matcher goes back to the pool when the returned [PooledTemplateMatcher] is
+/// dropped, unless the pool already holds `max_idle` idle matchers.
+pub struct TemplateMatcherPool {
+    builder: TemplateMatcherBuilder,
+    max_idle: usize,
+    idle: Mutex<Vec<TemplateMatcher>>,
+    created: AtomicUsize,
+}
+
+impl TemplateMatcherPool {
+    /// Keeps at most `max_idle` idle matchers, built with the default [TemplateMatcherBuilder].
+    pub fn new(max_idle: usize) -> Self {
+        Self::with_builder(TemplateMatcher::builder(), max_idle)
+    }
+
+    /// Same as [TemplateMatcherPool::new], new matchers are built by `builder`.
+    pub fn with_builder(builder: TemplateMatcherBuilder, max_idle: usize) -> Self {
+        Self {
+            builder,
+            max_idle,
+            idle: Mutex::new(vec![]),
+            created: AtomicUsize::new(0),
+        }
+    }
+
+    /// Takes an idle matcher, or builds a new one if there is none.
+    ///
+    /// Fails if a new matcher is needed and [TemplateMatcherBuilder::build] fails.
+    pub fn get(&self) -> Result<PooledTemplateMatcher<'_>, String> {
+        let idle = self.idle.lock().unwrap().pop();
+        let matcher = match idle {
+            Some(matcher) => matcher,
+            None => {
+                let matcher = self.builder.clone().build()?;
+                self.created.fetch_add(1, Ordering::Relaxed);
+                matcher
+            }
+        };
+        Ok(PooledTemplateMatcher {
+            pool: self,
+            matcher: Some(matcher),
+        })
+    }
+
+    /// Number of idle matchers in the pool.
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+
+    /// Number of matchers built by the pool so far.
+    pub fn created(&self) -> usize {
+        self.created.load(Ordering::Relaxed)
+    }
+
+    /// Drops the cached buffers of the idle matchers, see [TemplateMatcher::release_buffers].
+    pub fn release_buffers(&self) {
+        for matcher in self.idle.lock().unwrap().iter_mut() {
+            matcher.release_buffers();
+        }
+    }
+
+    fn recycle(&self, mut matcher: TemplateMatcher) {
+        // The next user must not receive the result of this one
+        if matcher.matching_ongoing {
+            matcher.try_wait_for_result();
+        }
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.max_idle {
+            idle.push(matcher);
+        }
+    }
+}
+
+/// A [TemplateMatcher] taken from a [TemplateMatcherPool], goes back to the pool when dropped.
+///
+/// Derefs to [TemplateMatcher]. A result that was not collected yet is discarded when the
+/// matcher goes back to the pool.
+pub struct PooledTemplateMatcher<'a> {
+    pool: &'a TemplateMatcherPool,
+    matcher: Option<TemplateMatcher>,
+}
+
+impl Deref for PooledTemplateMatcher<'_> {
+    type Target = TemplateMatcher;
+
+    fn deref(&self) -> &Self::Target {
+        self.matcher.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledTemplateMatcher<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.matcher.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledTemplateMatcher<'_> {
+    fn drop(&mut self) {
+        if let Some(matcher) = self.matcher.take() {
+            self.pool.recycle(matcher);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{ImageBuffer, Luma};
+
+    use crate::{match_template, MatchTemplateMethod};
+
+    use super::*;
+
+    #[test]
+    fn test_template_matcher_pool() {
+        let input = ImageBuffer::from_fn(32, 24, |x, y| Luma([((x * 3 + y) % 5) as f32]));
+        let template = ImageBuffer::from_fn(4, 4, |x, y| Luma([((x + y) % 3) as f32]));
+        // On another thread, so that the matcher kept by `match_template` is gone before the pool
+        // builds its own
+        let expected = std::thread::scope(|s| {
+            s.spawn(|| match_template(&input, &template, MatchTemplateMethod::CrossCorrelation))
+                .join()
+                .unwrap()
+        })
+        .unwrap();
+
+        // Only one matcher is alive at a time, some drivers don't like more
+        let pool = TemplateMatcherPool::new(1);
+        assert_eq!((pool.idle(), pool.created()), (0, 0));
+        for _ in 0..3 {
+            std::thread::scope(|s| {
+                s.spawn(|| {
+                    let mut matcher = pool.get().unwrap();
+                    assert_eq!(pool.idle(), 0);
+                    matcher
+                        .match_template(
+                            (&input).into(),
+                            (&template).into(),
+                            MatchTemplateMethod::CrossCorrelation,
+                            true,
+                        )
+                        .unwrap();
+                    assert_eq!(matcher.wait_for_result().unwrap().data, expected.data);
+                });
+            });
+            assert_eq!((pool.idle(), pool.created()), (1, 1));
+        }
+
+        // An uncollected result is discarded when going back to the pool
+        {
+            let mut matcher = pool.get().unwrap();
+            matcher
+                .match_template(
+                    (&input).into(),
+                    (&template).into(),
+                    MatchTemplateMethod::CrossCorrelation,
+                    true,
+                )
+                .unwrap();
+        }
+        assert!(pool.get().unwrap().wait_for_result().is_none());
+        assert_eq!(pool.created(), 1);
+    }
+}
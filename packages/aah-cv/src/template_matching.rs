@@ -7,73 +7,122 @@ use std::{
 use imageproc::template_matching::Extremes;
 use ndarray::{Array2, AssignElem};
 
-use crate::convolve::gpu_convolve_block;
+use crate::{convolve::correlate, FLAT_VARIANCE_EPSILON};
 
 
 
-pub fn match_template(image: &Array2<f32>, kernel: &Array2<f32>) -> Array2<f32> {
-    // let start = Instant::now();
-    // let image = image.map(|&x| x as f64);
-    let squared_image = image.map(|&x| x * x);
-    // let kernel = kernel.map(|&x| x as f64);
-    // println!("map to f64 cost: {}ms", start.elapsed().as_millis());
+/// Returns an error if the kernel is empty or larger than the image.
+///
+/// To match multiple kernels against the same image, use [MatchContext] to reuse its integral images.
+pub fn match_template(image: &Array2<f32>, kernel: &Array2<f32>) -> Result<Array2<f32>, String> {
+    MatchContext::new(image.clone()).match_template(kernel)
+}
 
-    let start = Instant::now();
-    // let mut res = fftcorrelate(&image, &kernel, fftconvolve::Mode::Valid).unwrap();
-    let mut res = gpu_convolve_block(&image, &kernel).unwrap();
-    println!("correlate cost: {}ms", start.elapsed().as_millis());
-    let start = Instant::now();
+/// An image with its integral and squared integral precomputed, for matching multiple kernels
+/// against it (e.g. all the templates of a frame) without recomputing the integrals each time.
+pub struct MatchContext {
+    image: Array2<f32>,
+    integral_image: Array2<f32>,
+    integral_squared_image: Array2<f32>,
+}
 
-    let integral_image = integral_arr2(&image);
-    let integral_squared_image = integral_arr2(&squared_image);
-    println!(
-        "integral and integral squared cost: {}ms",
-        start.elapsed().as_millis()
-    );
-    let start = Instant::now();
+impl MatchContext {
+    pub fn new(image: Array2<f32>) -> Self {
+        let start = Instant::now();
+        let squared_image = image.map(|&x| x * x);
+        let integral_image = integral_arr2(&image);
+        let integral_squared_image = integral_arr2(&squared_image);
+        println!(
+            "integral and integral squared cost: {}ms",
+            start.elapsed().as_millis()
+        );
 
-    let kernel_sum = kernel.sum();
-    let kernel_sqsum = kernel.map(|x| x * x).sum();
+        Self {
+            image,
+            integral_image,
+            integral_squared_image,
+        }
+    }
 
-    let kernel_avg = kernel_sum / kernel.len() as f32;
-    let kernel_var = kernel_sqsum / kernel.len() as f32 - kernel_avg * kernel_avg;
-    println!("kernel avg and var cost: {}ms", start.elapsed().as_millis());
-    let start = Instant::now();
+    pub fn image(&self) -> &Array2<f32> {
+        &self.image
+    }
 
-    let (image_h, image_w) = image.dim();
-    let (kernel_h, kernel_w) = kernel.dim();
-    let (y_len, x_len) = (image_h - kernel_h + 1, image_w - kernel_w + 1);
-    for x in 0..x_len {
-        for y in 0..y_len {
-            let value_sum = subsum_from_integral(&integral_image, x, y, kernel_w, kernel_h);
-            let value_sqsum =
-                subsum_from_integral(&integral_squared_image, x, y, kernel_w, kernel_h);
-
-            let value_avg = value_sum / kernel.len() as f32;
-            let value_var = value_sqsum / kernel.len() as f32 - value_avg * value_avg;
-
-            let mut v = res[[y, x]];
-            v -= value_sum * kernel_avg;
-
-            let factor = (value_var * kernel_var).sqrt() * kernel.len() as f32;
-            if v.abs() < factor {
-                v /= factor;
-            } else if v.abs() < 1.125 * factor {
-                v = v.signum()
-            } else {
-                v = 0.0;
-            }
+    /// Replaces the image, the integrals are only recomputed if the image actually changed.
+    pub fn set_image(&mut self, image: Array2<f32>) {
+        if image != self.image {
+            *self = Self::new(image);
+        }
+    }
+
+    /// Same as [match_template], against the cached image.
+    pub fn match_template(&self, kernel: &Array2<f32>) -> Result<Array2<f32>, String> {
+        let image = &self.image;
+        let (image_h, image_w) = image.dim();
+        let (kernel_h, kernel_w) = kernel.dim();
+        if kernel_h == 0 || kernel_w == 0 || kernel_h > image_h || kernel_w > image_w {
+            return Err(format!(
+                "kernel {}x{} doesn't fit in the image {}x{}",
+                kernel_w, kernel_h, image_w, image_h
+            ));
+        }
 
-            // if v.is_infinite() {
-            //     println!("value_sum: {}, kernel_avg: {}, value_var: {}, kernel_var: {}", value_sum, kernel_avg, value_var, kernel_var);
-            // }
+        let start = Instant::now();
+        // let mut res = fftcorrelate(&image, &kernel, fftconvolve::Mode::Valid).unwrap();
+        let mut res = correlate(image, kernel).ok_or("correlate failed".to_string())?;
+        println!("correlate cost: {}ms", start.elapsed().as_millis());
+        let start = Instant::now();
 
-            res.get_mut((y, x)).unwrap().assign_elem(v)
+        let kernel_sum = kernel.sum();
+        let kernel_sqsum = kernel.map(|x| x * x).sum();
+
+        let kernel_avg = kernel_sum / kernel.len() as f32;
+        let kernel_var = kernel_sqsum / kernel.len() as f32 - kernel_avg * kernel_avg;
+        println!("kernel avg and var cost: {}ms", start.elapsed().as_millis());
+        let start = Instant::now();
+
+        let (y_len, x_len) = (image_h - kernel_h + 1, image_w - kernel_w + 1);
+        for x in 0..x_len {
+            for y in 0..y_len {
+                let value_sum = subsum_from_integral(&self.integral_image, x, y, kernel_w, kernel_h);
+                let value_sqsum =
+                    subsum_from_integral(&self.integral_squared_image, x, y, kernel_w, kernel_h);
+
+                let value_avg = value_sum / kernel.len() as f32;
+                let value_var = value_sqsum / kernel.len() as f32 - value_avg * value_avg;
+
+                // Flat windows or kernels score 0 instead of dividing by zero into NaN
+                if kernel_var <= FLAT_VARIANCE_EPSILON
+                    || value_var.is_nan()
+                    || value_var <= FLAT_VARIANCE_EPSILON
+                {
+                    res.get_mut((y, x)).unwrap().assign_elem(0.0);
+                    continue;
+                }
+
+                let mut v = res[[y, x]];
+                v -= value_sum * kernel_avg;
+
+                let factor = (value_var * kernel_var).sqrt() * kernel.len() as f32;
+                if v.abs() < factor {
+                    v /= factor;
+                } else if v.abs() < 1.125 * factor {
+                    v = v.signum()
+                } else {
+                    v = 0.0;
+                }
+
+                // if v.is_infinite() {
+                //     println!("value_sum: {}, kernel_avg: {}, value_var: {}, kernel_var: {}", value_sum, kernel_avg, value_var, kernel_var);
+                // }
+
+                res.get_mut((y, x)).unwrap().assign_elem(v)
+            }
         }
-    }
-    println!("normalize cost: {}ms", start.elapsed().as_millis());
+        println!("normalize cost: {}ms", start.elapsed().as_millis());
 
-    res
+        Ok(res)
+    }
 }
 
 pub fn find_extremes(input: &Array2<f32>) -> Extremes<f32> {
@@ -134,6 +183,53 @@ pub fn integral_arr2<T: AddAssign + SubAssign + Copy>(mat: &Array2<T>) -> Array2
     res
 }
 
+/// Integer prefix sum of a `u8` matrix, exact unlike [integral_arr2] of the matrix as floats.
+///
+/// A `u32` holds the sum of up to 16843009 values of 255, i.e. more than a 4K screen.
+pub fn integral_u8(mat: &Array2<u8>) -> Array2<u32> {
+    integral_arr2(&mat.mapv(u32::from))
+}
+
+/// Integer prefix sum of the squares of a `u8` matrix, see [integral_u8].
+pub fn squared_integral_u8(mat: &Array2<u8>) -> Array2<u64> {
+    integral_arr2(&mat.mapv(|v| u64::from(v) * u64::from(v)))
+}
+
+/// Sum of squared errors of the kernel at each location of the image, computed exactly with
+/// integers for `u8` inputs (e.g. grayscale screenshots) instead of converting them to floats.
+///
+/// Expands `Σ(i - t)²` into `Σi² - 2Σi·t + Σt²`, where the window sums `Σi²` are read from
+/// [squared_integral_u8] and only the cross term is computed per window. The result has the same
+/// `(height, width)` layout as [match_template], lower is better.
+///
+/// Returns an error if the kernel is empty or larger than the image.
+pub fn sse_u8(image: &Array2<u8>, kernel: &Array2<u8>) -> Result<Array2<u64>, String> {
+    let (image_h, image_w) = image.dim();
+    let (kernel_h, kernel_w) = kernel.dim();
+    if kernel_h == 0 || kernel_w == 0 || kernel_h > image_h || kernel_w > image_w {
+        return Err(format!(
+            "kernel {}x{} doesn't fit in the image {}x{}",
+            kernel_w, kernel_h, image_w, image_h
+        ));
+    }
+
+    let integral_squared_image = squared_integral_u8(image);
+    let kernel_sqsum = kernel
+        .iter()
+        .map(|&t| u64::from(t) * u64::from(t))
+        .sum::<u64>();
+
+    let (y_len, x_len) = (image_h - kernel_h + 1, image_w - kernel_w + 1);
+    Ok(Array2::from_shape_fn((y_len, x_len), |(y, x)| {
+        let value_sqsum = subsum_from_integral(&integral_squared_image, x, y, kernel_w, kernel_h);
+        let cross = kernel
+            .indexed_iter()
+            .map(|((j, i), &t)| u64::from(image[[y + j, x + i]]) * u64::from(t))
+            .sum::<u64>();
+        value_sqsum + kernel_sqsum - 2 * cross
+    }))
+}
+
 /// Calculate the sum of the sub-matrix from (x, y) with width and height through the integral matrix
 pub fn subsum_from_integral<T: Add<T, Output = T> + Sub<T, Output = T> + Copy>(
     integral_mat: &Array2<T>,
@@ -192,4 +288,129 @@ mod test {
         let res = subsum_from_integral(&integral, 0, 0, 2, 2);
         assert_eq!(res, 4.0);
     }
+
+    #[test]
+    fn test_integral_u8() {
+        let mat = Array2::from_shape_fn((37, 53), |(y, x)| ((x * 31 + y * 17) % 256) as u8);
+
+        // The float integral of small integers is exact too, and must match
+        let integral = integral_u8(&mat);
+        assert_eq!(
+            integral.mapv(|v| v as f64),
+            integral_arr2(&mat.mapv(f64::from))
+        );
+        let squared = squared_integral_u8(&mat);
+        assert_eq!(
+            squared.mapv(|v| v as f64),
+            integral_arr2(&mat.mapv(|v| f64::from(v) * f64::from(v)))
+        );
+
+        assert_eq!(
+            integral[[36, 52]],
+            mat.iter().map(|&v| v as u32).sum::<u32>()
+        );
+        assert_eq!(
+            subsum_from_integral(&integral, 3, 5, 4, 2),
+            mat.slice(ndarray::s![5..7, 3..7])
+                .iter()
+                .map(|&v| v as u32)
+                .sum::<u32>()
+        );
+
+        // Sums of a 4K screen of 255 don't overflow
+        let white = Array2::from_elem((2160, 3840), 255u8);
+        assert_eq!(integral_u8(&white)[[2159, 3839]], 255 * 3840 * 2160);
+        assert_eq!(
+            squared_integral_u8(&white)[[2159, 3839]],
+            255 * 255 * 3840 * 2160
+        );
+    }
+
+    #[test]
+    fn test_sse_u8() {
+        let image = Array2::from_shape_fn((40, 48), |(y, x)| ((x * 7 + y * 3) % 13 * 19) as u8);
+        let kernel = Array2::from_shape_fn((6, 9), |(y, x)| ((x + y * 5) % 7 * 36) as u8);
+
+        let res = sse_u8(&image, &kernel).unwrap();
+        assert_eq!(res.dim(), (35, 40));
+        let naive = Array2::from_shape_fn(res.dim(), |(y, x)| {
+            kernel
+                .indexed_iter()
+                .map(|((j, i), &t)| (image[[y + j, x + i]] as i64 - t as i64).pow(2) as u64)
+                .sum::<u64>()
+        });
+        assert_eq!(res, naive);
+
+        // An exact match scores exactly 0
+        let kernel = image.slice(ndarray::s![10..16, 20..29]).to_owned();
+        let res = sse_u8(&image, &kernel).unwrap();
+        assert_eq!(res[[10, 20]], 0);
+
+        assert!(sse_u8(&image, &Array2::zeros((41, 1))).is_err());
+        assert!(sse_u8(&image, &Array2::zeros((0, 1))).is_err());
+    }
+
+    #[test]
+    fn test_match_template_flat() {
+        // Textured on the left, flat on the right
+        let image = Array2::from_shape_fn((32, 48), |(y, x)| {
+            if x < 24 {
+                ((x * 7 + y * 3) % 13) as f32
+            } else {
+                9.0
+            }
+        });
+        // A constant-color kernel has no variance, every window scores 0
+        let constant = Array2::from_elem((5, 6), 4.0);
+        let res = match_template(&image, &constant).unwrap();
+        assert!(res.iter().all(|&v| v == 0.0));
+
+        let kernel = Array2::from_shape_fn((5, 6), |(y, x)| ((x + y * 2) % 5) as f32);
+        let res = match_template(&image, &kernel).unwrap();
+        assert!(res.iter().all(|v| v.is_finite()));
+        // Windows fully inside the flat area score 0
+        assert!(res.slice(ndarray::s![.., 24..]).iter().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn test_match_context() {
+        let image = Array2::from_shape_fn((270, 480), |(y, x)| ((x * 7 + y * 13) % 31) as f32);
+        let kernels = (0..10)
+            .map(|i| {
+                Array2::from_shape_fn((16 + i, 16 + i), |(y, x)| ((x * 3 + y * 5 + i) % 17) as f32)
+            })
+            .collect::<Vec<_>>();
+
+        let t = Instant::now();
+        let expected = kernels
+            .iter()
+            .map(|kernel| match_template(&image, kernel).unwrap())
+            .collect::<Vec<_>>();
+        let separate_cost = t.elapsed();
+
+        let t = Instant::now();
+        let ctx = MatchContext::new(image.clone());
+        let res = kernels
+            .iter()
+            .map(|kernel| ctx.match_template(kernel).unwrap())
+            .collect::<Vec<_>>();
+        let ctx_cost = t.elapsed();
+        println!("10 kernels, separate: {separate_cost:?}, with MatchContext: {ctx_cost:?}");
+        assert_eq!(res, expected);
+
+        let mut ctx = ctx;
+        ctx.set_image(image.clone());
+        assert_eq!(ctx.match_template(&kernels[0]).unwrap(), expected[0]);
+        let image = image.map(|v| v * 2.0);
+        ctx.set_image(image.clone());
+        assert_eq!(ctx.integral_image, integral_arr2(&image));
+    }
+
+    #[test]
+    fn test_kernel_larger_than_image() {
+        let image = Array2::zeros((5, 5));
+        let kernel = Array2::ones((10, 10));
+        let err = match_template(&image, &kernel).unwrap_err();
+        println!("{err}");
+    }
 }
\ No newline at end of file
@@ -3,6 +3,10 @@ use std::{
     ops::{Add, Div, Mul, Sub},
 };
 
+/// A single channel f32 image.
+///
+/// `data` is stored in row-major order with tightly packed rows, i.e. the pixel at `(x, y)`
+/// is `data[y * width + x]`. Prefer [Image::get] / [Image::row] over indexing `data` directly.
 #[derive(Clone, Debug)]
 pub struct Image<'a> {
     pub data: Cow<'a, [f32]>,
@@ -10,6 +14,34 @@ pub struct Image<'a> {
     pub height: u32,
 }
 
+impl Image<'static> {
+    /// An image with all the values set to `0.0`.
+    pub fn zeros(width: u32, height: u32) -> Self {
+        Self::filled(width, height, 0.0)
+    }
+
+    /// An image with all the values set to `value`.
+    pub fn filled(width: u32, height: u32, value: f32) -> Self {
+        Self::new(vec![value; (width * height) as usize], width, height)
+    }
+
+    /// Takes ownership of the buffer's `Vec<f32>` without copying it.
+    ///
+    /// Same as the [From] impl, but spelled out at call sites that care about the copy.
+    pub fn from_luma32f_buffer(buf: image::ImageBuffer<image::Luma<f32>, Vec<f32>>) -> Self {
+        let (width, height) = buf.dimensions();
+        Self::new(buf.into_raw(), width, height)
+    }
+}
+
+/// Rec. 709 luma weights, [image::DynamicImage::to_luma32f] derives about the same ones from the
+/// sRGB primaries.
+const LUMA_WEIGHTS: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+fn rgb_to_luma32f(r: u8, g: u8, b: u8) -> f32 {
+    (LUMA_WEIGHTS[0] * r as f32 + LUMA_WEIGHTS[1] * g as f32 + LUMA_WEIGHTS[2] * b as f32) / 255.0
+}
+
 impl<'a> Image<'a> {
     pub fn new(data: impl Into<Cow<'a, [f32]>>, width: u32, height: u32) -> Self {
         Self {
@@ -19,6 +51,108 @@ impl<'a> Image<'a> {
         }
     }
 
+    /// Converts `image` to grayscale into `scratch` and borrows it, so that a loop converting a
+    /// frame per iteration (e.g. the battle analyzer) allocates only until `scratch` is big enough.
+    ///
+    /// The buffer-reuse contract:
+    /// - `scratch` is cleared and refilled on every call, its previous content is lost. Its
+    ///   capacity is kept, so pass the same `Vec` to every call and never shrink it.
+    /// - The returned image borrows `scratch`, drop it before the next conversion. Use
+    ///   [Cow::into_owned] on `data` to keep a frame around, that copies.
+    /// - The values are in `[0, 1]` and within `1e-4` of [image::DynamicImage::to_luma32f], the
+    ///   alpha channel is ignored. 8-bit luma, RGB and RGBA images are converted in place, other
+    ///   formats go through `to_luma32f` and still allocate a temporary buffer.
+    pub fn from_dynamic_luma(image: &image::DynamicImage, scratch: &'a mut Vec<f32>) -> Self {
+        use image::DynamicImage;
+
+        scratch.clear();
+        match image {
+            DynamicImage::ImageLuma8(img) => {
+                scratch.extend(img.as_raw().iter().map(|&v| v as f32 / 255.0))
+            }
+            DynamicImage::ImageRgb8(img) => scratch.extend(
+                img.as_raw()
+                    .chunks_exact(3)
+                    .map(|p| rgb_to_luma32f(p[0], p[1], p[2])),
+            ),
+            DynamicImage::ImageRgba8(img) => scratch.extend(
+                img.as_raw()
+                    .chunks_exact(4)
+                    .map(|p| rgb_to_luma32f(p[0], p[1], p[2])),
+            ),
+            _ => scratch.extend_from_slice(image.to_luma32f().as_raw()),
+        }
+        Self::new(scratch.as_slice(), image.width(), image.height())
+    }
+
+    /// Returns the value at `(x, y)`, or [None] if it is out of bounds.
+    pub fn try_get(&self, x: u32, y: u32) -> Option<f32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.data.get((y * self.width + x) as usize).copied()
+    }
+
+    /// Returns the value at `(x, y)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `(x, y)` is out of bounds.
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.try_get(x, y).unwrap_or_else(|| {
+            panic!(
+                "pixel ({x}, {y}) is out of bounds of a {}x{} image",
+                self.width, self.height
+            )
+        })
+    }
+
+    /// Returns the `y`th row.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `y` is out of bounds.
+    pub fn row(&self, y: u32) -> &[f32] {
+        assert!(
+            y < self.height,
+            "row {y} is out of bounds of a {}x{} image",
+            self.width,
+            self.height
+        );
+        let start = (y * self.width) as usize;
+        &self.data[start..start + self.width as usize]
+    }
+
+    /// Copies the `width`x`height` sub-rectangle whose top-left corner is `(x, y)` into a new
+    /// image, or returns [None] if it does not fit inside this image.
+    pub fn try_crop(&self, x: u32, y: u32, width: u32, height: u32) -> Option<Image<'static>> {
+        let fits =
+            |start: u32, len: u32, max: u32| start.checked_add(len).is_some_and(|end| end <= max);
+        if !fits(x, width, self.width) || !fits(y, height, self.height) {
+            return None;
+        }
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for row in y..y + height {
+            data.extend_from_slice(&self.row(row)[x as usize..(x + width) as usize]);
+        }
+        Some(Image::new(data, width, height))
+    }
+
+    /// Copies the `width`x`height` sub-rectangle whose top-left corner is `(x, y)` into a new
+    /// image, e.g. to match against a region of interest without going back to [image::DynamicImage].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sub-rectangle does not fit inside this image.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Image<'static> {
+        self.try_crop(x, y, width, height).unwrap_or_else(|| {
+            panic!(
+                "{width}x{height} region at ({x}, {y}) is out of bounds of a {}x{} image",
+                self.width, self.height
+            )
+        })
+    }
+
     pub fn sum(&self) -> f32 {
         self.data.iter().sum()
     }
@@ -106,6 +240,13 @@ impl<'a> From<&'a image::ImageBuffer<image::Luma<f32>, Vec<f32>>> for Image<'a>
     }
 }
 
+/// Takes ownership of the buffer, e.g. to pass a temporary `to_luma32f()` without binding it first.
+impl From<image::ImageBuffer<image::Luma<f32>, Vec<f32>>> for Image<'static> {
+    fn from(img: image::ImageBuffer<image::Luma<f32>, Vec<f32>>) -> Self {
+        Self::from_luma32f_buffer(img)
+    }
+}
+
 // With f32
 impl Add<f32> for Image<'_> {
     type Output = Image<'static>;
@@ -249,3 +390,137 @@ impl<'a> Sub for Image<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use super::Image;
+
+    #[test]
+    fn test_accessors() {
+        let image = Image::new((0..12).map(|v| v as f32).collect::<Vec<_>>(), 4, 3);
+        assert_eq!(image.get(0, 0), 0.0);
+        assert_eq!(image.get(3, 0), 3.0);
+        assert_eq!(image.get(1, 2), 9.0);
+        assert_eq!(image.row(1), &[4.0, 5.0, 6.0, 7.0]);
+
+        assert_eq!(image.try_get(3, 2), Some(11.0));
+        assert_eq!(image.try_get(4, 0), None);
+        assert_eq!(image.try_get(0, 3), None);
+        assert_eq!(image.try_get(u32::MAX, u32::MAX), None);
+    }
+
+    #[test]
+    fn test_zeros_and_filled() {
+        let zeros = Image::zeros(4, 3);
+        assert_eq!((zeros.width, zeros.height), (4, 3));
+        assert_eq!(zeros.data.len(), 12);
+        assert!(zeros.data.iter().all(|&v| v == 0.0));
+
+        let filled = Image::filled(2, 5, 1.5);
+        assert_eq!((filled.width, filled.height), (2, 5));
+        assert_eq!(filled.row(4), &[1.5, 1.5]);
+        assert_eq!(filled.sum(), 15.0);
+
+        assert!(Image::zeros(0, 3).data.is_empty());
+    }
+
+    #[test]
+    fn test_from_image_buffer() {
+        let buffer = image::ImageBuffer::from_fn(3, 2, |x, y| image::Luma([(y * 3 + x) as f32]));
+
+        let borrowed: Image = (&buffer).into();
+        assert!(matches!(borrowed.data, Cow::Borrowed(_)));
+
+        let owned: Image<'static> = buffer.clone().into();
+        assert!(matches!(owned.data, Cow::Owned(_)));
+        assert_eq!((owned.width, owned.height), (3, 2));
+        assert_eq!(owned.data, borrowed.data);
+        assert_eq!(owned.row(1), &[3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_from_luma32f_buffer() {
+        let buffer = image::ImageBuffer::from_fn(3, 2, |x, y| image::Luma([(y * 3 + x) as f32]));
+        let ptr = buffer.as_raw().as_ptr();
+
+        let image = Image::from_luma32f_buffer(buffer);
+        assert!(matches!(image.data, Cow::Owned(_)));
+        // No copy, the image owns the very same allocation
+        assert_eq!(image.data.as_ptr(), ptr);
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(image.row(1), &[3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_from_dynamic_luma() {
+        let rgba = image::RgbaImage::from_fn(16, 9, |x, y| {
+            image::Rgba([
+                (x * 16) as u8,
+                (y * 28) as u8,
+                ((x + y) * 9) as u8,
+                (x * y) as u8,
+            ])
+        });
+        let frames = [
+            image::DynamicImage::ImageRgb8(image::DynamicImage::ImageRgba8(rgba.clone()).to_rgb8()),
+            image::DynamicImage::ImageLuma8(
+                image::DynamicImage::ImageRgba8(rgba.clone()).to_luma8(),
+            ),
+            image::DynamicImage::ImageRgba8(rgba),
+            image::DynamicImage::new_rgba16(4, 3),
+        ];
+
+        let mut scratch = vec![];
+        for frame in &frames {
+            let expected = frame.to_luma32f();
+            let image = Image::from_dynamic_luma(frame, &mut scratch);
+            assert!(matches!(image.data, Cow::Borrowed(_)));
+            assert_eq!((image.width, image.height), expected.dimensions());
+            for (a, b) in image.data.iter().zip(expected.as_raw()) {
+                assert!((a - b).abs() < 1e-4, "{a} != {b}");
+            }
+        }
+
+        // The scratch buffer is reused once it is big enough
+        let capacity = scratch.capacity();
+        let ptr = scratch.as_ptr();
+        let image = Image::from_dynamic_luma(&frames[0], &mut scratch);
+        assert_eq!(image.data.len(), 16 * 9);
+        assert_eq!((scratch.capacity(), scratch.as_ptr()), (capacity, ptr));
+    }
+
+    #[test]
+    fn test_crop() {
+        let image = Image::new((0..12).map(|v| v as f32).collect::<Vec<_>>(), 4, 3);
+
+        let crop = image.crop(1, 1, 2, 2);
+        assert_eq!((crop.width, crop.height), (2, 2));
+        assert_eq!(crop.data, vec![5.0, 6.0, 9.0, 10.0]);
+
+        let full = image.crop(0, 0, 4, 3);
+        assert_eq!(full.data, image.data);
+        assert_eq!(image.crop(3, 2, 1, 1).data, vec![11.0]);
+        assert!(image.crop(4, 3, 0, 0).data.is_empty());
+
+        assert!(image.try_crop(3, 0, 2, 1).is_none());
+        assert!(image.try_crop(0, 2, 1, 2).is_none());
+        assert!(image.try_crop(5, 0, 0, 0).is_none());
+        assert!(image.try_crop(u32::MAX, 0, 2, 1).is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_crop_out_of_bounds() {
+        let image = Image::new(vec![0.0; 4], 2, 2);
+        image.crop(1, 1, 2, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_row_out_of_bounds() {
+        let image = Image::new(vec![0.0; 4], 2, 2);
+        image.row(2);
+    }
+}